@@ -0,0 +1,73 @@
+//! Benchmark of building the inverted index of a file, on a single thread and on all of them.
+//!
+//! Run it with `cargo bench --bench index`. The number of records is taken from `ED_JOIN_BENCH_RECORDS`,
+//! e.g. `ED_JOIN_BENCH_RECORDS=1000000 cargo bench --bench index`.
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ed_join::config::ErrorPolicy;
+use ed_join::qgram::{build_inverted_index, read_records, InvertedIndex};
+use ed_join::report::Side;
+
+/// Write `n` records of a small alphabet, so that records share many q-grams, as names or addresses do.
+fn write_records(path: &PathBuf, n: usize) {
+    let mut writer: BufWriter<File> = BufWriter::new(File::create(path).unwrap());
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    for _ in 0..n {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let len: usize = 8 + (state >> 59) as usize;
+        let record: String = (0..len)
+            .map(|i| (b'a' + ((state >> (i * 2 % 56)) % 16) as u8) as char)
+            .collect();
+        writeln!(writer, "{}", record).unwrap();
+    }
+    writer.flush().unwrap();
+}
+
+/// Read and index a file, returning the index so that it isn't optimized away.
+fn index(path: &PathBuf, q: usize) -> InvertedIndex {
+    let (records, _) = read_records(path, Side::Y, ErrorPolicy::default()).unwrap();
+    build_inverted_index(None, &records, q)
+}
+
+fn bench_index(c: &mut Criterion) {
+    let n: usize = std::env::var("ED_JOIN_BENCH_RECORDS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(200_000);
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("ed_join_bench_index_{}.txt", std::process::id()));
+    write_records(&path, n);
+
+    let threads: usize = rayon::current_num_threads();
+    let single = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("index");
+    group
+        .sample_size(10)
+        .throughput(Throughput::Elements(n as u64));
+    for q in [2, 3, 4] {
+        group.bench_with_input(BenchmarkId::new("1 thread", q), &q, |b, &q| {
+            b.iter(|| single.install(|| index(&path, q)))
+        });
+        group.bench_with_input(
+            BenchmarkId::new(format!("{} threads", threads), q),
+            &q,
+            |b, &q| b.iter(|| index(&path, q)),
+        );
+    }
+    group.finish();
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+criterion_group!(benches, bench_index);
+criterion_main!(benches);
@@ -0,0 +1,74 @@
+//! Benchmark of a self-join in batches of records of different sizes, of which a batch of 1 schedules every record
+//! as a task of its own.
+//!
+//! Run it with `cargo bench --bench join`. The number of records is taken from `ED_JOIN_BENCH_RECORDS`,
+//! e.g. `ED_JOIN_BENCH_RECORDS=50000 cargo bench --bench join`.
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ed_join::config::EdJoinConfig;
+use ed_join::matching::{EdJoinIter, DEFAULT_BATCH_SIZE};
+
+/// Write `n` records of a small alphabet, half of which are a substitution away from an earlier one,
+/// so that records share many q-grams, and have candidates to verify and matches.
+fn write_records(path: &PathBuf, n: usize) {
+    let mut writer: BufWriter<File> = BufWriter::new(File::create(path).unwrap());
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut records: Vec<Vec<u8>> = Vec::with_capacity(n);
+    for i in 0..n {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let record: Vec<u8> = if i > 0 && state >> 63 == 1 {
+            let mut record: Vec<u8> = records[(state >> 20) as usize % i].clone();
+            let at: usize = (state >> 8) as usize % record.len();
+            record[at] = b'a' + (state >> 40) as u8 % 12;
+            record
+        } else {
+            let len: usize = 12 + (state >> 60) as usize % 8;
+            (0..len)
+                .map(|i| b'a' + ((state >> (i * 3 % 56)) % 12) as u8)
+                .collect()
+        };
+        writer.write_all(&record).unwrap();
+        writer.write_all(b"\n").unwrap();
+        records.push(record);
+    }
+    writer.flush().unwrap();
+}
+
+fn bench_join(c: &mut Criterion) {
+    let n: usize = std::env::var("ED_JOIN_BENCH_RECORDS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(20_000);
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("ed_join_bench_join_{}.txt", std::process::id()));
+    write_records(&path, n);
+
+    let mut group = c.benchmark_group(format!(
+        "self-join on {} threads",
+        rayon::current_num_threads()
+    ));
+    group
+        .sample_size(10)
+        .throughput(Throughput::Elements(n as u64));
+    for batch_size in [1, 16, DEFAULT_BATCH_SIZE, 256] {
+        let config: EdJoinConfig = EdJoinConfig::new(3, 1).batch_size(batch_size);
+        group.bench_with_input(
+            BenchmarkId::new("batch size", batch_size),
+            &config,
+            |b, config| b.iter(|| EdJoinIter::new(&path, &path, config).unwrap().count()),
+        );
+    }
+    group.finish();
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+criterion_group!(benches, bench_join);
+criterion_main!(benches);
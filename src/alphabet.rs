@@ -0,0 +1,270 @@
+use crate::errors::*;
+
+/// The characters records are expected to consist of, e.g. to catch encoding bugs of the pipelines that produce them.
+///
+/// An alphabet is parsed from a character class, such as `[A-Za-z0-9 ]`:
+///
+/// ```
+/// use ed_join::alphabet::Alphabet;
+///
+/// let alphabet = Alphabet::parse("[A-Za-z0-9 ]").unwrap();
+/// assert_eq!(alphabet.first_outside("Hello World"), None);
+/// assert_eq!(alphabet.first_outside("Grüße"), Some('ü'));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Alphabet {
+    /// Inclusive ranges of characters, sorted and disjoint.
+    ranges: Vec<(char, char)>,
+}
+
+impl Alphabet {
+    /// Parse a character class of single characters and ranges such as `a-z`, optionally in brackets.
+    ///
+    /// A backslash escapes the next character, e.g. `\]`, `\-` or `\\`, and `\t` is a tab.
+    /// A `-` at the start or end of the class is a character itself.
+    pub fn parse(class: &str) -> Result<Self> {
+        let inner: &str = match class
+            .strip_prefix('[')
+            .and_then(|class| class.strip_suffix(']'))
+        {
+            Some(inner) => inner,
+            None => class,
+        };
+        // characters, and whether they were escaped, so that an escaped `-` is never a range
+        let mut chars: Vec<(char, bool)> = Vec::new();
+        let mut iter = inner.chars();
+        while let Some(c) = iter.next() {
+            if c == '\\' {
+                match iter.next() {
+                    Some('t') => chars.push(('\t', true)),
+                    Some(escaped) => chars.push((escaped, true)),
+                    None => bail!(ErrorKind::InvalidAlphabet(format!(
+                        "trailing backslash in `{}`",
+                        class
+                    ))),
+                }
+            } else {
+                chars.push((c, false));
+            }
+        }
+        if chars.is_empty() {
+            bail!(ErrorKind::InvalidAlphabet(format!(
+                "no characters in `{}`",
+                class
+            )));
+        }
+
+        let mut ranges: Vec<(char, char)> = Vec::new();
+        let mut i: usize = 0;
+        while i < chars.len() {
+            let start: char = chars[i].0;
+            if i + 2 < chars.len() && chars[i + 1] == ('-', false) {
+                let end: char = chars[i + 2].0;
+                if start > end {
+                    bail!(ErrorKind::InvalidAlphabet(format!(
+                        "range `{}-{}` is reversed",
+                        start, end
+                    )));
+                }
+                ranges.push((start, end));
+                i += 3;
+            } else {
+                ranges.push((start, start));
+                i += 1;
+            }
+        }
+        ranges.sort_unstable();
+        let mut merged: Vec<(char, char)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start as u32 <= last.1 as u32 + 1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        Ok(Self { ranges: merged })
+    }
+
+    /// Whether a character is in the alphabet.
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&c))
+    }
+
+    /// The first character of a record that is not in the alphabet, if any.
+    pub fn first_outside(&self, record: &str) -> Option<char> {
+        record.chars().find(|c| !self.contains(*c))
+    }
+
+    /// Replace the characters of a record that are not in the alphabet by their closest equivalents which are,
+    /// e.g. `é` by `e`, `ß` by `ss` or `“` by `"`, and drop those without any.
+    pub fn transliterate(&self, record: &str) -> String {
+        let mut transliterated: String = String::with_capacity(record.len());
+        for c in record.chars() {
+            if self.contains(c) {
+                transliterated.push(c);
+            } else if let Some(folded) = fold(c) {
+                transliterated.extend(folded.chars().filter(|c| self.contains(*c)));
+            }
+        }
+        transliterated
+    }
+}
+
+/// Transliterate a record into ASCII, e.g. `Müller` into `Muller`, `Straße` into `Strasse` or `“quoted”` into `"quoted"`,
+/// so that records spelt with and without diacritics are within a small edit distance.
+///
+/// Combining diacritical marks are dropped, and characters without an equivalent are kept as they are.
+///
+/// ```
+/// use ed_join::alphabet::to_ascii;
+///
+/// assert_eq!(to_ascii("Müller – Mu\u{308}ller"), "Muller - Muller");
+/// assert_eq!(to_ascii("東京"), "東京");
+/// ```
+pub fn to_ascii(record: &str) -> String {
+    let mut transliterated: String = String::with_capacity(record.len());
+    for c in record.chars() {
+        if c.is_ascii() {
+            transliterated.push(c);
+        } else if !('\u{300}'..='\u{36f}').contains(&c) {
+            match fold(c) {
+                Some(folded) => transliterated.push_str(folded),
+                None => transliterated.push(c),
+            }
+        }
+    }
+    transliterated
+}
+
+/// Letters with diacritics, as their base letter.
+const LETTERS: &[(&str, &str)] = &[
+    ("ÀÁÂÃÄÅĀĂĄ", "A"),
+    ("àáâãäåāăą", "a"),
+    ("ÇĆĈĊČ", "C"),
+    ("çćĉċč", "c"),
+    ("ĎĐ", "D"),
+    ("ďđ", "d"),
+    ("ÈÉÊËĒĔĖĘĚ", "E"),
+    ("èéêëēĕėęě", "e"),
+    ("ĜĞĠĢ", "G"),
+    ("ĝğġģ", "g"),
+    ("ĤĦ", "H"),
+    ("ĥħ", "h"),
+    ("ÌÍÎÏĨĪĬĮİ", "I"),
+    ("ìíîïĩīĭįı", "i"),
+    ("Ĵ", "J"),
+    ("ĵ", "j"),
+    ("Ķ", "K"),
+    ("ķ", "k"),
+    ("ĹĻĽĿŁ", "L"),
+    ("ĺļľŀł", "l"),
+    ("ÑŃŅŇ", "N"),
+    ("ñńņň", "n"),
+    ("ÒÓÔÕÖØŌŎŐ", "O"),
+    ("òóôõöøōŏő", "o"),
+    ("ŔŖŘ", "R"),
+    ("ŕŗř", "r"),
+    ("ŚŜŞŠ", "S"),
+    ("śŝşš", "s"),
+    ("ŢŤŦ", "T"),
+    ("ţťŧ", "t"),
+    ("ÙÚÛÜŨŪŬŮŰŲ", "U"),
+    ("ùúûüũūŭůűų", "u"),
+    ("Ŵ", "W"),
+    ("ŵ", "w"),
+    ("ÝŶŸ", "Y"),
+    ("ýÿŷ", "y"),
+    ("ŹŻŽ", "Z"),
+    ("źżž", "z"),
+];
+
+/// The closest equivalent of a character, in ASCII.
+fn fold(c: char) -> Option<&'static str> {
+    let folded: &'static str = match c {
+        'ß' => "ss",
+        'Æ' => "AE",
+        'æ' => "ae",
+        'Œ' => "OE",
+        'œ' => "oe",
+        'Þ' => "TH",
+        'þ' => "th",
+        'Ð' => "D",
+        'ð' => "d",
+        '‘' | '’' | '‚' | '′' => "'",
+        '“' | '”' | '„' | '″' => "\"",
+        '‐' | '‑' | '‒' | '–' | '—' | '−' => "-",
+        '\u{a0}' | '\u{2007}' | '\u{202f}' => " ",
+        '…' => "...",
+        _ => LETTERS.iter().find(|(letters, _)| letters.contains(c))?.1,
+    };
+    Some(folded)
+}
+
+/// What happens to a record with characters out of the [`Alphabet`].
+///
+/// Each such record is reported as a [`crate::report::Warning::OutOfAlphabet`], with the first of its characters
+/// that is out of the alphabet, unless the join is aborted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AlphabetAction {
+    /// Only report the record, which is joined as it is.
+    #[default]
+    Report,
+    /// Skip the record, which still occupies its ID.
+    Skip,
+    /// Join the record transliterated into the alphabet, see [`Alphabet::transliterate`].
+    Transliterate,
+    /// Abort the join.
+    Abort,
+}
+
+impl AlphabetAction {
+    /// Parse the name of an action, i.e. `report`, `skip`, `transliterate` or `abort`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "report" => Some(AlphabetAction::Report),
+            "skip" => Some(AlphabetAction::Skip),
+            "transliterate" => Some(AlphabetAction::Transliterate),
+            "abort" => Some(AlphabetAction::Abort),
+            _ => None,
+        }
+    }
+
+    /// The name of the action, as parsed by [`Self::from_name`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlphabetAction::Report => "report",
+            AlphabetAction::Skip => "skip",
+            AlphabetAction::Transliterate => "transliterate",
+            AlphabetAction::Abort => "abort",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alphabet() {
+        let alphabet: Alphabet = Alphabet::parse("[a-zA-Z0-9 \\-]").unwrap();
+        assert!(alphabet.contains('q') && alphabet.contains('-') && alphabet.contains(' '));
+        assert!(!alphabet.contains('_') && !alphabet.contains('é'));
+        assert_eq!(alphabet.first_outside("well-known"), None);
+        assert_eq!(alphabet.first_outside("naïve café"), Some('ï'));
+        assert_eq!(
+            alphabet.transliterate("Straße – naïve café™"),
+            "Strasse - naive cafe"
+        );
+
+        assert_eq!(
+            Alphabet::parse("a-cb-d").unwrap(),
+            Alphabet::parse("[a-d]").unwrap()
+        );
+        assert!(Alphabet::parse("-a").unwrap().contains('-'));
+        assert!(Alphabet::parse("[]").is_err());
+        assert!(Alphabet::parse("z-a").is_err());
+        assert!(Alphabet::parse("ab\\").is_err());
+    }
+}
@@ -0,0 +1,49 @@
+//! The stable API of this crate, which follows semantic versioning.
+//!
+//! Everything here only changes incompatibly in a major release. The algorithm itself, i.e. the inverted index,
+//! candidate generation and the filters, lives in [`crate::internals`], which may change in any release.
+//!
+//! ```
+//! use ed_join::api::{check_pair, EdJoinConfig, Verdict};
+//!
+//! let config: EdJoinConfig = EdJoinConfig::new(2, 1);
+//! assert_eq!(check_pair("hello", "hallo", config.q, config.tau), Verdict::Matched { ed: 1 });
+//! ```
+
+pub use crate::alphabet::{Alphabet, AlphabetAction};
+pub use crate::assignment::Assignment;
+pub use crate::chain::{chain_join, write_paths, MatchPath};
+pub use crate::cluster::{Clustering, Linkage};
+pub use crate::config::{
+    CountFilter, EdJoinConfig, ErrorPolicy, Filters, InputFormat, JoinMode, Limits, Normalization,
+    QualityFilter, Violation,
+};
+pub use crate::delta::Delta;
+pub use crate::errors::{Error, ErrorKind, Result};
+pub use crate::idmap::IdMap;
+pub use crate::matching::{
+    calc_prefix_len, check_pair, ed_join, ed_join_candidates, ed_join_matches, ed_join_records,
+    ed_join_with_config, ed_self_join_records, min_edit_errors, EdJoinIter,
+};
+pub use crate::offsets::LineOffsets;
+pub use crate::output::{
+    JoinCandidates, JoinOutput, NamingField, OutputFormat, OutputNaming, OutputTemplate,
+};
+pub use crate::persist::Phase;
+pub use crate::plugin::{DylibPlugin, MatchPlugin, PluginMatch, PLUGIN_ABI_VERSION};
+pub use crate::progress::{ProgressEvent, ProgressFn, ProgressObserver};
+pub use crate::qgram::{IndexBackend, PosQGram};
+pub use crate::report::{JoinReport, JoinStats, Side, Warning};
+pub use crate::scoring::{DefaultScorer, ScoreContext, Scorer};
+pub use crate::sink::{
+    FileSink, MemorySink, OutputSink, RetrySink, RowSink, SinkContext, StdoutSink, WriteSink,
+};
+pub use crate::spool::{run_spool, watch, Job};
+pub use crate::store::RecordStore;
+pub use crate::tokenizer::{
+    CanonicalKmers, CharQGrams, FnTokenizer, GraphemeQGrams, PackedGrams, QGrams, TokenQGrams,
+    Tokenizer, TokenizerMode, WordQGrams,
+};
+pub use crate::unicode::UnicodeForm;
+pub use crate::verification::{DistanceMetric, Verdict};
+pub use crate::window::Windowing;
@@ -0,0 +1,259 @@
+use rayon::prelude::*;
+use std::collections::HashSet;
+
+use crate::matching::generate_candidates_pruned;
+use crate::qgram::{InternedIndex, ID};
+use crate::tokenizer::Tokenizer;
+use crate::verification::EditDistanceBuffer;
+
+/// Number of records of `doc_x` that the pruning of an approximate join is tuned on.
+const SAMPLE_SIZE: usize = 1000;
+
+/// How aggressively candidates are pruned in an approximate join, see [`crate::matching::generate_candidates_pruned`].
+///
+/// The default prunes nothing, and is thus exact.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pruning {
+    /// Q-grams with longer inverted lists than this are stop-grams, which are not probed.
+    pub max_postings: Option<usize>,
+    /// At most this many candidates are kept for each record, those sharing the most q-grams of its prefix.
+    pub max_candidates: Option<usize>,
+}
+
+/// Choose the pruning which generates the fewest candidates, while still finding at least `recall` of the matches.
+///
+/// The recall of each pruning is estimated on an evenly spaced sample of up to 1000 records of `doc_x`,
+/// whose matches are found exactly first.
+///
+/// # Parameters
+///
+///  * `x_vec` and `y_vec`: Records of `doc_x` and `doc_y`.
+///  * `skipped_x`: Line numbers of records of `doc_x` which are not processed.
+///  * `inverted_index`, `q`, `tau` and `self_join`: The same as for [`generate_candidates`].
+///  * `recall`: The smallest acceptable recall, in `(0, 1]`.
+///  * `tokenizer`: The tokenizer the index was built with.
+///
+/// # Return
+///
+/// The chosen pruning, and its estimated recall, or `None` if the sample has no match to estimate it by,
+/// in which case nothing is pruned.
+#[allow(clippy::too_many_arguments)]
+pub fn tune(
+    x_vec: &[String],
+    skipped_x: &HashSet<ID>,
+    y_vec: &[String],
+    inverted_index: &InternedIndex,
+    q: usize,
+    tau: usize,
+    self_join: bool,
+    recall: f64,
+    tokenizer: &dyn Tokenizer,
+) -> (Pruning, Option<f64>) {
+    let ids: Vec<ID> = (0..x_vec.len())
+        .filter(|x_id| !skipped_x.contains(x_id))
+        .collect();
+    let step: usize = ids.len() / SAMPLE_SIZE + 1;
+    let sample: Vec<ID> = ids.into_iter().step_by(step).collect();
+
+    // matches of the sample, which are all among the exact candidates
+    let matches: Vec<(ID, Vec<ID>)> = sample
+        .par_iter()
+        .map(|x_id| {
+            let x_content: &str = &x_vec[*x_id];
+            let (_, candidates) = generate_candidates_pruned(
+                *x_id,
+                x_content,
+                y_vec,
+                inverted_index,
+                q,
+                tau,
+                self_join,
+                &Pruning::default(),
+                tokenizer,
+            );
+            let mut buffer: EditDistanceBuffer = EditDistanceBuffer::default();
+            let matched: Vec<ID> = candidates
+                .into_iter()
+                .filter(|y_id| {
+                    buffer
+                        .bounded_edit_distance_in(x_content, &y_vec[*y_id], tau, tokenizer.mode())
+                        .is_some()
+                })
+                .collect();
+            (*x_id, matched)
+        })
+        .collect();
+    let total: usize = matches.iter().map(|(_, matched)| matched.len()).sum();
+    if total == 0 {
+        return (Pruning::default(), None);
+    }
+
+    // stop-grams by quantiles of the lengths of inverted lists, and caps of candidates, from mild to aggressive
+    let mut lengths: Vec<usize> = inverted_index
+        .iter()
+        .map(|(_, (inverted_list, _))| inverted_list.len())
+        .collect();
+    lengths.sort_unstable();
+    let quantile = |p: f64| lengths[((lengths.len() - 1) as f64 * p) as usize];
+    let max_postings: Vec<Option<usize>> = vec![
+        None,
+        Some(quantile(0.99)),
+        Some(quantile(0.95)),
+        Some(quantile(0.9)),
+        Some(quantile(0.75)),
+    ];
+    let max_candidates: Vec<Option<usize>> = vec![None, Some(100), Some(30), Some(10), Some(3)];
+
+    let mut best: (usize, Pruning, f64) = (usize::MAX, Pruning::default(), 1.0);
+    for max_postings in &max_postings {
+        for max_candidates in &max_candidates {
+            let pruning: Pruning = Pruning {
+                max_postings: *max_postings,
+                max_candidates: *max_candidates,
+            };
+            let (cost, found): (usize, usize) = matches
+                .par_iter()
+                .map(|(x_id, matched)| {
+                    let (_, candidates) = generate_candidates_pruned(
+                        *x_id,
+                        &x_vec[*x_id],
+                        y_vec,
+                        inverted_index,
+                        q,
+                        tau,
+                        self_join,
+                        &pruning,
+                        tokenizer,
+                    );
+                    let found: usize = matched
+                        .iter()
+                        .filter(|y_id| candidates.binary_search(y_id).is_ok())
+                        .count();
+                    (candidates.len(), found)
+                })
+                .reduce(|| (0, 0), |(a, b), (c, d)| (a + c, b + d));
+            let estimate: f64 = found as f64 / total as f64;
+            if estimate >= recall && cost < best.0 {
+                best = (cost, pruning, estimate);
+            }
+        }
+    }
+    #[cfg(feature = "logging")]
+    info!(
+        "Chose {:?} for an approximate join, with an estimated recall of {} on {} records",
+        best.1,
+        best.2,
+        sample.len()
+    );
+
+    (best.1, Some(best.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching::{generate_candidates, generate_candidates_capped};
+    use crate::qgram::build_inverted_index;
+    use crate::tokenizer::QGrams;
+    use crate::verification::bounded_edit_distance;
+
+    #[test]
+    fn tune_recall() {
+        let records: Vec<String> = [
+            "hello", "hallo", "hullo", "jello", "world", "words", "sword", "hell",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let inverted_index: InternedIndex = build_inverted_index(None, &records, 2).into();
+        let skipped: HashSet<ID> = HashSet::new();
+
+        // a perfect recall can't prune anything that's needed
+        let (pruning, recall) = tune(
+            &records,
+            &skipped,
+            &records,
+            &inverted_index,
+            2,
+            1,
+            true,
+            1.0,
+            &QGrams,
+        );
+        assert_eq!(recall, Some(1.0));
+        for x_id in 0..records.len() {
+            let exact =
+                generate_candidates(x_id, &records[x_id], &records, &inverted_index, 2, 1, true).1;
+            let pruned = generate_candidates_pruned(
+                x_id,
+                &records[x_id],
+                &records,
+                &inverted_index,
+                2,
+                1,
+                true,
+                &pruning,
+                &QGrams,
+            )
+            .1;
+            exact
+                .iter()
+                .filter(|y_id| bounded_edit_distance(&records[x_id], &records[**y_id], 1).is_some())
+                .for_each(|y_id| assert!(pruned.contains(y_id)));
+        }
+
+        let (_, recall) = tune(
+            &records,
+            &skipped,
+            &records,
+            &inverted_index,
+            2,
+            1,
+            true,
+            0.5,
+            &QGrams,
+        );
+        assert!(recall.unwrap() >= 0.5);
+    }
+
+    #[test]
+    fn capped_candidates() {
+        let records: Vec<String> = ["hello", "hallo", "hullo", "jello", "hell"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let inverted_index: InternedIndex = build_inverted_index(None, &records, 2).into();
+        let exact = generate_candidates(0, &records[0], &records, &inverted_index, 2, 1, true).1;
+        assert!(exact.len() > 1);
+
+        // the candidates beyond the cap are counted, so that they can be reported
+        let pruning: Pruning = Pruning {
+            max_postings: None,
+            max_candidates: Some(1),
+        };
+        let (_, capped, dropped) = generate_candidates_capped(
+            0,
+            &records[0],
+            &records,
+            &inverted_index,
+            2,
+            1,
+            true,
+            &pruning,
+            &QGrams,
+        );
+        assert_eq!((capped.len(), dropped), (1, exact.len() - 1));
+        let (_, _, dropped) = generate_candidates_capped(
+            0,
+            &records[0],
+            &records,
+            &inverted_index,
+            2,
+            1,
+            true,
+            &Pruning::default(),
+            &QGrams,
+        );
+        assert_eq!(dropped, 0);
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+use crate::qgram::ID;
+
+/// How matches are reduced to a one-to-one assignment between records of `doc_x` and `doc_y`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Assignment {
+    /// Repeatedly take the closest pair whose records are both unassigned, ties broken by line numbers.
+    Greedy,
+    /// Assign as many records as possible, and among those assignments, one with the smallest total distance.
+    ///
+    /// This is the Hungarian method restricted to the sparse graph of matches,
+    /// solved as a minimum-cost flow by successive shortest paths.
+    Optimal,
+}
+
+impl Assignment {
+    /// Parse the name of a method, i.e. `greedy` or `optimal`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "greedy" => Some(Assignment::Greedy),
+            "optimal" => Some(Assignment::Optimal),
+            _ => None,
+        }
+    }
+}
+
+/// Reduce matches such that each record of `doc_x` and each record of `doc_y` is in at most one pair.
+///
+/// Records of `doc_x` and `doc_y` are always told apart, even in a self-join.
+///
+/// # Parameters
+///
+///  * `pairs`: Matched pairs, grouped by line number of `doc_x`, as returned by `verify()`.
+///  * `method`: How pairs are chosen.
+///
+/// # Return
+///
+/// The assigned pairs in the same layout, in increasing line numbers of `doc_x`.
+pub fn assign(pairs: &[(ID, Vec<(ID, usize)>)], method: Assignment) -> Vec<(ID, Vec<(ID, usize)>)> {
+    let mut edges: Vec<(ID, ID, usize)> = pairs
+        .iter()
+        .flat_map(|(x_id, yvec)| yvec.iter().map(move |(y_id, ed)| (*x_id, *y_id, *ed)))
+        .collect();
+
+    let mut assigned: Vec<(ID, ID, usize)> = match method {
+        Assignment::Greedy => {
+            edges.sort_unstable_by_key(|(x_id, y_id, ed)| (*ed, *x_id, *y_id));
+            let mut used_x: HashSet<ID> = HashSet::new();
+            let mut used_y: HashSet<ID> = HashSet::new();
+            edges
+                .into_iter()
+                .filter(|(x_id, y_id, _)| {
+                    !used_x.contains(x_id)
+                        && !used_y.contains(y_id)
+                        && used_x.insert(*x_id)
+                        && used_y.insert(*y_id)
+                })
+                .collect()
+        }
+        Assignment::Optimal => min_cost_matching(&edges),
+    };
+
+    assigned.sort_unstable_by_key(|(x_id, y_id, _)| (*x_id, *y_id));
+    assigned
+        .into_iter()
+        .map(|(x_id, y_id, ed)| (x_id, vec![(y_id, ed)]))
+        .collect()
+}
+
+/// An arc of the flow network, with its reverse arc at `graph[to][rev]`.
+struct Arc {
+    to: usize,
+    capacity: usize,
+    cost: isize,
+    rev: usize,
+}
+
+/// Find a maximum matching with the smallest total distance, by successive shortest paths on a flow network
+/// `source -> x -> y -> sink` with unit capacities.
+fn min_cost_matching(edges: &[(ID, ID, usize)]) -> Vec<(ID, ID, usize)> {
+    let mut nodes_x: BTreeMap<ID, usize> = BTreeMap::new();
+    let mut nodes_y: BTreeMap<ID, usize> = BTreeMap::new();
+    edges.iter().for_each(|(x_id, y_id, _)| {
+        nodes_x.insert(*x_id, 0);
+        nodes_y.insert(*y_id, 0);
+    });
+    // node 0 is the source, then all x, all y, and the sink last
+    nodes_x
+        .values_mut()
+        .enumerate()
+        .for_each(|(i, node)| *node = 1 + i);
+    let offset: usize = 1 + nodes_x.len();
+    nodes_y
+        .values_mut()
+        .enumerate()
+        .for_each(|(i, node)| *node = offset + i);
+    let (source, sink): (usize, usize) = (0, offset + nodes_y.len());
+
+    let mut graph: Vec<Vec<Arc>> = (0..=sink).map(|_| Vec::new()).collect();
+    let add_arc = |graph: &mut Vec<Vec<Arc>>, from: usize, to: usize, cost: isize| {
+        let (rev_from, rev_to): (usize, usize) = (graph[to].len(), graph[from].len());
+        graph[from].push(Arc {
+            to,
+            capacity: 1,
+            cost,
+            rev: rev_from,
+        });
+        graph[to].push(Arc {
+            to: from,
+            capacity: 0,
+            cost: -cost,
+            rev: rev_to,
+        });
+    };
+    nodes_x
+        .values()
+        .for_each(|x| add_arc(&mut graph, source, *x, 0));
+    nodes_y
+        .values()
+        .for_each(|y| add_arc(&mut graph, *y, sink, 0));
+    edges.iter().for_each(|(x_id, y_id, ed)| {
+        add_arc(&mut graph, nodes_x[x_id], nodes_y[y_id], *ed as isize)
+    });
+
+    // each augmenting path assigns one more pair, and the shortest one keeps the total distance minimal
+    loop {
+        // Bellman-Ford with a queue, as residual arcs have negative costs
+        let mut dist: Vec<Option<isize>> = vec![None; graph.len()];
+        let mut prev: Vec<Option<(usize, usize)>> = vec![None; graph.len()];
+        let mut queued: Vec<bool> = vec![false; graph.len()];
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        dist[source] = Some(0);
+        queue.push_back(source);
+        while let Some(node) = queue.pop_front() {
+            queued[node] = false;
+            let d: isize = dist[node].unwrap();
+            for (i, arc) in graph[node].iter().enumerate() {
+                if arc.capacity > 0 && dist[arc.to].map_or(true, |to| d + arc.cost < to) {
+                    dist[arc.to] = Some(d + arc.cost);
+                    prev[arc.to] = Some((node, i));
+                    if !queued[arc.to] {
+                        queued[arc.to] = true;
+                        queue.push_back(arc.to);
+                    }
+                }
+            }
+        }
+        if dist[sink].is_none() {
+            break;
+        }
+
+        let mut node: usize = sink;
+        while let Some((from, i)) = prev[node] {
+            graph[from][i].capacity -= 1;
+            let (to, rev): (usize, usize) = (graph[from][i].to, graph[from][i].rev);
+            graph[to][rev].capacity += 1;
+            node = from;
+        }
+    }
+
+    // a saturated arc from x to y is an assigned pair
+    let ids_y: BTreeMap<usize, ID> = nodes_y.iter().map(|(id, node)| (*node, *id)).collect();
+    nodes_x
+        .iter()
+        .flat_map(|(x_id, x)| {
+            graph[*x]
+                .iter()
+                .filter(|arc| arc.to != source && arc.capacity == 0)
+                .map(|arc| (*x_id, ids_y[&arc.to], arc.cost as usize))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assignments() {
+        // the closest pair `x0 - y1` blocks both of the other pairs
+        let pairs = vec![(0, vec![(0, 1), (1, 0)]), (1, vec![(1, 1)])];
+
+        assert_eq!(assign(&pairs, Assignment::Greedy), vec![(0, vec![(1, 0)])]);
+        assert_eq!(
+            assign(&pairs, Assignment::Optimal),
+            vec![(0, vec![(0, 1)]), (1, vec![(1, 1)])]
+        );
+    }
+}
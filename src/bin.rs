@@ -3,40 +3,509 @@ extern crate error_chain;
 #[macro_use]
 extern crate log;
 
-use env_logger;
-use std::io::Write;
+use edit_distance::edit_distance;
+use std::{
+    cmp,
+    fs::File,
+    io::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
 
+pub mod alphabet;
+pub mod api;
+pub mod approx;
+pub mod assignment;
+pub mod cache;
+pub mod chain;
 pub mod cli;
+pub mod cluster;
+pub mod config;
+pub mod csv;
+pub mod delta;
 pub mod errors;
+pub mod external;
+pub mod fastq;
+pub mod filters;
+pub mod hash;
+pub mod idmap;
+pub mod intern;
+pub mod internals;
+pub mod jsonl;
 pub mod matching;
+pub mod offsets;
+pub mod output;
+pub mod persist;
+pub mod plugin;
+pub mod prelude;
+pub mod progress;
 pub mod qgram;
+pub mod report;
+pub mod sample;
+pub mod scoring;
+pub mod sink;
+pub mod spool;
+pub mod store;
+#[cfg(test)]
+mod testutil;
+pub mod timeline;
+pub mod tokenizer;
+pub mod unicode;
 pub mod verification;
+pub mod window;
 
+use crate::config::EdJoinConfig;
 use crate::errors::*;
-use crate::matching::ed_join;
+use crate::matching::{check_pair, ed_join_candidates, ed_join_with_config, output_name};
+use crate::qgram::generate_inverted_index;
+
+/// Set on SIGTERM or SIGINT, on which a daemon shuts down gracefully.
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn request_shutdown(_signal: libc::c_int) {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
+/// Request a graceful shutdown on SIGTERM, as sent before a pod is killed, and on SIGINT.
+fn handle_signals() {
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(
+            libc::SIGTERM,
+            request_shutdown as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+        libc::signal(
+            libc::SIGINT,
+            request_shutdown as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
 
 fn main() -> Result<()> {
+    // Parsing CLI Argument and get configurations
+    let command: cli::Command = cli::parse_config().unwrap_or_else(|err| {
+        eprintln!("Error when parsing CLI arguments:\n {}", err);
+        std::process::exit(1);
+    });
+
     // See https://docs.rs/env_logger/0.7.0/env_logger/ for details on controlling the log output
-    env_logger::builder()
-        .format(|buf, record| {
-            writeln!(
-                buf,
-                "{}",
-                // "[{}] \n {}",
-                // record.module_path().unwrap(),
-                record.args()
-            )
-        })
-        .init();
+    let log_format: cli::LogFormat = match &command {
+        cli::Command::Join(config) => config.log_format,
+        cli::Command::Pairs(_)
+        | cli::Command::Index(_)
+        | cli::Command::IndexFile(_)
+        | cli::Command::IndexMerge(_)
+        | cli::Command::Chain(_)
+        | cli::Command::Daemon(_) => cli::LogFormat::Text,
+    };
+    match log_format {
+        cli::LogFormat::Text => env_logger::builder()
+            .format(|buf, record| {
+                writeln!(
+                    buf,
+                    "{}",
+                    // "[{}] \n {}",
+                    // record.module_path().unwrap(),
+                    record.args()
+                )
+            })
+            .init(),
+        // single-line records, so that log ingestion doesn't break on multi-line messages
+        cli::LogFormat::Json => env_logger::builder()
+            .format(|buf, record| {
+                writeln!(
+                    buf,
+                    "{{\"timestamp\":\"{}\",\"level\":\"{}\",\"module\":\"{}\",\"message\":\"{}\"}}",
+                    buf.timestamp(),
+                    record.level(),
+                    output::escape_json(record.module_path().unwrap_or_default()),
+                    output::escape_json(&record.args().to_string())
+                )
+            })
+            .init(),
+    }
 
-    // Parsing CLI Argument and get configurations
-    let config: cli::Config = cli::parse_config().unwrap_or_else(|err| {
+    let config: cli::Config = match command {
+        cli::Command::Join(config) => *config,
+        cli::Command::Pairs(config) => return check_pairs(&config),
+        cli::Command::Index(config) => return dump_index(&config),
+        cli::Command::IndexFile(config) => return check_index(&config),
+        cli::Command::IndexMerge(config) => return merge_index(&config),
+        cli::Command::Chain(config) => return join_chain(&config),
+        cli::Command::Daemon(config) => {
+            handle_signals();
+            let shutdown: spool::Shutdown = spool::Shutdown {
+                requested: &SHUTDOWN,
+                grace: config.grace,
+            };
+            if !config.once {
+                return spool::watch_until(
+                    &config.spool,
+                    config.interval,
+                    config.workers,
+                    shutdown,
+                );
+            }
+            let jobs: usize = spool::run_spool_until(&config.spool, config.workers, shutdown)?;
+            println!("Ran {} jobs of {}", jobs, config.spool.display());
+            return Ok(());
+        }
+    };
+
+    let mut join_config = EdJoinConfig::new(config.q, config.tau)
+        .error_policy(config.error_policy)
+        .delimiter(config.delimiter)
+        .output_format(config.output_format)
+        .count_filter(config.count_filter)
+        .metric(config.metric)
+        .progress(cli::ProgressBarBuilder::new(0, "Processing").build());
+    join_config.tokenizer = config.tokenizer;
+    if let Some(cache_dir) = config.cache_dir {
+        join_config = join_config.cache_dir(cache_dir);
+    }
+    if config.resume {
+        join_config = join_config.resume();
+    }
+    if let Some(interval) = config.checkpoint_interval {
+        join_config = join_config.checkpoint_interval(interval);
+    }
+    if let Some(phase) = config.stop_after {
+        join_config = join_config.stop_after(phase);
+    }
+    if let Some(index_from) = config.index_from {
+        join_config = join_config.index_from(index_from);
+    }
+    if config.provenance {
+        join_config = join_config.provenance();
+    }
+    if let Some(candidates_from) = config.candidates_from {
+        join_config = join_config.candidates_from(candidates_from);
+    }
+    if let Some(tombstones) = &config.tombstones {
+        join_config = join_config.tombstones(persist::read_tombstones(tombstones)?);
+    }
+    if let Some(emit_candidates) = config.emit_candidates {
+        join_config = join_config.emit_candidates(emit_candidates);
+    }
+    if let Some(diagnostics) = config.diagnostics {
+        join_config = join_config.diagnostics(diagnostics);
+    }
+    if let Some(dir) = config.debug_sample {
+        join_config = join_config.debug_sample(dir);
+    }
+    if config.confidence {
+        join_config = join_config.scorer(scoring::DefaultScorer);
+    }
+    if let Some(assignment) = config.assignment {
+        join_config = join_config.assignment(assignment);
+    }
+    if let Some(sample) = config.adaptive_filters {
+        join_config = join_config.adaptive_filters(sample);
+    }
+    if let Some(len) = config.bypass_len {
+        join_config = join_config.bypass_len(len);
+    }
+    if let Some(batch_size) = config.batch_size {
+        join_config = join_config.batch_size(batch_size);
+    }
+    if let Some(threads) = config.threads {
+        join_config = join_config.num_threads(threads);
+    }
+    if let Some(recall) = config.recall {
+        join_config = join_config.approximate(recall);
+    }
+    if let Some(warm_dir) = config.warm_dir {
+        join_config = join_config.warm_start(warm_dir);
+    }
+    if let Some(external_index) = config.external_index {
+        join_config = join_config.external_index(external_index);
+    }
+    if config.reverse_complement {
+        join_config = join_config.reverse_complement();
+    }
+    join_config = join_config.input_format(config.input_format);
+    if let Some(windowing) = config.windowing {
+        join_config =
+            join_config.sliding_windows(windowing.size, windowing.step, windowing.min_coverage);
+    }
+    if let Some((previous_x, previous_output)) = config.delta {
+        join_config = join_config.delta(previous_x, previous_output);
+    }
+    if let Some(timeout) = config.record_timeout {
+        join_config = join_config.record_timeout(timeout);
+    }
+    join_config = join_config.limits(config.limits);
+    match &config.output {
+        Some(output) if output.as_os_str() == "-" => {
+            join_config = join_config.output(sink::StdoutSink)
+        }
+        Some(output) => join_config = join_config.output_path(output),
+        None => {}
+    }
+    if let Some(naming) = config.output_naming {
+        join_config = join_config.output_naming(naming);
+    }
+    if let Some(output_dir) = config.output_dir {
+        join_config = join_config.output_dir(output_dir);
+    }
+    if config.line_offsets {
+        join_config = join_config.line_offsets();
+    }
+    if config.emit_content {
+        join_config = join_config.emit_content();
+    }
+    if let Some(salt) = config.mask_salt {
+        join_config = join_config.mask_texts(output::TextMask::new(salt)?);
+    }
+    for path in &config.plugins {
+        join_config = join_config.plugin(plugin::DylibPlugin::load(path)?);
+    }
+    join_config = join_config.mode(config.mode);
+    join_config = join_config
+        .filters(config.filters)
+        .normalization(config.normalization);
+    if let Some((alphabet, action)) = config.expected_alphabet {
+        join_config = join_config.alphabet(alphabet, action);
+    }
+    if config.progressive {
+        join_config = join_config.progressive();
+    }
+    if let Some(k) = config.knn {
+        join_config = join_config.knn(k);
+    }
+    if let Some(ratio) = config.tau_ratio {
+        join_config = join_config.tau_ratio(ratio);
+    }
+    if let Some(clustering) = config.clustering {
+        join_config = join_config.clustering(clustering.linkage, clustering.cutoff);
+    }
+    // options which contradict each other are all reported at once, as invalid arguments are
+    if let Err(err) = join_config.validate() {
         eprintln!("Error when parsing CLI arguments:\n {}", err);
         std::process::exit(1);
+    }
+    if config.timeline.is_some() {
+        timeline::start()?;
+    }
+    let report = if config.candidates_only {
+        let candidates = ed_join_candidates(&config.doc_x, &config.doc_y, &join_config)?;
+        let out_name = output_name(&config.doc_x, &join_config);
+        candidates.write(join_config.open_output(&out_name)?, config.delimiter)?;
+        if join_config.output_sink.is_none() {
+            eprintln!(
+                "{} candidate pairs were written to {}",
+                candidates.len(),
+                out_name.display()
+            );
+        }
+        candidates.report
+    } else {
+        ed_join_with_config(&config.doc_x, &config.doc_y, &join_config)?
+    };
+    if let Some(path) = &config.timeline {
+        timeline::write(path)?;
+        eprintln!("The timeline was written to {}", path.display());
+    }
+
+    if let Some(warnings_file) = &config.warnings_file {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(warnings_file)?);
+        for warning in &report.warnings {
+            writeln!(writer, "{}", warning)?;
+        }
+    }
+    if let Some(output) = &report.output {
+        match config.stop_after {
+            Some(persist::Phase::Index) => {
+                eprintln!("The index was written to {}", output.display())
+            }
+            Some(persist::Phase::Candidates) => {
+                eprintln!("Candidates were written to {}", output.display())
+            }
+            None => eprintln!("Matches were written to {}", output.display()),
+        }
+    }
+    if let Some(clusters) = &report.clusters {
+        eprintln!("Clusters were written to {}", clusters.display());
+    }
+    if let Some(recall) = report.estimated_recall {
+        eprintln!(
+            "The approximate join has an estimated recall of {:.3}",
+            recall
+        );
+    }
+    if !report.warnings.is_empty() {
+        eprintln!(
+            "{} warnings were reported, {} records and {} pairs were skipped",
+            report.warnings.len(),
+            report.skipped_records(),
+            report.skipped_pairs()
+        );
+    }
+    if report.low_quality_reads() > 0 {
+        eprintln!(
+            "{} reads of a low quality were skipped",
+            report.low_quality_reads()
+        );
+    }
+    if config.stats {
+        eprintln!("{}", report.stats);
+    }
+
+    Ok(())
+}
+
+/// Dump the inverted index of the documents given to `ed-join index`, or write it for later joins if it's `index build`.
+fn dump_index(config: &cli::IndexConfig) -> Result<()> {
+    if config.build {
+        // the index knows the number of records of `doc_y`, so that it can be merged with others
+        let tokenizer = config.mode.tokenizer();
+        let (records_y, _) = qgram::read_records(
+            &config.doc_y,
+            report::Side::Y,
+            config::ErrorPolicy::default(),
+        )?;
+        let inverted_index = if config.doc_x != config.doc_y {
+            let (records_x, _) = qgram::read_records(
+                &config.doc_x,
+                report::Side::X,
+                config::ErrorPolicy::default(),
+            )?;
+            qgram::build_inverted_index_with(Some(&records_x), &records_y, config.q, &*tokenizer)
+        } else {
+            qgram::build_inverted_index_with(None, &records_y, config.q, &*tokenizer)
+        };
+        persist::write_index(
+            &config.output,
+            &inverted_index,
+            config.q,
+            Some(records_y.len()),
+        )?;
+        println!(
+            "Wrote the index of {} q-grams to {}",
+            inverted_index.len(),
+            config.output.display()
+        );
+        return Ok(());
+    }
+    let inverted_index =
+        generate_inverted_index(&config.doc_x, &config.doc_y, config.q, config.mode)?;
+    persist::dump_index(
+        File::create(&config.output)?,
+        &inverted_index,
+        config.postings,
+    )?;
+    println!(
+        "Dumped {} q-grams to {}",
+        inverted_index.len(),
+        config.output.display()
+    );
+
+    Ok(())
+}
+
+/// Verify the index given to `ed-join index verify`, or copy it for `ed-join index copy`.
+fn check_index(config: &cli::IndexFileConfig) -> Result<()> {
+    match &config.copy_to {
+        Some(to) => {
+            let summary: persist::IndexSummary = persist::copy_index(&config.index, to)?;
+            println!(
+                "Copied the index of {} q-grams with q={} to {}, in {} verified segments",
+                summary.qgrams,
+                summary.q,
+                to.display(),
+                summary.segments
+            );
+        }
+        None => {
+            let summary: persist::IndexSummary = persist::verify_index(&config.index)?;
+            if summary.version < 3 {
+                println!(
+                    "{} is a well-formed index of {} q-grams with q={}, of version {} without checksums",
+                    config.index.display(),
+                    summary.qgrams,
+                    summary.q,
+                    summary.version
+                );
+            } else {
+                println!(
+                    "{} is an intact index of {} q-grams with q={}, all {} segments match their checksums",
+                    config.index.display(),
+                    summary.qgrams,
+                    summary.q,
+                    summary.segments
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Merge the indexes given to `ed-join index merge`.
+fn merge_index(config: &cli::IndexMergeConfig) -> Result<()> {
+    let (summary, parts) = persist::merge_indexes(&config.indexes, &config.output)?;
+    println!(
+        "Merged {} indexes of {} records into the index of {} q-grams with q={} at {}",
+        parts.len(),
+        summary.records.unwrap_or_default(),
+        summary.qgrams,
+        summary.q,
+        config.output.display()
+    );
+
+    Ok(())
+}
+
+/// Join the documents given to `ed-join chain`, and write the paths through all of them.
+fn join_chain(config: &cli::ChainConfig) -> Result<()> {
+    let (paths, report) =
+        chain::chain_join(&config.docs, &EdJoinConfig::new(config.q, config.tau))?;
+    chain::write_paths(File::create(&config.output)?, &paths, b',')?;
+    println!(
+        "Wrote {} paths through {} files to {}",
+        paths.len(),
+        config.docs.len(),
+        config.output.display()
+    );
+    if !report.warnings.is_empty() {
+        eprintln!(
+            "{} warnings were reported, {} records were skipped",
+            report.warnings.len(),
+            report.skipped_records()
+        );
+    }
+
+    Ok(())
+}
+
+/// Print the edit distance of each pair given to `ed-join pair` or `ed-join pairs`, and optionally the verdict on it.
+fn check_pairs(config: &cli::PairsConfig) -> Result<()> {
+    // pairs from a file are printed along with their results, so that they can be told apart
+    let (pairs, print_pair): (Vec<(String, String)>, bool) = match &config.input {
+        cli::PairsInput::Strings(x, y) => (vec![(x.clone(), y.clone())], false),
+        cli::PairsInput::File(path) => (cli::read_pairs(path)?, true),
+    };
+    let q: usize = config.q.unwrap_or_else(|| {
+        pairs
+            .iter()
+            .map(|(x, y)| cmp::min(x.len(), y.len()))
+            .min()
+            .unwrap_or(1)
+            .max(1)
     });
 
-    match ed_join(&config.doc_x, &config.doc_y, config.q, config.tau) {
-        Ok(()) => Ok(()),
-        Err(e) => Err(e),
+    let stdout = io::stdout();
+    let mut writer = stdout.lock();
+    for (x, y) in &pairs {
+        if print_pair {
+            write!(writer, "{}\t{}\t", x, y)?;
+        }
+        write!(writer, "{}", edit_distance(x, y))?;
+        if config.verdict {
+            write!(writer, "\t{}", check_pair(x, y, q, config.tau))?;
+        }
+        writeln!(writer)?;
     }
+
+    Ok(())
 }
@@ -0,0 +1,527 @@
+use std::{
+    convert::TryFrom,
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::alphabet::AlphabetAction;
+use crate::config::EdJoinConfig;
+use crate::errors::*;
+use crate::hash::Sha256;
+use crate::persist::{escape_field, unescape_field};
+use crate::report::{JoinReport, JoinStats, Side, Warning};
+
+/// Name of the file in an entry, which holds the warnings of the run. An entry is complete if it exists.
+const REPORT: &str = "report";
+/// Prefix of the line of the report which holds the estimated recall of an approximate join, unlike any kind of warning.
+const ESTIMATED_RECALL: &str = "estimated_recall\t";
+/// Prefix of the lines of the report which hold the counts of [`JoinStats`], by their names.
+const STAT: &str = "stat\t";
+/// Prefix of the lines of the report which hold the phases of [`JoinStats`], by their names and in nanoseconds.
+const PHASE: &str = "phase\t";
+/// Names of the phases of a join, see [`JoinStats::phases`].
+const PHASES: [&str; 4] = ["read_inputs", "index", "join", "write_output"];
+/// Header of the parameters of a run in its key, whose version is bumped whenever the parameters or how they're written change.
+const KEY_VERSION: &str = "ed_join cache key v1";
+
+/// An entry of a cache of whole runs.
+///
+/// The key is a hash of the contents of both inputs, every parameter that affects the output, and the version of this crate,
+/// so an entry is only reused by an identical run. Each entry is a directory, which holds the output files and the report,
+/// with its warnings and statistics.
+#[derive(Clone, Debug)]
+pub struct CacheEntry {
+    path: PathBuf,
+}
+
+impl CacheEntry {
+    /// Find the entry of a run in the cache directory, which is created if it doesn't exist.
+    pub fn new(
+        cache_dir: &Path,
+        doc_x: &Path,
+        doc_y: &Path,
+        config: &EdJoinConfig,
+        self_join: bool,
+    ) -> Result<Self> {
+        let mut hasher: Sha256 = Sha256::new()?;
+        hasher.update(
+            concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"), "\n").as_bytes(),
+        );
+        hasher.update(key(config)?.as_bytes());
+        hash_inputs(&mut hasher, doc_x, doc_y, self_join)?;
+
+        fs::create_dir_all(cache_dir)?;
+        Ok(Self {
+            path: cache_dir.join(hasher.hex_digest()),
+        })
+    }
+
+    /// Restore the output files of a completed run to their destinations.
+    ///
+    /// # Parameters
+    ///
+    ///  * `outputs`: Names of the output files in the entry, and where they're restored to.
+    ///
+    /// # Return
+    ///
+    /// The report of the run, or `None` if there's no such run in the cache.
+    pub fn restore(&self, outputs: &[(&str, &Path)]) -> Result<Option<JoinReport>> {
+        let report_name: PathBuf = self.path.join(REPORT);
+        if !report_name.is_file()
+            || outputs
+                .iter()
+                .any(|(name, _)| !self.path.join(name).is_file())
+        {
+            return Ok(None);
+        }
+
+        for (name, destination) in outputs {
+            fs::copy(self.path.join(name), destination)?;
+        }
+        let corrupted = || Error::from(format!("Corrupted cache entry: {}", self.path.display()));
+        let mut report: JoinReport = JoinReport::default();
+        for line in BufReader::new(File::open(&report_name)?).lines() {
+            let line: String = line?;
+            if let Some(recall) = line.strip_prefix(ESTIMATED_RECALL) {
+                report.estimated_recall = Some(recall.parse().map_err(|_| corrupted())?);
+            } else if let Some(stat) = line.strip_prefix(STAT) {
+                decode_stat(&mut report.stats, stat).ok_or_else(corrupted)?;
+            } else if let Some(phase) = line.strip_prefix(PHASE) {
+                let phase: (&'static str, Duration) = decode_phase(phase).ok_or_else(corrupted)?;
+                report.stats.phases.push(phase);
+            } else {
+                report
+                    .warnings
+                    .push(decode_warning(&line).ok_or_else(corrupted)?);
+            }
+        }
+
+        Ok(Some(report))
+    }
+
+    /// Store the output files and the report of a completed run.
+    ///
+    /// The entry is written to a temporary directory first, and then renamed, so an incomplete entry is never used.
+    pub fn store(&self, outputs: &[(&str, &Path)], report: &JoinReport) -> Result<()> {
+        let temp: PathBuf = self
+            .path
+            .with_extension(format!("tmp{}", std::process::id()));
+        fs::create_dir_all(&temp)?;
+        for (name, source) in outputs {
+            fs::copy(source, temp.join(name))?;
+        }
+        let mut writer: BufWriter<File> = BufWriter::new(File::create(temp.join(REPORT))?);
+        for warning in &report.warnings {
+            writeln!(writer, "{}", encode_warning(warning))?;
+        }
+        if let Some(recall) = report.estimated_recall {
+            writeln!(writer, "{}{}", ESTIMATED_RECALL, recall)?;
+        }
+        for (name, count) in stat_counts(&report.stats) {
+            writeln!(writer, "{}{}\t{}", STAT, name, count)?;
+        }
+        for (name, elapsed) in &report.stats.phases {
+            writeln!(writer, "{}{}\t{}", PHASE, name, elapsed.as_nanos())?;
+        }
+        writer.flush()?;
+        drop(writer);
+
+        // another run may have stored the same entry in the meantime, which is just as good
+        if fs::rename(&temp, &self.path).is_err() {
+            fs::remove_dir_all(&temp)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// State kept between joins of the same inputs and `q`, so that a join with a wider threshold only does the marginal work.
+///
+/// It holds the inverted index, which doesn't depend on `tau`, and the exact edit distances of all pairs verified so far.
+/// Pairs whose distance is known are not verified again, whichever `tau` they were found with.
+#[derive(Clone, Debug)]
+pub struct WarmEntry {
+    path: PathBuf,
+}
+
+impl WarmEntry {
+    /// Find the entry of the inputs and `q` in the warm-start directory, which is created if it doesn't exist.
+    ///
+    /// The index of a self-join differs from that of joining two copies of a file, so `self_join` is part of the key,
+    /// and so are `q`, the tokenizer, and the format the records are parsed in, how they're normalized, and their alphabet.
+    pub fn new(
+        warm_dir: &Path,
+        doc_x: &Path,
+        doc_y: &Path,
+        config: &EdJoinConfig,
+        self_join: bool,
+    ) -> Result<Self> {
+        let mut hasher: Sha256 = Sha256::new()?;
+        hasher.update(
+            concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"), "\n").as_bytes(),
+        );
+        hasher.update(
+            format!(
+                "q={} tokenizer={:?} input_format={:?} normalization={:?} alphabet={:?} {:?}\n",
+                config.q,
+                config.tokenizer,
+                config.input_format,
+                config.normalization,
+                config.alphabet,
+                config.alphabet_action
+            )
+            .as_bytes(),
+        );
+        hash_inputs(&mut hasher, doc_x, doc_y, self_join)?;
+
+        let path: PathBuf = warm_dir.join(hasher.hex_digest());
+        fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+
+    /// Path of the inverted index, see [`crate::persist::write_index`].
+    pub fn index_path(&self) -> PathBuf {
+        self.path.join("index")
+    }
+
+    /// Path of the verified distances, see [`crate::persist::write_distances`].
+    pub fn distances_path(&self) -> PathBuf {
+        self.path.join("distances")
+    }
+}
+
+/// The parameters of a run that change its output, one `name=value` per line after [`KEY_VERSION`].
+///
+/// Every field of the config is taken apart, so that a new one is either written here or left out on purpose.
+/// Tokenizers and scorers are written by their `cache_key`, since what trait objects print needn't tell them apart.
+fn key(config: &EdJoinConfig) -> Result<String> {
+    let EdJoinConfig {
+        q,
+        tau,
+        tau_ratio,
+        metric,
+        error_policy,
+        delimiter,
+        output_format,
+        clustering,
+        knn,
+        assignment,
+        scorer,
+        tombstones,
+        emit_candidates,
+        count_filter,
+        adaptive_filters,
+        bypass_len,
+        recall,
+        progressive,
+        mode,
+        filters,
+        normalization,
+        alphabet,
+        alphabet_action,
+        diagnostics,
+        emit_content,
+        text_mask,
+        tokenizer,
+        reverse_complement,
+        input_format,
+        // options that don't change the output
+        resume: _,
+        checkpoint_interval: _,
+        cache_dir: _,
+        num_threads: _,
+        progress: _,
+        record_store: _,
+        batch_size: _,
+        warm_dir: _,
+        external_index: _,
+        debug_sample: _,
+        line_offsets: _,
+        limits: _,
+        cancel: _,
+        output_naming: _,
+        output_dir: _,
+        // runs with these are never cached, see `EdJoinConfig::violations` and `join`
+        stop_after: _,
+        index_from: _,
+        provenance: _,
+        candidates_from: _,
+        windowing: _,
+        delta: _,
+        record_timeout: _,
+        output_sink: _,
+        plugins: _,
+    } = config;
+    let uncached = |what: &str| Error::from(format!("A join with {} cannot be cached", what));
+
+    let scorer: Option<String> = match scorer {
+        Some(scorer) => Some(
+            scorer
+                .cache_key()
+                .ok_or_else(|| uncached("a scorer of your own"))?,
+        ),
+        None => None,
+    };
+    let tokenizer: String = tokenizer
+        .cache_key()
+        .ok_or_else(|| uncached("a tokenizer of your own"))?;
+    let tombstones: Vec<String> = tombstones.iter().map(|id| id.to_string()).collect();
+
+    let key: Vec<String> = vec![
+        KEY_VERSION.to_string(),
+        format!("q={}", q),
+        format!("tau={}", tau),
+        format!("tau_ratio={:?}", tau_ratio),
+        format!("metric={}", metric.name()),
+        format!("error_policy={:?}", error_policy),
+        format!("delimiter={}", delimiter),
+        format!("output_format={:?}", output_format),
+        format!("clustering={:?}", clustering),
+        format!("knn={:?}", knn),
+        format!("assignment={:?}", assignment),
+        format!("scorer={:?}", scorer),
+        format!("tombstones={}", tombstones.join(",")),
+        // their paths are where the files are restored to, so only whether they're written matters
+        format!("emit_candidates={}", emit_candidates.is_some()),
+        format!("diagnostics={}", diagnostics.is_some()),
+        format!("count_filter={:?}", count_filter),
+        format!("adaptive_filters={:?}", adaptive_filters),
+        format!("bypass_len={:?}", bypass_len),
+        format!("recall={:?}", recall),
+        format!("progressive={}", progressive),
+        format!("mode={:?}", mode),
+        format!("filters={:?}", filters),
+        format!("normalization={:?}", normalization),
+        format!("alphabet={:?}", alphabet),
+        format!("alphabet_action={}", alphabet_action.name()),
+        format!("emit_content={}", emit_content),
+        // the salt is secret, so a mask prints its checksum instead
+        format!("text_mask={:?}", text_mask),
+        format!("tokenizer={}", tokenizer),
+        format!("reverse_complement={}", reverse_complement),
+        format!("input_format={:?}", input_format),
+    ];
+
+    Ok(key.join("\n") + "\n")
+}
+
+/// Hash of the parameters of a run that change its output, or `None` if they can't be told apart,
+/// or there's no hashing, see [`crate::sink::SinkContext::fingerprint`].
+pub(crate) fn fingerprint(config: &EdJoinConfig) -> Option<String> {
+    let mut hasher: Sha256 = Sha256::new().ok()?;
+    hasher.update(key(config).ok()?.as_bytes());
+    Some(hasher.hex_digest())
+}
+
+/// The counts of statistics, by their names.
+fn stat_counts(stats: &JoinStats) -> [(&'static str, usize); 5] {
+    [
+        ("candidates", stats.candidates),
+        ("passed_count_filter", stats.passed_count_filter),
+        ("passed_location_filter", stats.passed_location_filter),
+        ("passed_content_filter", stats.passed_content_filter),
+        ("edit_distances", stats.edit_distances),
+    ]
+}
+
+/// Decode a count of statistics written as `name\tcount` into `stats`.
+fn decode_stat(stats: &mut JoinStats, line: &str) -> Option<()> {
+    let (name, count) = line.split_once('\t')?;
+    let count: usize = count.parse().ok()?;
+    match name {
+        "candidates" => stats.candidates = count,
+        "passed_count_filter" => stats.passed_count_filter = count,
+        "passed_location_filter" => stats.passed_location_filter = count,
+        "passed_content_filter" => stats.passed_content_filter = count,
+        "edit_distances" => stats.edit_distances = count,
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Decode a phase written as `name\tnanoseconds`.
+fn decode_phase(line: &str) -> Option<(&'static str, Duration)> {
+    let (name, nanos) = line.split_once('\t')?;
+    let name: &'static str = PHASES.iter().find(|phase| **phase == name)?;
+    Some((name, Duration::from_nanos(nanos.parse().ok()?)))
+}
+
+/// Hash the contents of both inputs, and whether it's a self-join.
+fn hash_inputs(hasher: &mut Sha256, doc_x: &Path, doc_y: &Path, self_join: bool) -> Result<()> {
+    // a self-join only reports each pair once, so it differs from joining two copies of a file
+    hasher.update(if self_join { b"self-join\n" } else { b"join\n" });
+    for doc in &[doc_x, doc_y] {
+        let mut reader: BufReader<File> = BufReader::new(File::open(doc)?);
+        let mut buffer: Vec<u8> = vec![0; 1 << 16];
+        let mut len: u64 = 0;
+        loop {
+            let n: usize = reader.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+            len += n as u64;
+        }
+        // so that the boundary between the inputs is unambiguous
+        hasher.update(&len.to_be_bytes());
+    }
+
+    Ok(())
+}
+
+/// Encode a warning as a line of tab-separated `kind`, `side`, `id`, `value` and `message`.
+fn encode_warning(warning: &Warning) -> String {
+    let (side, id, value, msg): (Side, usize, usize, String) = match warning {
+        Warning::NonUtf8Record { side, id } => (*side, *id, 0, String::new()),
+        Warning::ShortRecord { side, id, len } => (*side, *id, *len, String::new()),
+        Warning::LowQualityRead { side, id, mean } => (*side, *id, *mean, String::new()),
+        Warning::MissingField { side, id, fields } => (*side, *id, *fields, String::new()),
+        Warning::MalformedRecord { side, id, msg } => (*side, *id, 0, escape_field(msg)),
+        Warning::ReadError { side, id, msg } => (*side, *id, 0, escape_field(msg)),
+        Warning::OutOfAlphabet {
+            side,
+            id,
+            c,
+            action,
+        } => (*side, *id, *c as usize, action.name().to_string()),
+        Warning::SkippedRecord { id, msg } => (Side::X, *id, 0, escape_field(msg)),
+        Warning::RecordTimeout { id, skipped } => (Side::X, *id, *skipped, String::new()),
+        Warning::CappedCandidates { id, dropped } => (Side::X, *id, *dropped, String::new()),
+        Warning::SkippedPair { x_id, y_id, msg } => (Side::X, *x_id, *y_id, escape_field(msg)),
+    };
+    format!("{}\t{}\t{}\t{}\t{}", warning.kind(), side, id, value, msg)
+}
+
+/// Decode a warning encoded by `encode_warning()`.
+fn decode_warning(line: &str) -> Option<Warning> {
+    let fields: Vec<&str> = line.splitn(5, '\t').collect();
+    if fields.len() != 5 {
+        return None;
+    }
+    let side: Side = match fields[1] {
+        "x" => Side::X,
+        "y" => Side::Y,
+        _ => return None,
+    };
+    let (id, value): (usize, usize) = (fields[2].parse().ok()?, fields[3].parse().ok()?);
+    let msg: String = unescape_field(fields[4])?;
+
+    match fields[0] {
+        "non_utf8_record" => Some(Warning::NonUtf8Record { side, id }),
+        "short_record" => Some(Warning::ShortRecord {
+            side,
+            id,
+            len: value,
+        }),
+        "low_quality_read" => Some(Warning::LowQualityRead {
+            side,
+            id,
+            mean: value,
+        }),
+        "missing_field" => Some(Warning::MissingField {
+            side,
+            id,
+            fields: value,
+        }),
+        "malformed_record" => Some(Warning::MalformedRecord { side, id, msg }),
+        "read_error" => Some(Warning::ReadError { side, id, msg }),
+        "out_of_alphabet" => Some(Warning::OutOfAlphabet {
+            side,
+            id,
+            c: char::from_u32(u32::try_from(value).ok()?)?,
+            action: AlphabetAction::from_name(&msg)?,
+        }),
+        "skipped_record" => Some(Warning::SkippedRecord { id, msg }),
+        "record_timeout" => Some(Warning::RecordTimeout { id, skipped: value }),
+        "capped_candidates" => Some(Warning::CappedCandidates { id, dropped: value }),
+        "skipped_pair" => Some(Warning::SkippedPair {
+            x_id: id,
+            y_id: value,
+            msg,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warnings_round_trip() {
+        let warnings = [
+            Warning::NonUtf8Record {
+                side: Side::Y,
+                id: 3,
+            },
+            Warning::ShortRecord {
+                side: Side::X,
+                id: 1,
+                len: 2,
+            },
+            Warning::LowQualityRead {
+                side: Side::Y,
+                id: 4,
+                mean: 17,
+            },
+            Warning::MissingField {
+                side: Side::X,
+                id: 6,
+                fields: 2,
+            },
+            Warning::MalformedRecord {
+                side: Side::Y,
+                id: 7,
+                msg: "no field `name`".to_string(),
+            },
+            Warning::OutOfAlphabet {
+                side: Side::X,
+                id: 2,
+                c: 'é',
+                action: AlphabetAction::Transliterate,
+            },
+            Warning::RecordTimeout { id: 5, skipped: 12 },
+            Warning::CappedCandidates { id: 8, dropped: 4 },
+            Warning::ReadError {
+                side: Side::X,
+                id: 7,
+                msg: "broken\tpipe\n\\".to_string(),
+            },
+            Warning::SkippedRecord {
+                id: 4,
+                msg: "oops".to_string(),
+            },
+            Warning::SkippedPair {
+                x_id: 4,
+                y_id: 9,
+                msg: String::new(),
+            },
+        ];
+        warnings.iter().for_each(|warning| {
+            assert_eq!(
+                decode_warning(&encode_warning(warning)).as_ref(),
+                Some(warning)
+            );
+        });
+        assert_eq!(decode_warning("short_record\tz\t1\t2\t"), None);
+    }
+
+    #[cfg(feature = "hashing")]
+    #[test]
+    fn fingerprints() {
+        use crate::tokenizer::{FnTokenizer, QGrams, Tokenizer};
+
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1);
+        assert!(fingerprint(&config).is_some());
+        // options that don't change the output don't change the fingerprint
+        assert_eq!(
+            fingerprint(&config.clone().batch_size(4).output_dir("out")),
+            fingerprint(&config)
+        );
+        assert_ne!(fingerprint(&EdJoinConfig::new(2, 2)), fingerprint(&config));
+        let custom = FnTokenizer::new("qgrams", |record: &str, q| QGrams.tokenize(record, q));
+        assert_eq!(fingerprint(&config.tokenizer(custom)), None);
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    collections::HashMap,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use crate::config::EdJoinConfig;
+use crate::errors::*;
+use crate::matching::{check_inputs, join_records};
+use crate::qgram::{read_input, ID};
+use crate::report::{JoinReport, Side};
+
+/// A transitive match through a chain of documents, e.g. a record of `A` matching one of `B`, which matches one of `C`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchPath {
+    /// Line number of the record in each document of the chain, in order.
+    pub ids: Vec<ID>,
+    /// Edit distance of each hop, i.e. between the records of consecutive documents.
+    pub eds: Vec<usize>,
+}
+
+/// Join a chain of documents, each with the next one, and follow the matches through all of them.
+///
+/// Only records of a document that are matched by the previous hop are joined with the next document,
+/// and all records stay in memory, so nothing is written or parsed in between.
+/// Each hop is within `config.tau`, and only `q`, `tau`, the error policy, the tokenizer and the input format of `config` apply.
+///
+/// # Parameters
+///
+///  * `docs`: At least two documents. The first one is reported as side `x`, and all others as side `y`.
+///
+/// # Return
+///
+/// All paths through every document, in increasing order of IDs, and the report of reading the documents.
+pub fn chain_join(docs: &[PathBuf], config: &EdJoinConfig) -> Result<(Vec<MatchPath>, JoinReport)> {
+    if docs.len() < 2 {
+        bail!(
+            "A chained join needs at least two documents, but got {}",
+            docs.len()
+        );
+    }
+    let (q, tau, tokenizer) = (config.q, config.tau, &*config.tokenizer);
+    let mut report: JoinReport = JoinReport::default();
+    for pair in docs.windows(2) {
+        check_inputs(&pair[0], &pair[1], false)?;
+    }
+    let mut records: Vec<Vec<String>> = Vec::with_capacity(docs.len());
+    for (i, doc) in docs.iter().enumerate() {
+        let side: Side = if i == 0 { Side::X } else { Side::Y };
+        let (doc_records, mut warnings) = read_input(doc, side, config)?;
+        report.warnings.append(&mut warnings);
+        records.push(doc_records);
+    }
+
+    let mut paths: Vec<MatchPath> = join_records(Some(&records[0]), &records[1], q, tau, tokenizer)
+        .into_iter()
+        .map(|(x_id, y_id, ed)| MatchPath {
+            ids: vec![x_id, y_id],
+            eds: vec![ed],
+        })
+        .collect();
+    for hop in 1..docs.len() - 1 {
+        // the records reached so far are the probe side of the next hop
+        let mut frontier: Vec<ID> = paths.iter().map(|path| path.ids[hop]).collect();
+        frontier.sort_unstable();
+        frontier.dedup();
+        let probes: Vec<String> = frontier
+            .iter()
+            .map(|id| records[hop][*id].clone())
+            .collect();
+        let mut next: HashMap<ID, Vec<(ID, usize)>> = HashMap::new();
+        for (probe, y_id, ed) in join_records(Some(&probes), &records[hop + 1], q, tau, tokenizer) {
+            next.entry(frontier[probe]).or_default().push((y_id, ed));
+        }
+        #[cfg(feature = "logging")]
+        info!(
+            "Hop {} of the chain: {} records matched {} of the next document",
+            hop,
+            frontier.len(),
+            next.len()
+        );
+
+        paths = paths
+            .into_iter()
+            .flat_map(|path| {
+                let matched: &[(ID, usize)] = next.get(&path.ids[hop]).map_or(&[], Vec::as_slice);
+                matched
+                    .iter()
+                    .map(|(y_id, ed)| {
+                        let mut path: MatchPath = path.clone();
+                        path.ids.push(*y_id);
+                        path.eds.push(*ed);
+                        path
+                    })
+                    .collect::<Vec<MatchPath>>()
+            })
+            .collect();
+    }
+    paths.sort_unstable_by(|a, b| a.ids.cmp(&b.ids));
+
+    Ok((paths, report))
+}
+
+/// Write paths, one per line, as the line numbers of their records followed by the edit distances of their hops.
+pub fn write_paths<W: Write>(writer: W, paths: &[MatchPath], delimiter: u8) -> Result<()> {
+    let mut writer: BufWriter<W> = BufWriter::new(writer);
+    let delimiter: String = (delimiter as char).to_string();
+    for path in paths {
+        let fields: Vec<String> = path
+            .ids
+            .iter()
+            .chain(&path.eds)
+            .map(|field| field.to_string())
+            .collect();
+        writeln!(writer, "{}", fields.join(&delimiter))?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn chain() {
+        let dir: TempDir = TempDir::new("chain");
+        let docs: Vec<PathBuf> = (0..3).map(|i| dir.join(format!("{}.txt", i))).collect();
+        std::fs::write(&docs[0], "hello\nworld\n").unwrap();
+        std::fs::write(&docs[1], "words\nhallo\nhella\n").unwrap();
+        std::fs::write(&docs[2], "hullo\nsword\nhalls\n").unwrap();
+
+        // `hella` is matched, but not by anything of the last document, nor is `words`
+        let (paths, report) = chain_join(&docs, &EdJoinConfig::new(2, 1)).unwrap();
+        assert!(report.warnings.is_empty());
+        assert_eq!(
+            paths,
+            vec![
+                MatchPath {
+                    ids: vec![0, 1, 0],
+                    eds: vec![1, 1],
+                },
+                MatchPath {
+                    ids: vec![0, 1, 2],
+                    eds: vec![1, 1],
+                },
+            ]
+        );
+
+        let mut written: Vec<u8> = Vec::new();
+        write_paths(&mut written, &paths, b',').unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "0,1,0,1,1\n0,1,2,1,1\n"
+        );
+        assert!(chain_join(&docs[..1], &EdJoinConfig::new(2, 1)).is_err());
+    }
+}
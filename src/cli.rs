@@ -1,20 +1,40 @@
-use clap::App;
+use clap::{App, AppSettings, ArgMatches, SubCommand};
 use dialoguer::{theme::ColorfulTheme, Confirmation, Input};
 use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use std::{
-    cmp,
-    fs::File,
-    io::{prelude::*, BufReader},
-    path::PathBuf,
+    cmp, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 
+use crate::alphabet::{Alphabet, AlphabetAction};
+use crate::assignment::Assignment;
+use crate::cluster::{Clustering, Linkage};
+use crate::config::{
+    CountFilter, ErrorPolicy, Filters, InputFormat, JoinMode, Limits, Normalization, QualityFilter,
+};
+use crate::csv::{CsvColumn, CsvInput};
 use crate::errors::*;
+use crate::jsonl::JsonlInput;
+use crate::matching::{check_inputs, is_self_join};
+use crate::output::{MatrixValue, OutputFormat, OutputNaming, OutputTemplate};
+use crate::persist::Phase;
+use crate::progress::ProgressObserver;
+use crate::qgram::{read_records, read_records_as, ID};
+use crate::report::{Side, Warning};
+use crate::tokenizer::{self, PackedGrams, Tokenizer, TokenizerMode};
+use crate::unicode::UnicodeForm;
+use crate::verification::DistanceMetric;
+use crate::window::Windowing;
 
+#[allow(dead_code)]
 pub(crate) struct ProgressBarBuilder<'a> {
     count: u64,
     messege: &'a str,
 }
 
+#[allow(dead_code)]
 impl<'a> ProgressBarBuilder<'a> {
     pub fn new(count: usize, messege: &'a str) -> Self {
         Self {
@@ -32,18 +52,198 @@ impl<'a> ProgressBarBuilder<'a> {
                 ).progress_chars("#>-")
         );
         pbar.set_draw_target(ProgressDrawTarget::stdout());
-        pbar.set_message(self.messege);
+        pbar.set_message(self.messege.to_string());
 
         pbar
     }
 }
 
+impl ProgressObserver for ProgressBar {
+    fn start(&self, total: usize) {
+        self.set_length(total as u64);
+    }
+
+    fn advance(&self) {
+        self.inc(1);
+    }
+
+    fn finish(&self) {
+        ProgressBar::finish(self);
+    }
+}
+
+/// Format of the log records emitted by `env_logger`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum LogFormat {
+    /// The message only, as is. Multi-line messages span multiple lines.
+    Text,
+    /// One JSON object per line, with `timestamp`, `level`, `module` and `message` fields.
+    Json,
+}
+
+/// What the binary is asked to do.
+#[allow(dead_code)]
+pub(crate) enum Command {
+    /// Join two documents, or a document with itself.
+    Join(Box<Config>),
+    /// Check explicitly listed pairs of strings, see `ed-join pair --help` and `ed-join pairs --help`.
+    Pairs(PairsConfig),
+    /// Dump the inverted index of documents, see `ed-join index --help`.
+    Index(IndexConfig),
+    /// Verify or copy a persisted index, see `ed-join index verify --help` and `ed-join index copy --help`.
+    IndexFile(IndexFileConfig),
+    /// Merge persisted indexes, see `ed-join index merge --help`.
+    IndexMerge(IndexMergeConfig),
+    /// Join a chain of documents, see `ed-join chain --help`.
+    Chain(ChainConfig),
+    /// Run the jobs of a spool directory, see `ed-join daemon --help`.
+    Daemon(DaemonConfig),
+}
+
+/// Where the pairs of `ed-join pair` and `ed-join pairs` come from.
+#[allow(dead_code)]
+pub(crate) enum PairsInput {
+    /// A single pair given on the command line.
+    Strings(String, String),
+    /// A file of tab-separated pairs, one per line.
+    File(PathBuf),
+}
+
+#[allow(dead_code)]
+pub(crate) struct PairsConfig {
+    pub input: PairsInput,
+    /// `q` of the filters, or the length of the shortest string if not given.
+    pub q: Option<usize>,
+    pub tau: usize,
+    /// Whether to print which filter decided about each pair.
+    pub verdict: bool,
+}
+
+#[allow(dead_code)]
+pub(crate) struct IndexConfig {
+    pub doc_x: PathBuf,
+    pub doc_y: PathBuf,
+    pub q: usize,
+    pub mode: TokenizerMode,
+    /// File which the index is dumped to, or written to if it's built.
+    pub output: PathBuf,
+    /// Whether to dump the inverted lists too.
+    pub postings: bool,
+    /// Whether the index is built for later joins, see [`crate::persist::write_index`], rather than dumped.
+    pub build: bool,
+}
+
+#[allow(dead_code)]
+pub(crate) struct IndexFileConfig {
+    /// The persisted index.
+    pub index: PathBuf,
+    /// File which the index is copied to, or `None` if it's only verified.
+    pub copy_to: Option<PathBuf>,
+}
+
+#[allow(dead_code)]
+pub(crate) struct IndexMergeConfig {
+    /// The persisted indexes, in the order their records are concatenated.
+    pub indexes: Vec<PathBuf>,
+    /// File which the merged index is written to.
+    pub output: PathBuf,
+}
+
+#[allow(dead_code)]
+pub(crate) struct ChainConfig {
+    pub docs: Vec<PathBuf>,
+    pub q: usize,
+    pub tau: usize,
+    /// File which the paths are written to.
+    pub output: PathBuf,
+}
+
+#[allow(dead_code)]
+pub(crate) struct DaemonConfig {
+    pub spool: PathBuf,
+    /// How often the spool directory is checked for new jobs.
+    pub interval: Duration,
+    /// Whether to exit once the jobs waiting now are done.
+    pub once: bool,
+    /// How long a running job has to finish on SIGTERM or SIGINT, before it's interrupted.
+    pub grace: Duration,
+    /// How many jobs run at once.
+    pub workers: usize,
+}
+
 #[allow(dead_code)]
 pub(crate) struct Config {
     pub doc_x: PathBuf,
     pub doc_y: PathBuf,
     pub q: usize,
     pub tau: usize,
+    pub log_format: LogFormat,
+    pub warnings_file: Option<PathBuf>,
+    /// File which a timeline of the run is written to, see [`crate::timeline::write`].
+    pub timeline: Option<PathBuf>,
+    /// Whether statistics of the join are printed, see [`crate::report::JoinStats`].
+    pub stats: bool,
+    pub error_policy: ErrorPolicy,
+    pub delimiter: u8,
+    pub output_format: OutputFormat,
+    pub clustering: Option<Clustering>,
+    pub knn: Option<usize>,
+    /// Threshold of each pair as a fraction of the length of its longer record, see [`crate::config::EdJoinConfig::tau_ratio`].
+    pub tau_ratio: Option<f64>,
+    pub metric: DistanceMetric,
+    pub assignment: Option<Assignment>,
+    pub confidence: bool,
+    pub resume: bool,
+    /// If set, the partial output is checkpointed this often, for `--resume`.
+    pub checkpoint_interval: Option<Duration>,
+    pub cache_dir: Option<PathBuf>,
+    pub stop_after: Option<Phase>,
+    /// Whether the candidate pairs are written instead of matches, see [`crate::matching::ed_join_candidates`].
+    pub candidates_only: bool,
+    pub index_from: Option<PathBuf>,
+    /// Whether matches are written with the part of the merged index they're from, see [`crate::config::EdJoinConfig::provenance`].
+    pub provenance: bool,
+    pub candidates_from: Option<PathBuf>,
+    /// File of line numbers of records of `doc_y` which are never matched, see [`crate::persist::read_tombstones`].
+    pub tombstones: Option<PathBuf>,
+    pub emit_candidates: Option<PathBuf>,
+    pub diagnostics: Option<PathBuf>,
+    /// If set, a scrambled sample of the records that failed or were slow is written to this directory.
+    pub debug_sample: Option<PathBuf>,
+    pub count_filter: CountFilter,
+    pub adaptive_filters: Option<usize>,
+    pub bypass_len: Option<usize>,
+    pub batch_size: Option<usize>,
+    /// Threads of the join's own pool, or `None` for the global pool of `rayon`.
+    pub threads: Option<usize>,
+    /// If set, the join is approximate, with this recall target.
+    pub recall: Option<f64>,
+    pub progressive: bool,
+    pub warm_dir: Option<PathBuf>,
+    pub external_index: Option<PathBuf>,
+    pub mode: JoinMode,
+    pub filters: Filters,
+    pub normalization: Normalization,
+    pub expected_alphabet: Option<(Alphabet, AlphabetAction)>,
+    pub line_offsets: bool,
+    pub emit_content: bool,
+    /// If set, the salt which records are written as hashes under, see [`crate::output::TextMask`].
+    pub mask_salt: Option<Vec<u8>>,
+    /// Dynamic libraries of match plugins, in the order they're applied, see [`crate::plugin::DylibPlugin`].
+    pub plugins: Vec<PathBuf>,
+    pub tokenizer: Arc<dyn Tokenizer>,
+    pub reverse_complement: bool,
+    pub input_format: InputFormat,
+    pub windowing: Option<Windowing>,
+    /// Previous version of `doc_x` and the output of its run.
+    pub delta: Option<(PathBuf, PathBuf)>,
+    pub record_timeout: Option<Duration>,
+    /// Guardrails of the resources of each phase, see [`crate::config::EdJoinConfig::limits`].
+    pub limits: Limits,
+    /// File which matches are written to, or `-` for the standard output.
+    pub output: Option<PathBuf>,
+    pub output_naming: Option<OutputNaming>,
+    pub output_dir: Option<PathBuf>,
 }
 
 #[allow(dead_code)]
@@ -55,17 +255,110 @@ fn input_file_validator(f: &str) -> Result<PathBuf> {
     }
 }
 
-fn q_validator(v: &str, min_line_len: usize) -> Result<usize> {
+fn q_validator(v: Option<&str>, min_line_len: Option<usize>) -> Result<usize> {
+    // inputs without any records don't bound `q`, so it defaults to 1
+    let v: String = v.map_or_else(|| min_line_len.unwrap_or(1).to_string(), str::to_string);
     #[allow(dead_code)]
     match v.parse::<usize>() {
-        Ok(q) if q >= 1 && q <= min_line_len => Ok(q),
         Ok(q) if q < 1 => bail!(ErrorKind::QTooSmall(q)),
-        Ok(q) if q > min_line_len => bail!(ErrorKind::QTooLarge(q, min_line_len)),
-        Ok(_) => unreachable!(),
+        Ok(q) => match min_line_len {
+            Some(min_line_len) if q > min_line_len => bail!(ErrorKind::QTooLarge(q, min_line_len)),
+            _ => Ok(q),
+        },
         Err(_) => bail!("Not a valid integer: q = {}", v),
     }
 }
 
+#[allow(dead_code)]
+fn log_format_validator(v: &str) -> Result<LogFormat> {
+    match v {
+        "text" => Ok(LogFormat::Text),
+        "json" => Ok(LogFormat::Json),
+        _ => bail!("Not a valid log format, expected `text` or `json`: {}", v),
+    }
+}
+
+fn error_policy_validator(v: &str) -> Result<ErrorPolicy> {
+    match v {
+        "abort" => Ok(ErrorPolicy::Abort),
+        "skip-record" => Ok(ErrorPolicy::SkipRecord),
+        "skip-pair" => Ok(ErrorPolicy::SkipPair),
+        _ => bail!(
+            "Not a valid error policy, expected `abort`, `skip-record` or `skip-pair`: {}",
+            v
+        ),
+    }
+}
+
+fn delimiter_validator(v: &str) -> Result<u8> {
+    match v {
+        "\\t" | "tab" => Ok(b'\t'),
+        v if v.len() == 1 && v != "\"" && v != "\n" && v != "\r" => Ok(v.as_bytes()[0]),
+        _ => bail!(
+            "Not a valid delimiter, expected a single ASCII character: {}",
+            v
+        ),
+    }
+}
+
+/// The CSV input given by `--column`, if any, with its delimiter, header and passthrough columns.
+#[allow(dead_code)]
+fn csv_input_validator(
+    column: Option<&str>,
+    delimiter: Option<&str>,
+    no_header: bool,
+    passthrough: Option<&str>,
+) -> Result<Option<CsvInput>> {
+    let column: &str = match column {
+        Some(column) => column,
+        None if delimiter.is_some() || no_header || passthrough.is_some() => {
+            bail!("`--input-delimiter`, `--no-header` and `--passthrough` can only be used with `--column`")
+        }
+        None => return Ok(None),
+    };
+    let passthrough: Vec<CsvColumn> = passthrough.map_or_else(Vec::new, |columns| {
+        columns.split(',').map(CsvColumn::parse).collect()
+    });
+    Ok(Some(
+        CsvInput::new(CsvColumn::parse(column))
+            .delimiter(delimiter_validator(delimiter.unwrap_or(","))?)
+            .header(!no_header)
+            .passthrough(passthrough),
+    ))
+}
+
+#[allow(dead_code)]
+fn input_format_validator(
+    fastq: bool,
+    trim_quality: Option<&str>,
+    min_mean_quality: Option<&str>,
+) -> Result<InputFormat> {
+    if !fastq {
+        if trim_quality.is_some() || min_mean_quality.is_some() {
+            bail!("`--trim-quality` and `--min-mean-quality` can only be used with `--fastq`");
+        }
+        return Ok(InputFormat::Lines);
+    }
+    let trim_quality: Option<u8> = match trim_quality {
+        Some(q) => Some(
+            q.parse()
+                .map_err(|_| Error::from(format!("Not a valid Phred quality: {}", q)))?,
+        ),
+        None => None,
+    };
+    let min_mean_quality: Option<f64> = match min_mean_quality {
+        Some(q) => match q.parse::<f64>() {
+            Ok(q) if q >= 0.0 => Some(q),
+            _ => bail!("Not a valid Phred quality: {}", q),
+        },
+        None => None,
+    };
+    Ok(InputFormat::Fastq(QualityFilter {
+        trim_quality,
+        min_mean_quality,
+    }))
+}
+
 #[allow(dead_code)]
 fn tau_validator(v: &str) -> Result<usize> {
     match v.parse::<usize>() {
@@ -75,24 +368,90 @@ fn tau_validator(v: &str) -> Result<usize> {
     }
 }
 
+/// The length of the shortest record of `doc_x` and `doc_y`, in units of `mode`, which `q` must not exceed,
+/// or `None` if neither has any records.
+///
+/// A zero-length `doc_y` is an error, unless it's a self-join, as for the join itself.
+#[allow(dead_code)]
+fn calc_min_line_len(
+    doc_x: &PathBuf,
+    doc_y: &PathBuf,
+    input_format: &InputFormat,
+    self_join: bool,
+    mode: TokenizerMode,
+) -> Result<Option<usize>> {
+    check_inputs(doc_x, doc_y, self_join)?;
+    // records that are not valid UTF-8, reads of a low quality, or malformed records, are skipped by the join,
+    // so they don't count either
+    let min_len = |doc: &PathBuf| -> Result<Option<usize>> {
+        let (records, warnings) =
+            read_records_as(doc, Side::X, ErrorPolicy::SkipRecord, input_format)?;
+        let skipped: Vec<ID> = warnings
+            .iter()
+            .filter_map(|warning| match warning {
+                Warning::NonUtf8Record { id, .. }
+                | Warning::LowQualityRead { id, .. }
+                | Warning::MissingField { id, .. }
+                | Warning::MalformedRecord { id, .. } => Some(*id),
+                _ => None,
+            })
+            .collect();
+        Ok(records
+            .iter()
+            .enumerate()
+            .filter(|(id, _)| !skipped.contains(id))
+            .map(|(_, record)| mode.len_of(record))
+            .min())
+    };
+    Ok(min_len(doc_x)?.into_iter().chain(min_len(doc_y)?).min())
+}
+
+/// The units of q-grams given by `--tokenizer-mode`, bytes by default.
+fn tokenizer_mode_validator(mode: Option<&str>) -> Result<TokenizerMode> {
+    match mode {
+        Some(mode) => TokenizerMode::from_name(mode).ok_or_else(|| {
+            Error::from(format!("Not a valid tokenizer mode, expected `bytes`, `chars`, `graphemes`, `words` or `words-punct`: {}", mode))
+        }),
+        None => Ok(TokenizerMode::default()),
+    }
+}
+
+/// Read pairs of strings from a file, one tab-separated pair per line.
+#[allow(dead_code)]
+pub(crate) fn read_pairs(path: &PathBuf) -> Result<Vec<(String, String)>> {
+    let (records, warnings) = read_records(path, Side::X, ErrorPolicy::Abort)?;
+    debug_assert!(warnings.is_empty());
+    records
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| !record.is_empty())
+        .map(|(id, record)| match record.find('\t') {
+            Some(i) => Ok((record[..i].to_string(), record[i + 1..].to_string())),
+            None => bail!(
+                "Line {} of {} is not a tab-separated pair",
+                id,
+                path.display()
+            ),
+        })
+        .collect()
+}
+
 #[allow(dead_code)]
-fn calc_min_line_len(doc_x: &PathBuf, doc_y: &PathBuf) -> usize {
-    cmp::min(
-        BufReader::new(File::open(doc_x).unwrap())
-            .lines()
-            .map(|line| line.unwrap().len())
-            .min()
-            .unwrap(),
-        BufReader::new(File::open(doc_y).unwrap())
-            .lines()
-            .map(|line| line.unwrap().len())
-            .min()
-            .unwrap(),
-    )
+fn pairs_config(matches: &ArgMatches, input: PairsInput) -> Result<PairsConfig> {
+    let q: Option<usize> = match matches.value_of("q") {
+        Some(q) => Some(q_validator(Some(q), None)?),
+        None => None,
+    };
+    Ok(PairsConfig {
+        input,
+        q,
+        tau: tau_validator(matches.value_of("tau").unwrap_or("2"))?,
+        verdict: matches.is_present("verdict"),
+    })
 }
 
 #[allow(dead_code)]
-pub(crate) fn parse_config() -> Result<Config> {
+pub(crate) fn parse_config() -> Result<Command> {
     let matches = App::new("EdJoin")
         .author(clap::crate_authors!())
         .version(clap::crate_version!())
@@ -107,11 +466,347 @@ pub(crate) fn parse_config() -> Result<Config> {
             [doc_y] '(Optional) File which matches come from' \n
             [q] -q [INTEGER] '`q` as used in `q-gram`' \n
             [tau] -t [INTEGER] '`tau` as threshold for matching' \n
-            [interactive] -i, --interactive 'Interactive mode' ",
+            [tau_ratio] --tau-ratio [RATIO] '(Optional) Threshold of each pair as this fraction of the length of its longer record, rounded up, and at most `tau`' \n
+            [metric] --metric [METRIC] 'Distance which pairs are matched by, `levenshtein` (default) or `damerau`, where swapping adjacent characters is one edit' \n
+            [output] -o, --output [FILE] '(Optional) File which matches are written to, or `-` for the standard output' \n
+            [output_name] --output-name [TEMPLATE] 'Name of the output file, `{stem}_out_q{q}_tau{tau}.{ext}` by default, also with `{date}` and `{algo}`' \n
+            [output_dir] --output-dir [DIR] '(Optional) Directory which the output file is written to, instead of the current one' \n
+            [interactive] -i, --interactive 'Interactive mode' \n
+            [log_format] --log-format [FORMAT] 'Log output format, `text` (default) or `json`' \n
+            [warnings_file] --warnings-file [FILE] '(Optional) File which warnings are written to, one per line' \n
+            [timeline] --timeline [FILE] '(Optional) File which a timeline of the phases of the run is written to, for chrome://tracing, if built with the `timeline` feature' \n
+            [stats] --stats 'Print the candidates of the join, how many passed each filter, the edit distances computed, and the time of each phase' \n
+            [on_error] --on-error [POLICY] 'What to do on errors, `abort`, `skip-record` (default) or `skip-pair`' \n
+            [delimiter] -d, --delimiter [CHAR] 'Field delimiter of the output, `,` by default, `tab` for TSV' \n
+            [output_template] --output-template [TEMPLATE] 'Format of output lines, e.g. `{x_id}\\t{y_id}\\t{ed}\\t{x_text}`' \n
+            [format] -f, --format [FORMAT] 'Output format, `csv` (default), `csv-header`, `tsv`, `jsonl`, `json`, `parquet`, `edgelist`, `dot`, `graphml`, `mtx` or `coo`' \n
+            [matrix_value] --matrix-value [VALUE] 'Entries of `mtx` and `coo` output, `distance` (default) or `similarity`' \n
+            [cluster] --cluster [LINKAGE] '(Optional) Cluster matched records with `single` or `complete` linkage' \n
+            [cluster_cutoff] --cluster-cutoff [INTEGER] 'Largest distance at which clusters are merged, `tau` by default' \n
+            [assign] --assign [METHOD] '(Optional) Match each record at most once, by `greedy` or `optimal` assignment' \n
+            [confidence] --confidence 'Score every match by how likely it is a true duplicate' \n
+            [resume] --resume 'Resume from the partial output of an interrupted run with the same arguments' \n
+            [checkpoint_interval] --checkpoint-interval [SECONDS] '(Optional) Flush the partial output every this many seconds, and record where `--resume` continues from' \n
+            [cache_dir] --cache-dir [DIR] '(Optional) Directory of cached runs, which identical runs are restored from' \n
+            [stop_after] --stop-after [PHASE] '(Optional) Stop after `index` or `candidates`, and persist them instead of matching' \n
+            [candidates_only] --candidates-only 'Write the candidate pairs of the prefix filter instead of matches, as line numbers without edit distances, e.g. to block records for another matcher' \n
+            [index_from] --index-from [FILE] '(Optional) Load the inverted index persisted by `--stop-after index` or `ed-join index build`' \n
+            [provenance] --provenance 'Write the part of the index of `--index-from` merged by `ed-join index merge` each record of doc_y is from, and its line number in that part, as `y_source` and `y_line`' \n
+            [candidates_from] --candidates-from [FILE] '(Optional) Load candidates persisted by `--stop-after candidates`, and only verify them' \n
+            [tombstones] --tombstones [FILE] '(Optional) File of line numbers of doc_y, one per line, which are never matched, e.g. records deleted since its index was built' \n
+            [emit_candidates] --emit-candidates [FILE] '(Optional) File which candidate pairs that passed all filters are written to' \n
+            [diagnostics] --diagnostics [FILE] '(Optional) File which the prefix length, candidates generated and verified, and matches of each record of X are written to' \n
+            [debug_sample] --debug-sample [DIR] '(Optional) Directory which a scrambled sample of the records that failed or were slow, and their candidates, is written to, for bug reports' \n
+            [count_filter] --count-filter [FILTER] 'Count filter, `approximate` (default) or `exact`, which prunes more pairs' \n
+            [disable_filters] --disable-filters [FILTERS] '(Optional) Filters not applied, a comma-separated list of `count`, `location` and `content`' \n
+            [normalize] --normalize [STEPS] '(Optional) Normalize records first, by a comma-separated list of `lowercase`, `trim`, `collapse-whitespace`, `transliterate` into ASCII, `strip-diacritics`, and one Unicode form of `nfc`, `nfd`, `nfkc` or `nfkd`' \n
+            [expected_alphabet] --expected-alphabet [CLASS] '(Optional) Characters normalized records are expected to consist of, e.g. `[A-Za-z0-9 ]`, and those that do not are reported' \n
+            [alphabet_action] --alphabet-action [ACTION] 'What happens to records out of the alphabet, `report` (default), `skip`, `transliterate` or `abort`' \n
+            [adaptive_filters] --adaptive-filters [N] '(Optional) Disable the content filter after N pairs if it costs more than it saves' \n
+            [bypass_len] --bypass-len [LEN] 'Pairs shorter than LEN skip the filters, `q * (tau + 2)` by default, 0 never' \n
+            [batch_size] --batch-size [RECORDS] 'Records of `doc_x` matched by each task, 64 by default' \n
+            [threads] --threads [N] 'Run the join on N threads, rather than one per CPU' \n
+            [approx] --approx 'Prune candidates aggressively, trading recall for speed' \n
+            [recall] --recall [RECALL] 'Recall target of `--approx`, estimated on a sample, 0.95 by default' \n
+            [progressive] --progressive 'Process the cheapest records first, and write their matches as soon as they are found' \n
+            [warm_dir] --warm-start [DIR] '(Optional) Directory of the index and verified distances, reused by joins of the same inputs with another tau' \n
+            [external_index] --external-index [DIR] '(Optional) Build the inverted index on disk in DIR, instead of in memory, for inputs whose index does not fit in memory' \n
+            [mode] --mode [MODE] 'Join the inputs as one document or two, `auto` (default) when they are the same file, `self` or `cross`' \n
+            [force_cross_join] --force-cross-join 'Join the inputs as two documents even if they are the same path, i.e. `--mode cross`' \n
+            [line_offsets] --line-offsets 'Write the line offsets of doc_y to <doc_y>.offsets, for random access to its records' \n
+            [emit_content] --emit-content 'Write the matched records after their line numbers, in CSV, TSV and JSON output' \n
+            [mask_texts] --mask-texts [SALT_FILE] '(Optional) Write records as their HMAC-SHA256 under the contents of this file, as they are, instead of their texts' \n
+            [plugins] --plugins [LIBS] '(Optional) Comma-separated dynamic libraries of plugins which filter matches before they are written, in order' \n
+            [tokenizer] --tokenizer [TOKENIZER] 'Grams of records, `qgrams` (default), `words`, `kmers` for DNA, or `packed` and `packed-kmers` for small alphabets' \n
+            [tokenizer_mode] --tokenizer-mode [MODE] 'Units of `qgrams`, `bytes` (default), `chars` or `graphemes` for any UTF-8 input, or `words` or `words-punct` for a token join' \n
+            [alphabet] --alphabet [SYMBOLS] '(Optional) Alphabet of `packed` grams, e.g. `ACGT`, detected from the inputs by default' \n
+            [reverse_complement] --reverse-complement 'Also match reverse complements of records of doc_x, as DNA' \n
+            [fastq] --fastq 'Read both inputs as FASTQ files, whose records are the sequences of reads' \n
+            [trim_quality] --trim-quality [Q] '(Optional) In FASTQ mode, trim the tail of each read up to its last base of at least this Phred quality' \n
+            [min_mean_quality] --min-mean-quality [Q] '(Optional) In FASTQ mode, skip reads of a lower mean Phred quality after trimming' \n
+            [column] --column [COLUMN] '(Optional) Read both inputs as CSV files, and join their rows on this column, by name or 0-based index' \n
+            [input_delimiter] --input-delimiter [CHAR] 'Field delimiter of CSV inputs, `,` by default, `tab` for TSV' \n
+            [no_header] --no-header 'CSV inputs have no header, so columns are only given by index' \n
+            [json_field] --json-field [FIELD] '(Optional) Read both inputs as JSON Lines, and join them on this field, e.g. `name` or `address.city`' \n
+            [passthrough] --passthrough [COLUMNS] '(Optional) Comma-separated columns of CSV inputs to echo after each match, those of doc_x first' \n
+            [windows] --windows [SIZE] '(Optional) Join long records by their overlapping windows of this many characters' \n
+            [window_step] --window-step [STEP] 'Distance between windows, half their size by default' \n
+            [min_coverage] --min-coverage [FRACTION] 'Fraction of a record that matched windows must cover, 0.5 by default' \n
+            [delta_from] --delta-from [FILE] '(Optional) Previous version of doc_x, so that only its added or changed records are joined' \n
+            [previous_output] --previous-output [FILE] 'CSV output of the previous run of `--delta-from`, which its matches are reused from' \n
+            [record_timeout] --record-timeout [MS] '(Optional) Skip the remaining candidates of a record after this many milliseconds' \n
+            [max_index_bytes] --max-index-bytes [BYTES] '(Optional) Abort if the inverted index in memory takes more bytes, and write a manifest of what was done' \n
+            [max_candidates] --max-candidates [N] '(Optional) Abort if more candidates are generated in total, and write a manifest of what was done' \n
+            [max_output_rows] --max-output-rows [N] '(Optional) Abort if more pairs match in total, and write a manifest of what was done' \n
+            [knn] --knn [K] '(Optional) Match each record with its K nearest records only, with `tau` as the largest distance' \n
+            [top_k] --top-k [K] '(Optional) The same as `--knn`, a top-k join of each record with its K nearest records' ",
+        )
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            SubCommand::with_name("pair")
+                .about("Edit distance of two strings, checked as a pair of the join")
+                .args_from_usage(
+                    "\
+                    <x> 'String as a record of the first file' \n
+                    <y> 'String as a record of the second file' \n
+                    [q] -q [INTEGER] '`q` as used in `q-gram`, the length of the shorter string by default' \n
+                    [tau] -t [INTEGER] '`tau` as threshold for matching' \n
+                    [verdict] --verdict 'Also print which filter decided about the pair' ",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("pairs")
+                .about("Edit distances of pairs of strings, checked as pairs of the join")
+                .args_from_usage(
+                    "\
+                    <file> 'File of tab-separated pairs, one per line' \n
+                    [q] -q [INTEGER] '`q` as used in `q-gram`, the length of the shortest string by default' \n
+                    [tau] -t [INTEGER] '`tau` as threshold for matching' \n
+                    [verdict] --verdict 'Also print which filter decided about each pair' ",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("index")
+                .about("Dump the inverted index of the join, to inspect the distribution of q-grams")
+                .setting(AppSettings::SubcommandsNegateReqs)
+                .setting(AppSettings::ArgsNegateSubcommands)
+                .args_from_usage(
+                    "\
+                    <doc_x> 'File which matches are generated for' \n
+                    [doc_y] '(Optional) File which matches come from' \n
+                    [q] -q [INTEGER] '`q` as used in `q-gram`, the length of the shortest record by default' \n
+                    [tokenizer_mode] --tokenizer-mode [MODE] 'Units of q-grams, `bytes` (default), `chars`, `graphemes`, `words` or `words-punct`' \n
+                    <dump> --dump <FILE> 'File which tab-separated tokens, frequencies and lengths of inverted lists are written to' \n
+                    [postings] --postings 'Also write the inverted lists, as `id:loc` entries' ",
+                )
+                .subcommand(
+                    SubCommand::with_name("build")
+                        .about("Build the inverted index of the join once, for joins of the same inputs to load by `--index-from`")
+                        .args_from_usage(
+                            "\
+                            <doc_x> 'File which matches are generated for' \n
+                            [doc_y] '(Optional) File which matches come from' \n
+                            [q] -q [INTEGER] '`q` as used in `q-gram`, the length of the shortest record by default' \n
+                            [tokenizer_mode] --tokenizer-mode [MODE] 'Units of q-grams, `bytes` (default), `chars`, `graphemes`, `words` or `words-punct`' \n
+                            <output> -o, --output <FILE> 'File which the index is written to' ",
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("verify")
+                        .about("Check that a persisted index is well-formed and the checksums of all its segments match")
+                        .args_from_usage("<index> 'File of the index'"),
+                )
+                .subcommand(
+                    SubCommand::with_name("copy")
+                        .alias("export")
+                        .about("Verify a persisted index and copy it, e.g. to replicate it, in the current format of indexes")
+                        .args_from_usage(
+                            "\
+                            <index> 'File of the index' \n
+                            <to> 'File which the index is copied to, only once the copy is verified' ",
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("merge")
+                        .about("Merge the indexes of parts of a corpus into the index of all of them, e.g. of shards indexed separately")
+                        .args_from_usage(
+                            "\
+                            <indexes>... 'Files of the indexes, in the order their records are concatenated' \n
+                            <output> -o, --output <FILE> 'File which the index is written to, and its parts to `<FILE>.sources`' ",
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("chain")
+                .about("Join each file with the next one, and write the transitive matches through all of them")
+                .args_from_usage(
+                    "\
+                    <docs>... 'Files to be joined in order, at least two' \n
+                    [q] -q [INTEGER] '`q` as used in `q-gram`, the length of the shortest record by default' \n
+                    [tau] -t [INTEGER] '`tau` as threshold for matching each hop' \n
+                    [output] -o, --output [FILE] 'File which paths are written to, `<doc>_chain_q<q>_tau<tau>.csv` of the first file by default' ",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("daemon")
+                .about("Run the jobs of a spool directory as they appear, see the documentation of `spool::Job` for their format")
+                .args_from_usage(
+                    "\
+                    <spool> 'Directory which `<name>.job` files are picked up from' \n
+                    [interval] --interval [SECONDS] 'How often the spool directory is checked for new jobs, 5 by default' \n
+                    [grace] --grace [SECONDS] 'How long a running job has to finish on SIGTERM or SIGINT, before it is put back in the spool to be resumed, 25 by default' \n
+                    [workers] --workers [N] 'How many jobs run at once, 1 by default' \n
+                    [once] --once 'Run the jobs waiting now, and exit' ",
+                ),
         )
         .get_matches();
 
-    println!("Ed-Join by Lucius Hu");
+    match matches.subcommand() {
+        ("pair", Some(sub)) => {
+            let input = PairsInput::Strings(
+                sub.value_of("x").unwrap().to_string(),
+                sub.value_of("y").unwrap().to_string(),
+            );
+            return Ok(Command::Pairs(pairs_config(sub, input)?));
+        }
+        ("pairs", Some(sub)) => {
+            let input = PairsInput::File(input_file_validator(sub.value_of("file").unwrap())?);
+            return Ok(Command::Pairs(pairs_config(sub, input)?));
+        }
+        ("index", Some(sub)) if sub.subcommand_matches("merge").is_some() => {
+            let sub: &ArgMatches = sub.subcommand_matches("merge").unwrap();
+            return Ok(Command::IndexMerge(IndexMergeConfig {
+                indexes: sub
+                    .values_of("indexes")
+                    .unwrap()
+                    .map(input_file_validator)
+                    .collect::<Result<_>>()?,
+                output: PathBuf::from(sub.value_of("output").unwrap()),
+            }));
+        }
+        ("index", Some(sub))
+            if sub.subcommand_matches("verify").is_some()
+                || sub.subcommand_matches("copy").is_some() =>
+        {
+            let (sub, copy): (&ArgMatches, bool) = match sub.subcommand_matches("copy") {
+                Some(copy) => (copy, true),
+                None => (sub.subcommand_matches("verify").unwrap(), false),
+            };
+            return Ok(Command::IndexFile(IndexFileConfig {
+                index: input_file_validator(sub.value_of("index").unwrap())?,
+                copy_to: sub.value_of("to").filter(|_| copy).map(PathBuf::from),
+            }));
+        }
+        ("index", Some(sub)) => {
+            let (sub, build): (&ArgMatches, bool) = match sub.subcommand_matches("build") {
+                Some(build) => (build, true),
+                None => (sub, false),
+            };
+            let doc_x: PathBuf = input_file_validator(sub.value_of("doc_x").unwrap())?;
+            let doc_y: PathBuf = match sub.value_of("doc_y") {
+                Some(doc_y) => input_file_validator(doc_y)?,
+                None => doc_x.clone(),
+            };
+            // nothing is matched against `doc_y`, so it may be empty
+            let mode: TokenizerMode = tokenizer_mode_validator(sub.value_of("tokenizer_mode"))?;
+            let min_line_len: Option<usize> =
+                calc_min_line_len(&doc_x, &doc_y, &InputFormat::Lines, true, mode)?;
+            let q: usize = q_validator(sub.value_of("q"), min_line_len)?;
+            return Ok(Command::Index(IndexConfig {
+                doc_x,
+                doc_y,
+                q,
+                mode,
+                output: PathBuf::from(sub.value_of(if build { "output" } else { "dump" }).unwrap()),
+                postings: sub.is_present("postings"),
+                build,
+            }));
+        }
+        ("daemon", Some(sub)) => {
+            let spool: PathBuf = PathBuf::from(sub.value_of("spool").unwrap());
+            if !spool.is_dir() {
+                bail!("Not a directory: {}", spool.display());
+            }
+            let interval: u64 = match sub.value_of("interval") {
+                Some(interval) => match interval.parse::<u64>() {
+                    Ok(interval) if interval >= 1 => interval,
+                    _ => bail!("Not a valid positive integer: interval = {}", interval),
+                },
+                None => 5,
+            };
+            // less than the 30 seconds that Kubernetes waits by default before it kills a pod
+            let grace: u64 = match sub.value_of("grace") {
+                Some(grace) => match grace.parse::<u64>() {
+                    Ok(grace) => grace,
+                    _ => bail!("Not a valid integer: grace = {}", grace),
+                },
+                None => 25,
+            };
+            let workers: usize = match sub.value_of("workers") {
+                Some(workers) => match workers.parse::<usize>() {
+                    Ok(workers) if workers >= 1 => workers,
+                    _ => bail!("Not a valid positive integer: workers = {}", workers),
+                },
+                None => 1,
+            };
+            return Ok(Command::Daemon(DaemonConfig {
+                spool,
+                interval: Duration::from_secs(interval),
+                once: sub.is_present("once"),
+                grace: Duration::from_secs(grace),
+                workers,
+            }));
+        }
+        ("chain", Some(sub)) => {
+            let docs: Vec<PathBuf> = sub
+                .values_of("docs")
+                .unwrap()
+                .map(input_file_validator)
+                .collect::<Result<Vec<PathBuf>>>()?;
+            if docs.len() < 2 {
+                bail!("A chained join needs at least two files");
+            }
+            // empty references are checked by the join itself, hop by hop
+            let mut min_line_len: Option<usize> = None;
+            for doc in &docs {
+                let doc_len: Option<usize> =
+                    calc_min_line_len(doc, doc, &InputFormat::Lines, true, TokenizerMode::Bytes)?;
+                min_line_len = min_line_len.into_iter().chain(doc_len).min();
+            }
+            let q: usize = q_validator(sub.value_of("q"), min_line_len)?;
+            let tau: usize = tau_validator(sub.value_of("tau").unwrap_or("2"))?;
+            let output: PathBuf = match sub.value_of("output") {
+                Some(output) => PathBuf::from(output),
+                None => PathBuf::from(format!(
+                    "{}_chain_q{}_tau{}.csv",
+                    docs[0].file_stem().unwrap().to_str().unwrap(),
+                    q,
+                    tau
+                )),
+            };
+            return Ok(Command::Chain(ChainConfig {
+                docs,
+                q,
+                tau,
+                output,
+            }));
+        }
+        _ => (),
+    }
+
+    // the banner would be mixed into matches written to the standard output
+    if matches!(matches.value_of("output"), Some("-" | "/dev/stdout")) {
+        eprintln!("Ed-Join by Lucius Hu");
+    } else {
+        println!("Ed-Join by Lucius Hu");
+    }
+
+    let log_format: LogFormat =
+        log_format_validator(matches.value_of("log_format").unwrap_or("text"))?;
+    let error_policy: ErrorPolicy =
+        error_policy_validator(matches.value_of("on_error").unwrap_or("skip-record"))?;
+    let delimiter: u8 = delimiter_validator(matches.value_of("delimiter").unwrap_or(","))?;
+    let format: &str = matches.value_of("format").unwrap_or("csv");
+    let mut output_format: OutputFormat = OutputFormat::from_name(format)
+        .ok_or_else(|| Error::from(format!("Not a valid output format: {}", format)))?;
+    if let Some(template) = matches.value_of("output_template") {
+        if output_format != OutputFormat::Csv {
+            bail!(
+                "`--output-template` cannot be used with `--format {}`",
+                format
+            );
+        }
+        output_format = OutputFormat::Template(OutputTemplate::parse(template)?);
+    }
+    if let Some(value) = matches.value_of("matrix_value") {
+        let value: MatrixValue = MatrixValue::from_name(value)
+            .ok_or_else(|| Error::from(format!("Not a valid matrix value: {}", value)))?;
+        output_format = match output_format {
+            OutputFormat::MatrixMarket(_) => OutputFormat::MatrixMarket(value),
+            OutputFormat::Coo(_) => OutputFormat::Coo(value),
+            _ => bail!("`--matrix-value` can only be used with `--format mtx` or `--format coo`"),
+        };
+    }
 
     // Get `doc_x` from user input or fallback t
     // Throw an error if user-provided file is not readable
@@ -119,20 +814,66 @@ pub(crate) fn parse_config() -> Result<Config> {
 
     // Get `doc_y` from user input or fallback to default as `doc_x`
     // Throw an error if user-provided file is not readable
-    let doc_y: PathBuf;
-    if matches.is_present("doc_y") {
-        doc_y = input_file_validator(matches.value_of("doc_y").unwrap())?;
+    let doc_y: PathBuf = if matches.is_present("doc_y") {
+        input_file_validator(matches.value_of("doc_y").unwrap())?
     } else {
-        doc_y = doc_x.clone();
-    }
+        doc_x.clone()
+    };
+
+    let input_format: InputFormat = match csv_input_validator(
+        matches.value_of("column"),
+        matches.value_of("input_delimiter"),
+        matches.is_present("no_header"),
+        matches.value_of("passthrough"),
+    )? {
+        Some(_) if matches.is_present("fastq") || matches.is_present("json_field") => {
+            bail!("`--column` cannot be used with `--fastq` or `--json-field`")
+        }
+        Some(input) => InputFormat::Csv(input),
+        None if matches.is_present("json_field") => {
+            if matches.is_present("fastq") {
+                bail!("`--json-field` and `--fastq` cannot be used together");
+            }
+            InputFormat::JsonLines(JsonlInput::new(matches.value_of("json_field").unwrap()))
+        }
+        None => input_format_validator(
+            matches.is_present("fastq"),
+            matches.value_of("trim_quality"),
+            matches.value_of("min_mean_quality"),
+        )?,
+    };
+
+    let mode: JoinMode = match (
+        matches.value_of("mode"),
+        matches.is_present("force_cross_join"),
+    ) {
+        (Some(_), true) => {
+            bail!("`--force-cross-join` is the same as `--mode cross`, so they cannot be combined")
+        }
+        (Some(mode), false) => JoinMode::from_name(mode).ok_or_else(|| {
+            Error::from(format!(
+                "Not a valid join mode, expected `auto`, `self` or `cross`: {}",
+                mode
+            ))
+        })?,
+        (None, true) => JoinMode::CrossJoin,
+        (None, false) => JoinMode::Auto,
+    };
 
     // Get `q` from user input or fallback to default value 1
     // Throw an error if user-provided value is not a valid positive integer
-    let mut min_line_len = calc_min_line_len(&doc_x, &doc_y);
-    let mut q: usize = q_validator(
-        matches.value_of("q").unwrap_or(&min_line_len.to_string()),
-        min_line_len,
-    )?;
+    let self_join: bool = is_self_join(&doc_x, &doc_y, mode)?;
+    let tokenizer_mode: TokenizerMode =
+        tokenizer_mode_validator(matches.value_of("tokenizer_mode"))?;
+    let mut min_line_len: Option<usize> =
+        calc_min_line_len(&doc_x, &doc_y, &input_format, self_join, tokenizer_mode)?;
+    let mut q: usize = q_validator(matches.value_of("q"), min_line_len)?;
+    if fs::metadata(&doc_x)?.len() == 0 {
+        eprintln!(
+            "{} is empty, so the output will be empty too",
+            doc_x.display()
+        );
+    }
 
     // Get `tau` from user input or fallback to default value 2
     // Throw an error if user-provided value is not a valid positive integer
@@ -182,19 +923,12 @@ pub(crate) fn parse_config() -> Result<Config> {
             );
 
 
-            min_line_len = calc_min_line_len(&doc_x.clone(), &doc_y.clone());
+            let self_join: bool = is_self_join(&doc_x, &doc_y, mode)?;
+            min_line_len = calc_min_line_len(&doc_x, &doc_y, &input_format, self_join, tokenizer_mode)?;
             q = Input::with_theme(&theme)
                 .with_prompt("q")
-                .default(min_line_len)
-                .validate_with(move |v: &str| -> Result<()> {
-                    match v.parse::<usize>() {
-                        Ok(q) if q >= 1 && q <= min_line_len => Ok(()),
-                        Ok(q) if q < 1 => bail!(ErrorKind::QTooSmall(q)),
-                        Ok(q) if q > min_line_len => bail!(ErrorKind::QTooLarge(q, min_line_len)),
-                        Ok(_) => unreachable!(),
-                        Err(_) => bail!("Not a valid integer: q = {}", v),
-                    }
-                })
+                .default(min_line_len.unwrap_or(1))
+                .validate_with(move |v: &str| -> Result<()> { q_validator(Some(v), min_line_len).map(|_| ()) })
                 .interact()?;
 
             tau = Input::with_theme(&theme)
@@ -226,10 +960,371 @@ pub(crate) fn parse_config() -> Result<Config> {
             }
         }
     }
-    Ok(Config {
+
+    let clustering: Option<Clustering> = match matches.value_of("cluster") {
+        Some(linkage) => Some(Clustering {
+            linkage: Linkage::from_name(linkage).ok_or_else(|| {
+                Error::from(format!(
+                    "Not a valid linkage, expected `single` or `complete`: {}",
+                    linkage
+                ))
+            })?,
+            cutoff: match matches.value_of("cluster_cutoff") {
+                Some(cutoff) => cutoff.parse::<usize>().map_err(|_| {
+                    Error::from(format!("Not a valid integer: cluster cutoff = {}", cutoff))
+                })?,
+                None => tau,
+            },
+        }),
+        None if matches.is_present("cluster_cutoff") => {
+            bail!("`--cluster-cutoff` requires `--cluster`")
+        }
+        None => None,
+    };
+    let knn: Option<usize> = match (matches.value_of("knn"), matches.value_of("top_k")) {
+        (Some(_), Some(_)) => bail!("`--top-k` is the same as `--knn`, so they cannot be combined"),
+        (Some(k), None) | (None, Some(k)) => match k.parse::<usize>() {
+            Ok(k) if k >= 1 => Some(k),
+            _ => bail!("Not a valid positive integer: knn = {}", k),
+        },
+        (None, None) => None,
+    };
+    let tau_ratio: Option<f64> = match matches.value_of("tau_ratio") {
+        Some(ratio) => match ratio.parse::<f64>() {
+            Ok(ratio) if (0.0..=1.0).contains(&ratio) => Some(ratio),
+            _ => bail!("Not a valid ratio in [0, 1]: tau-ratio = {}", ratio),
+        },
+        None => None,
+    };
+    let metric: DistanceMetric = match matches.value_of("metric") {
+        Some(metric) => DistanceMetric::from_name(metric).ok_or_else(|| {
+            Error::from(format!(
+                "Not a valid metric, expected `levenshtein` or `damerau`: {}",
+                metric
+            ))
+        })?,
+        None => DistanceMetric::default(),
+    };
+    let assignment: Option<Assignment> = match matches.value_of("assign") {
+        Some(method) => Some(Assignment::from_name(method).ok_or_else(|| {
+            Error::from(format!(
+                "Not a valid assignment, expected `greedy` or `optimal`: {}",
+                method
+            ))
+        })?),
+        None => None,
+    };
+    let stop_after: Option<Phase> = match matches.value_of("stop_after") {
+        Some(phase) => Some(Phase::from_name(phase).ok_or_else(|| {
+            Error::from(format!(
+                "Not a valid phase, expected `index` or `candidates`: {}",
+                phase
+            ))
+        })?),
+        None => None,
+    };
+    let count_filter: CountFilter = match matches.value_of("count_filter") {
+        Some(filter) => CountFilter::from_name(filter).ok_or_else(|| {
+            Error::from(format!(
+                "Not a valid count filter, expected `approximate` or `exact`: {}",
+                filter
+            ))
+        })?,
+        None => CountFilter::default(),
+    };
+    let tokenizer: Arc<dyn Tokenizer> = match matches.value_of("tokenizer") {
+        Some(name @ ("packed" | "packed-kmers")) => {
+            let packed: Option<PackedGrams> = match matches.value_of("alphabet") {
+                Some(alphabet) => PackedGrams::new(alphabet.as_bytes()),
+                None => {
+                    let (mut records, _) = read_records_as(&doc_x, Side::X, error_policy, &input_format)?;
+                    if doc_y != doc_x {
+                        records.append(&mut read_records_as(&doc_y, Side::Y, error_policy, &input_format)?.0);
+                    }
+                    PackedGrams::detect(&records)
+                }
+            };
+            let packed: PackedGrams =
+                packed.ok_or_else(|| Error::from("The alphabet has more than 8 symbols, which cannot be packed"))?;
+            if name == "packed" {
+                Arc::new(packed)
+            } else {
+                Arc::new(packed.canonical())
+            }
+        }
+        Some("qgrams") | None => tokenizer_mode.tokenizer(),
+        Some(name) => tokenizer::from_name(name).ok_or_else(|| {
+            Error::from(format!(
+                "Not a valid tokenizer, expected `qgrams`, `words`, `kmers`, `packed` or `packed-kmers`: {}",
+                name
+            ))
+        })?,
+    };
+    if matches.is_present("tokenizer_mode")
+        && !matches!(matches.value_of("tokenizer"), Some("qgrams") | None)
+    {
+        bail!("`--tokenizer-mode` can only be used with `--tokenizer qgrams`");
+    }
+    if matches.is_present("alphabet")
+        && !matches!(
+            matches.value_of("tokenizer"),
+            Some("packed" | "packed-kmers")
+        )
+    {
+        bail!(
+            "`--alphabet` can only be used with `--tokenizer packed` or `--tokenizer packed-kmers`"
+        );
+    }
+    let adaptive_filters: Option<usize> = match matches.value_of("adaptive_filters") {
+        Some(n) => match n.parse::<usize>() {
+            Ok(n) if n >= 1 => Some(n),
+            _ => bail!("Not a valid positive integer: adaptive filters = {}", n),
+        },
+        None => None,
+    };
+    let bypass_len: Option<usize> =
+        match matches.value_of("bypass_len") {
+            Some(len) => Some(len.parse::<usize>().map_err(|_| {
+                Error::from(format!("Not a valid integer: bypass length = {}", len))
+            })?),
+            None => None,
+        };
+    let batch_size: Option<usize> = match matches.value_of("batch_size") {
+        Some(n) => match n.parse::<usize>() {
+            Ok(n) if n >= 1 => Some(n),
+            _ => bail!("Not a valid positive integer: batch size = {}", n),
+        },
+        None => None,
+    };
+    let threads: Option<usize> = match matches.value_of("threads") {
+        Some(n) => match n.parse::<usize>() {
+            Ok(n) if n >= 1 => Some(n),
+            _ => bail!("Not a valid positive integer: threads = {}", n),
+        },
+        None => None,
+    };
+    let recall: Option<f64> = match (matches.is_present("approx"), matches.value_of("recall")) {
+        (true, Some(recall)) => match recall.parse::<f64>() {
+            Ok(recall) if recall > 0.0 && recall <= 1.0 => Some(recall),
+            _ => bail!("Not a valid recall in (0, 1]: {}", recall),
+        },
+        (true, None) => Some(0.95),
+        (false, Some(_)) => bail!("`--recall` requires `--approx`"),
+        (false, None) => None,
+    };
+    let windowing: Option<Windowing> = match matches.value_of("windows") {
+        Some(size) => {
+            let size: usize = size
+                .parse()
+                .map_err(|_| Error::from(format!("Not a valid integer: window size = {}", size)))?;
+            let step: usize = match matches.value_of("window_step") {
+                Some(step) => step.parse().map_err(|_| {
+                    Error::from(format!("Not a valid integer: window step = {}", step))
+                })?,
+                None => cmp::max(size / 2, 1),
+            };
+            let min_coverage: f64 = match matches.value_of("min_coverage") {
+                Some(coverage) => match coverage.parse::<f64>() {
+                    Ok(coverage) if (0.0..=1.0).contains(&coverage) => coverage,
+                    _ => bail!("Not a valid coverage in [0, 1]: {}", coverage),
+                },
+                None => 0.5,
+            };
+            Some(Windowing {
+                size,
+                step,
+                min_coverage,
+            })
+        }
+        None if matches.is_present("window_step") || matches.is_present("min_coverage") => {
+            bail!("`--window-step` and `--min-coverage` require `--windows`")
+        }
+        None => None,
+    };
+    let delta: Option<(PathBuf, PathBuf)> = match (
+        matches.value_of("delta_from"),
+        matches.value_of("previous_output"),
+    ) {
+        (Some(previous_x), Some(previous_output)) => Some((
+            input_file_validator(previous_x)?,
+            input_file_validator(previous_output)?,
+        )),
+        (None, None) => None,
+        _ => bail!("`--delta-from` and `--previous-output` must be given together"),
+    };
+    let mask_salt: Option<Vec<u8>> = match matches.value_of("mask_texts") {
+        Some(salt_file) => match std::fs::read(salt_file) {
+            Ok(salt) if !salt.is_empty() => Some(salt),
+            Ok(_) => bail!("The salt file of `--mask-texts` is empty: {}", salt_file),
+            Err(e) => bail!(
+                "Cannot read the salt file of `--mask-texts` {}: {}",
+                salt_file,
+                e
+            ),
+        },
+        None => None,
+    };
+    let checkpoint_interval: Option<Duration> = match matches.value_of("checkpoint_interval") {
+        Some(secs) => match secs.parse::<u64>() {
+            Ok(secs) if secs >= 1 => Some(Duration::from_secs(secs)),
+            _ => bail!(
+                "Not a valid positive integer: checkpoint interval = {}",
+                secs
+            ),
+        },
+        None => None,
+    };
+    let record_timeout: Option<Duration> = match matches.value_of("record_timeout") {
+        Some(ms) => match ms.parse::<u64>() {
+            Ok(ms) if ms >= 1 => Some(Duration::from_millis(ms)),
+            _ => bail!("Not a valid positive integer: record timeout = {}", ms),
+        },
+        None => None,
+    };
+    let limit = |name: &str, what: &str| -> Result<Option<usize>> {
+        match matches.value_of(name) {
+            Some(n) => match n.parse::<usize>() {
+                Ok(n) if n >= 1 => Ok(Some(n)),
+                _ => bail!("Not a valid positive integer: {} = {}", what, n),
+            },
+            None => Ok(None),
+        }
+    };
+    let limits: Limits = Limits {
+        index_bytes: limit("max_index_bytes", "max index bytes")?,
+        candidates: limit("max_candidates", "max candidates")?,
+        output_rows: limit("max_output_rows", "max output rows")?,
+    };
+    if matches.is_present("output")
+        && (matches.is_present("output_name") || matches.is_present("output_dir"))
+    {
+        bail!("`--output` is the output file itself, so it cannot be combined with `--output-name` or `--output-dir`");
+    }
+    let output_naming: Option<OutputNaming> = match matches.value_of("output_name") {
+        Some(template) => Some(OutputNaming::parse(template)?),
+        None => None,
+    };
+    let output_dir: Option<PathBuf> = match matches.value_of("output_dir") {
+        Some(dir) if Path::new(dir).is_dir() => Some(PathBuf::from(dir)),
+        Some(dir) => bail!("Not a directory: output directory = {}", dir),
+        None => None,
+    };
+    let mut filters: Filters = Filters::default();
+    for filter in matches
+        .value_of("disable_filters")
+        .into_iter()
+        .flat_map(|list| list.split(','))
+    {
+        match filter.trim() {
+            "count" => filters.count = false,
+            "location" => filters.location = false,
+            "content" => filters.content = false,
+            _ => bail!(
+                "Not a valid filter, expected `count`, `location` or `content`: {}",
+                filter
+            ),
+        }
+    }
+    let mut normalization: Normalization = Normalization::default();
+    for step in matches
+        .value_of("normalize")
+        .into_iter()
+        .flat_map(|list| list.split(','))
+    {
+        match step.trim() {
+            "lowercase" => normalization.lowercase = true,
+            "trim" => normalization.trim = true,
+            "collapse-whitespace" => normalization.collapse_whitespace = true,
+            "transliterate" => normalization.transliterate = true,
+            "strip-diacritics" => normalization.strip_diacritics = true,
+            form => match UnicodeForm::from_name(form) {
+                Some(_) if normalization.unicode_form.is_some() => {
+                    bail!("Records are only normalized into one Unicode form: {}", form)
+                }
+                Some(form) => normalization.unicode_form = Some(form),
+                None => bail!(
+                    "Not a valid normalization, expected `lowercase`, `trim`, `collapse-whitespace`, `transliterate`, \
+                     `strip-diacritics`, `nfc`, `nfd`, `nfkc` or `nfkd`: {}",
+                    step
+                ),
+            },
+        }
+    }
+    let alphabet_action: AlphabetAction = match matches.value_of("alphabet_action") {
+        Some(action) => AlphabetAction::from_name(action).ok_or_else(|| {
+            Error::from(format!(
+                "Not a valid alphabet action, expected `report`, `skip`, `transliterate` or `abort`: {}",
+                action
+            ))
+        })?,
+        None => AlphabetAction::default(),
+    };
+    let expected_alphabet: Option<(Alphabet, AlphabetAction)> =
+        match matches.value_of("expected_alphabet") {
+            Some(class) => Some((Alphabet::parse(class)?, alphabet_action)),
+            None if matches.is_present("alphabet_action") => {
+                bail!("`--alphabet-action` needs an `--expected-alphabet`")
+            }
+            None => None,
+        };
+    Ok(Command::Join(Box::new(Config {
         doc_x,
         doc_y,
         q,
         tau,
-    })
+        log_format,
+        warnings_file: matches.value_of("warnings_file").map(PathBuf::from),
+        timeline: matches.value_of("timeline").map(PathBuf::from),
+        stats: matches.is_present("stats"),
+        error_policy,
+        delimiter,
+        output_format,
+        clustering,
+        knn,
+        tau_ratio,
+        metric,
+        assignment,
+        confidence: matches.is_present("confidence"),
+        resume: matches.is_present("resume"),
+        checkpoint_interval,
+        cache_dir: matches.value_of("cache_dir").map(PathBuf::from),
+        stop_after,
+        candidates_only: matches.is_present("candidates_only"),
+        index_from: matches.value_of("index_from").map(PathBuf::from),
+        provenance: matches.is_present("provenance"),
+        candidates_from: matches.value_of("candidates_from").map(PathBuf::from),
+        tombstones: matches.value_of("tombstones").map(PathBuf::from),
+        emit_candidates: matches.value_of("emit_candidates").map(PathBuf::from),
+        diagnostics: matches.value_of("diagnostics").map(PathBuf::from),
+        debug_sample: matches.value_of("debug_sample").map(PathBuf::from),
+        count_filter,
+        adaptive_filters,
+        bypass_len,
+        batch_size,
+        threads,
+        recall,
+        progressive: matches.is_present("progressive"),
+        warm_dir: matches.value_of("warm_dir").map(PathBuf::from),
+        external_index: matches.value_of("external_index").map(PathBuf::from),
+        mode,
+        filters,
+        normalization,
+        expected_alphabet,
+        line_offsets: matches.is_present("line_offsets"),
+        emit_content: matches.is_present("emit_content"),
+        mask_salt,
+        plugins: matches.value_of("plugins").map_or_else(Vec::new, |libs| {
+            libs.split(',').map(PathBuf::from).collect()
+        }),
+        tokenizer,
+        reverse_complement: matches.is_present("reverse_complement"),
+        input_format,
+        windowing,
+        delta,
+        record_timeout,
+        limits,
+        output: matches.value_of("output").map(PathBuf::from),
+        output_naming,
+        output_dir,
+    })))
 }
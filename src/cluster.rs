@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::errors::*;
+use crate::output::{CsvWriter, Matches};
+use crate::qgram::ID;
+use crate::report::Side;
+
+/// How the distance between two clusters is defined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Linkage {
+    /// Distance of the closest pair of records, i.e. connected components of pairs within the cutoff.
+    Single,
+    /// Distance of the farthest pair of records.
+    ///
+    /// A pair that wasn't matched has an unknown distance, which is larger than any cutoff,
+    /// so a cluster only grows while all of its records are pairwise matched within the cutoff.
+    Complete,
+}
+
+impl Linkage {
+    /// Parse the name of a linkage, i.e. `single` or `complete`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "single" => Some(Linkage::Single),
+            "complete" => Some(Linkage::Complete),
+            _ => None,
+        }
+    }
+}
+
+/// Configuration of the clustering step over matched pairs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Clustering {
+    pub linkage: Linkage,
+    /// Clusters are only merged while their distance is at most `cutoff`.
+    pub cutoff: usize,
+}
+
+/// Cluster all records involved in at least one match.
+///
+/// # Parameters
+///
+///  * `matches`: All matches of a join.
+///  * `clustering`: Linkage and distance cutoff of the clustering.
+///
+/// # Return
+///
+/// Pairs of node name and cluster number, in the same order and naming as the nodes of a match graph.
+/// Clusters are numbered from 0, in the order of their first record.
+pub fn cluster(matches: &Matches, clustering: &Clustering) -> Vec<(String, usize)> {
+    // every endpoint of a match is a node, keyed by its side and line number
+    let key = |side: Side, id: ID| {
+        if matches.self_join {
+            (Side::X, id)
+        } else {
+            (side, id)
+        }
+    };
+    let mut nodes: BTreeMap<(Side, ID), usize> = BTreeMap::new();
+    matches.iter().for_each(|m| {
+        nodes.insert(key(Side::X, m.x_id), 0);
+        nodes.insert(key(Side::Y, m.y_id), 0);
+    });
+    nodes
+        .values_mut()
+        .enumerate()
+        .for_each(|(i, node)| *node = i);
+
+    // distances between nodes, keyed by the smaller node first
+    let mut edges: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+    matches.iter().for_each(|m| {
+        let (a, b) = (nodes[&key(Side::X, m.x_id)], nodes[&key(Side::Y, m.y_id)]);
+        if a != b {
+            edges.insert((a.min(b), a.max(b)), m.ed);
+        }
+    });
+
+    let roots: Vec<usize> = match clustering.linkage {
+        Linkage::Single => single_linkage(nodes.len(), &edges, clustering.cutoff),
+        Linkage::Complete => complete_linkage(nodes.len(), &edges, clustering.cutoff),
+    };
+
+    // number clusters in the order of their first node
+    let mut labels: BTreeMap<usize, usize> = BTreeMap::new();
+    nodes
+        .into_iter()
+        .map(|((side, id), node)| {
+            let next_label: usize = labels.len();
+            let label: usize = *labels.entry(roots[node]).or_insert(next_label);
+            (matches.node_name(side, id), label)
+        })
+        .collect()
+}
+
+/// Find the representative of every node, with union-find over all edges within the cutoff.
+fn single_linkage(n: usize, edges: &BTreeMap<(usize, usize), usize>, cutoff: usize) -> Vec<usize> {
+    fn find(parents: &mut [usize], mut node: usize) -> usize {
+        while parents[node] != node {
+            parents[node] = parents[parents[node]];
+            node = parents[node];
+        }
+        node
+    }
+
+    let mut parents: Vec<usize> = (0..n).collect();
+    edges
+        .iter()
+        .filter(|(_, ed)| **ed <= cutoff)
+        .for_each(|((a, b), _)| {
+            let (root_a, root_b) = (find(&mut parents, *a), find(&mut parents, *b));
+            parents[root_a.max(root_b)] = root_a.min(root_b);
+        });
+
+    (0..n).map(|node| find(&mut parents, node)).collect()
+}
+
+/// Find the representative of every node, by repeatedly merging the closest pair of clusters.
+///
+/// This takes `O(n * E)` time, which is fine for the sparse graphs of near-duplicates.
+fn complete_linkage(
+    n: usize,
+    edges: &BTreeMap<(usize, usize), usize>,
+    cutoff: usize,
+) -> Vec<usize> {
+    let mut roots: Vec<usize> = (0..n).collect();
+    let mut sizes: Vec<usize> = vec![1; n];
+    // links between clusters, keyed by their representatives: (largest distance, number of known pairs)
+    let mut links: BTreeMap<(usize, usize), (usize, usize)> =
+        edges.iter().map(|(pair, ed)| (*pair, (*ed, 1))).collect();
+
+    loop {
+        // only clusters whose pairs are all known and within the cutoff can be merged
+        let closest: Option<(usize, usize)> = links
+            .iter()
+            .filter(|((a, b), (ed, count))| *ed <= cutoff && *count == sizes[*a] * sizes[*b])
+            .min_by_key(|(pair, (ed, _))| (*ed, **pair))
+            .map(|(pair, _)| *pair);
+        let (a, b) = match closest {
+            Some(pair) => pair,
+            None => break,
+        };
+
+        // merge `b` into `a`
+        roots
+            .iter_mut()
+            .filter(|root| **root == b)
+            .for_each(|root| *root = a);
+        sizes[a] += sizes[b];
+        sizes[b] = 0;
+        let mut merged: BTreeMap<(usize, usize), (usize, usize)> = BTreeMap::new();
+        links.into_iter().for_each(|((c, d), (ed, count))| {
+            let (c, d) = (if c == b { a } else { c }, if d == b { a } else { d });
+            if c != d {
+                let link = merged.entry((c.min(d), c.max(d))).or_insert((0, 0));
+                *link = (link.0.max(ed), link.1 + count);
+            }
+        });
+        links = merged;
+    }
+
+    roots
+}
+
+/// Write cluster assignments as `node,cluster` lines, with the given delimiter.
+pub fn write_clusters<W: Write>(
+    writer: W,
+    assignments: &[(String, usize)],
+    delimiter: u8,
+) -> Result<()> {
+    let mut csv_writer: CsvWriter<W> = CsvWriter::new(writer, delimiter);
+    for (node, label) in assignments {
+        csv_writer.write_record(&[node.to_string(), label.to_string()])?;
+    }
+    csv_writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linkages() {
+        // a chain `0 - 1 - 2`, where `0` and `2` aren't matched, and a separate pair `3 - 4`
+        let records: Vec<String> = ["aaaa", "aaab", "aabb", "cccc", "cccd"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let pairs = vec![
+            (0, vec![(1, 1)]),
+            (1, vec![(0, 1), (2, 1)]),
+            (3, vec![(4, 1)]),
+        ];
+        let matches = Matches {
+            pairs: &pairs,
+            x_records: &records,
+            y_records: &records,
+            self_join: true,
+            confidence: None,
+            passthrough: None,
+            provenance: None,
+            emit_content: false,
+            text_mask: None,
+            plugins: &[],
+        };
+        let names = |labels: &[usize]| -> Vec<(String, usize)> {
+            labels
+                .iter()
+                .enumerate()
+                .map(|(id, label)| (id.to_string(), *label))
+                .collect()
+        };
+
+        let single = Clustering {
+            linkage: Linkage::Single,
+            cutoff: 1,
+        };
+        assert_eq!(cluster(&matches, &single), names(&[0, 0, 0, 1, 1]));
+
+        let complete = Clustering {
+            linkage: Linkage::Complete,
+            cutoff: 1,
+        };
+        assert_eq!(cluster(&matches, &complete), names(&[0, 0, 1, 2, 2]));
+
+        let strict = Clustering {
+            linkage: Linkage::Complete,
+            cutoff: 0,
+        };
+        assert_eq!(cluster(&matches, &strict), names(&[0, 1, 2, 3, 4]));
+    }
+}
@@ -0,0 +1,1153 @@
+use std::{
+    collections::BTreeSet,
+    fmt,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
+
+use crate::alphabet::{to_ascii, Alphabet, AlphabetAction};
+use crate::assignment::Assignment;
+use crate::cache::fingerprint;
+use crate::cluster::{Clustering, Linkage};
+use crate::csv::CsvInput;
+use crate::delta::Delta;
+use crate::errors::*;
+use crate::jsonl::JsonlInput;
+use crate::output::{OutputFormat, OutputNaming, OutputTemplate, TextMask};
+use crate::persist::Phase;
+use crate::plugin::MatchPlugin;
+use crate::progress::{ProgressEvent, ProgressFn, ProgressObserver};
+use crate::qgram::ID;
+use crate::scoring::Scorer;
+use crate::sink::{FileSink, OutputSink, SinkContext};
+use crate::store::RecordStore;
+use crate::tokenizer::{QGrams, Tokenizer};
+use crate::unicode::{normalize, strip_diacritics, UnicodeForm};
+use crate::verification::DistanceMetric;
+use crate::window::Windowing;
+
+/// How errors encountered in the middle of a join are handled.
+///
+/// This applies to I/O errors when reading the inputs, records that cannot be parsed,
+/// and violations of internal invariants, e.g. a q-gram missing from the inverted index.
+/// Whatever is skipped is reported as a warning in the `JoinReport`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Abort the join on the first error.
+    Abort,
+    /// Skip the record which the error occurs in, and keep going.
+    ///
+    /// On an I/O error the rest of that input cannot be read reliably, so it's truncated there.
+    #[default]
+    SkipRecord,
+    /// Skip only the pair of records which the error occurs in, and keep going.
+    ///
+    /// Errors that aren't specific to a pair, such as I/O and parse errors, skip the record instead.
+    SkipPair,
+}
+
+/// How the count filter bounds the number of q-grams two records share.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CountFilter {
+    /// Count strictly mismatching q-grams of `x` while comparing the q-grams of both records, as the paper does.
+    #[default]
+    Approximate,
+    /// Count the q-grams of the longer record without an exact counterpart in the other one, see
+    /// [`crate::filters::count_mismatch`], and prune the pair before the other filters if that's more than `q * tau`.
+    Exact,
+}
+
+impl CountFilter {
+    /// Parse the name of a count filter, i.e. `approximate` or `exact`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "approximate" => Some(CountFilter::Approximate),
+            "exact" => Some(CountFilter::Exact),
+            _ => None,
+        }
+    }
+}
+
+/// Which filters of the verification are applied to candidate pairs, all of them by default.
+///
+/// The filters only prune pairs whose edit distance is certainly above `tau`, so turning them off never changes
+/// the matches, only how many edit distances are calculated, e.g. to measure what each filter saves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Filters {
+    /// The count filter, on the number of mismatching q-grams, whichever [`CountFilter`] it is.
+    pub count: bool,
+    /// The location-based mismatch filter, on the number of edits needed to destroy the mismatching q-grams.
+    pub location: bool,
+    /// The content-based mismatch filter, on the differences of characters around the mismatching q-grams.
+    pub content: bool,
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        Self {
+            count: true,
+            location: true,
+            content: true,
+        }
+    }
+}
+
+/// Guardrails of the resources each phase of a join may use, none of them by default, see [`EdJoinConfig::limits`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Most bytes of the inverted index in memory, as estimated from its tokens and postings, see
+    /// [`crate::qgram::index_bytes`]. An index built on disk, see [`EdJoinConfig::external_index`], isn't limited.
+    pub index_bytes: Option<usize>,
+    /// Most candidates generated in total, for all records of `doc_x`, which a join that stops after generating them
+    /// doesn't check.
+    pub candidates: Option<usize>,
+    /// Most rows of output, i.e. matching pairs, as counted before an assignment picks some of them.
+    pub output_rows: Option<usize>,
+}
+
+/// How records are normalized after they're read, before they're joined, none of it by default.
+///
+/// Matches refer to the normalized records, e.g. their `x_text` and `y_text` fields and edit distances,
+/// except that transliterated records are written with their original text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Normalization {
+    /// If set, bring records into this Unicode normalization form first, so that e.g. `é` is the same either way
+    /// it's encoded.
+    pub unicode_form: Option<UnicodeForm>,
+    /// Strip diacritics, e.g. `é` into `e`, see [`crate::unicode::strip_diacritics`].
+    pub strip_diacritics: bool,
+    /// Convert records to lower case.
+    pub lowercase: bool,
+    /// Strip leading and trailing whitespace.
+    pub trim: bool,
+    /// Replace each run of whitespace by a single space.
+    pub collapse_whitespace: bool,
+    /// Transliterate records into ASCII first, e.g. `Müller` into `Muller`, see [`crate::alphabet::to_ascii`].
+    pub transliterate: bool,
+}
+
+impl Normalization {
+    /// Whether records are left as they are.
+    pub fn is_identity(&self) -> bool {
+        *self == Normalization::default()
+    }
+
+    /// Normalize a record, or an error if it's brought into a Unicode form or stripped of its diacritics
+    /// without the `unicode` feature.
+    pub fn apply(&self, record: &str) -> Result<String> {
+        let normalized: String;
+        let record: &str = match self.unicode_form {
+            Some(form) => {
+                normalized = normalize(record, form)?;
+                &normalized
+            }
+            None => record,
+        };
+        let stripped: String;
+        let record: &str = if self.strip_diacritics {
+            stripped = strip_diacritics(record)?;
+            &stripped
+        } else {
+            record
+        };
+        let transliterated: String;
+        let record: &str = if self.transliterate {
+            transliterated = to_ascii(record);
+            &transliterated
+        } else {
+            record
+        };
+        let mut record: String = if self.trim {
+            record.trim().to_string()
+        } else {
+            record.to_string()
+        };
+        if self.collapse_whitespace {
+            record = record.split_whitespace().collect::<Vec<&str>>().join(" ");
+        }
+        if self.lowercase {
+            record = record.to_lowercase();
+        }
+        Ok(record)
+    }
+}
+
+/// Whether a join is a self-join of one document, or a cross join of two.
+///
+/// A self-join only reports each pair once, and never a record with itself, while a cross join reports all pairs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JoinMode {
+    /// A self-join if `doc_x` and `doc_y` are the same file, even by different paths, e.g. a symlink, and a cross join otherwise.
+    #[default]
+    Auto,
+    /// A self-join of `doc_x`, which `doc_y` must be the same file as, or a copy of it with the same content.
+    SelfJoin,
+    /// A cross join, even if `doc_x` and `doc_y` are the same file.
+    CrossJoin,
+}
+
+impl JoinMode {
+    /// Parse the name of a join mode, i.e. `auto`, `self` or `cross`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(JoinMode::Auto),
+            "self" => Some(JoinMode::SelfJoin),
+            "cross" => Some(JoinMode::CrossJoin),
+            _ => None,
+        }
+    }
+}
+
+/// How records are parsed from the inputs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum InputFormat {
+    /// Every line is a record.
+    #[default]
+    Lines,
+    /// Every read of a FASTQ file is a record of its sequence, after trimming and filtering it by its quality,
+    /// see [`crate::fastq::read_fastq`].
+    Fastq(QualityFilter),
+    /// Every row of a CSV or TSV file is a record of its join column, see [`crate::csv::read_csv`].
+    Csv(CsvInput),
+    /// Every line of a JSON Lines file is a record of its join field, see [`crate::jsonl::read_jsonl`].
+    JsonLines(JsonlInput),
+}
+
+/// How reads of a FASTQ file are trimmed and filtered by their Phred+33 quality scores. The default keeps reads as they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QualityFilter {
+    /// If set, the tail of each read is trimmed up to its last base of at least this quality.
+    pub trim_quality: Option<u8>,
+    /// If set, reads of a lower mean quality after trimming are skipped.
+    pub min_mean_quality: Option<f64>,
+}
+
+/// Options of an [`EdJoinConfig`] which contradict each other, or are invalid on their own, see [`EdJoinConfig::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// The options involved, by the names of their fields, e.g. `["knn", "candidates_from"]`.
+    pub options: Vec<&'static str>,
+    pub message: String,
+    /// How the options may be fixed.
+    pub suggestion: String,
+}
+
+impl Violation {
+    fn new(
+        options: &[&'static str],
+        message: impl Into<String>,
+        suggestion: impl Into<String>,
+    ) -> Self {
+        Self {
+            options: options.to_vec(),
+            message: message.into(),
+            suggestion: suggestion.into(),
+        }
+    }
+}
+
+/// The message, followed by the suggestion, e.g. `The batch size must be positive, so set \`batch_size\` to 1 or more`.
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, so {}", self.message, self.suggestion)
+    }
+}
+
+/// Configuration of a join.
+///
+/// Required parameters are given to `new()`, while optional ones are set by chained methods, e.g.
+///
+/// ```
+/// use ed_join::config::{EdJoinConfig, ErrorPolicy};
+///
+/// let config = EdJoinConfig::new(2, 1).error_policy(ErrorPolicy::Abort);
+/// assert_eq!(config.q, 2);
+/// ```
+#[derive(Clone, Debug)]
+pub struct EdJoinConfig {
+    /// A positive integer as the tuning parameter for length of q-grams.
+    pub q: usize,
+    /// A positive integer as the tuning parameter for threshold for matching.
+    pub tau: usize,
+    /// If set, the threshold of each pair is this fraction of the length of its longer record, see [`Self::tau_ratio`].
+    pub tau_ratio: Option<f64>,
+    /// The distance which pairs are matched within `tau` by, the Levenshtein distance by default.
+    pub metric: DistanceMetric,
+    /// How errors in the middle of a join are handled.
+    pub error_policy: ErrorPolicy,
+    /// Field delimiter of the output file. Fields containing it are quoted.
+    pub delimiter: u8,
+    /// Format of the output file.
+    pub output_format: OutputFormat,
+    /// If set, matched records are also clustered, and cluster assignments are written to a separate file.
+    pub clustering: Option<Clustering>,
+    /// If set, each record of `doc_x` is matched with its `knn` nearest records of `doc_y` within `tau`, instead of all of them.
+    pub knn: Option<usize>,
+    /// If set, matches are reduced such that each record is in at most one pair.
+    pub assignment: Option<Assignment>,
+    /// If set, every match is scored by how likely it's a true duplicate, and the confidence is written along with it.
+    pub scorer: Option<Arc<dyn Scorer>>,
+    /// Whether to resume from the partial output of an interrupted join with the same inputs and parameters.
+    pub resume: bool,
+    /// If set, the partial output is checkpointed this often, see [`EdJoinConfig::checkpoint_interval`].
+    pub checkpoint_interval: Option<Duration>,
+    /// If set, completed runs are cached in this directory, and an identical run restores their output instead.
+    pub cache_dir: Option<PathBuf>,
+    /// If set, the join stops after this phase, and persists its result instead of matching.
+    pub stop_after: Option<Phase>,
+    /// If set, the inverted index is loaded from this file, instead of being built.
+    pub index_from: Option<PathBuf>,
+    /// Whether matches are written with the part of the merged index their record of `doc_y` is from,
+    /// see [`Self::provenance`].
+    pub provenance: bool,
+    /// If set, candidates are loaded from this file, instead of being generated.
+    pub candidates_from: Option<PathBuf>,
+    /// Line numbers of records of `doc_y` which are never matched, see [`Self::tombstones`].
+    pub tombstones: BTreeSet<ID>,
+    /// If set, candidate pairs that passed all filters are also written to this file, before their edit distance is calculated.
+    pub emit_candidates: Option<PathBuf>,
+    /// If set, the join runs on its own thread pool of this many threads, instead of the global one of `rayon`.
+    pub num_threads: Option<usize>,
+    /// If set, the progress of the join is reported to this observer.
+    pub progress: Option<Arc<dyn ProgressObserver>>,
+    /// If set, the records of `doc_y` are taken from this store, instead of being read from the file.
+    pub record_store: Option<Arc<RecordStore>>,
+    /// How the count filter bounds the number of shared q-grams.
+    pub count_filter: CountFilter,
+    /// If set, the content-based filter is profiled on this many pairs, and disabled if it costs more than it saves.
+    pub adaptive_filters: Option<usize>,
+    /// Pairs of records shorter than this skip the filters and go straight to the edit distance,
+    /// or `q * (tau + 2)` if not set.
+    pub bypass_len: Option<usize>,
+    /// Records of `doc_x` matched by each task, or [`crate::matching::DEFAULT_BATCH_SIZE`] if not set.
+    pub batch_size: Option<usize>,
+    /// If set, the join is approximate, and candidates are pruned as much as this recall allows.
+    pub recall: Option<f64>,
+    /// Whether records of `doc_x` are processed cheapest first, and their matches written as soon as they're found.
+    pub progressive: bool,
+    /// If set, the inverted index and verified distances are kept in this directory, and reused by later joins of the same inputs.
+    pub warm_dir: Option<PathBuf>,
+    /// If set, the inverted index is built on disk in this directory, instead of in memory, see [`Self::external_index`].
+    pub external_index: Option<PathBuf>,
+    /// Whether the inputs are joined as one document or two, see [`JoinMode`].
+    pub mode: JoinMode,
+    /// Which filters of the verification are applied.
+    pub filters: Filters,
+    /// How records are normalized before they're joined.
+    pub normalization: Normalization,
+    /// If set, the characters records are expected to consist of, after they're normalized.
+    pub alphabet: Option<Alphabet>,
+    /// What happens to records with characters out of [`Self::alphabet`].
+    pub alphabet_action: AlphabetAction,
+    /// If set, the work of matching each record of `doc_x` is written to this file, see [`crate::output::RecordDiagnostics`].
+    pub diagnostics: Option<PathBuf>,
+    /// If set, a scrambled sample of the records that failed or were slow is written to this directory,
+    /// see [`Self::debug_sample`].
+    pub debug_sample: Option<PathBuf>,
+    /// Whether the line offsets of `doc_y` are persisted to its sidecar file, see [`crate::offsets::LineOffsets`].
+    pub line_offsets: bool,
+    /// Whether the matched records are written along with their line numbers, see [`Self::emit_content`].
+    pub emit_content: bool,
+    /// If set, records are written as their salted hashes, see [`Self::mask_texts`].
+    pub text_mask: Option<TextMask>,
+    /// How the grams of records are generated, [`QGrams`] by default.
+    pub tokenizer: Arc<dyn Tokenizer>,
+    /// Whether records of `doc_x` are also matched by their reverse complement, as sequences of DNA.
+    pub reverse_complement: bool,
+    /// How records are parsed from both inputs.
+    pub input_format: InputFormat,
+    /// If set, records are split into overlapping windows, which are joined instead, see [`crate::window::window_join`].
+    pub windowing: Option<Windowing>,
+    /// If set, only records of `doc_x` changed since this previous run are joined, see [`crate::delta::delta_join`].
+    pub delta: Option<Delta>,
+    /// If set, each record of `doc_x` has this much time to verify its candidates, and the rest of them are skipped.
+    pub record_timeout: Option<Duration>,
+    /// Guardrails of the resources of each phase, see [`Self::limits`].
+    pub limits: Limits,
+    /// If set, the join is cancelled once this flag is set, see [`Self::cancel_on`].
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// If set, the output is written to this sink, instead of a file named after `doc_x` in the current directory.
+    pub output_sink: Option<Arc<dyn OutputSink>>,
+    /// How the output file is named, `{stem}_out_q{q}_tau{tau}.{ext}` by default.
+    pub output_naming: OutputNaming,
+    /// If set, the output file is written to this directory, instead of the current one.
+    pub output_dir: Option<PathBuf>,
+    /// Plugins which every match is passed through before it's written, see [`Self::plugin`].
+    pub plugins: Vec<Arc<dyn MatchPlugin>>,
+}
+
+impl EdJoinConfig {
+    pub fn new(q: usize, tau: usize) -> Self {
+        Self {
+            q,
+            tau,
+            tau_ratio: None,
+            metric: DistanceMetric::default(),
+            error_policy: ErrorPolicy::default(),
+            delimiter: b',',
+            output_format: OutputFormat::default(),
+            clustering: None,
+            knn: None,
+            assignment: None,
+            scorer: None,
+            resume: false,
+            checkpoint_interval: None,
+            cache_dir: None,
+            stop_after: None,
+            index_from: None,
+            provenance: false,
+            candidates_from: None,
+            tombstones: BTreeSet::new(),
+            emit_candidates: None,
+            num_threads: None,
+            progress: None,
+            record_store: None,
+            count_filter: CountFilter::default(),
+            adaptive_filters: None,
+            bypass_len: None,
+            batch_size: None,
+            recall: None,
+            progressive: false,
+            warm_dir: None,
+            external_index: None,
+            mode: JoinMode::default(),
+            filters: Filters::default(),
+            normalization: Normalization::default(),
+            alphabet: None,
+            alphabet_action: AlphabetAction::default(),
+            diagnostics: None,
+            debug_sample: None,
+            line_offsets: false,
+            emit_content: false,
+            text_mask: None,
+            tokenizer: Arc::new(QGrams),
+            reverse_complement: false,
+            input_format: InputFormat::default(),
+            windowing: None,
+            delta: None,
+            record_timeout: None,
+            limits: Limits::default(),
+            cancel: None,
+            output_sink: None,
+            output_naming: OutputNaming::default(),
+            output_dir: None,
+            plugins: Vec::new(),
+        }
+    }
+
+    /// Set how errors in the middle of a join are handled.
+    pub fn error_policy(mut self, error_policy: ErrorPolicy) -> Self {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// Set the field delimiter of the output file.
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Set the format of the output file.
+    pub fn output_format(mut self, output_format: OutputFormat) -> Self {
+        self.output_format = output_format;
+        self
+    }
+
+    /// Set a user-defined format of output lines, i.e. `OutputFormat::Template`.
+    pub fn template(self, template: OutputTemplate) -> Self {
+        self.output_format(OutputFormat::Template(template))
+    }
+
+    /// Cluster matched records with the given linkage, merging clusters within distance `cutoff`.
+    pub fn clustering(mut self, linkage: Linkage, cutoff: usize) -> Self {
+        self.clustering = Some(Clustering { linkage, cutoff });
+        self
+    }
+
+    /// Match each pair within a fraction of the length of its longer record, rounded up, instead of within `tau`,
+    /// e.g. `0.1` for at most 10% of edits. `tau` is still the largest threshold of any pair.
+    ///
+    /// Lengths are in the units of the tokenizer, see [`crate::tokenizer::TokenizerMode::len_of`]. Each record of `doc_x`
+    /// is matched with the largest threshold of any record it may match, and its matches outside their own are dropped.
+    pub fn tau_ratio(mut self, ratio: f64) -> Self {
+        self.tau_ratio = Some(ratio);
+        self
+    }
+
+    /// Match pairs by `metric`, e.g. [`DistanceMetric::DamerauLevenshtein`] so that swapped letters are one edit.
+    ///
+    /// Candidates are still generated and filtered by the Levenshtein distance, within [`DistanceMetric::filter_tau`],
+    /// so a join of transpositions has more candidates than one of the same `tau` without them.
+    pub fn metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Match each record of `doc_x` with its `k` nearest records only, with `tau` as the largest distance searched,
+    /// i.e. a top-k join.
+    ///
+    /// The threshold is widened from 1 per record, or from 0 if `tau` is 0, until `k` matches are found or `tau` is hit.
+    /// In a self-join, a record is never its own neighbour, but all others are considered, so a pair may be reported twice.
+    pub fn knn(mut self, k: usize) -> Self {
+        self.knn = Some(k);
+        self
+    }
+
+    /// Reduce matches to a one-to-one assignment between records of `doc_x` and `doc_y`, before they're written.
+    pub fn assignment(mut self, assignment: Assignment) -> Self {
+        self.assignment = Some(assignment);
+        self
+    }
+
+    /// Score every match with the given scorer, e.g. `scoring::DefaultScorer`.
+    pub fn scorer(mut self, scorer: impl Scorer + 'static) -> Self {
+        self.scorer = Some(Arc::new(scorer));
+        self
+    }
+
+    /// Resume from the partial output of an interrupted join, if there's any.
+    ///
+    /// Matches are first written to `<output>.partial`, which is renamed to the output file once the join succeeded.
+    /// When resuming, the partial file is validated, its incomplete tail is truncated,
+    /// and records of `doc_x` that are already in it are skipped, or those before its checkpoint if there's one,
+    /// see [`EdJoinConfig::checkpoint_interval`]. This requires CSV output, without assignment or clustering.
+    pub fn resume(mut self) -> Self {
+        self.resume = true;
+        self
+    }
+
+    /// Checkpoint the partial output of the join every `interval`, so that a long join loses at most about that much work
+    /// to a crash.
+    ///
+    /// At a checkpoint, the matches written so far are flushed and synced to `<output>.partial`, and the first record of
+    /// `doc_x` whose matches aren't written yet is recorded in `<output>.partial.checkpoint`, see
+    /// [`crate::persist::write_checkpoint`]. A resumed join then continues from that record, rather than from the last one
+    /// with matches in the partial output. This has the same requirements as resuming, and not progressive results.
+    pub fn checkpoint_interval(mut self, interval: Duration) -> Self {
+        self.checkpoint_interval = Some(interval);
+        self
+    }
+
+    /// Cache completed runs in the given directory, see [`crate::cache::CacheEntry`].
+    pub fn cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// Stop after the given phase, and persist its result next to the output file, see [`crate::persist`].
+    ///
+    /// The index is written under the name of the output file with the extension `idx`, and candidates with
+    /// `candidates.tsv`, see [`crate::matching::phase_name`]. The report's output is the path of either.
+    /// No matches are written.
+    pub fn stop_after(mut self, phase: Phase) -> Self {
+        self.stop_after = Some(phase);
+        self
+    }
+
+    /// Load the inverted index from a file written by a run that stopped after [`Phase::Index`], or by
+    /// [`crate::persist::write_index`].
+    ///
+    /// The index must be of the same inputs, which is not checked, and the same `q`, which is.
+    pub fn index_from(mut self, path: impl Into<PathBuf>) -> Self {
+        self.index_from = Some(path.into());
+        self
+    }
+
+    /// Write each match with the provenance of its record of `doc_y`, i.e. the part of the index loaded by
+    /// [`Self::index_from`] it's from, as `y_source`, and its line number in that part, counted from 0, as `y_line`.
+    ///
+    /// The index must be merged by [`crate::persist::merge_indexes`], whose parts are read from `<index>.sources`,
+    /// and the output must be CSV, TSV, JSON or Parquet.
+    pub fn provenance(mut self) -> Self {
+        self.provenance = true;
+        self
+    }
+
+    /// Load candidates from a file written by a run that stopped after [`Phase::Candidates`], and only verify them.
+    ///
+    /// The candidates must be of the same inputs, which is not checked, and the same `q` and `tau`, which are.
+    pub fn candidates_from(mut self, path: impl Into<PathBuf>) -> Self {
+        self.candidates_from = Some(path.into());
+        self
+    }
+
+    /// Never match the records of `doc_y` at these line numbers, e.g. those deleted since its index was persisted,
+    /// see [`crate::persist::read_tombstones`].
+    ///
+    /// They're masked out of the candidates, whether generated or loaded, so neither the index nor the candidates
+    /// have to be rebuilt. In a self-join, they're not matched as records of `doc_x` either.
+    pub fn tombstones(mut self, y_ids: impl IntoIterator<Item = ID>) -> Self {
+        self.tombstones.extend(y_ids);
+        self
+    }
+
+    /// Also write candidate pairs that passed all filters to the given file, as CSV with the field delimiter of the output.
+    ///
+    /// These are the pairs whose edit distance is calculated, e.g. to compare the filters with other verification algorithms.
+    /// In a nearest neighbour search, they're the candidates at the threshold the search stopped at.
+    pub fn emit_candidates(mut self, path: impl Into<PathBuf>) -> Self {
+        self.emit_candidates = Some(path.into());
+        self
+    }
+
+    /// Run the join on its own thread pool with the given number of threads, e.g. to run several joins concurrently.
+    ///
+    /// If it's 0, `rayon` picks the number of threads, as for its global thread pool.
+    pub fn num_threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = Some(num_threads);
+        self
+    }
+
+    /// Report the progress of the join to the given observer.
+    pub fn progress(mut self, observer: impl ProgressObserver + 'static) -> Self {
+        self.progress = Some(Arc::new(observer));
+        self
+    }
+
+    /// Report the progress of the join to the given function, whenever a record of `doc_x` is done,
+    /// see [`crate::progress::ProgressEvent`]. It replaces the observer of [`Self::progress`], if any.
+    pub fn on_progress(self, on_progress: impl Fn(ProgressEvent) + Send + Sync + 'static) -> Self {
+        self.progress(ProgressFn(on_progress))
+    }
+
+    /// Take the records of `doc_y` from a store shared with other joins, which must have been loaded from `doc_y`.
+    ///
+    /// A self-join doesn't use the store, since its records are those of `doc_x`.
+    pub fn record_store(mut self, store: Arc<RecordStore>) -> Self {
+        self.record_store = Some(store);
+        self
+    }
+
+    /// Set how the count filter bounds the number of shared q-grams.
+    pub fn count_filter(mut self, count_filter: CountFilter) -> Self {
+        self.count_filter = count_filter;
+        self
+    }
+
+    /// Profile the content-based filter on the first `sample` pairs it's applied to, and disable it for the rest
+    /// of the join if it costs more than it saves, see [`crate::verification::FilterProfile`].
+    ///
+    /// This only changes how fast the join is, not its matches.
+    pub fn adaptive_filters(mut self, sample: usize) -> Self {
+        self.adaptive_filters = Some(sample);
+        self
+    }
+
+    /// Verify pairs of records shorter than `len` by [`crate::verification::bounded_edit_distance`] alone,
+    /// since the filters cost more than they save for them. It's `q * (tau + 2)` by default, and 0 disables the bypass.
+    pub fn bypass_len(mut self, len: usize) -> Self {
+        self.bypass_len = Some(len);
+        self
+    }
+
+    /// Match records of `doc_x` in batches of `batch_size` records, each of them matched sequentially by one task,
+    /// rather than [`crate::matching::DEFAULT_BATCH_SIZE`].
+    ///
+    /// Larger batches cost less to schedule, while smaller ones balance the load better, e.g. when a few records have
+    /// far more candidates than the rest. It must be positive.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = Some(batch_size);
+        self
+    }
+
+    /// Make the join approximate, pruning candidates with stop-grams and caps, tuned to find at least `recall` of the matches.
+    ///
+    /// The pruning is chosen on a sample of `doc_x`, see [`crate::approx::tune`], and the recall it achieves on the sample
+    /// is reported as [`crate::report::JoinReport::estimated_recall`]. Records whose candidates were capped are reported
+    /// by [`crate::report::Warning::CappedCandidates`].
+    pub fn approximate(mut self, recall: f64) -> Self {
+        self.recall = Some(recall);
+        self
+    }
+
+    /// Process records of `doc_x` in increasing order of their estimated cost, and write their matches as soon as they're found.
+    ///
+    /// The bulk of the matches is then in `<output>.partial` early on, while the expensive records are still being processed.
+    /// Matches are thus not in the order of `doc_x`. This requires CSV output, without assignment or clustering,
+    /// and can't be resumed.
+    pub fn progressive(mut self) -> Self {
+        self.progressive = true;
+        self
+    }
+
+    /// Keep the inverted index and the edit distances of verified pairs in the given directory, see [`crate::cache::WarmEntry`].
+    ///
+    /// A later join of the same inputs and `q` reuses them, so rerunning it with a wider threshold, e.g. `tau` from 1 to 2 to 3,
+    /// doesn't build the index again, nor verify pairs which an earlier run already did.
+    pub fn warm_start(mut self, warm_dir: impl Into<PathBuf>) -> Self {
+        self.warm_dir = Some(warm_dir.into());
+        self
+    }
+
+    /// Build the inverted index on disk, in the given directory, for inputs whose index doesn't fit in memory,
+    /// see [`crate::external::ExternalIndex`].
+    ///
+    /// Only plain joins of files index on disk, and such an index is neither persisted, loaded, warm-started,
+    /// nor tuned for an approximate join.
+    pub fn external_index(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.external_index = Some(dir.into());
+        self
+    }
+
+    /// Set whether the inputs are joined as one document or two, which is decided by whether they're the same file by default.
+    pub fn mode(mut self, mode: JoinMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set which filters of the verification are applied, e.g. to measure what each of them saves.
+    pub fn filters(mut self, filters: Filters) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// Normalize records before they're joined, e.g. to match them regardless of case.
+    pub fn normalization(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Check that normalized records only consist of the characters of an alphabet, and report, skip or transliterate
+    /// those that don't, or abort the join, as `action` says.
+    pub fn alphabet(mut self, alphabet: Alphabet, action: AlphabetAction) -> Self {
+        self.alphabet = Some(alphabet);
+        self.alphabet_action = action;
+        self
+    }
+
+    /// Write the prefix length, numbers of candidates generated and verified, and matches of each record of `doc_x`
+    /// to a CSV file, e.g. to find the records responsible for a slow join.
+    pub fn diagnostics(mut self, path: impl Into<PathBuf>) -> Self {
+        self.diagnostics = Some(path.into());
+        self
+    }
+
+    /// Write a sample of the records involved in failures and slowdowns of the join to a directory, scrambled so that
+    /// it can be attached to a bug report without the data itself, see [`crate::sample::Scrambler`].
+    ///
+    /// The sample has the records of `doc_x` that violated invariants, including one that aborted the join,
+    /// that ran out of their time budget, and the slowest ones, each with all of its candidates in `doc_y`,
+    /// and is joined as plain text with the same `q`, `tau` and tokenizer. It's written even if the join fails.
+    pub fn debug_sample(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.debug_sample = Some(dir.into());
+        self
+    }
+
+    /// Join `doc_x` and `doc_y` as two documents even if they're the same path, i.e. [`JoinMode::CrossJoin`].
+    ///
+    /// A self-join only reports each pair once, and never a record with itself. This reports all pairs instead,
+    /// e.g. when the same path refers to different contents, or the full cross product is wanted.
+    pub fn force_cross_join(self) -> Self {
+        self.mode(JoinMode::CrossJoin)
+    }
+
+    /// Persist the line offsets of `doc_y` to `<doc_y>.offsets`, unless it's already up to date,
+    /// so that later tools can read its records by line number without scanning it.
+    pub fn line_offsets(mut self) -> Self {
+        self.line_offsets = true;
+        self
+    }
+
+    /// Write the matched records themselves after their line numbers and edit distance, as they were read,
+    /// so that the output can be used without joining it back with the inputs.
+    ///
+    /// Only formats that can hold them can be written with them, see [`OutputFormat::emits_content`].
+    pub fn emit_content(mut self) -> Self {
+        self.emit_content = true;
+        self
+    }
+
+    /// Write records as their salted hashes instead of their texts, wherever the output has them, i.e. by
+    /// [`Self::emit_content`] and the `{x_text}` and `{y_text}` fields of templates, see [`TextMask`].
+    ///
+    /// The salt must be kept secret, as anyone who has it can test guesses of records against their hashes.
+    pub fn mask_texts(mut self, mask: TextMask) -> Self {
+        self.text_mask = Some(mask);
+        self
+    }
+
+    /// Generate the grams of records by the given tokenizer, instead of [`QGrams`].
+    ///
+    /// The join stays exact only if the tokenizer is positional, see [`Tokenizer`].
+    pub fn tokenizer(mut self, tokenizer: impl Tokenizer + 'static) -> Self {
+        self.tokenizer = Arc::new(tokenizer);
+        self
+    }
+
+    /// Also match the reverse complement of each record of `doc_x`, see [`crate::tokenizer::reverse_complement`],
+    /// so that reads of DNA from opposite strands are joined, with the smaller of both distances.
+    ///
+    /// Use it along with [`crate::tokenizer::CanonicalKmers`], which indexes both strands as the same grams.
+    pub fn reverse_complement(mut self) -> Self {
+        self.reverse_complement = true;
+        self
+    }
+
+    /// Set how records are parsed from both inputs, e.g. as reads of FASTQ files.
+    pub fn input_format(mut self, input_format: InputFormat) -> Self {
+        self.input_format = input_format;
+        self
+    }
+
+    /// Join long records, e.g. whole documents, by their windows of `size` characters, `step` characters apart,
+    /// and report the pairs of records whose matched windows cover at least `min_coverage` of either of them.
+    pub fn sliding_windows(mut self, size: usize, step: usize, min_coverage: f64) -> Self {
+        self.windowing = Some(Windowing {
+            size,
+            step,
+            min_coverage,
+        });
+        self
+    }
+
+    /// Join only the records of `doc_x` added or changed since a previous run, of `previous_x` with the same `doc_y`,
+    /// and merge them with the matches in its CSV output `previous_output`.
+    pub fn delta(
+        mut self,
+        previous_x: impl Into<PathBuf>,
+        previous_output: impl Into<PathBuf>,
+    ) -> Self {
+        self.delta = Some(Delta {
+            previous_x: previous_x.into(),
+            previous_output: previous_output.into(),
+        });
+        self
+    }
+
+    /// Give each record of `doc_x` a time budget, from the start of its candidate generation, after which its remaining
+    /// candidates are skipped, and it's reported by a [`crate::report::Warning::RecordTimeout`].
+    ///
+    /// This bounds the time spent on adversarial records, e.g. very long and repetitive ones, at the cost of the matches
+    /// they may miss. Which candidates are skipped depends on timing, so the output is no longer deterministic.
+    pub fn record_timeout(mut self, timeout: Duration) -> Self {
+        self.record_timeout = Some(timeout);
+        self
+    }
+
+    /// Abort the join with an [`crate::errors::ErrorKind::LimitExceeded`] as soon as a phase exceeds one of the limits,
+    /// e.g. so that a misconfigured `q` or `tau` doesn't consume a shared machine.
+    ///
+    /// What the join completed before is written next to the output file, as `<output>.manifest`, see
+    /// [`crate::persist::Manifest`]. The matches of the records done are kept in the partial output, if it's CSV.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Cancel the join with an [`crate::errors::ErrorKind::Cancelled`] once the flag is set, e.g. by a daemon shutting down.
+    ///
+    /// The flag is checked before each record of `doc_x` is matched, so the phases before are run to their end.
+    /// The matches of the records done are kept in the partial output, if it's CSV, from which the join can be resumed.
+    /// Only a join written to its output is cancelled, so the matches returned by [`crate::matching::ed_join_matches`]
+    /// are not.
+    pub fn cancel_on(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    /// Write the output to the given sink, e.g. [`crate::sink::StdoutSink`], instead of a file named after `doc_x`.
+    ///
+    /// Since a sink is written as the join goes, it cannot be combined with resuming or caching,
+    /// which both depend on the output being a file of its own.
+    pub fn output(mut self, sink: impl OutputSink + 'static) -> Self {
+        self.output_sink = Some(Arc::new(sink));
+        self
+    }
+
+    /// Write the output to a file at the given path, instead of one named after `doc_x` in the current directory.
+    pub fn output_path(self, path: impl Into<PathBuf>) -> Self {
+        self.output(FileSink(path.into()))
+    }
+
+    /// Name the output file by a template, e.g. `{stem}_{algo}_{date}.{ext}`, see [`crate::output::NamingField`].
+    pub fn output_naming(mut self, naming: OutputNaming) -> Self {
+        self.output_naming = naming;
+        self
+    }
+
+    /// Write the output file to a directory, which must exist, instead of the current one.
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = Some(dir.into());
+        self
+    }
+
+    /// Pass every match through the given plugin before it's written, after those added before it,
+    /// e.g. a [`crate::plugin::DylibPlugin`].
+    ///
+    /// Since the cache cannot tell when a plugin changed, a join with plugins cannot be cached.
+    pub fn plugin(mut self, plugin: impl MatchPlugin + 'static) -> Self {
+        self.plugins.push(Arc::new(plugin));
+        self
+    }
+
+    /// Open the sink of the output, or create the file `out_name` if there's none.
+    pub(crate) fn open_output(&self, out_name: &Path) -> io::Result<Box<dyn Write + Send>> {
+        match &self.output_sink {
+            Some(sink) => sink.open_join(&SinkContext {
+                fingerprint: fingerprint(self),
+                format: self.output_format.clone(),
+                delimiter: self.delimiter,
+            }),
+            None => Ok(Box::new(File::create(out_name)?)),
+        }
+    }
+
+    /// Cross-check all options, and return all of their violations at once, with the ways they may be fixed,
+    /// as an [`ErrorKind::InvalidConfig`]. A join validates its configuration before it reads its inputs.
+    ///
+    /// ```
+    /// use ed_join::config::EdJoinConfig;
+    /// use ed_join::api::ErrorKind;
+    /// use ed_join::persist::Phase;
+    ///
+    /// let config = EdJoinConfig::new(2, 1).knn(3).candidates_from("candidates.tsv").batch_size(0);
+    /// match config.validate().unwrap_err().kind() {
+    ///     ErrorKind::InvalidConfig(violations) => assert_eq!(violations.len(), 2),
+    ///     _ => unreachable!(),
+    /// }
+    /// assert!(EdJoinConfig::new(2, 1).stop_after(Phase::Candidates).validate().is_ok());
+    /// ```
+    pub fn validate(&self) -> Result<()> {
+        let violations: Vec<Violation> = self.violations();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            bail!(ErrorKind::InvalidConfig(violations))
+        }
+    }
+
+    /// All violations of the options, in the order of [`Self::validate`].
+    pub fn violations(&self) -> Vec<Violation> {
+        let mut violations: Vec<Violation> = Vec::new();
+        let mut check =
+            |violated: bool, options: &[&'static str], message: &str, suggestion: &str| {
+                if violated {
+                    violations.push(Violation::new(options, message, suggestion));
+                }
+            };
+        // a join writes matches as they're found, and can resume or checkpoint them, only if they don't depend on each other
+        let streaming: bool = self.output_format == OutputFormat::Csv
+            && self.assignment.is_none()
+            && self.clustering.is_none();
+
+        check(
+            self.tau_ratio
+                .is_some_and(|ratio| !(0.0..=1.0).contains(&ratio)),
+            &["tau_ratio"],
+            &format!(
+                "Not a valid ratio of the threshold in [0, 1]: {}",
+                self.tau_ratio.unwrap_or_default()
+            ),
+            "set `tau_ratio` to a fraction of the length of the longer record, e.g. 0.1",
+        );
+        check(
+            self.batch_size == Some(0),
+            &["batch_size"],
+            "The batch size must be positive",
+            "set `batch_size` to 1 or more",
+        );
+        check(
+            self.knn == Some(0),
+            &["knn"],
+            "The number of nearest neighbours must be positive",
+            "set `knn` to 1 or more",
+        );
+        check(
+            self.knn.is_some() && self.candidates_from.is_some(),
+            &["knn", "candidates_from"],
+            "Nearest neighbours cannot be searched from persisted candidates, which are of a fixed threshold",
+            "drop `candidates_from`, or search with a fixed `tau` instead of `knn`",
+        );
+        check(
+            self.line_offsets && self.input_format != InputFormat::Lines,
+            &["line_offsets", "input_format"],
+            "Line offsets are only available for inputs of one record per line",
+            "drop `line_offsets`, or join the records by their line numbers instead",
+        );
+        check(
+            self.external_index.is_some()
+                && (self.index_from.is_some()
+                    || self.warm_dir.is_some()
+                    || self.stop_after == Some(Phase::Index)
+                    || self.recall.is_some()),
+            &["external_index", "index_from", "warm_dir", "stop_after", "recall"],
+            "Indexes on disk are neither persisted, loaded, warm-started, nor tuned for approximate joins",
+            "drop `external_index`, or the options of persisted, warm-started or approximate indexes",
+        );
+        check(
+            self.metric != DistanceMetric::Levenshtein
+                && (self.recall.is_some() || self.warm_dir.is_some() || self.candidates_from.is_some()),
+            &["metric", "recall", "warm_dir", "candidates_from"],
+            "Approximate joins, warm starts and persisted candidates are only of the Levenshtein distance",
+            "join by the Levenshtein `metric`, or drop `recall`, `warm_dir` and `candidates_from`",
+        );
+        check(
+            self.emit_content && !self.output_format.emits_content(),
+            &["emit_content", "output_format"],
+            "Matched records are only written in CSV, TSV, JSON or Parquet output, and by `{x}` and `{y}` of templates",
+            "drop `emit_content`, or write CSV, TSV, JSON or Parquet output",
+        );
+        check(
+            self.provenance && self.index_from.is_none(),
+            &["provenance", "index_from"],
+            "Provenance is only known of the records of a merged index",
+            "load the merged index of doc_y with `index_from`, or drop `provenance`",
+        );
+        check(
+            self.provenance && !self.output_format.emits_content(),
+            &["provenance", "output_format"],
+            "Provenance is only written in CSV, TSV, JSON or Parquet output",
+            "drop `provenance`, or write CSV, TSV, JSON or Parquet output",
+        );
+        check(
+            self.output_format == OutputFormat::Parquet && cfg!(not(feature = "parquet")),
+            &["output_format"],
+            "Parquet output needs the `parquet` feature of ed_join",
+            "build ed_join with the `parquet` feature, or write another `output_format`",
+        );
+        check(
+            (self.normalization.unicode_form.is_some() || self.normalization.strip_diacritics)
+                && cfg!(not(feature = "unicode")),
+            &["normalization"],
+            "Unicode normalization forms and stripping diacritics need the `unicode` feature of ed_join",
+            "build ed_join with the `unicode` feature, or drop `unicode_form` and `strip_diacritics`",
+        );
+        check(
+            self.output_sink.is_some() && (self.resume || self.cache_dir.is_some()),
+            &["output", "resume", "cache_dir"],
+            "An output sink cannot be resumed or cached, which both need the output to be a file of its own",
+            "write the output to a file with `output_path`, or drop `resume` and `cache_dir`",
+        );
+        check(
+            !self.plugins.is_empty() && self.cache_dir.is_some(),
+            &["plugins", "cache_dir"],
+            "A join with plugins cannot be cached, since the cache cannot tell when a plugin changed",
+            "drop `cache_dir`, or apply the plugins to the cached output afterwards",
+        );
+        check(
+            self.cache_dir.is_some()
+                && (self.tokenizer.cache_key().is_none()
+                    || self
+                        .scorer
+                        .as_ref()
+                        .is_some_and(|scorer| scorer.cache_key().is_none())),
+            &["tokenizer", "scorer", "cache_dir"],
+            "A join with a tokenizer or scorer of your own cannot be cached, since the cache cannot tell when it changed",
+            "drop `cache_dir`, or give the tokenizer and scorer a `cache_key`",
+        );
+        check(
+            self.record_timeout.is_some() && self.cache_dir.is_some(),
+            &["record_timeout", "cache_dir"],
+            "A join with a time budget per record cannot be cached, since the candidates it skips vary from run to run",
+            "drop `cache_dir`, or `record_timeout`",
+        );
+        check(
+            self.resume && !streaming,
+            &["resume", "output_format", "assignment", "clustering"],
+            "Resuming requires CSV output, without assignment or clustering",
+            "drop `resume`, or write plain CSV output",
+        );
+        check(
+            self.resume && (self.emit_candidates.is_some() || self.diagnostics.is_some()),
+            &["resume", "emit_candidates", "diagnostics"],
+            "Candidates and diagnostics cannot be emitted when resuming, as those of records done before are not known",
+            "drop `resume`, or `emit_candidates` and `diagnostics`",
+        );
+        check(
+            self.reverse_complement && (self.warm_dir.is_some() || self.candidates_from.is_some()),
+            &["reverse_complement", "warm_dir", "candidates_from"],
+            "Reverse complements cannot be compared with warm starts or persisted candidates, which are of one strand",
+            "drop `warm_dir` and `candidates_from`, or `reverse_complement`",
+        );
+        check(
+            self.progressive && (!streaming || self.resume),
+            &[
+                "progressive",
+                "output_format",
+                "assignment",
+                "clustering",
+                "resume",
+            ],
+            "Progressive results require CSV output, without assignment, clustering, or resuming",
+            "drop `progressive`, or write plain CSV output without `resume`",
+        );
+        check(
+            self.checkpoint_interval.is_some() && (!streaming || self.progressive || self.output_sink.is_some()),
+            &["checkpoint_interval", "output_format", "assignment", "clustering", "progressive", "output"],
+            "Checkpoints require CSV output to a file, without assignment, clustering, or progressive results",
+            "drop `checkpoint_interval`, or write plain CSV output to a file without `progressive`",
+        );
+        if let Some(windowing) = &self.windowing {
+            check(
+                windowing.size < self.q || windowing.step == 0 || windowing.step > windowing.size,
+                &["windowing", "q"],
+                &format!(
+                    "Not a valid window of size {} and step {}, the size must be at least q and the step at most the size",
+                    windowing.size, windowing.step
+                ),
+                "make the windows at least `q` long, and step by at most their size",
+            );
+            check(
+                !(0.0..=1.0).contains(&windowing.min_coverage),
+                &["windowing"],
+                &format!("Not a valid coverage in [0, 1]: {}", windowing.min_coverage),
+                "set the minimum coverage of windows to a fraction of the record, e.g. 0.5",
+            );
+            check(
+                self.output_format != OutputFormat::Csv
+                    || !self.is_plain()
+                    || self.delta.is_some()
+                    || self.emit_content
+                    || !self.plugins.is_empty()
+                    || self.tau_ratio.is_some()
+                    || !self.tombstones.is_empty()
+                    || self.metric != DistanceMetric::Levenshtein,
+                &["windowing"],
+                "Windowed joins only write CSV output of coverages, without any other mode of joining, persisted phases, \
+                 content, plugins, ratios of the threshold, tombstones or metrics other than Levenshtein",
+                "drop `windowing`, or the other options",
+            );
+        }
+        if self.delta.is_some() {
+            check(
+                self.output_format != OutputFormat::Csv
+                    || !self.is_plain()
+                    || self.scorer.is_some()
+                    || self.tau_ratio.is_some()
+                    || !self.tombstones.is_empty()
+                    || self.metric != DistanceMetric::Levenshtein,
+                &["delta"],
+                "Delta joins only write plain CSV output, without any other mode of joining, persisted phases, \
+                 ratios of the threshold, tombstones or metrics other than Levenshtein",
+                "drop `delta`, or the other options",
+            );
+        }
+
+        violations
+    }
+
+    /// Whether the join is a plain join of records, without any other mode of joining or persisted phases,
+    /// which joins of windows and deltas, and joins returning their matches, don't support.
+    pub(crate) fn is_plain(&self) -> bool {
+        self.clustering.is_none()
+            && self.knn.is_none()
+            && self.assignment.is_none()
+            && !self.resume
+            && self.checkpoint_interval.is_none()
+            && self.stop_after.is_none()
+            && self.index_from.is_none()
+            && self.candidates_from.is_none()
+            && self.emit_candidates.is_none()
+            && self.diagnostics.is_none()
+            && self.debug_sample.is_none()
+            && self.limits == Limits::default()
+            && self.recall.is_none()
+            && !self.progressive
+            && self.warm_dir.is_none()
+            && self.external_index.is_none()
+            && !self.reverse_complement
+    }
+}
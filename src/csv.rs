@@ -0,0 +1,314 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use crate::config::ErrorPolicy;
+use crate::errors::*;
+use crate::qgram::ID;
+use crate::report::{Side, Warning};
+
+/// Passthrough fields of a row of a CSV input, see [`CsvInput::passthrough`].
+pub type Fields = Vec<String>;
+
+/// A column of a CSV input, by its name in the header or its 0-based index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CsvColumn {
+    Name(String),
+    Index(usize),
+}
+
+impl CsvColumn {
+    /// Parse a column, which is an index if it's a number, and a name otherwise.
+    pub fn parse(column: &str) -> Self {
+        match column.parse::<usize>() {
+            Ok(index) => CsvColumn::Index(index),
+            Err(_) => CsvColumn::Name(column.to_string()),
+        }
+    }
+
+    /// Index of the column, looked up in the header if it's a name.
+    fn resolve(&self, header: Option<&[String]>, path: &Path) -> Result<usize> {
+        match (self, header) {
+            (CsvColumn::Index(index), _) => Ok(*index),
+            (CsvColumn::Name(name), Some(header)) => {
+                match header.iter().position(|field| field == name) {
+                    Some(index) => Ok(index),
+                    None => bail!("{} has no column `{}`", path.display(), name),
+                }
+            }
+            (CsvColumn::Name(name), None) => bail!(
+                "Column `{}` is a name, but {} has no header",
+                name,
+                path.display()
+            ),
+        }
+    }
+}
+
+/// How records are parsed from a CSV or TSV input, whose rows are the records, see [`read_csv`].
+///
+/// ```
+/// use ed_join::csv::{CsvColumn, CsvInput};
+///
+/// let input = CsvInput::new(CsvColumn::parse("name")).passthrough(vec![CsvColumn::parse("0")]);
+/// assert_eq!(input.passthrough, [CsvColumn::Index(0)]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CsvInput {
+    /// Field delimiter, `,` by default.
+    pub delimiter: u8,
+    /// Whether the first row is a header, which is not a record. It's `true` by default.
+    pub header: bool,
+    /// The column which records are joined on.
+    pub column: CsvColumn,
+    /// Columns which are echoed after each match of CSV output, those of `doc_x` first, see [`read_passthrough`].
+    /// Both inputs must have them.
+    pub passthrough: Vec<CsvColumn>,
+}
+
+impl CsvInput {
+    pub fn new(column: CsvColumn) -> Self {
+        Self {
+            delimiter: b',',
+            header: true,
+            column,
+            passthrough: Vec::new(),
+        }
+    }
+
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    pub fn passthrough(mut self, passthrough: Vec<CsvColumn>) -> Self {
+        self.passthrough = passthrough;
+        self
+    }
+}
+
+/// A reader of RFC 4180 rows, whose quoted fields may hold delimiters, doubled quotes and line breaks.
+struct CsvReader<R: BufRead> {
+    reader: R,
+    delimiter: u8,
+    /// Names of the columns, if the input has a header.
+    header: Option<Vec<String>>,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    /// The fields of the next row, as bytes, or `None` at the end of the input.
+    fn next_row(&mut self) -> Option<io::Result<Vec<Vec<u8>>>> {
+        let mut line: Vec<u8> = Vec::new();
+        let mut fields: Vec<Vec<u8>> = Vec::new();
+        let mut field: Vec<u8> = Vec::new();
+        let mut quoted: bool = false;
+        loop {
+            line.clear();
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) if fields.is_empty() && field.is_empty() && !quoted => return None,
+                // an unterminated quote ends with the input
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(e) => return Some(Err(e)),
+            }
+            let mut bytes = line.iter().copied().peekable();
+            while let Some(byte) = bytes.next() {
+                match (quoted, byte) {
+                    (true, b'"') if bytes.peek() == Some(&b'"') => {
+                        field.push(b'"');
+                        bytes.next();
+                    }
+                    (true, b'"') => quoted = false,
+                    (true, byte) => field.push(byte),
+                    (false, b'"') if field.is_empty() => quoted = true,
+                    (false, byte) if byte == self.delimiter => {
+                        fields.push(std::mem::take(&mut field))
+                    }
+                    (false, b'\n') => (),
+                    // same as `BufRead::lines()`, strip the carriage return of CRLF line endings
+                    (false, b'\r') if bytes.peek().map_or(true, |next| *next == b'\n') => (),
+                    (false, byte) => field.push(byte),
+                }
+            }
+            if !quoted {
+                break;
+            }
+        }
+        fields.push(field);
+        Some(Ok(fields))
+    }
+}
+
+/// Open a CSV input, and read its header, if it has one.
+fn open(path: &PathBuf, input: &CsvInput) -> Result<CsvReader<BufReader<File>>> {
+    let mut reader: CsvReader<BufReader<File>> = CsvReader {
+        reader: BufReader::new(File::open(path)?),
+        delimiter: input.delimiter,
+        header: None,
+    };
+    if input.header {
+        let header: Vec<String> = match reader.next_row() {
+            Some(row) => row?
+                .iter()
+                .map(|field| String::from_utf8_lossy(field).into_owned())
+                .collect(),
+            None => Vec::new(),
+        };
+        reader.header = Some(header);
+    }
+    Ok(reader)
+}
+
+/// Read all records of a CSV or TSV file, as the join column of its rows.
+///
+/// A row's ID is its index among the rows after the header. Rows without the join column, and fields that are not valid
+/// UTF-8, are kept as empty records, so that IDs are preserved, and a [`Warning::MissingField`] or
+/// [`Warning::NonUtf8Record`] is emitted for each of them.
+///
+/// # Args
+///
+/// * `path`: Path, absolute or relative, to the file to be read.
+/// * `side`: Which side of the join this file is on, used for reporting.
+/// * `policy`: How read errors and malformed rows are handled.
+/// * `input`: The delimiter, header and join column of the file.
+///
+/// # Returns
+///
+/// * When succesful, returns all the records and the warnings encountered while reading.
+pub fn read_csv(
+    path: &PathBuf,
+    side: Side,
+    policy: ErrorPolicy,
+    input: &CsvInput,
+) -> Result<(Vec<String>, Vec<Warning>)> {
+    let mut reader: CsvReader<BufReader<File>> = open(path, input)?;
+    let column: usize = input.column.resolve(reader.header.as_deref(), path)?;
+    let mut records: Vec<String> = Vec::new();
+    let mut warnings: Vec<Warning> = Vec::new();
+
+    while let Some(row) = reader.next_row() {
+        let id: ID = records.len();
+        let mut row: Vec<Vec<u8>> = match row {
+            Ok(row) => row,
+            Err(e) if policy == ErrorPolicy::Abort => return Err(e.into()),
+            Err(e) => {
+                // the reader cannot be resynchronised with row boundaries after an error
+                warnings.push(Warning::ReadError {
+                    side,
+                    id,
+                    msg: e.to_string(),
+                });
+                break;
+            }
+        };
+        if column >= row.len() {
+            if policy == ErrorPolicy::Abort {
+                bail!(
+                    "Row {} of {} has {} fields, but no column {}",
+                    id,
+                    path.display(),
+                    row.len(),
+                    column
+                );
+            }
+            warnings.push(Warning::MissingField {
+                side,
+                id,
+                fields: row.len(),
+            });
+            records.push(String::new());
+            continue;
+        }
+        match String::from_utf8(row.swap_remove(column)) {
+            Ok(record) => records.push(record),
+            Err(_) if policy == ErrorPolicy::Abort => bail!(ErrorKind::NonUtf8Record(side, id)),
+            Err(_) => {
+                warnings.push(Warning::NonUtf8Record { side, id });
+                records.push(String::new());
+            }
+        }
+    }
+
+    Ok((records, warnings))
+}
+
+/// Read the passthrough columns of all rows of a CSV or TSV file, in the same order as [`read_csv`] reads its records.
+///
+/// Fields are decoded lossily, and those missing from a row are empty. Reading stops at the first read error,
+/// as [`read_csv`] does, so that the rows after it have no fields.
+pub fn read_passthrough(path: &PathBuf, input: &CsvInput) -> Result<Vec<Fields>> {
+    let mut reader: CsvReader<BufReader<File>> = open(path, input)?;
+    let columns: Vec<usize> = input
+        .passthrough
+        .iter()
+        .map(|column| column.resolve(reader.header.as_deref(), path))
+        .collect::<Result<Vec<usize>>>()?;
+    let mut rows: Vec<Fields> = Vec::new();
+    while let Some(Ok(row)) = reader.next_row() {
+        rows.push(
+            columns
+                .iter()
+                .map(|column| {
+                    row.get(*column).map_or_else(String::new, |field| {
+                        String::from_utf8_lossy(field).into_owned()
+                    })
+                })
+                .collect(),
+        );
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn csv() {
+        let dir: TempDir = TempDir::new("csv");
+        let path: PathBuf = dir.join("records.csv");
+        std::fs::write(
+            &path,
+            "id,name,city\r\n1,\"Smith, John\",London\r\n2,\"say \"\"hi\"\"\nthere\",Paris\n3\n4,,\"Rome\"\n",
+        )
+        .unwrap();
+
+        let input: CsvInput = CsvInput::new(CsvColumn::parse("name"))
+            .passthrough(vec![CsvColumn::parse("0"), CsvColumn::parse("city")]);
+        let (records, warnings) =
+            read_csv(&path, Side::X, ErrorPolicy::SkipRecord, &input).unwrap();
+        assert_eq!(records, ["Smith, John", "say \"hi\"\nthere", "", ""]);
+        assert_eq!(
+            warnings,
+            [Warning::MissingField {
+                side: Side::X,
+                id: 2,
+                fields: 1
+            }]
+        );
+        assert!(read_csv(&path, Side::X, ErrorPolicy::Abort, &input).is_err());
+        let rows: Vec<Fields> = read_passthrough(&path, &input).unwrap();
+        assert_eq!(
+            rows,
+            [["1", "London"], ["2", "Paris"], ["3", ""], ["4", "Rome"]]
+        );
+
+        let input: CsvInput = CsvInput::new(CsvColumn::Index(2)).header(false);
+        assert_eq!(
+            read_csv(&path, Side::X, ErrorPolicy::SkipRecord, &input)
+                .unwrap()
+                .0[..3],
+            ["city", "London", "Paris"]
+        );
+        let input: CsvInput = CsvInput::new(CsvColumn::parse("nothing"));
+        assert!(read_csv(&path, Side::X, ErrorPolicy::SkipRecord, &input).is_err());
+    }
+}
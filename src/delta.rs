@@ -0,0 +1,172 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::config::EdJoinConfig;
+use crate::errors::*;
+use crate::matching::join_records;
+use crate::output::{write_matches, Matches, OutputFormat};
+use crate::qgram::{read_input, ID};
+use crate::report::{JoinReport, Side};
+
+/// A previous run of the same join, which a delta join reuses the matches of.
+///
+/// The previous run must have joined `previous_x` with the same `doc_y`, `q` and `tau`, and written CSV output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Delta {
+    /// The previous version of `doc_x`.
+    pub previous_x: PathBuf,
+    /// The output of the previous run.
+    pub previous_output: PathBuf,
+}
+
+/// Read the matches of a previous run from its CSV output, keyed by line number of `doc_x`.
+fn read_previous_output(path: &Path, delimiter: u8) -> Result<HashMap<ID, Vec<(ID, usize)>>> {
+    let mut pairs: HashMap<ID, Vec<(ID, usize)>> = HashMap::new();
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line: String = line?;
+        let mut fields = line.split(delimiter as char).map(str::parse::<usize>);
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(Ok(x_id)), Some(Ok(y_id)), Some(Ok(ed))) => {
+                pairs.entry(x_id).or_default().push((y_id, ed))
+            }
+            _ => bail!(
+                "Invalid line {} of previous output {}",
+                i + 1,
+                path.display()
+            ),
+        }
+    }
+    Ok(pairs)
+}
+
+/// Join only the records of `doc_x` that were added or changed since a previous run, and merge them with its matches.
+///
+/// A record is unchanged if the previous `doc_x` has a record of the same content anywhere, whose matches are then reused
+/// under its new line number. All other records are joined with `doc_y`, so the cost is proportional to the delta,
+/// while the output is the same as that of a full join. This is the body of [`crate::matching::ed_join_with_config`]
+/// when [`EdJoinConfig::delta`] is set.
+pub fn delta_join(
+    doc_x: &PathBuf,
+    doc_y: &PathBuf,
+    out_name: &Path,
+    config: &EdJoinConfig,
+    delta: &Delta,
+) -> Result<JoinReport> {
+    if doc_x == doc_y {
+        bail!("A delta join needs a reference document other than doc_x");
+    }
+    config.validate()?;
+    let (q, tau) = (config.q, config.tau);
+    let mut report: JoinReport = JoinReport::default();
+
+    let (x_vec, mut warnings_x) = read_input(doc_x, Side::X, config)?;
+    report.warnings.append(&mut warnings_x);
+    let (y_vec, mut warnings_y) = read_input(doc_y, Side::Y, config)?;
+    report.warnings.append(&mut warnings_y);
+    // warnings about the previous version were already reported by the previous run
+    let (previous_x, _) = read_input(&delta.previous_x, Side::X, config)?;
+    let mut previous_ids: HashMap<&str, ID> = HashMap::new();
+    for (id, record) in previous_x.iter().enumerate() {
+        previous_ids.entry(record.as_str()).or_insert(id);
+    }
+    let previous_pairs: HashMap<ID, Vec<(ID, usize)>> =
+        read_previous_output(&delta.previous_output, config.delimiter)?;
+
+    let mut merged: BTreeMap<ID, Vec<(ID, usize)>> = BTreeMap::new();
+    let mut changed: Vec<ID> = Vec::new();
+    for (x_id, record) in x_vec.iter().enumerate() {
+        match previous_ids.get(record.as_str()) {
+            Some(previous_id) => {
+                if let Some(pairs) = previous_pairs.get(previous_id) {
+                    merged.insert(x_id, pairs.clone());
+                }
+            }
+            None => changed.push(x_id),
+        }
+    }
+    #[cfg(feature = "logging")]
+    info!(
+        "Reused the matches of {} unchanged records, and joining {} added or changed records",
+        x_vec.len() - changed.len(),
+        changed.len()
+    );
+
+    let probes: Vec<String> = changed.iter().map(|x_id| x_vec[*x_id].clone()).collect();
+    for (probe, y_id, ed) in join_records(Some(&probes), &y_vec, q, tau, &*config.tokenizer) {
+        merged.entry(changed[probe]).or_default().push((y_id, ed));
+    }
+    let pairs: Vec<(ID, Vec<(ID, usize)>)> = merged
+        .into_iter()
+        .map(|(x_id, mut pairs)| {
+            pairs.sort_unstable();
+            (x_id, pairs)
+        })
+        .collect();
+
+    let matches: Matches = Matches {
+        pairs: &pairs,
+        x_records: &x_vec,
+        y_records: &y_vec,
+        self_join: false,
+        confidence: None,
+        passthrough: None,
+        provenance: None,
+        emit_content: config.emit_content,
+        text_mask: config.text_mask.as_ref(),
+        plugins: &config.plugins,
+    };
+    let mut writer: BufWriter<Box<dyn Write + Send>> =
+        BufWriter::new(config.open_output(out_name)?);
+    write_matches(&mut writer, &matches, &OutputFormat::Csv, config.delimiter)?;
+    writer.flush()?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn delta() {
+        let dir: TempDir = TempDir::new("delta");
+        let (previous_x, previous_output, doc_x, doc_y, out_name) = (
+            dir.join("previous_x"),
+            dir.join("previous_output"),
+            dir.join("x"),
+            dir.join("y"),
+            dir.join("out"),
+        );
+        std::fs::write(&previous_x, "hello\nworld\n").unwrap();
+        std::fs::write(&previous_output, "0,0,1\n1,1,1\n").unwrap();
+        std::fs::write(&doc_x, "spam\nworld\nhullo\n").unwrap();
+        std::fs::write(&doc_y, "hallo\nwords\nspan\n").unwrap();
+
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1).delta(&previous_x, &previous_output);
+        delta_join(
+            &doc_x,
+            &doc_y,
+            &out_name,
+            &config,
+            config.delta.as_ref().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out_name).unwrap(),
+            "0,2,1\n1,1,1\n2,0,1\n"
+        );
+        assert!(delta_join(
+            &doc_x,
+            &doc_x,
+            &out_name,
+            &config,
+            config.delta.as_ref().unwrap()
+        )
+        .is_err());
+    }
+}
@@ -1,3 +1,6 @@
+use crate::config::Violation;
+use crate::report::Side;
+
 error_chain! {
 
     foreign_links {
@@ -27,10 +30,56 @@ error_chain! {
             display("q cannot excess the length of records: 'q = {} > {}'", q, min_line_len)
         }
 
+        EmptyReference(f: String) {
+            description("empty reference file"),
+            display("reference file has no records to match against: 'filepath = {}'", f)
+        }
+
         TauTooSmall(t: usize) {
             description("tau is to small"),
             display("tau should be an integer, and tau >= 1: 'tau = {}'", t)
         }
 
+        NonUtf8Record(side: Side, id: usize) {
+            description("record is not valid UTF-8"),
+            display("record is not valid UTF-8: 'doc_{} line {}'", side, id)
+        }
+
+        InvalidAlphabet(msg: String) {
+            description("invalid alphabet"),
+            display("invalid alphabet: {}", msg)
+        }
+
+        OutOfAlphabet(side: Side, id: usize, c: char) {
+            description("record is out of the alphabet"),
+            display("record has {:?}, which is out of the alphabet: 'doc_{} line {}'", c, side, id)
+        }
+
+        InvalidTemplate(msg: String) {
+            description("invalid output template"),
+            display("invalid output template: {}", msg)
+        }
+
+        InvariantViolation(msg: String) {
+            description("internal invariant violated"),
+            display("internal invariant violated: {}", msg)
+        }
+
+        LimitExceeded(phase: String, limit: String, value: usize, max: usize) {
+            description("limit exceeded"),
+            display("the {} phase exceeded its limit: '{} = {} > {}'", phase, limit, value, max)
+        }
+
+        Cancelled {
+            description("join cancelled"),
+            display("the join was cancelled")
+        }
+
+        InvalidConfig(violations: Vec<Violation>) {
+            description("invalid configuration"),
+            display("invalid configuration, {} violations:{}", violations.len(),
+                violations.iter().map(|violation| format!("\n  * {}", violation)).collect::<String>())
+        }
+
     }
 }
@@ -0,0 +1,429 @@
+use rayon::prelude::*;
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap},
+    fmt::{self, Debug, Formatter},
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::errors::*;
+use crate::persist::{read_varint, write_varint};
+use crate::qgram::{
+    build_inverted_index_with, count_qgrams, IndexBackend, InvertedIndex, InvertedList, Loc, Token,
+    ID,
+};
+use crate::tokenizer::Tokenizer;
+
+/// Number of records of `doc_y` whose inverted lists are collected in memory at once, and written to a run on disk.
+pub const RECORDS_PER_RUN: usize = 1 << 20;
+
+/// Number of indexes built so far by this process, so that those built at once in the same directory are apart.
+static NEXT_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+/// What the dictionary of an [`ExternalIndex`] holds of a token.
+#[derive(Clone, Copy, Debug, Default)]
+struct Entry {
+    /// Number of occurences of the token in both documents.
+    count: usize,
+    /// Length of the inverted list of the token.
+    len: usize,
+    /// Where the inverted list starts in the file of postings, and its length in bytes.
+    offset: u64,
+    bytes: usize,
+}
+
+/// An inverted index whose inverted lists are on disk, with only a dictionary of the tokens in memory,
+/// for inputs whose index is larger than memory, see [`crate::config::EdJoinConfig::external_index`].
+///
+/// It's built in runs of [`RECORDS_PER_RUN`] records of `doc_y`, whose inverted lists are sorted by token and written
+/// to a file each, and then merged into a single file of postings. Inverted lists are read from that file when they're
+/// probed, so the memory of a join is bounded by the dictionary, a run, and the lists being probed. The records
+/// themselves are still held in memory.
+///
+/// The file of postings is in a directory of the caller's choosing, and removed when the index is dropped.
+pub struct ExternalIndex {
+    dictionary: HashMap<Token, Entry>,
+    total_count: usize,
+    path: PathBuf,
+    /// The file of postings, which is only `None` while it's being dropped.
+    file: Option<File>,
+}
+
+/// Files that are removed when they're dropped, e.g. on an error halfway through building an index.
+struct TempFiles(Vec<PathBuf>);
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// A run of inverted lists being merged, and the token and length of its next list, if any.
+struct Run {
+    reader: BufReader<File>,
+    head: Option<(Token, usize, u64)>,
+}
+
+impl Run {
+    /// Read the token, length, and length in bytes of the next inverted list, as written by `write_run()`.
+    fn advance(&mut self) -> io::Result<()> {
+        self.head = match read_stream_varint(&mut self.reader)? {
+            None => None,
+            Some(token_len) => {
+                let mut token: Vec<u8> = vec![0; token_len as usize];
+                self.reader.read_exact(&mut token)?;
+                let token: Token = String::from_utf8(token)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let len: u64 =
+                    read_stream_varint(&mut self.reader)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+                let bytes: u64 =
+                    read_stream_varint(&mut self.reader)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+                Some((token, len as usize, bytes))
+            }
+        };
+        Ok(())
+    }
+}
+
+impl ExternalIndex {
+    /// Build the index of `records_y` in `dir`, which is created if it doesn't exist, with the occurences of the grams
+    /// of `records_x` counted too, unless it's `None` for a self-join, the same as
+    /// [`crate::qgram::build_inverted_index_with`].
+    pub fn build(
+        dir: &Path,
+        records_x: Option<&[String]>,
+        records_y: &[String],
+        q: usize,
+        tokenizer: &dyn Tokenizer,
+    ) -> Result<Self> {
+        Self::build_in_runs(dir, records_x, records_y, q, tokenizer, RECORDS_PER_RUN)
+    }
+
+    /// Same as [`Self::build`], but with `run_len` records of `records_y` per run.
+    pub fn build_in_runs(
+        dir: &Path,
+        records_x: Option<&[String]>,
+        records_y: &[String],
+        q: usize,
+        tokenizer: &dyn Tokenizer,
+        run_len: usize,
+    ) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let stem: String = format!(
+            "ed_join_index_{}_{}",
+            std::process::id(),
+            NEXT_INDEX.fetch_add(1, Ordering::Relaxed)
+        );
+        let run_len: usize = run_len.max(1);
+
+        let mut dictionary: HashMap<Token, Entry> = HashMap::new();
+        if let Some(records_x) = records_x {
+            for (token, count) in count_qgrams(records_x, q, tokenizer) {
+                dictionary.entry(token).or_default().count += count;
+            }
+        }
+        let mut runs: TempFiles = TempFiles(Vec::new());
+        for (run, records) in records_y.chunks(run_len).enumerate() {
+            let run_path: PathBuf = dir.join(format!("{}.run{}", stem, run));
+            runs.0.push(run_path.clone());
+            let index: InvertedIndex = build_inverted_index_with(None, records, q, tokenizer);
+            write_run(&run_path, index, run * run_len, &mut dictionary)?;
+        }
+
+        // the lists of a token are appended in the order of their runs, all of whose IDs are less than those of the next
+        let path: PathBuf = dir.join(format!("{}.postings", stem));
+        let mut postings: TempFiles = TempFiles(vec![path.clone()]);
+        let mut readers: Vec<Run> = Vec::with_capacity(runs.0.len());
+        let mut heap: BinaryHeap<Reverse<(Token, usize)>> = BinaryHeap::new();
+        for (i, run_path) in runs.0.iter().enumerate() {
+            let mut run: Run = Run {
+                reader: BufReader::new(File::open(run_path)?),
+                head: None,
+            };
+            run.advance()?;
+            if let Some((token, _, _)) = &run.head {
+                heap.push(Reverse((token.clone(), i)));
+            }
+            readers.push(run);
+        }
+        let mut writer: BufWriter<File> = BufWriter::new(File::create(&path)?);
+        let mut offset: u64 = 0;
+        // ties are broken by the number of the run, so the runs of a token are popped in order
+        while let Some(Reverse((token, i))) = heap.pop() {
+            let entry: &mut Entry = dictionary
+                .get_mut(&token)
+                .expect("a token of a run is in the dictionary");
+            entry.offset = offset;
+            let mut next: Option<usize> = Some(i);
+            while let Some(i) = next {
+                let run: &mut Run = &mut readers[i];
+                let (_, len, bytes) = run.head.take().unwrap();
+                let copied: u64 = io::copy(&mut (&mut run.reader).take(bytes), &mut writer)?;
+                if copied != bytes {
+                    bail!("The run {} of the index ended early", runs.0[i].display());
+                }
+                offset += bytes;
+                entry.len += len;
+                run.advance()?;
+                if let Some((next_token, _, _)) = &run.head {
+                    heap.push(Reverse((next_token.clone(), i)));
+                }
+                next = match heap.peek() {
+                    Some(Reverse((next_token, _))) if *next_token == token => {
+                        heap.pop().map(|Reverse((_, i))| i)
+                    }
+                    _ => None,
+                };
+            }
+            entry.bytes = (offset - entry.offset) as usize;
+        }
+        writer.flush()?;
+        drop(writer);
+        drop(readers);
+        drop(runs);
+
+        let file: File = File::open(&path)?;
+        // the index removes its file of postings from now on
+        postings.0.clear();
+        Ok(Self {
+            total_count: dictionary.values().map(|entry| entry.count).sum(),
+            dictionary,
+            path,
+            file: Some(file),
+        })
+    }
+
+    /// Number of distinct tokens.
+    pub fn len(&self) -> usize {
+        self.dictionary.len()
+    }
+
+    /// Whether there's no token at all.
+    pub fn is_empty(&self) -> bool {
+        self.dictionary.is_empty()
+    }
+}
+
+/// The dictionary is listed in the order of its tokens, so that it's the same across runs.
+impl Debug for ExternalIndex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let dictionary: BTreeMap<&Token, &Entry> = self.dictionary.iter().collect();
+        f.debug_struct("ExternalIndex")
+            .field("dictionary", &dictionary)
+            .field("total_count", &self.total_count)
+            .field("path", &self.path)
+            .field("file", &self.file)
+            .finish()
+    }
+}
+
+impl IndexBackend for ExternalIndex {
+    fn count(&self, token: &str) -> Option<usize> {
+        self.dictionary.get(token).map(|entry| entry.count)
+    }
+
+    /// The inverted list of a token, as read from the file of postings.
+    ///
+    /// # Panics
+    ///
+    /// If the file cannot be read, or it's corrupted, which is handled as a violated invariant of the join.
+    fn postings(&self, token: &str) -> Option<Cow<'_, [(ID, Loc)]>> {
+        let entry: &Entry = self.dictionary.get(token)?;
+        if entry.len == 0 {
+            return Some(Cow::Borrowed(&[]));
+        }
+        let mut bytes: Vec<u8> = vec![0; entry.bytes];
+        if let Err(e) = read_exact_at(self.file.as_ref().unwrap(), &mut bytes, entry.offset) {
+            panic!(
+                "Cannot read the postings of `{}` from {}: {}",
+                token,
+                self.path.display(),
+                e
+            );
+        }
+        let mut iter = bytes.iter();
+        let inverted_list: Option<InvertedList> = (0..entry.len)
+            .map(|_| {
+                Some((
+                    read_varint(&mut iter)? as ID,
+                    read_varint(&mut iter)? as Loc,
+                ))
+            })
+            .collect();
+        match inverted_list {
+            Some(inverted_list) => Some(Cow::Owned(inverted_list)),
+            None => panic!(
+                "The postings of `{}` in {} are corrupted",
+                token,
+                self.path.display()
+            ),
+        }
+    }
+
+    fn total_count(&self) -> usize {
+        self.total_count
+    }
+}
+
+impl Drop for ExternalIndex {
+    fn drop(&mut self) {
+        // the file is closed first, as an open file cannot be removed on every platform
+        drop(self.file.take());
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Write the inverted lists of a run, in the order of their tokens, with IDs offset by `first_id`,
+/// and add the occurences of their tokens to the dictionary.
+///
+/// Each list is its token's length and bytes, its own length, its length in bytes, and then the ID and location
+/// of each posting, all as varints.
+fn write_run(
+    path: &Path,
+    index: InvertedIndex,
+    first_id: ID,
+    dictionary: &mut HashMap<Token, Entry>,
+) -> Result<()> {
+    let mut lists: Vec<(Token, (InvertedList, usize))> = index.into_iter().collect();
+    lists.par_sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    let mut writer: BufWriter<File> = BufWriter::new(File::create(path)?);
+    let (mut header, mut postings): (Vec<u8>, Vec<u8>) = (Vec::new(), Vec::new());
+    for (token, (inverted_list, count)) in lists {
+        postings.clear();
+        for (id, loc) in &inverted_list {
+            write_varint(&mut postings, (first_id + id) as u64);
+            write_varint(&mut postings, *loc as u64);
+        }
+        header.clear();
+        write_varint(&mut header, token.len() as u64);
+        header.extend_from_slice(token.as_bytes());
+        write_varint(&mut header, inverted_list.len() as u64);
+        write_varint(&mut header, postings.len() as u64);
+        writer.write_all(&header)?;
+        writer.write_all(&postings)?;
+        dictionary.entry(token).or_default().count += count;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read a varint written by `write_varint()` from a run, or `None` if the run ends before it.
+fn read_stream_varint(reader: &mut impl Read) -> io::Result<Option<u64>> {
+    let mut value: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte: [u8; 1] = [0];
+        if reader.read(&mut byte)? == 0 {
+            return match shift {
+                0 => Ok(None),
+                _ => Err(io::ErrorKind::UnexpectedEof.into()),
+            };
+        }
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] < 0x80 {
+            return Ok(Some(value));
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "a varint overflows",
+    ))
+}
+
+/// Read exactly `buffer.len()` bytes of a file from `offset`, without moving its cursor, so that threads can share it.
+#[cfg(unix)]
+fn read_exact_at(file: &File, buffer: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buffer, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buffer: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buffer.is_empty() {
+        match file.seek_read(buffer, offset)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            read => {
+                buffer = &mut buffer[read..];
+                offset += read as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn read_exact_at(_file: &File, _buffer: &mut [u8], _offset: u64) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "indexes on disk are only read on Unix and Windows",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EdJoinConfig;
+    use crate::matching::ed_join_with_config;
+    use crate::scoring::DefaultScorer;
+    use crate::sink::MemorySink;
+    use crate::testutil::TempDir;
+    use crate::tokenizer::QGrams;
+
+    #[test]
+    fn external_index() {
+        let records_x: Vec<String> = ["zebra", "hello"].iter().map(|s| s.to_string()).collect();
+        let records_y: Vec<String> = ["hello", "hallo", "", "jello", "yellow", "hell", "o"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let temp: TempDir = TempDir::new("external");
+        // the index is built in a directory of its own, which only holds the runs and postings
+        let dir: PathBuf = temp.join("index");
+        let in_memory: InvertedIndex =
+            build_inverted_index_with(Some(&records_x), &records_y, 2, &QGrams);
+        for run_len in [1, 3, 100] {
+            let external: ExternalIndex = ExternalIndex::build_in_runs(
+                &dir,
+                Some(&records_x),
+                &records_y,
+                2,
+                &QGrams,
+                run_len,
+            )
+            .unwrap();
+            assert_eq!(external.len(), in_memory.len());
+            assert_eq!(external.total_count(), in_memory.total_count());
+            for (token, (inverted_list, count)) in &in_memory {
+                assert_eq!(external.count(token), Some(*count));
+                assert_eq!(external.postings(token).unwrap(), inverted_list.as_slice());
+            }
+            assert!(external.postings("qq").is_none());
+        }
+        // the runs are removed once merged, and the postings once the index is dropped
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        // a join of files matches the same pairs with either index, and scores them the same
+        let doc: PathBuf = temp.join("records.txt");
+        fs::write(&doc, records_y.join("\n")).unwrap();
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1).scorer(DefaultScorer);
+        let outputs: Vec<String> = [config.clone(), config.clone().external_index(&dir)]
+            .iter()
+            .map(|config| {
+                let sink: MemorySink = MemorySink::default();
+                ed_join_with_config(&doc, &doc, &config.clone().output(sink.clone())).unwrap();
+                String::from_utf8(sink.contents()).unwrap()
+            })
+            .collect();
+        assert_eq!(outputs[0], outputs[1]);
+        assert!(outputs[0].starts_with("0,1,1,"));
+        assert!(
+            ed_join_with_config(&doc, &doc, &config.external_index(&dir).approximate(0.9)).is_err()
+        );
+    }
+}
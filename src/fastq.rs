@@ -0,0 +1,189 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+};
+
+use crate::config::{ErrorPolicy, QualityFilter};
+use crate::errors::*;
+use crate::qgram::ID;
+use crate::report::{Side, Warning};
+
+/// Offset of the ASCII encoding of Phred quality scores, i.e. Phred+33 as used by Sanger and Illumina 1.8+.
+const PHRED_OFFSET: u8 = 33;
+
+/// Read all reads of a FASTQ file, as records of their sequences.
+///
+/// Each read is four lines, its `@` header, sequence, `+` separator and quality, and its ID is its index among the reads.
+/// Sequences are trimmed and filtered by `filter` first. Reads that are filtered out are kept as empty records,
+/// so that IDs are preserved, and a [`Warning::LowQualityRead`] is emitted for each of them,
+/// as a [`Warning::NonUtf8Record`] is for a read that is not valid UTF-8.
+///
+/// # Args
+///
+/// * `path`: Path, absolute or relative, to the FASTQ file to be read.
+/// * `side`: Which side of the join this file is on, used for reporting.
+/// * `policy`: How read errors and malformed reads are handled.
+///   A malformed read desynchronises the reader from read boundaries, so the rest of the file is skipped.
+/// * `filter`: How reads are trimmed and filtered by their quality.
+///
+/// # Returns
+///
+/// * When succesful, returns all the records and the warnings encountered while reading.
+pub fn read_fastq(
+    path: &PathBuf,
+    side: Side,
+    policy: ErrorPolicy,
+    filter: &QualityFilter,
+) -> Result<(Vec<String>, Vec<Warning>)> {
+    let mut lines = BufReader::new(File::open(path)?).split(b'\n');
+    let mut records: Vec<String> = Vec::new();
+    let mut warnings: Vec<Warning> = Vec::new();
+
+    let mut read: Vec<Vec<u8>> = Vec::with_capacity(4);
+    loop {
+        read.clear();
+        let id: ID = records.len();
+        let mut error: Option<String> = None;
+        for line in lines.by_ref().take(4) {
+            match line {
+                Ok(mut line) => {
+                    if line.last() == Some(&b'\r') {
+                        line.pop();
+                    }
+                    read.push(line);
+                }
+                Err(e) => {
+                    error = Some(e.to_string());
+                    break;
+                }
+            }
+        }
+        if error.is_none() && read.is_empty() {
+            break;
+        }
+        if error.is_none()
+            && (read.len() < 4 || !read[0].starts_with(b"@") || !read[2].starts_with(b"+"))
+        {
+            error = Some("malformed FASTQ read".to_string());
+        }
+        if error.is_none() && read[1].len() != read[3].len() {
+            error = Some("sequence and quality of different lengths".to_string());
+        }
+        if let Some(msg) = error {
+            if policy == ErrorPolicy::Abort {
+                bail!("Failed to read {} at read {}: {}", path.display(), id, msg);
+            }
+            warnings.push(Warning::ReadError { side, id, msg });
+            break;
+        }
+
+        let (sequence, quality): (&[u8], &[u8]) = filter.trim(&read[1], &read[3]);
+        if let Some(mean) = filter.rejects(quality) {
+            warnings.push(Warning::LowQualityRead { side, id, mean });
+            records.push(String::new());
+            continue;
+        }
+        match String::from_utf8(sequence.to_vec()) {
+            Ok(record) => records.push(record),
+            Err(_) if policy == ErrorPolicy::Abort => bail!(ErrorKind::NonUtf8Record(side, id)),
+            Err(_) => {
+                warnings.push(Warning::NonUtf8Record { side, id });
+                records.push(String::new());
+            }
+        }
+    }
+
+    Ok((records, warnings))
+}
+
+impl QualityFilter {
+    /// Trim the 3' tail of a read, i.e. drop bases from its end as long as their quality is below `trim_quality`.
+    fn trim<'a>(&self, sequence: &'a [u8], quality: &'a [u8]) -> (&'a [u8], &'a [u8]) {
+        let len: usize = match self.trim_quality {
+            Some(threshold) => quality
+                .iter()
+                .rposition(|score| score.saturating_sub(PHRED_OFFSET) >= threshold)
+                .map_or(0, |last| last + 1),
+            None => quality.len(),
+        };
+        (&sequence[..len], &quality[..len])
+    }
+
+    /// The mean quality of a trimmed read, rounded down, if it's below `min_mean_quality`.
+    ///
+    /// A read trimmed to nothing has no quality at all, and is rejected whenever there's a threshold.
+    fn rejects(&self, quality: &[u8]) -> Option<usize> {
+        let threshold: f64 = self.min_mean_quality?;
+        let total: usize = quality
+            .iter()
+            .map(|score| score.saturating_sub(PHRED_OFFSET) as usize)
+            .sum();
+        let mean: f64 = if quality.is_empty() {
+            0.0
+        } else {
+            total as f64 / quality.len() as f64
+        };
+        (quality.is_empty() || mean < threshold).then_some(mean as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn quality_filter() {
+        let dir: TempDir = TempDir::new("fastq");
+        let testfile: PathBuf = dir.join("reads.fastq");
+        // qualities `I` = 40, `5` = 20, `#` = 2
+        std::fs::write(
+            &testfile,
+            "@r0\nACGTAC\n+\nIIII##\n@r1\nACGT\n+r1\n5555\n@r2\nGGGG\n+\n####\n",
+        )
+        .unwrap();
+
+        let (records, warnings) = read_fastq(
+            &testfile,
+            Side::X,
+            ErrorPolicy::Abort,
+            &QualityFilter::default(),
+        )
+        .unwrap();
+        assert_eq!(records, vec!["ACGTAC", "ACGT", "GGGG"]);
+        assert!(warnings.is_empty());
+
+        let filter: QualityFilter = QualityFilter {
+            trim_quality: Some(10),
+            min_mean_quality: Some(25.0),
+        };
+        let (records, warnings) =
+            read_fastq(&testfile, Side::X, ErrorPolicy::Abort, &filter).unwrap();
+        assert_eq!(records, vec!["ACGT", "", ""]);
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::LowQualityRead {
+                    side: Side::X,
+                    id: 1,
+                    mean: 20
+                },
+                Warning::LowQualityRead {
+                    side: Side::X,
+                    id: 2,
+                    mean: 0
+                },
+            ]
+        );
+
+        // a truncated read
+        std::fs::write(&testfile, "@r0\nACGT\n+\nIIII\n@r1\nACGT\n").unwrap();
+        let (records, warnings) =
+            read_fastq(&testfile, Side::Y, ErrorPolicy::SkipRecord, &filter).unwrap();
+        assert_eq!(records, vec!["ACGT"]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind(), "read_error");
+        assert!(read_fastq(&testfile, Side::Y, ErrorPolicy::Abort, &filter).is_err());
+    }
+}
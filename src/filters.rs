@@ -0,0 +1,575 @@
+//! The filters which prune candidate pairs before their edit distance is computed, as functions of their own,
+//! so that verification pipelines can be composed of them, or single filters reused.
+//!
+//! [`crate::verification::filter_pair`] applies them in this order, each only to the pairs the last one didn't prune:
+//!
+//!  1. The count filter, [`compare_qgrams`], or the exact one, [`count_mismatch`].
+//!  2. The location-based filter, [`min_edit_errors`] of the loosely mismatching q-grams.
+//!  3. The content-based filter, [`content_filter`] with the [`sum_right_errors`] of the same q-grams.
+//!
+//! As [`Filter`]s, i.e. [`CountFiltering`], [`LocationFiltering`] and [`ContentFiltering`], they can be composed in any
+//! order, along with filters of the caller's own, by [`crate::verification::filter_pair_with`].
+//!
+//! ```
+//! use ed_join::filters::{compare_qgrams, content_filter, min_edit_errors, sum_right_errors};
+//! use ed_join::qgram::{build_inverted_index, InvertedIndex, PosQGramArray};
+//! use ed_join::tokenizer::TokenizerMode;
+//!
+//! let (q, tau): (usize, usize) = (2, 1);
+//! let records: Vec<String> = vec!["abcdef".to_string(), "abxdef".to_string()];
+//! let index: InvertedIndex = build_inverted_index(None, &records, q);
+//! let (mut x, mut y) = (PosQGramArray::from(&records[0], q), PosQGramArray::from(&records[1], q));
+//! x.sort_by_frequency(&index);
+//! y.sort_by_frequency(&index);
+//!
+//! // the substitution destroys `bc` and `cd`, which is within the `q * tau` q-grams a single edit may destroy
+//! let (mut mismatch, epsilon_1) = compare_qgrams(&x, &y, &index, tau);
+//! assert!(epsilon_1 <= q * tau);
+//! assert!(min_edit_errors(&mismatch, q) <= tau);
+//! let suffix_sum = sum_right_errors(&mut mismatch, q).unwrap();
+//! let epsilon_3 = content_filter(&records[0], &records[1], &mismatch, &suffix_sum, q, tau, TokenizerMode::Bytes);
+//! assert!(epsilon_3.map_or(true, |epsilon_3| epsilon_3 <= tau));
+//! ```
+use std::{collections::HashMap, time::Instant};
+
+use crate::intern::TokenId;
+use crate::qgram::*;
+use crate::timeline::{self, Region};
+use crate::tokenizer::TokenizerMode;
+use crate::verification::{FilterProfile, Verdict};
+
+pub use crate::matching::min_edit_errors;
+
+/// Minimum number of edit operations which destroy the q-grams of a suffix.
+pub type RightError = usize;
+/// The [`RightError`] of each suffix which starts at a location where it grows, as found by [`sum_right_errors`].
+pub type SuffixSumArray = Vec<(Loc, RightError)>;
+
+// Algorithm 8
+/// Given two q-gram arrays, sorted in the global order of the join by [`PosQGramArray::sort_by_frequency`],
+/// find the set of loosely mismatching q-grams and the number of strictly mismatching q-grams.
+///
+/// This is the count filter: a pair within edit distance `tau` has at most `q * tau` strictly mismatching q-grams.
+///
+/// # Parameters
+///
+///  * `x` and `y`: PosQGramArrays as `source` and `target` for matching.
+///  * `invert`: The inverted index, which both arrays were sorted by.
+///  * `tau`: The largest difference of the locations of q-grams which still match.
+///
+/// # Return
+///
+///  * A set of loosely mismatching q-grams from `x` to `y`, in increasing order of location.
+///  * The number of strictly mismatching q-grams from `x` to `y`.
+pub fn compare_qgrams(
+    x: &PosQGramArray,
+    y: &PosQGramArray,
+    invert: &dyn IndexBackend,
+    tau: usize,
+) -> (PosQGramArray, usize) {
+    // each gram is looked up once, as its ID if the tokens are interned, or its number of occurences and bytes
+    match invert.interner() {
+        Some(interner) => {
+            let rank_x: Vec<TokenId> = x
+                .iter()
+                .map(|qgram| interner.get(&qgram.token).unwrap())
+                .collect();
+            let rank_y: Vec<TokenId> = y
+                .iter()
+                .map(|qgram| interner.get(&qgram.token).unwrap())
+                .collect();
+            compare_ranked(x, y, &rank_x, &rank_y, tau)
+        }
+        None => {
+            let rank_x: Vec<(usize, &[u8])> = x
+                .iter()
+                .map(|qgram| (invert.count(&qgram.token).unwrap(), qgram.token.as_bytes()))
+                .collect();
+            let rank_y: Vec<(usize, &[u8])> = y
+                .iter()
+                .map(|qgram| (invert.count(&qgram.token).unwrap(), qgram.token.as_bytes()))
+                .collect();
+            compare_ranked(x, y, &rank_x, &rank_y, tau)
+        }
+    }
+}
+
+/// Same as [`compare_qgrams`], with grams compared by their ranks in the order they're sorted by, which are equal
+/// if and only if their tokens are.
+fn compare_ranked<R: Ord>(
+    x: &PosQGramArray,
+    y: &PosQGramArray,
+    rank_x: &[R],
+    rank_y: &[R],
+    tau: usize,
+) -> (PosQGramArray, usize) {
+    let mut i: usize = 0;
+    let mut j: usize = 0;
+    let mut epsilon: usize = 0;
+    let mut loose_mismatch: PosQGramArray = PosQGramArray::new();
+
+    let comparator =
+        |i: &mut usize, j: usize, epsilon: &mut usize, loose_mismatch: &mut PosQGramArray| {
+            if ((*i >= 1) && (rank_x[*i] != rank_x[*i - 1]))
+                || ((j >= 1) && (rank_x[*i] != rank_y[j - 1]))
+                || ((j >= 1) && ((x[*i].loc as isize - y[j - 1].loc as isize).abs() > tau as isize))
+            {
+                loose_mismatch.push(x[*i].clone());
+            }
+            *i += 1;
+            *epsilon += 1;
+        };
+
+    while i < x.len() && j < y.len() {
+        if rank_x[i] == rank_y[j] {
+            if (x[i].loc as isize - y[j].loc as isize).abs() <= tau as isize {
+                i += 1;
+                j += 1;
+            } else if x[i].loc < y[j].loc {
+                comparator(&mut i, j, &mut epsilon, &mut loose_mismatch);
+            } else {
+                j += 1;
+            }
+        } else if rank_x[i] < rank_y[j] {
+            comparator(&mut i, j, &mut epsilon, &mut loose_mismatch);
+        } else {
+            j += 1;
+        }
+    }
+    while i < x.len() {
+        comparator(&mut i, j, &mut epsilon, &mut loose_mismatch);
+    }
+
+    loose_mismatch.sort_by_location();
+
+    (loose_mismatch, epsilon)
+}
+
+// Based on Algorithm 2
+/// Given a set of q-grams, find the minimum number of edit operations in the suffix that destroys all q-grams.
+///
+/// # Parameters
+///
+///  * `qgram_array`: A PosQGramArray, i.e. a set of positional q-grams.
+///  * `q`: A positive integer as the tuning parameter for length of q-grams.
+///
+/// # Return
+///
+/// The minimum number of edit operations on the suffix that destroy all q-grams, from each location at which it grows,
+/// right to left, or `None` if there are no q-grams.
+pub fn sum_right_errors(qgram_array: &mut PosQGramArray, q: usize) -> Option<SuffixSumArray> {
+    if qgram_array.is_empty() {
+        None
+    } else {
+        qgram_array.reverse();
+        let mut cnt: usize = 0;
+        let mut loc: usize = qgram_array[0].loc + 1;
+
+        let mut suffix_sum: SuffixSumArray = Vec::new();
+
+        qgram_array.iter().for_each(|qgram| {
+            if qgram.loc < loc {
+                cnt += 1;
+                suffix_sum.push((qgram.loc, cnt));
+                if qgram.loc + 1 >= q {
+                    loc = qgram.loc + 1 - q;
+                } else {
+                    loc = 0;
+                }
+            }
+        });
+
+        qgram_array.reverse();
+        Some(suffix_sum)
+    }
+}
+
+/// Number of occurences of each unit.
+fn frequency_histogram<'a>(units: &[&'a [u8]]) -> HashMap<&'a [u8], usize> {
+    let mut map: HashMap<&[u8], usize> = HashMap::new();
+
+    units.iter().for_each(|unit| {
+        map.entry(*unit).and_modify(|v| *v += 1).or_insert(1);
+    });
+
+    map
+}
+
+/// Units of a probing window, as their bytes, where a window of bytes is counted by single bytes.
+///
+/// The window is clamped to the record, as it may end past a record shorter than the one whose q-grams it's of,
+/// and it may split a character of a record that isn't ASCII.
+fn window_units(record: &str, lo: usize, hi: usize, mode: TokenizerMode) -> Vec<&[u8]> {
+    match mode {
+        TokenizerMode::Bytes => {
+            let hi: usize = hi.min(record.len());
+            let lo: usize = lo.min(hi);
+            record.as_bytes()[lo..hi].chunks(1).collect()
+        }
+        _ => mode
+            .units(record, lo, hi)
+            .into_iter()
+            .map(str::as_bytes)
+            .collect(),
+    }
+}
+
+// Algorithm 6
+/// Given two strings, calculate their L1 distance.
+///
+/// # Parameters
+///
+///  * `s` and `t`: (Sub-)String that is under probing window.
+///  * `lo` and `hi`: Indicates the start and end point of the probing window, where `hi` is excluded.
+///  * `mode`: The units which the window counts, and whose histograms are compared.
+///
+/// # Return
+///
+/// L1 distance of the two given strings with given probing window, in units of `mode`,
+/// which is at most twice their edit distance within the window.
+pub fn l1_distance(s: &str, t: &str, lo: usize, hi: usize, mode: TokenizerMode) -> usize {
+    let (units_s, units_t): (Vec<&[u8]>, Vec<&[u8]>) =
+        (window_units(s, lo, hi, mode), window_units(t, lo, hi, mode));
+    let h_s: HashMap<&[u8], usize> = frequency_histogram(&units_s);
+    let h_t: HashMap<&[u8], usize> = frequency_histogram(&units_t);
+
+    let mut keys: Vec<&&[u8]> = h_s.keys().collect::<Vec<&&[u8]>>();
+    keys.append(&mut h_t.keys().collect::<Vec<&&[u8]>>());
+    keys.sort();
+    keys.dedup();
+
+    let mut v_s: Vec<usize> = Vec::with_capacity(keys.len());
+    let mut v_t: Vec<usize> = Vec::with_capacity(keys.len());
+
+    keys.iter().for_each(|k| {
+        v_s.push(*h_s.get(*k).unwrap_or(&0));
+        v_t.push(*h_t.get(*k).unwrap_or(&0));
+    });
+
+    let distance: usize = v_s
+        .iter()
+        .zip(&v_t)
+        .map(|(a, b)| (*a as isize - *b as isize).unsigned_abs())
+        .sum();
+    distance
+}
+
+// Algorithm 5
+/// Content-based mismatch filtering by combining L1-distance and minimum edit errors in the suffix to the probing window.
+///
+/// # Parameters
+///
+///  * `from` and `to`: (Sub-)String that is under probing window.
+///  * `mismatch`: A PosQGramArray with loosely mismatching q-grams from `s` to `t`.
+///  * `suffix_sum`: A condensed suffix sum array.
+///  * `q`: A positive integer as the tuning parameter for length of q-grams.
+///  * `tau`: A positive integer as the tuning parameter for threshold for matching.
+///  * `mode`: The units which locations of q-grams count.
+///
+/// # Return
+///
+/// A lower bound of the edit distance from `s` to `t`, which is larger than `tau` if any window alone implies it,
+/// or `None` if there are fewer than two mismatching q-grams.
+pub fn content_filter(
+    from: &str,
+    to: &str,
+    mismatch: &PosQGramArray,
+    suffix_sum: &[(Loc, RightError)],
+    q: usize,
+    tau: usize,
+    mode: TokenizerMode,
+) -> Option<usize> {
+    let mut i: usize = 1;
+    let mut j: usize = 0;
+    let mut epsilon: usize;
+
+    let epsi = |s, t, mismatch: &PosQGramArray, q, ii: usize, jj: usize| {
+        let l1 = l1_distance(s, t, mismatch[jj].loc, mismatch[ii - 1].loc + q - 1, mode);
+        let right_error = suffix_sum
+            .iter()
+            .find(|e| e.0 >= mismatch[ii - 1].loc + q) // e is a PosQGram, e.0 is location
+            .unwrap_or(&(0, 0)) // returns (Loc, RightError)
+            .1; // returns RightError
+        l1 / 2 + right_error // NOTE: I believe author had a typo here and I fixed it
+    };
+
+    // otherwise index is out-of-bound
+    if mismatch.len() >= 2 {
+        while i < mismatch.len() {
+            if mismatch[i].loc - mismatch[i - 1].loc > 1 {
+                epsilon = epsi(from, to, mismatch, q, i, j);
+                if epsilon > tau {
+                    return Some(2 * tau + 1);
+                }
+                j = i;
+            }
+            i += 1;
+        }
+
+        let epsilon = epsi(from, to, mismatch, q, i, j);
+        Some(epsilon)
+    } else {
+        None
+    }
+}
+
+/// Count the q-grams of `x` or `y`, whichever has more, that have no exact counterpart in the other string.
+///
+/// Two q-grams are counterparts if they're the same token within `tau` locations of each other, and each q-gram has at most
+/// one counterpart. This is the exact count filter: each edit destroys at most `q` q-grams, and a q-gram that isn't destroyed
+/// is only shifted by at most `tau`, so a pair within edit distance `tau` has at most `q * tau` q-grams without counterpart.
+pub fn count_mismatch(x: &[PosQGram], y: &[PosQGram], tau: usize) -> usize {
+    // locations of each token, in increasing order
+    fn locations(qgrams: &[PosQGram]) -> HashMap<&str, Vec<Loc>> {
+        let mut locations: HashMap<&str, Vec<Loc>> = HashMap::new();
+        qgrams.iter().for_each(|qgram| {
+            locations
+                .entry(qgram.token.as_str())
+                .or_default()
+                .push(qgram.loc)
+        });
+        locations.values_mut().for_each(|locs| locs.sort_unstable());
+        locations
+    }
+    let locations_y: HashMap<&str, Vec<Loc>> = locations(y);
+
+    // greedily matching the leftmost locations within `tau` finds a maximum matching for each token
+    let overlap: usize = locations(x)
+        .iter()
+        .map(|(token, locs_x)| {
+            let locs_y: &[Loc] = locations_y.get(token).map_or(&[], Vec::as_slice);
+            let (mut i, mut j, mut matched): (usize, usize, usize) = (0, 0, 0);
+            while i < locs_x.len() && j < locs_y.len() {
+                if (locs_x[i] as isize - locs_y[j] as isize).abs() <= tau as isize {
+                    matched += 1;
+                    i += 1;
+                    j += 1;
+                } else if locs_x[i] < locs_y[j] {
+                    i += 1;
+                } else {
+                    j += 1;
+                }
+            }
+            matched
+        })
+        .sum();
+
+    std::cmp::max(x.len(), y.len()) - overlap
+}
+
+/// A candidate pair as it goes through a pipeline of [`Filter`]s, with its loosely mismatching q-grams.
+#[derive(Debug)]
+pub struct CandidatePair<'a> {
+    /// Line number and content of the record of `doc_x`.
+    pub x_id: usize,
+    pub x: &'a str,
+    /// Line number and content of the candidate in `doc_y`.
+    pub y_id: usize,
+    pub y: &'a str,
+    pub q: usize,
+    /// The threshold the filters prune by, i.e. that of the Levenshtein distance, which they bound.
+    pub tau: usize,
+    pub mode: TokenizerMode,
+    /// The loosely mismatching q-grams of `x` by [`compare_qgrams`], which filters may sort as they need.
+    pub mismatch: PosQGramArray,
+    /// Number of the strictly mismatching q-grams of `x`.
+    pub strict_mismatches: usize,
+}
+
+/// A filter of a verification pipeline, see [`crate::verification::filter_pair_with`].
+///
+/// ```
+/// use ed_join::filters::{CandidatePair, Filter};
+/// use ed_join::verification::Verdict;
+///
+/// /// Prunes pairs whose lengths differ by more than `tau`.
+/// struct Lengths;
+///
+/// impl Filter for Lengths {
+///     fn apply(&self, pair: &mut CandidatePair<'_>) -> Option<Verdict> {
+///         Some(Verdict::LengthFilter).filter(|_| pair.x.len().abs_diff(pair.y.len()) > pair.tau)
+///     }
+/// }
+/// ```
+pub trait Filter: Sync {
+    /// The verdict of a pair which the filter prunes, or `None` if it passes to the next filter.
+    fn apply(&self, pair: &mut CandidatePair<'_>) -> Option<Verdict>;
+}
+
+/// The count filter, by the strictly mismatching q-grams of [`compare_qgrams`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CountFiltering;
+
+impl Filter for CountFiltering {
+    fn apply(&self, pair: &mut CandidatePair<'_>) -> Option<Verdict> {
+        let _region = timeline::region(Region::CountFilter);
+        #[cfg(feature = "logging")]
+        trace!(
+            "Count filtering on `{}: {}`: epsilon_1 = {}",
+            pair.y_id,
+            pair.y,
+            pair.strict_mismatches
+        );
+        if pair.strict_mismatches > pair.q * pair.tau {
+            Some(Verdict::CountFilter {
+                epsilon: pair.strict_mismatches,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// The location-based filter, by the [`min_edit_errors`] of the loosely mismatching q-grams.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LocationFiltering;
+
+impl Filter for LocationFiltering {
+    fn apply(&self, pair: &mut CandidatePair<'_>) -> Option<Verdict> {
+        let _region = timeline::region(Region::LocationFilter);
+        // the mismatch was found in increasing order of frequency, now sort it in increasing order of location
+        pair.mismatch.sort_by_location();
+        let epsilon_2: usize = min_edit_errors(&pair.mismatch, pair.q);
+        #[cfg(feature = "logging")]
+        trace!(
+            "Location-based filtering on `{}: {}`: epsilon_2 = {}",
+            pair.y_id,
+            pair.y,
+            epsilon_2
+        );
+        if epsilon_2 > pair.tau {
+            Some(Verdict::LocationFilter { epsilon: epsilon_2 })
+        } else {
+            None
+        }
+    }
+}
+
+/// The content-based filter, by the [`content_filter`] of the loosely mismatching q-grams, timed in a profile if any.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ContentFiltering<'a> {
+    pub profile: Option<&'a FilterProfile>,
+}
+
+impl Filter for ContentFiltering<'_> {
+    fn apply(&self, pair: &mut CandidatePair<'_>) -> Option<Verdict> {
+        let start: Instant = Instant::now();
+        let _region = timeline::region(Region::ContentFilter);
+        pair.mismatch.sort_by_location();
+        // there is no bound without mismatching q-grams
+        let suffix_sum: SuffixSumArray = sum_right_errors(&mut pair.mismatch, pair.q)?;
+        #[cfg(feature = "logging")]
+        trace!("Suffix Sum Array: {:?}", suffix_sum);
+        let epsilon_3: Option<usize> = content_filter(
+            pair.x,
+            pair.y,
+            &pair.mismatch,
+            &suffix_sum,
+            pair.q,
+            pair.tau,
+            pair.mode,
+        );
+        if let Some(profile) = self.profile {
+            profile.record_content(
+                start.elapsed(),
+                epsilon_3.is_some_and(|epsilon_3| epsilon_3 > pair.tau),
+            );
+        }
+        #[cfg(feature = "logging")]
+        trace!(
+            "Content-based filtering on `{}: {}`: epsilon_3 = {:?}",
+            pair.y_id,
+            pair.y,
+            epsilon_3
+        );
+        epsilon_3
+            .filter(|epsilon_3| *epsilon_3 > pair.tau)
+            .map(|epsilon| Verdict::ContentFilter { epsilon })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters() {
+        let (q, tau): (usize, usize) = (2, 1);
+        let records: Vec<String> = ["abcdef", "abxdef", "fedcba", "uvwxyz"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let index: InvertedIndex = build_inverted_index(None, &records, q);
+        let sorted = |record: &str| {
+            let mut qgram_array: PosQGramArray = PosQGramArray::from(record, q);
+            qgram_array.sort_by_frequency(&index);
+            qgram_array
+        };
+
+        // a substitution destroys at most `q` q-grams, and a reversed record shares none of them in place
+        let (mismatch, epsilon_1) =
+            compare_qgrams(&sorted("abcdef"), &sorted("abxdef"), &index, tau);
+        assert_eq!(
+            (
+                mismatch.iter().map(|qgram| qgram.loc).collect::<Vec<_>>(),
+                epsilon_1
+            ),
+            (vec![2], 2)
+        );
+        assert_eq!(min_edit_errors(&mismatch, q), 1);
+        let (reversed, epsilon_1) =
+            compare_qgrams(&sorted("abcdef"), &sorted("fedcba"), &index, tau);
+        assert_eq!(epsilon_1, 5);
+        assert_eq!(count_mismatch(&sorted("abcdef"), &sorted("fedcba"), tau), 5);
+        // each edit destroys at most 2 adjacent q-grams of the 5
+        assert_eq!(min_edit_errors(&reversed, q), 3);
+
+        // the q-grams are reversed to be summed from the right, and restored
+        let (mut suffix, _) = compare_qgrams(&sorted("abcdef"), &sorted("fedcba"), &index, tau);
+        let suffix_sum: SuffixSumArray = sum_right_errors(&mut suffix, q).unwrap();
+        assert_eq!(suffix_sum, vec![(4, 1), (2, 2), (0, 3)]);
+        assert_eq!(suffix.to_string(), reversed.to_string());
+        assert_eq!(sum_right_errors(&mut PosQGramArray::new(), q), None);
+
+        // the histograms of a window only differ by the units that were replaced, not by ones that were moved
+        assert_eq!(
+            l1_distance("abcdef", "abxdef", 0, 6, TokenizerMode::Bytes),
+            2
+        );
+        assert_eq!(
+            l1_distance("abcdef", "fedcba", 0, 6, TokenizerMode::Bytes),
+            0
+        );
+        assert_eq!(l1_distance("äbc", "abc", 0, 3, TokenizerMode::Chars), 2);
+        // a window of bytes may split a character
+        assert_eq!(l1_distance("äbc", "abc", 0, 1, TokenizerMode::Bytes), 2);
+
+        // so a reversed record passes the content filter, but one of other units is pruned, as `2 * tau + 1`
+        assert_eq!(
+            content_filter(
+                "abcdef",
+                "fedcba",
+                &reversed,
+                &suffix_sum,
+                q,
+                tau,
+                TokenizerMode::Bytes
+            ),
+            Some(1)
+        );
+        let (mut disjoint, _) = compare_qgrams(&sorted("abcdef"), &sorted("uvwxyz"), &index, tau);
+        let suffix_sum: SuffixSumArray = sum_right_errors(&mut disjoint, q).unwrap();
+        assert_eq!(
+            content_filter(
+                "abcdef",
+                "uvwxyz",
+                &disjoint,
+                &suffix_sum,
+                q,
+                tau,
+                TokenizerMode::Bytes
+            ),
+            Some(3)
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::filters::{compare_qgrams, content_filter, sum_right_errors};
+use crate::matching::{ed_join_records, ed_self_join_records};
+use crate::qgram::{build_inverted_index, InvertedIndex, PosQGramArray};
+use crate::tokenizer::{byte_token, TokenizerMode};
+use crate::verification::EditDistanceBuffer;
+use std::collections::BTreeSet;
+
+/// The parameters and records that the bytes of a fuzzer are decoded into, see [`decode`].
+struct Input {
+    q: usize,
+    tau: usize,
+    records: Vec<String>,
+}
+
+/// Decode arbitrary bytes into a join: `q` in `1..=4` and `tau` in `0..=3` from the first two bytes, and up to 8 records
+/// of up to 64 bytes from the lines of the rest, as UTF-8 with invalid sequences replaced, so that records aren't all ASCII.
+fn decode(data: &[u8]) -> Option<Input> {
+    let (q, tau, rest): (u8, u8, &[u8]) = match data {
+        [q, tau, rest @ ..] => (*q, *tau, rest),
+        _ => return None,
+    };
+    let records: Vec<String> = rest
+        .split(|byte| *byte == b'\n')
+        .take(8)
+        .map(|record| String::from_utf8_lossy(&record[..record.len().min(64)]).into_owned())
+        .collect();
+
+    Some(Input {
+        q: 1 + q as usize % 4,
+        tau: tau as usize % 4,
+        records,
+    })
+}
+
+/// The first two records of an input, and the arrays of their q-grams sorted by frequency in an index of both of them.
+fn sorted_pair(input: &Input) -> Option<(&str, &str, PosQGramArray, PosQGramArray, InvertedIndex)> {
+    let (x, y): (&String, &String) = (input.records.first()?, input.records.get(1)?);
+    let inverted_index: InvertedIndex = build_inverted_index(None, &input.records[..2], input.q);
+    let mut x_array: PosQGramArray = PosQGramArray::from(x, input.q);
+    let mut y_array: PosQGramArray = PosQGramArray::from(y, input.q);
+    x_array.sort_by_frequency(&inverted_index);
+    y_array.sort_by_frequency(&inverted_index);
+    Some((x, y, x_array, y_array, inverted_index))
+}
+
+/// Fuzz [`PosQGramArray::from`], whose grams must be every window of `q` bytes, in increasing order of location,
+/// even those that split a character.
+pub fn pos_qgram_array(data: &[u8]) {
+    let input: Input = match decode(data) {
+        Some(input) => input,
+        None => return,
+    };
+    for record in &input.records {
+        let array: PosQGramArray = PosQGramArray::from(record, input.q);
+        assert_eq!(array.len(), (record.len() + 1).saturating_sub(input.q));
+        for (loc, qgram) in array.iter().enumerate() {
+            assert_eq!(qgram.loc, loc);
+            assert_eq!(
+                qgram.token,
+                byte_token(&record.as_bytes()[loc..loc + input.q])
+            );
+        }
+    }
+}
+
+/// Fuzz the comparison of the q-grams of a pair, whose mismatches are at most the q-grams of `x`.
+pub fn compare_qgram_arrays(data: &[u8]) {
+    let input: Input = match decode(data) {
+        Some(input) => input,
+        None => return,
+    };
+    if let Some((_, _, x_array, y_array, inverted_index)) = sorted_pair(&input) {
+        let (loose_mismatch, epsilon) =
+            compare_qgrams(&x_array, &y_array, &inverted_index, input.tau);
+        assert!(epsilon <= x_array.len());
+        assert!(loose_mismatch.len() <= x_array.len());
+    }
+}
+
+/// Fuzz the content-based filter on the mismatching q-grams of a pair, as the verification of a join applies it.
+pub fn content_filter_pair(data: &[u8]) {
+    let input: Input = match decode(data) {
+        Some(input) => input,
+        None => return,
+    };
+    if let Some((x, y, x_array, y_array, inverted_index)) = sorted_pair(&input) {
+        let (mut loose_mismatch, _) =
+            compare_qgrams(&x_array, &y_array, &inverted_index, input.tau);
+        loose_mismatch.sort_by_location();
+        if let Some(suffix_sum) = sum_right_errors(&mut loose_mismatch, input.q) {
+            content_filter(
+                x,
+                y,
+                &loose_mismatch,
+                &suffix_sum,
+                input.q,
+                input.tau,
+                TokenizerMode::Bytes,
+            );
+        }
+    }
+}
+
+/// The pairs of `x` and `y` within `tau` by a brute-force comparison of all of them, which a join must all report,
+/// or of `y` alone with `x_id < y_id` if `x` is `None`. Records shorter than `q` have no q-gram, so they're never matched.
+fn brute_force_pairs(
+    x: Option<&[String]>,
+    y: &[String],
+    q: usize,
+    tau: usize,
+) -> BTreeSet<(usize, usize, usize)> {
+    let mut buffer: EditDistanceBuffer = EditDistanceBuffer::default();
+    let x_records: &[String] = x.unwrap_or(y);
+    let mut pairs: BTreeSet<(usize, usize, usize)> = BTreeSet::new();
+    for (x_id, x_record) in x_records.iter().enumerate() {
+        let y_from: usize = if x.is_some() { 0 } else { x_id + 1 };
+        for (y_id, y_record) in y.iter().enumerate().skip(y_from) {
+            if x_record.len() < q || y_record.len() < q {
+                continue;
+            }
+            if let Some(ed) =
+                buffer.bounded_edit_distance_in(x_record, y_record, tau, TokenizerMode::Bytes)
+            {
+                pairs.insert((x_id, y_id, ed));
+            }
+        }
+    }
+    pairs
+}
+
+/// Fuzz the joins of slices of records, whose pairs must be exactly those within `tau` of a brute-force join,
+/// with their edit distances in bytes.
+pub fn join_slices(data: &[u8]) {
+    let input: Input = match decode(data) {
+        Some(input) => input,
+        None => return,
+    };
+    let (x, y): (&[String], &[String]) = input.records.split_at(input.records.len() / 2);
+    let pairs: BTreeSet<(usize, usize, usize)> = ed_join_records(x, y, input.q, input.tau)
+        .into_iter()
+        .collect();
+    assert_eq!(
+        pairs,
+        brute_force_pairs(Some(x), y, input.q, input.tau),
+        "{:?} {:?} q={} tau={}",
+        x,
+        y,
+        input.q,
+        input.tau
+    );
+    let pairs: BTreeSet<(usize, usize, usize)> =
+        ed_self_join_records(&input.records, input.q, input.tau)
+            .into_iter()
+            .collect();
+    assert_eq!(
+        pairs,
+        brute_force_pairs(None, &input.records, input.q, input.tau),
+        "{:?} q={} tau={}",
+        input.records,
+        input.q,
+        input.tau
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzz_targets() {
+        // a few thousand inputs of a linear congruential generator, as a smoke test of the targets
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        for len in 0..2000 {
+            let data: Vec<u8> = (0..len % 97)
+                .flat_map(|_| {
+                    state = state
+                        .wrapping_mul(6_364_136_223_846_793_005)
+                        .wrapping_add(1_442_695_040_888_963_407);
+                    // mostly a small alphabet, so that records share q-grams, with a character of two bytes in it
+                    match (state >> 33) % 8 {
+                        0 => vec![b'\n'],
+                        1 => vec![(state >> 41) as u8],
+                        _ => ["a", "b", "c", "ü"][(state >> 45) as usize % 4]
+                            .as_bytes()
+                            .to_vec(),
+                    }
+                })
+                .collect();
+            pos_qgram_array(&data);
+            compare_qgram_arrays(&data);
+            content_filter_pair(&data);
+            join_slices(&data);
+        }
+    }
+}
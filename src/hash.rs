@@ -0,0 +1,106 @@
+#[cfg(not(feature = "hashing"))]
+use std::convert::Infallible;
+use std::fmt::Write;
+
+#[cfg(feature = "hashing")]
+use hmac::{Hmac, Mac};
+#[cfg(feature = "hashing")]
+use sha2::Digest;
+
+use crate::errors::*;
+
+/// SHA-256, for content hashes that are stable across platforms and versions,
+/// e.g. keys of cached runs and checksums of persisted indexes.
+///
+/// It's computed by the `sha2` crate, and thus needs the `hashing` feature.
+#[derive(Clone, Debug)]
+pub(crate) struct Sha256 {
+    #[cfg(feature = "hashing")]
+    inner: sha2::Sha256,
+    /// A hasher can't be created without the `hashing` feature.
+    #[cfg(not(feature = "hashing"))]
+    never: Infallible,
+}
+
+impl Sha256 {
+    /// A new hasher, or an error if the crate is built without the `hashing` feature.
+    pub fn new() -> Result<Self> {
+        #[cfg(feature = "hashing")]
+        {
+            Ok(Self {
+                inner: sha2::Sha256::new(),
+            })
+        }
+        #[cfg(not(feature = "hashing"))]
+        {
+            bail!("Content hashes need the `hashing` feature of ed_join")
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        #[cfg(feature = "hashing")]
+        self.inner.update(data);
+        #[cfg(not(feature = "hashing"))]
+        {
+            let _ = data;
+            match self.never {}
+        }
+    }
+
+    pub fn digest(self) -> [u8; 32] {
+        #[cfg(feature = "hashing")]
+        {
+            self.inner.finalize().into()
+        }
+        #[cfg(not(feature = "hashing"))]
+        match self.never {}
+    }
+
+    /// The digest as a lowercase hexadecimal string.
+    pub fn hex_digest(self) -> String {
+        to_hex(&self.digest())
+    }
+}
+
+/// Bytes as a lowercase hexadecimal string.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(2 * bytes.len()), |mut hex, byte| {
+            write!(hex, "{:02x}", byte).unwrap();
+            hex
+        })
+}
+
+/// HMAC-SHA256 of data under a key, by the `hmac` crate, for hashes that only holders of the key can reproduce.
+#[cfg(feature = "hashing")]
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    // HMAC takes keys of any length
+    let mut mac: Hmac<sha2::Sha256> = Hmac::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(all(test, feature = "hashing"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256() {
+        let hex = |data: &[u8]| {
+            let mut hasher = Sha256::new().unwrap();
+            hasher.update(data);
+            hasher.hex_digest()
+        };
+        assert_eq!(
+            hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        // test case 2 of RFC 4231
+        assert_eq!(
+            to_hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
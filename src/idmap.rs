@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use crate::errors::*;
+use crate::qgram::ID;
+
+/// A mapping between the IDs that inputs give their records, e.g. UUIDs or sparse integers, and dense internal IDs.
+///
+/// Internally, records are always identified by their position, so that the inverted index and the candidates stay compact.
+/// Dense IDs are assigned in the order records are inserted, starting from 0, and are translated back when writing output.
+///
+/// ```
+/// use ed_join::idmap::IdMap;
+///
+/// let mut ids = IdMap::new();
+/// assert_eq!(ids.insert("3f2a").unwrap(), 0);
+/// assert_eq!(ids.insert("9b71").unwrap(), 1);
+/// assert_eq!(ids.external(1), Some("9b71"));
+/// assert_eq!(ids.dense("3f2a"), Some(0));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IdMap {
+    external: Vec<String>,
+    dense: HashMap<String, ID>,
+}
+
+impl IdMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Assign the next dense ID to a record with the given external ID.
+    ///
+    /// External IDs must be unique within an input, so a duplicate is an error.
+    pub fn insert(&mut self, external: impl Into<String>) -> Result<ID> {
+        let external: String = external.into();
+        if self.dense.contains_key(&external) {
+            bail!("Duplicate ID: {}", external);
+        }
+        let id: ID = self.external.len();
+        self.dense.insert(external.clone(), id);
+        self.external.push(external);
+        Ok(id)
+    }
+
+    /// The external ID of a record, given its dense ID.
+    pub fn external(&self, id: ID) -> Option<&str> {
+        self.external.get(id).map(String::as_str)
+    }
+
+    /// The dense ID of a record, given its external ID.
+    pub fn dense(&self, external: &str) -> Option<ID> {
+        self.dense.get(external).copied()
+    }
+
+    /// Number of records in the mapping.
+    pub fn len(&self) -> usize {
+        self.external.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.external.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn id_map() {
+        let mut ids: IdMap = IdMap::new();
+        assert!(ids.is_empty());
+        ["7", "1000000", "42"]
+            .iter()
+            .enumerate()
+            .for_each(|(i, external)| {
+                assert_eq!(ids.insert(*external).unwrap(), i);
+            });
+        assert!(ids.insert("42").is_err());
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids.external(2), Some("42"));
+        assert_eq!(ids.external(3), None);
+        assert_eq!(ids.dense("1000000"), Some(1));
+        assert_eq!(ids.dense("8"), None);
+    }
+}
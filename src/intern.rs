@@ -0,0 +1,98 @@
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt::{self, Debug, Formatter},
+    iter::FromIterator,
+    sync::Arc,
+};
+
+use crate::qgram::Token;
+
+/// ID of an interned token, which is its index in the dictionary of the [`Interner`].
+pub type TokenId = u32;
+
+/// A dictionary of tokens, which each get a [`TokenId`] the first time they're interned, so that they're stored once,
+/// and compared and hashed as integers, rather than as strings.
+///
+/// IDs are assigned in the order tokens are first interned, so interning tokens in the order
+/// [`crate::qgram::PosQGramArray::sort_by_frequency`] sorts them, lets grams be sorted and compared by their IDs alone.
+///
+/// ```
+/// use ed_join::intern::Interner;
+///
+/// let mut interner = Interner::new();
+/// let he = interner.intern("he");
+/// assert_eq!(interner.intern("el"), he + 1);
+/// assert_eq!(interner.intern("he"), he);
+/// assert_eq!((interner.get("el"), interner.resolve(he)), (Some(he + 1), "he"));
+/// ```
+#[derive(Clone, Default)]
+pub struct Interner {
+    ids: HashMap<Arc<str>, TokenId>,
+    tokens: Vec<Arc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The ID of a token, which is interned if it's not yet.
+    ///
+    /// It panics if there are more tokens than IDs, i.e. 2^32.
+    pub fn intern(&mut self, token: &str) -> TokenId {
+        if let Some(id) = self.ids.get(token) {
+            return *id;
+        }
+        let id: TokenId =
+            TokenId::try_from(self.tokens.len()).expect("More distinct tokens than token IDs");
+        let token: Arc<str> = Arc::from(token);
+        self.tokens.push(Arc::clone(&token));
+        self.ids.insert(token, id);
+        id
+    }
+
+    /// The ID of a token, or `None` if it's not interned.
+    pub fn get(&self, token: &str) -> Option<TokenId> {
+        self.ids.get(token).copied()
+    }
+
+    /// The token of an ID, which panics if no token was interned as it.
+    pub fn resolve(&self, id: TokenId) -> &str {
+        &self.tokens[id as usize]
+    }
+
+    /// Number of tokens interned.
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Tokens in increasing order of ID.
+    pub fn tokens(&self) -> impl Iterator<Item = &str> + '_ {
+        self.tokens.iter().map(|token| &**token)
+    }
+}
+
+/// Tokens are listed in the order of their IDs, rather than in the arbitrary order of the map of IDs,
+/// so that dumps of an index are the same across runs.
+impl Debug for Interner {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interner")
+            .field("tokens", &self.tokens)
+            .finish()
+    }
+}
+
+impl FromIterator<Token> for Interner {
+    fn from_iter<I: IntoIterator<Item = Token>>(tokens: I) -> Self {
+        let mut interner: Interner = Interner::new();
+        tokens.into_iter().for_each(|token| {
+            interner.intern(&token);
+        });
+        interner
+    }
+}
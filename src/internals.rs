@@ -0,0 +1,12 @@
+//! Internals of the algorithm, which are public for experiments and benchmarks, but **not** stable.
+//!
+//! The layout of the inverted index, the signatures of the filters, and anything else in these modules
+//! may change in any release, including patch releases. Use [`crate::api`] for code that should keep compiling.
+
+pub use crate::approx;
+pub use crate::filters;
+pub use crate::intern;
+pub use crate::matching;
+pub use crate::persist;
+pub use crate::qgram;
+pub use crate::verification;
@@ -0,0 +1,335 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    iter::Peekable,
+    path::PathBuf,
+    str::Chars,
+};
+
+use crate::config::ErrorPolicy;
+use crate::errors::*;
+use crate::report::{Side, Warning};
+
+/// How records are parsed from a JSON Lines input, whose lines are objects holding the records, see [`read_jsonl`].
+///
+/// ```
+/// use ed_join::jsonl::JsonlInput;
+///
+/// let input = JsonlInput::new("address.city");
+/// assert_eq!(input.field("{\"address\": {\"city\": \"Paris\"}}"), Ok(Some("Paris".to_string())));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonlInput {
+    /// Keys of the field which records are joined on, from the outermost object in, e.g. `address.city` as
+    /// `["address", "city"]`.
+    pub path: Vec<String>,
+}
+
+impl JsonlInput {
+    /// The field at a path of keys separated by `.`.
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.split('.').map(str::to_string).collect(),
+        }
+    }
+
+    /// The join field of a line, as its text if it's a string, and as it's written if it's a number or boolean,
+    /// or `None` if it's missing or `null`.
+    pub fn field(&self, line: &str) -> std::result::Result<Option<String>, String> {
+        let mut value: Json = parse(line)?;
+        for key in &self.path {
+            value = match value {
+                Json::Object(fields) => {
+                    match fields.into_iter().rev().find(|(name, _)| name == key) {
+                        Some((_, value)) => value,
+                        None => return Ok(None),
+                    }
+                }
+                _ => return Ok(None),
+            };
+        }
+        match value {
+            Json::Null => Ok(None),
+            Json::Scalar(text) => Ok(Some(text)),
+            Json::Array | Json::Object(_) => {
+                Err(format!("field `{}` is not a string", self.path.join(".")))
+            }
+        }
+    }
+}
+
+/// A JSON value, where strings, numbers and booleans are all kept as their text, and arrays are only checked.
+#[derive(Debug)]
+enum Json {
+    Null,
+    Scalar(String),
+    Array,
+    Object(Vec<(String, Json)>),
+}
+
+/// Parse a line as a JSON value.
+fn parse(line: &str) -> std::result::Result<Json, String> {
+    let mut chars: Peekable<Chars> = line.chars().peekable();
+    let value: Json = parse_value(&mut chars)?;
+    skip_whitespace(&mut chars);
+    match chars.next() {
+        None => Ok(value),
+        Some(c) => Err(format!("unexpected {:?} after the value", c)),
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars
+        .next_if(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+        .is_some()
+    {}
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> std::result::Result<(), String> {
+    skip_whitespace(chars);
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(format!("expected {:?}, found {:?}", expected, c)),
+        None => Err(format!(
+            "expected {:?}, found the end of the line",
+            expected
+        )),
+    }
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> std::result::Result<Json, String> {
+    skip_whitespace(chars);
+    match chars.peek().copied() {
+        Some('{') => {
+            chars.next();
+            let mut fields: Vec<(String, Json)> = Vec::new();
+            skip_whitespace(chars);
+            if chars.next_if_eq(&'}').is_some() {
+                return Ok(Json::Object(fields));
+            }
+            loop {
+                expect(chars, '"')?;
+                let key: String = parse_string(chars)?;
+                expect(chars, ':')?;
+                fields.push((key, parse_value(chars)?));
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => (),
+                    Some('}') => return Ok(Json::Object(fields)),
+                    _ => return Err("expected `,` or `}` in an object".to_string()),
+                }
+            }
+        }
+        Some('[') => {
+            chars.next();
+            skip_whitespace(chars);
+            if chars.next_if_eq(&']').is_some() {
+                return Ok(Json::Array);
+            }
+            loop {
+                parse_value(chars)?;
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => (),
+                    Some(']') => return Ok(Json::Array),
+                    _ => return Err("expected `,` or `]` in an array".to_string()),
+                }
+            }
+        }
+        Some('"') => {
+            chars.next();
+            Ok(Json::Scalar(parse_string(chars)?))
+        }
+        Some(c) if c == '-' || c.is_ascii_alphanumeric() => {
+            let mut literal: String = String::new();
+            while let Some(c) =
+                chars.next_if(|c| matches!(c, '-' | '+' | '.') || c.is_ascii_alphanumeric())
+            {
+                literal.push(c);
+            }
+            match literal.as_str() {
+                "null" => Ok(Json::Null),
+                "true" | "false" => Ok(Json::Scalar(literal)),
+                // unlike Rust, JSON has no `inf` or `NaN`, and numbers start with a digit
+                _ if literal
+                    .trim_start_matches('-')
+                    .starts_with(|c: char| c.is_ascii_digit())
+                    && literal.parse::<f64>().is_ok() =>
+                {
+                    Ok(Json::Scalar(literal))
+                }
+                _ => Err(format!("invalid literal `{}`", literal)),
+            }
+        }
+        Some(c) => Err(format!("unexpected {:?}", c)),
+        None => Err("expected a value, found the end of the line".to_string()),
+    }
+}
+
+/// Parse the rest of a string, after its opening quote.
+fn parse_string(chars: &mut Peekable<Chars>) -> std::result::Result<String, String> {
+    let mut string: String = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(string),
+            Some('\\') => match chars.next() {
+                Some('"') => string.push('"'),
+                Some('\\') => string.push('\\'),
+                Some('/') => string.push('/'),
+                Some('b') => string.push('\u{8}'),
+                Some('f') => string.push('\u{c}'),
+                Some('n') => string.push('\n'),
+                Some('r') => string.push('\r'),
+                Some('t') => string.push('\t'),
+                Some('u') => {
+                    let high: u32 = parse_hex(chars)?;
+                    let code: u32 = if (0xd800..0xdc00).contains(&high) {
+                        // a surrogate pair, as JSON escapes characters beyond the basic multilingual plane
+                        let low: Option<u32> = match (chars.next(), chars.next()) {
+                            (Some('\\'), Some('u')) => Some(parse_hex(chars)?),
+                            _ => None,
+                        };
+                        match low {
+                            Some(low) if (0xdc00..0xe000).contains(&low) => {
+                                0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                            }
+                            _ => return Err("unpaired surrogate in a string".to_string()),
+                        }
+                    } else {
+                        high
+                    };
+                    string.push(
+                        char::from_u32(code)
+                            .ok_or_else(|| format!("invalid escape of {:#x}", code))?,
+                    );
+                }
+                _ => return Err("invalid escape in a string".to_string()),
+            },
+            Some(c) => string.push(c),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
+
+fn parse_hex(chars: &mut Peekable<Chars>) -> std::result::Result<u32, String> {
+    let digits: String = chars.take(4).collect();
+    match u32::from_str_radix(&digits, 16) {
+        Ok(code) if digits.len() == 4 && digits.chars().all(|c| c.is_ascii_hexdigit()) => Ok(code),
+        _ => Err(format!("invalid escape `\\u{}`", digits)),
+    }
+}
+
+/// Read all records of a JSON Lines file, as the join field of its lines.
+///
+/// A line's ID is its line number. Lines that are not valid JSON or UTF-8, or whose join field is missing, `null`,
+/// an array or an object, are kept as empty records, so that line numbers are preserved,
+/// and a [`Warning::MalformedRecord`] or [`Warning::NonUtf8Record`] is emitted for each of them.
+///
+/// # Args
+///
+/// * `path`: Path, absolute or relative, to the file to be read.
+/// * `side`: Which side of the join this file is on, used for reporting.
+/// * `policy`: How read errors and malformed lines are handled.
+/// * `input`: The join field of the lines.
+///
+/// # Returns
+///
+/// * When succesful, returns all the records and the warnings encountered while reading.
+pub fn read_jsonl(
+    path: &PathBuf,
+    side: Side,
+    policy: ErrorPolicy,
+    input: &JsonlInput,
+) -> Result<(Vec<String>, Vec<Warning>)> {
+    let reader: BufReader<File> = BufReader::new(File::open(path)?);
+    let mut records: Vec<String> = Vec::new();
+    let mut warnings: Vec<Warning> = Vec::new();
+
+    for (id, line) in reader.split(b'\n').enumerate() {
+        let line: Vec<u8> = match line {
+            Ok(line) => line,
+            Err(e) if policy == ErrorPolicy::Abort => return Err(e.into()),
+            Err(e) => {
+                // the reader cannot be resynchronised with line boundaries after an error
+                warnings.push(Warning::ReadError {
+                    side,
+                    id,
+                    msg: e.to_string(),
+                });
+                break;
+            }
+        };
+        let line: String = match String::from_utf8(line) {
+            Ok(line) => line,
+            Err(_) if policy == ErrorPolicy::Abort => bail!(ErrorKind::NonUtf8Record(side, id)),
+            Err(_) => {
+                warnings.push(Warning::NonUtf8Record { side, id });
+                records.push(String::new());
+                continue;
+            }
+        };
+        let msg: String = match input.field(&line) {
+            Ok(Some(record)) => {
+                records.push(record);
+                continue;
+            }
+            Ok(None) => format!("no field `{}`", input.path.join(".")),
+            Err(msg) => msg,
+        };
+        if policy == ErrorPolicy::Abort {
+            bail!("Line {} of {} is malformed: {}", id, path.display(), msg);
+        }
+        warnings.push(Warning::MalformedRecord { side, id, msg });
+        records.push(String::new());
+    }
+
+    Ok((records, warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn jsonl() {
+        let input: JsonlInput = JsonlInput::new("name");
+        assert_eq!(
+            input.field(r#"{"id": 1, "name": "Smith, \"J\" é😀"}"#),
+            Ok(Some("Smith, \"J\" é😀".to_string()))
+        );
+        assert_eq!(
+            input.field(r#"{"name": -1.5e3, "tags": [true, null, {}]}"#),
+            Ok(Some("-1.5e3".to_string()))
+        );
+        assert_eq!(input.field(r#"{"name": null}"#), Ok(None));
+        assert_eq!(input.field(r#"["name"]"#), Ok(None));
+        assert!(input.field(r#"{"name": ["a"]}"#).is_err());
+        assert!(input.field(r#"{"name": "a""#).is_err());
+        assert!(input.field(r#"{"name": "a"} x"#).is_err());
+        assert!(input.field(r#"{"name": nope}"#).is_err());
+        assert!(input.field(r#"{"name": -inf}"#).is_err());
+        assert_eq!(
+            input.field(r#"{"name": "\ud83d\ude00"}"#),
+            Ok(Some("😀".to_string()))
+        );
+        assert!(input.field(r#"{"name": "\ud83d"}"#).is_err());
+
+        let dir: TempDir = TempDir::new("jsonl");
+        let path: PathBuf = dir.join("records.jsonl");
+        std::fs::write(
+            &path,
+            "{\"name\": \"hello\"}\n{\"id\": 2}\nnot json\n{\"name\": \"world\"}\n",
+        )
+        .unwrap();
+        let (records, warnings) =
+            read_jsonl(&path, Side::Y, ErrorPolicy::SkipRecord, &input).unwrap();
+        assert_eq!(records, ["hello", "", "", "world"]);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            warnings[0].to_string(),
+            "malformed_record,y,1,skipped malformed record: no field `name`"
+        );
+        assert!(read_jsonl(&path, Side::Y, ErrorPolicy::Abort, &input).is_err());
+    }
+}
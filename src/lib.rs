@@ -1,17 +1,79 @@
+//! An implementation of the Ed-Join algorithm for similarity joins under an edit distance threshold.
+//!
+//! The stable API is [`api`], and [`prelude`] re-exports the most used parts of it.
+//! Modules of the algorithm itself are gathered in [`internals`], and are unstable.
+//!
+//! # Features
+//!
+//! Without default features, the library only depends on `rayon`, `crossbeam-channel` and `error-chain`.
+//!
+//!  * `hashing`: Hash contents by SHA-256 and HMAC-SHA256, with the `sha2` and `hmac` crates, which the cache of runs,
+//!    persisted indexes, idempotency keys of [`sink::RetrySink`] and masked texts need. It's a default feature.
+//!  * `logging`: Log the progress of joins with the `log` crate, e.g. for services that install their own logger.
+//!  * `cli`: Build the `ed-join` binary, which implies `logging`, `parquet`, `plugins` and `unicode`.
+//!  * `fuzzing`: Expose the entry points of the fuzz targets in `fuzz/`, which are run by `cargo fuzz`.
+//!  * `parquet`: Write the output as Parquet, with the `parquet` crate, see [`output::OutputFormat::Parquet`].
+//!  * `plugins`: Load match plugins from dynamic libraries on Unix, which depends on `libc`, see [`plugin::DylibPlugin`].
+//!  * `timeline`: Time the phases of joins and the filters of their pairs, and write them as a timeline for
+//!    `chrome://tracing`, see [`timeline`].
+//!  * `unicode`: Normalize records into Unicode normalization forms and strip their diacritics, with the
+//!    `unicode-normalization` crate, see [`unicode::normalize`]. It's a default feature.
+
 #[macro_use]
 extern crate error_chain;
-#[cfg(feature = "cli")]
+#[cfg(feature = "logging")]
 #[macro_use]
 extern crate log;
 
+pub mod alphabet;
+pub mod api;
+#[doc(hidden)]
+pub mod approx;
+pub mod assignment;
+pub mod cache;
+pub mod chain;
 #[cfg(feature = "cli")]
 #[doc(hidden)]
 pub(crate) mod cli;
+pub mod cluster;
+pub mod config;
+pub mod csv;
+pub mod delta;
 #[doc(hidden)]
 pub(crate) mod errors;
-#[doc(inline)]  
+pub mod external;
+pub mod fastq;
+pub mod filters;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+pub(crate) mod hash;
+pub mod idmap;
+pub mod intern;
+pub mod internals;
+pub mod jsonl;
+#[doc(hidden)]
 pub mod matching;
-#[doc(inline)]  
+pub mod offsets;
+pub mod output;
+#[doc(hidden)]
+pub mod persist;
+pub mod plugin;
+pub mod prelude;
+pub mod progress;
+#[doc(hidden)]
 pub mod qgram;
-#[doc(inline)]  
+pub mod report;
+pub mod sample;
+pub mod scoring;
+pub mod sink;
+pub mod spool;
+pub mod store;
+#[cfg(test)]
+pub(crate) mod testutil;
+pub mod timeline;
+pub mod tokenizer;
+pub mod unicode;
+#[doc(hidden)]
 pub mod verification;
+pub mod window;
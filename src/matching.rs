@@ -1,50 +1,105 @@
 use crossbeam_channel::unbounded;
-use rayon::prelude::*;
+use rayon::{iter::Either, prelude::*, ThreadPoolBuilder};
 use std::{
-    cmp::min,
-    fs::File,
-    io::{prelude::*, BufReader, BufWriter},
-    path::PathBuf,
+    borrow::Cow,
+    cmp::{self, min},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    ops::Range,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Instant,
 };
 
+use crate::alphabet::AlphabetAction;
+use crate::approx::{tune, Pruning};
+use crate::assignment::assign;
+use crate::cache::{CacheEntry, WarmEntry};
+use crate::cluster::{cluster, write_clusters, Clustering};
+use crate::config::{CountFilter, EdJoinConfig, ErrorPolicy, InputFormat, JoinMode};
+use crate::csv::{read_passthrough, Fields};
+use crate::delta::delta_join;
 use crate::errors::*;
+use crate::external::ExternalIndex;
+use crate::filters::count_mismatch;
+use crate::offsets::LineOffsets;
+use crate::output::{
+    truncate_partial, write_candidate_pairs, write_diagnostics, write_matches, JoinCandidates,
+    JoinOutput, Matches, OutputFormat, RecordDiagnostics,
+};
+use crate::persist::{
+    read_candidates, read_checkpoint, read_distances, read_index, read_sources, write_candidates,
+    write_checkpoint, write_distances, write_index, write_manifest, Checkpoint, Distances,
+    Manifest, MergedPart, Phase,
+};
+use crate::progress::ProgressEvent;
 use crate::qgram::*;
+use crate::report::{JoinReport, JoinStats, Side, Warning};
+use crate::sample::{write_sample, SampleCollector};
+use crate::scoring::ScoreContext;
+use crate::store::RecordStore;
+use crate::timeline::{self, Region, Span};
+use crate::tokenizer::{reverse_complement, QGrams, Tokenizer, TokenizerMode};
 use crate::verification::*;
+use crate::window::window_join;
 
-#[cfg(feature = "cli")]
-use crate::cli::ProgressBarBuilder;
-#[cfg(feature = "cli")]
-use indicatif::{ParallelProgressIterator, ProgressBar};
+/// Matched pairs, grouped by line number of `doc_x`, as returned by `verify()`.
+type MatchedPairs = Vec<(ID, Vec<(ID, usize)>)>;
+/// Candidates of a record of `doc_x` that passed all filters, with their edit distances if they're exact.
+type FilteredCandidates = Vec<(ID, Option<usize>)>;
+
+/// Records of `doc_x` matched by each task, unless [`EdJoinConfig::batch_size`] says otherwise.
+///
+/// Joins are parallel over batches of records, and each record is matched sequentially, as its q-grams and candidates
+/// are too few to be worth splitting among threads.
+pub const DEFAULT_BATCH_SIZE: usize = 64;
 
 // Algorithm 2
-// NOTE: PosQGram is not by default sorted in increasing frequency
 /// Given a set of q-grams, find the minimum number of edit operations that destroys all q-grams.
 ///
+/// The q-grams may be in any order, e.g. a prefix of a [`PosQGramArray`] sorted by frequency. When they're sorted by
+/// location, as the mismatching q-grams are during verification, their locations are not copied and sorted first.
+///
 /// # Parameters
 ///
-///  * `qgram_array`: A PosQGramArray, i.e. a set of positional q-grams.
+///  * `qgram_array`: A set of positional q-grams, all of the same length `q`.
 ///  * `q`: A positive integer as the tuning parameter for length of q-grams.
 ///
 /// # Return
 ///
-/// The minimum number of edit operations that destroy all q-grams in the given set.
+/// The minimum number of edit operations that destroy all q-grams in the given set, which is 0 if it's empty.
 pub fn min_edit_errors(qgram_array: &[PosQGram], q: usize) -> usize {
-    let mut cnt = 0;
-    let mut loc = 0;
-
-    let mut array_clone: Vec<PosQGram> = vec![PosQGram::default(); qgram_array.len()];
-    array_clone[..].clone_from_slice(qgram_array);
+    if qgram_array
+        .windows(2)
+        .all(|pair| pair[0].loc <= pair[1].loc)
+    {
+        return count_edits(qgram_array.iter().map(|qgram| qgram.loc), q);
+    }
+    // only the locations matter, which are cheaper to sort than the q-grams
+    let mut locs: Vec<Loc> = qgram_array.iter().map(|qgram| qgram.loc).collect();
+    locs.sort_unstable();
+    count_edits(locs.into_iter(), q)
+}
 
-    // qgram_array was sorted in increasing order before calling CalcPrefix,
-    // Now sort it according to location
-    array_clone.par_sort_unstable_by_key(|qgram| qgram.loc);
+/// The number of edits that destroy q-grams at increasing locations, as counted by [`min_edit_errors`].
+fn count_edits(locs: impl Iterator<Item = Loc>, q: usize) -> usize {
+    let mut cnt = 0;
+    // last location destroyed by the edits so far, if any
+    let mut last: Option<Loc> = None;
 
-    qgram_array.iter().for_each(|qgram| {
-        if qgram.loc > loc {
+    // an edit at the last location of the leftmost q-gram not yet destroyed destroys as many of the others as possible
+    for loc in locs {
+        if last.map_or(true, |last| loc > last) {
             cnt += 1;
-            loc = qgram.loc + q - 1;
+            last = Some(loc + q - 1);
         }
-    });
+    }
 
     cnt
 }
@@ -52,19 +107,24 @@ pub fn min_edit_errors(qgram_array: &[PosQGram], q: usize) -> usize {
 // Algorithm 3
 ///
 /// Given a set of q-grams, find the minimum length of prefix such that if all the q-grams in the prefix are mismatched,
-/// it will incur at least `tau + ` ed/it errors.
+/// it will incur at least `tau + 1` edit errors.
+///
+/// The q-grams must be in the global order of the join, i.e. in increasing order of frequency as by
+/// [`PosQGramArray::sort_by_frequency`], for the prefix to be that of the prefix filter, and all of the same length `q`.
+/// They're not modified.
 ///
 /// # Parameters
 ///
-///  * `qgram_array`: A PosQGramArray, i.e. a set of positional q-grams.
+///  * `qgram_array`: A set of positional q-grams, in the global order.
 ///  * `q`: A positive integer as the tuning parameter for length of q-grams.
 ///  * `tau`: A positive integer as the tuning parameter for threshold for matching.
 ///
 /// # Return
 ///
 /// The minimum length of prefix such that if all the q-grams in the prefix are mismatched,
-/// it will incur at least `tau + ` edit errors.
-pub fn calc_prefix_len(qgram_array: &mut PosQGramArray, q: usize, tau: usize) -> usize {
+/// it will incur at least `tau + 1` edit errors. It's between `tau + 1` and `q * tau + 1`,
+/// and at most the number of q-grams, which is returned if no prefix incurs enough errors.
+pub fn calc_prefix_len(qgram_array: &[PosQGram], q: usize, tau: usize) -> usize {
     let mut left: usize = tau + 1;
     let mut right: usize = q * tau + 1;
     let mut mid: usize;
@@ -80,16 +140,572 @@ pub fn calc_prefix_len(qgram_array: &mut PosQGramArray, q: usize, tau: usize) ->
             right = mid;
         }
     }
-    left = std::cmp::min(left, qgram_array.len());
-    #[cfg(feature = "cli")]
+    left = std::cmp::min(left, qgram_len);
+    #[cfg(feature = "logging")]
     trace!(
         "CalcPrefix for `{}`: prefix length = {}",
-        &qgram_array,
+        qgram_array
+            .iter()
+            .map(PosQGram::to_string)
+            .collect::<Vec<String>>()
+            .join(", "),
         &left
     );
     left
 }
 
+/// The threshold of a pair of records of `len_x` and `len_y` units, as a fraction of the longer one rounded up,
+/// and at most `tau`, see [`EdJoinConfig::tau_ratio`].
+///
+/// ```
+/// use ed_join::matching::ratio_tau;
+///
+/// assert_eq!(ratio_tau(0.1, 5, 30, 25), 3);
+/// assert_eq!(ratio_tau(0.1, 5, 31, 25), 4);
+/// assert_eq!(ratio_tau(0.1, 2, 31, 25), 2);
+/// ```
+pub fn ratio_tau(ratio: f64, tau: usize, len_x: usize, len_y: usize) -> usize {
+    // the product is nudged down first, so that e.g. `0.1 * 30` is 3 rather than 4, by its rounding error
+    let ratio_tau: f64 = (ratio * cmp::max(len_x, len_y) as f64 - 1e-9)
+        .ceil()
+        .max(0.0);
+    cmp::min(ratio_tau as usize, tau)
+}
+
+/// The largest threshold under a ratio of a record of `len` units with any record it may match, i.e. with a record
+/// as long as the length filter of that threshold allows, see [`ratio_tau`].
+pub fn record_tau(ratio: f64, tau: usize, len: usize) -> usize {
+    let mut record_tau: usize = ratio_tau(ratio, tau, len, len);
+    // a longer record gains at most one of threshold for each unit of length, as long as the ratio is at most 1
+    for len_y in len + 1..=len + tau {
+        let pair_tau: usize = ratio_tau(ratio, tau, len, len_y);
+        if len_y - len > pair_tau {
+            break;
+        }
+        record_tau = pair_tau;
+    }
+    record_tau
+}
+
+/// Generate candidates of a record of `doc_x` by the prefix filter, together with the length and position filters.
+///
+/// # Parameters
+///
+///  * `x_id` and `x_content`: Line number and content of the record.
+///  * `y_vec`: Records of `doc_y`.
+///  * `inverted_index`: The inverted index of `doc_y`.
+///  * `q`: A positive integer as the tuning parameter for length of q-grams.
+///  * `tau`: A positive integer as the tuning parameter for threshold for matching.
+///  * `self_join`: Whether `doc_x` and `doc_y` are the same, in which case only records after `x_id` are candidates.
+///
+/// # Return
+///
+/// The q-grams of the record, and line numbers of candidates in `doc_y` in increasing order.
+///
+/// Candidates are also pruned by the location-based mismatch filter on the prefix, which is the same bound as
+/// [`Verdict::LocationFilter`], but only needs the postings already probed, rather than all q-grams of the candidate.
+///
+/// A record whose q-grams can all be destroyed by `tau` edits has no prefix that a match must share, so its candidates
+/// are all records of `doc_y` within the length filter, which are scanned rather than probed.
+pub fn generate_candidates(
+    x_id: ID,
+    x_content: &str,
+    y_vec: &[String],
+    inverted_index: &dyn IndexBackend,
+    q: usize,
+    tau: usize,
+    self_join: bool,
+) -> (PosQGramArray, Vec<ID>) {
+    generate_candidates_pruned(
+        x_id,
+        x_content,
+        y_vec,
+        inverted_index,
+        q,
+        tau,
+        self_join,
+        &Pruning::default(),
+        &QGrams,
+    )
+}
+
+/// Same as [`generate_candidates`], but with candidates pruned aggressively, and thus possibly incompletely,
+/// and the grams of the record generated by the given tokenizer, which must be the one the index was built with.
+///
+/// Common q-grams of the prefix are not probed, and so don't count as mismatching, if their inverted lists are longer than
+/// `pruning.max_postings`, and the candidates sharing the most q-grams of the prefix are kept, up to `pruning.max_candidates`.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_candidates_pruned(
+    x_id: ID,
+    x_content: &str,
+    y_vec: &[String],
+    inverted_index: &dyn IndexBackend,
+    q: usize,
+    tau: usize,
+    self_join: bool,
+    pruning: &Pruning,
+    tokenizer: &dyn Tokenizer,
+) -> (PosQGramArray, Vec<ID>) {
+    let (qgram_array_x, candidates, _) = generate_candidates_capped(
+        x_id,
+        x_content,
+        y_vec,
+        inverted_index,
+        q,
+        tau,
+        self_join,
+        pruning,
+        tokenizer,
+    );
+    (qgram_array_x, candidates)
+}
+
+/// Same as [`generate_candidates_pruned`], but also return the number of candidates dropped by `pruning.max_candidates`.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_candidates_capped(
+    x_id: ID,
+    x_content: &str,
+    y_vec: &[String],
+    inverted_index: &dyn IndexBackend,
+    q: usize,
+    tau: usize,
+    self_join: bool,
+    pruning: &Pruning,
+    tokenizer: &dyn Tokenizer,
+) -> (PosQGramArray, Vec<ID>, usize) {
+    let mut qgram_array_x: PosQGramArray = PosQGramArray::tokenize(x_content, q, tokenizer);
+    // PosQGramArray is sorted in increasing order of location, but we need to sort it in increasing order of frequency
+    // to calculate the prefix length, which is stored in the secod slot of InvertedList
+    qgram_array_x.sort_by_frequency(inverted_index);
+
+    // calculate a prefix length between `tau + 1` and `q * tau + 1`, by `calc_prefix_len()`
+    let prefix_len: usize = calc_prefix_len(&qgram_array_x, q, tau);
+    let mode: TokenizerMode = tokenizer.mode();
+    let x_len: usize = mode.len_of(x_content);
+
+    // line numbers of the records each q-gram of the prefix is found in, within the length and position filters,
+    // or `None` if it's not probed
+    let prefix: &[PosQGram] = &qgram_array_x[..prefix_len];
+    let found: Vec<Option<Vec<ID>>> = prefix
+        .iter()
+        .map(|qgram| {
+            let token_x: Token = qgram.token.clone();
+            let loc_x: Loc = qgram.loc;
+
+            // NOTE, the first slot is the inverted list of document y
+            let inverted_list: Cow<[(ID, Loc)]> = inverted_index.postings(&token_x).unwrap();
+            if pruning
+                .max_postings
+                .is_some_and(|max_postings| inverted_list.len() > max_postings)
+            {
+                return None;
+            }
+            #[cfg(feature = "logging")]
+            trace!(
+                "**************\nI-list of `{}`: {:?}",
+                token_x,
+                inverted_list,
+            );
+
+            let mut filtered: Vec<ID> = inverted_list
+                .iter()
+                .filter(|(y_id, _loc_y)| {
+                    // only consider line id greater than current line when self-join
+                    // If not self-join => true
+                    // If self-join => (*id > x_id)
+                    !self_join || (*y_id > x_id)
+                })
+                .filter(|(y_id, loc_y)| {
+                    // length filter
+                    (mode.len_of(&y_vec[*y_id]) as isize - x_len as isize).abs() <= tau as isize
+                    // position filter
+                        && (loc_x as isize - *loc_y as isize).abs() <= tau as isize
+                })
+                .map(|pair| pair.0)
+                .collect();
+            filtered.sort_unstable();
+            filtered.dedup();
+            Some(filtered)
+        })
+        .collect();
+    let mut candidates: Vec<ID> = found.iter().flatten().flatten().copied().collect();
+    // if `tau` edits can destroy all q-grams of the record, a match may share none of them, so every record within
+    // the length filter is a candidate, except those too short to have any q-gram, which are never matched
+    let destroyable: bool = prefix_len == qgram_array_x.len() && min_edit_errors(prefix, q) <= tau;
+    if tokenizer.positional() && !prefix.is_empty() && destroyable {
+        let y_from: ID = if self_join { x_id + 1 } else { 0 };
+        candidates.extend((y_from..y_vec.len()).filter(|y_id| {
+            let y_len: usize = mode.len_of(&y_vec[*y_id]);
+            y_len >= q && (y_len as isize - x_len as isize).abs() <= tau as isize
+        }));
+    }
+    candidates.sort_unstable();
+    candidates.dedup();
+
+    // location-based mismatch filter on the prefix: each q-gram of the prefix that's not found in a candidate
+    // within `tau` positions must be destroyed by an edit, so a candidate is pruned if that takes more than `tau` edits
+    let mut candidates: Vec<ID> = candidates
+        .into_iter()
+        .filter(|y_id| {
+            let mut mismatch: Vec<PosQGram> = prefix
+                .iter()
+                .zip(&found)
+                .filter(|(_, y_ids)| {
+                    y_ids
+                        .as_ref()
+                        .is_some_and(|y_ids| y_ids.binary_search(y_id).is_err())
+                })
+                .map(|(qgram, _)| qgram.clone())
+                .collect();
+            mismatch.sort_unstable_by_key(|qgram| qgram.loc);
+            min_edit_errors(&mismatch, q) <= tau
+        })
+        .collect();
+
+    let mut dropped: usize = 0;
+    if let Some(max_candidates) = pruning.max_candidates.filter(|max| candidates.len() > *max) {
+        let hits = |y_id: &ID| {
+            found
+                .iter()
+                .flatten()
+                .filter(|y_ids| y_ids.binary_search(y_id).is_ok())
+                .count()
+        };
+        candidates.sort_by_cached_key(|y_id| (cmp::Reverse(hits(y_id)), *y_id));
+        dropped = candidates.len() - max_candidates;
+        candidates.truncate(max_candidates);
+        candidates.sort_unstable();
+    }
+
+    (qgram_array_x, candidates, dropped)
+}
+
+/// Estimate the cost of matching a record of `doc_x`, as the total length of the inverted lists its prefix probes.
+fn probe_cost(
+    x_content: &str,
+    inverted_index: &dyn IndexBackend,
+    q: usize,
+    tau: usize,
+    tokenizer: &dyn Tokenizer,
+) -> usize {
+    let mut qgram_array_x: PosQGramArray = PosQGramArray::tokenize(x_content, q, tokenizer);
+    qgram_array_x.sort_by_frequency(inverted_index);
+    let prefix_len: usize = calc_prefix_len(&qgram_array_x, q, tau);
+    qgram_array_x[..prefix_len]
+        .iter()
+        .map(|qgram| {
+            inverted_index
+                .postings(&qgram.token)
+                .map_or(0, |inverted_list| inverted_list.len())
+        })
+        .sum()
+}
+
+/// Join records held in memory, as `(x_id, y_id, ed)` of all pairs within `tau`, in no particular order.
+///
+/// Candidates are generated by the prefix filter as in a join of files, and verified by their edit distance directly.
+///
+/// # Parameters
+///
+///  * `records_x`: Records of `doc_x`, or `None` for a self-join of `records_y`.
+///  * `records_y`: Records of `doc_y`.
+///  * `q`, `tau`: The same as for [`generate_candidates`].
+///  * `tokenizer`: How the grams of records are generated.
+pub fn join_records(
+    records_x: Option<&[String]>,
+    records_y: &[String],
+    q: usize,
+    tau: usize,
+    tokenizer: &dyn Tokenizer,
+) -> Vec<(ID, ID, usize)> {
+    join_records_by(
+        records_x,
+        records_y,
+        q,
+        tau,
+        DistanceMetric::Levenshtein,
+        tokenizer,
+    )
+}
+
+/// Same as [`join_records`], but matching pairs within `tau` by `metric`.
+pub fn join_records_by(
+    records_x: Option<&[String]>,
+    records_y: &[String],
+    q: usize,
+    tau: usize,
+    metric: DistanceMetric,
+    tokenizer: &dyn Tokenizer,
+) -> Vec<(ID, ID, usize)> {
+    let inverted_index: InternedIndex = InternedIndex::from(build_inverted_index_with(
+        records_x, records_y, q, tokenizer,
+    ));
+    let x_vec: &[String] = records_x.unwrap_or(records_y);
+    (0..x_vec.len())
+        .into_par_iter()
+        .with_min_len(DEFAULT_BATCH_SIZE)
+        .flat_map_iter(|x_id| {
+            let x_content: &str = &x_vec[x_id];
+            match_in_memory(
+                x_id,
+                x_content,
+                records_y,
+                &inverted_index,
+                q,
+                tau,
+                metric,
+                records_x.is_none(),
+                tokenizer,
+            )
+            .into_iter()
+            .map(move |(y_id, ed)| (x_id, y_id, ed))
+        })
+        .collect()
+}
+
+/// Matches of a single record held in memory, as `(y_id, ed)` in increasing order of `y_id`, see [`join_records`].
+#[allow(clippy::too_many_arguments)]
+fn match_in_memory(
+    x_id: ID,
+    x_content: &str,
+    records_y: &[String],
+    inverted_index: &InternedIndex,
+    q: usize,
+    tau: usize,
+    metric: DistanceMetric,
+    self_join: bool,
+    tokenizer: &dyn Tokenizer,
+) -> Vec<(ID, usize)> {
+    let (_, candidates) = generate_candidates_pruned(
+        x_id,
+        x_content,
+        records_y,
+        inverted_index,
+        q,
+        metric.filter_tau(tau),
+        self_join,
+        &Pruning::default(),
+        tokenizer,
+    );
+    let mut buffer: EditDistanceBuffer = EditDistanceBuffer::default();
+    candidates
+        .into_iter()
+        .filter_map(|y_id| {
+            buffer
+                .bounded_distance_in(x_content, &records_y[y_id], tau, metric, tokenizer.mode())
+                .map(|ed| (y_id, ed))
+        })
+        .collect()
+}
+
+/// Matches of a join, as `(x_id, y_id, ed)` in increasing order of IDs, found lazily one batch of records of `doc_x`
+/// at a time, see [`EdJoinConfig::batch_size`].
+///
+/// Only the matches of the current batch are held, so a join of large inputs can be written, or otherwise consumed,
+/// as it goes, rather than after all of its matches are found. The records themselves and the inverted index stay in memory.
+///
+/// ```no_run
+/// use ed_join::{config::EdJoinConfig, matching::EdJoinIter};
+/// use std::path::PathBuf;
+///
+/// let doc = PathBuf::from("records.txt");
+/// for (x_id, y_id, ed) in EdJoinIter::new(&doc, &doc, &EdJoinConfig::new(2, 1)).unwrap() {
+///     println!("{},{},{}", x_id, y_id, ed);
+/// }
+/// ```
+pub struct EdJoinIter {
+    x_records: Vec<String>,
+    /// Records of `doc_y`, or `None` for a self-join.
+    y_records: Option<Vec<String>>,
+    inverted_index: InternedIndex,
+    q: usize,
+    tau: usize,
+    metric: DistanceMetric,
+    tau_ratio: Option<f64>,
+    tombstones: BTreeSet<ID>,
+    tokenizer: Arc<dyn Tokenizer>,
+    batch_size: usize,
+    warnings: Vec<Warning>,
+    /// The next record of `doc_x` to be matched.
+    next_x: ID,
+    /// Matches of the current batch, which are yet to be returned.
+    pending: std::vec::IntoIter<(ID, ID, usize)>,
+}
+
+impl EdJoinIter {
+    /// Read both documents and build their inverted index, with the same parameters as [`ed_join_matches`] supports.
+    pub fn new(doc_x: &PathBuf, doc_y: &PathBuf, config: &EdJoinConfig) -> Result<Self> {
+        if !config.is_plain() || config.windowing.is_some() || config.delta.is_some() {
+            bail!("Only plain joins can be iterated, without any other mode of joining or persisted phases");
+        }
+        if config.batch_size == Some(0) {
+            bail!("The batch size must be positive");
+        }
+        let self_join: bool = is_self_join(doc_x, doc_y, config.mode)?;
+        check_inputs(doc_x, doc_y, self_join)?;
+        let (x_records, mut warnings) = read_input(doc_x, Side::X, config)?;
+        let y_records: Option<Vec<String>> = if self_join {
+            None
+        } else {
+            let (y_records, mut warnings_y) = read_input(doc_y, Side::Y, config)?;
+            warnings.append(&mut warnings_y);
+            Some(y_records)
+        };
+        let inverted_index: InternedIndex = match &y_records {
+            Some(y_records) => {
+                build_inverted_index_with(Some(&x_records), y_records, config.q, &*config.tokenizer)
+            }
+            None => build_inverted_index_with(None, &x_records, config.q, &*config.tokenizer),
+        }
+        .into();
+
+        Ok(Self {
+            x_records,
+            y_records,
+            inverted_index,
+            q: config.q,
+            tau: config.tau,
+            metric: config.metric,
+            tau_ratio: config.tau_ratio,
+            tombstones: config.tombstones.clone(),
+            tokenizer: Arc::clone(&config.tokenizer),
+            batch_size: config.batch_size.unwrap_or(DEFAULT_BATCH_SIZE),
+            warnings,
+            next_x: 0,
+            pending: Vec::new().into_iter(),
+        })
+    }
+
+    /// Warnings encountered while reading the documents.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Matches of a record of `doc_x`, as `(y_id, ed)` in increasing order of `y_id`.
+    fn matches_of(&self, x_id: ID) -> Vec<(ID, usize)> {
+        if self.y_records.is_none() && self.tombstones.contains(&x_id) {
+            return Vec::new();
+        }
+        let records_y: &[String] = self.y_records.as_deref().unwrap_or(&self.x_records);
+        let mut matched: Vec<(ID, usize)> = match_in_memory(
+            x_id,
+            &self.x_records[x_id],
+            records_y,
+            &self.inverted_index,
+            self.q,
+            self.tau,
+            self.metric,
+            self.y_records.is_none(),
+            &*self.tokenizer,
+        );
+        matched.retain(|(y_id, _)| !self.tombstones.contains(y_id));
+        if let Some(ratio) = self.tau_ratio {
+            let mode: TokenizerMode = self.tokenizer.mode();
+            let x_len: usize = mode.len_of(&self.x_records[x_id]);
+            matched.retain(|(y_id, ed)| {
+                *ed <= ratio_tau(ratio, self.tau, x_len, mode.len_of(&records_y[*y_id]))
+            });
+        }
+        matched
+    }
+}
+
+impl Iterator for EdJoinIter {
+    type Item = (ID, ID, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(pair) = self.pending.next() {
+                return Some(pair);
+            }
+            if self.next_x == self.x_records.len() {
+                return None;
+            }
+            // the records of a batch are matched in parallel, and their matches kept in order
+            let batch: Range<ID> =
+                self.next_x..cmp::min(self.next_x + self.batch_size, self.x_records.len());
+            self.next_x = batch.end;
+            let this: &Self = self;
+            let matched: Vec<(ID, ID, usize)> = batch
+                .into_par_iter()
+                .flat_map_iter(|x_id| {
+                    this.matches_of(x_id)
+                        .into_iter()
+                        .map(move |(y_id, ed)| (x_id, y_id, ed))
+                })
+                .collect();
+            self.pending = matched.into_iter();
+        }
+    }
+}
+
+/// Join two collections of strings held in memory, without touching the filesystem.
+///
+/// # Return
+///
+/// All pairs `(x_id, y_id, ed)` within `tau`, where the IDs are indices into `x` and `y`, in increasing order of IDs.
+///
+/// ```
+/// use ed_join::api::ed_join_records;
+///
+/// let x: Vec<String> = vec!["hello".to_string(), "world".to_string()];
+/// let y: Vec<String> = vec!["word".to_string(), "hallo".to_string()];
+/// assert_eq!(ed_join_records(&x, &y, 2, 1), vec![(0, 1, 1), (1, 0, 1)]);
+/// ```
+pub fn ed_join_records(x: &[String], y: &[String], q: usize, tau: usize) -> Vec<(ID, ID, usize)> {
+    let mut pairs: Vec<(ID, ID, usize)> = join_records(Some(x), y, q, tau, &QGrams);
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Same as [`ed_join_records`], but a self-join of `records`, in which each pair is reported once, with `x_id < y_id`.
+pub fn ed_self_join_records(records: &[String], q: usize, tau: usize) -> Vec<(ID, ID, usize)> {
+    let mut pairs: Vec<(ID, ID, usize)> = join_records(None, records, q, tau, &QGrams);
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Check a single pair of strings by the same filters and threshold as the join, e.g. for spot checks.
+///
+/// # Parameters
+///
+///  * `x` and `y`: The pair of strings, as if they're records of `doc_x` and `doc_y`, respectively.
+///  * `q`: A positive integer as the tuning parameter for length of q-grams.
+///  * `tau`: A positive integer as the tuning parameter for threshold for matching.
+///
+/// # Return
+///
+/// The verdict on the pair, i.e. the filter which rejected it, or its edit distance.
+pub fn check_pair(x: &str, y: &str, q: usize, tau: usize) -> Verdict {
+    let mode: TokenizerMode = TokenizerMode::Bytes;
+    if (mode.len_of(x) as isize - mode.len_of(y) as isize).abs() > tau as isize {
+        return Verdict::LengthFilter;
+    }
+
+    let (records_x, records_y): ([String; 1], [String; 1]) = ([x.to_string()], [y.to_string()]);
+    let inverted_index: InvertedIndex = build_inverted_index(Some(&records_x), &records_y, q);
+    let (qgram_array_x, candidates) =
+        generate_candidates(0, x, &records_y, &inverted_index, q, tau, false);
+    if candidates.is_empty() {
+        return Verdict::PrefixFilter;
+    }
+
+    let mut qgram_array_y = PosQGramArray::from(y, q);
+    filter_pair(
+        qgram_array_x.to_vec(),
+        0,
+        x,
+        &mut qgram_array_y,
+        0,
+        y,
+        &inverted_index,
+        q,
+        tau,
+        DistanceMetric::Levenshtein,
+        mode,
+    )
+}
+
 // Algorithm 1
 /// Given two input files, `doc_x` and `doc_y`, and two parameters, `q` and `tau`,
 /// find all records in `doc_y` that match records in `doc_x` such that are matched pairs have edit-distance smaller or equal to `tau`.
@@ -106,184 +722,2885 @@ pub fn calc_prefix_len(qgram_array: &mut PosQGramArray, q: usize, tau: usize) ->
 /// # Return
 ///
 /// All matching pairs. This would be stored in a output file automatically under the same directory of the first input file.
-pub fn ed_join(doc_x: &PathBuf, doc_y: &PathBuf, q: usize, tau: usize) -> Result<()> {
-    // `doc_x` is read by a BufReader, line by line
-    let file_x: File = File::open(doc_x)?;
-    let reader_x: BufReader<File> = BufReader::new(file_x);
-
-    // Read entire `doc_y` into a vector to reduce IO
-    let file_y: File = File::open(doc_x)?;
-    let mut reader_y: BufReader<File> = BufReader::new(file_y);
-    let mut y_buffer: String = String::new();
-    reader_y.read_to_string(&mut y_buffer)?;
-    let y_vec: Vec<Vec<u8>> = y_buffer.par_lines().map(Vec::from).collect();
-
-    let out_name: PathBuf = PathBuf::from(
-        format!(
-            "{}_out_q{}_tau{}.{}",
-            doc_x.file_stem().unwrap().to_str().unwrap(),
-            q,
-            tau,
-            // note that extension may be empty
-            doc_x
-                .extension()
-                .unwrap_or_else(|| std::ffi::OsStr::new("txt"))
-                .to_str()
-                .unwrap()
-        )
-        .to_string(),
-    );
-    let doc_out: File = File::create(&out_name).expect("Failed to Create File");
-    let mut writer: BufWriter<File> = BufWriter::new(doc_out);
-    let mut output_vec: Vec<(ID, Vec<(ID, usize)>)> = Vec::new();
-    let (output_s, output_r) = unbounded::<Vec<(ID, Vec<(ID, usize)>)>>();
-
-    let inverted_index: InvertedIndex = generate_inverted_index(doc_x, doc_y, q)?;
-    #[cfg(feature = "cli")]
-    debug!("InvertedList: {:?}", &inverted_index);
-
-    #[cfg(not(feature = "cli"))]
-    let file_x_iter = reader_x.lines().enumerate().par_bridge();
-    #[cfg(feature = "cli")]
-    let file_x_iter;
-    #[cfg(feature = "cli")]
-    {
-        // progress bar
-        let file_x_len: usize = BufReader::new(File::open(doc_x)?).lines().count();
-        let pbar: ProgressBar = ProgressBarBuilder::new(file_x_len, "Processing").build();
-        file_x_iter = reader_x
-            .lines()
-            .enumerate()
-            .par_bridge()
-            .progress_with(pbar);
-    }
+/// The returned report holds the warnings encountered, e.g. records that were skipped.
+/// An empty `doc_x` has an empty output, while a cross join with an empty `doc_y` is an [`ErrorKind::EmptyReference`].
+pub fn ed_join(doc_x: &PathBuf, doc_y: &PathBuf, q: usize, tau: usize) -> Result<JoinReport> {
+    ed_join_with_config(doc_x, doc_y, &EdJoinConfig::new(q, tau))
+}
 
-    file_x_iter.for_each(|(x_id, line_x)| {
-        let x_content = String::from(line_x.unwrap());
-        #[cfg(feature = "cli")]
-        trace!(
-            "=====================\nCurrent line {}: {}",
-            x_id,
-            x_content
-        );
+/// Same as [`ed_join_with_config`], but the matches are returned rather than written to a file.
+///
+/// Candidates are generated by the prefix filter and verified by their edit distance, as by [`ed_join_records`], so only `q`,
+/// `tau`, the error policy, the tokenizer, the input format and a forced cross join of `config` apply, and the other modes
+/// of joining and persisted phases are errors.
+///
+/// ```no_run
+/// use ed_join::{config::EdJoinConfig, matching::ed_join_matches, output::OutputFormat};
+/// use std::{fs::File, path::PathBuf};
+///
+/// let doc = PathBuf::from("records.txt");
+/// let output = ed_join_matches(&doc, &doc, &EdJoinConfig::new(2, 1)).unwrap();
+/// println!("{} records have a match", output.pairs.len());
+/// // writing a file is up to the caller
+/// output.write(File::create("matches.csv").unwrap(), &OutputFormat::Csv, b',').unwrap();
+/// ```
+pub fn ed_join_matches(
+    doc_x: &PathBuf,
+    doc_y: &PathBuf,
+    config: &EdJoinConfig,
+) -> Result<JoinOutput> {
+    let PlainInputs {
+        self_join,
+        x_records,
+        original_x,
+        y_records,
+        original_y,
+        report,
+    } = read_plain_inputs(doc_x, doc_y, config, "return their matches")?;
 
-        let mut qgram_array_x: PosQGramArray = PosQGramArray::from(&x_content, q);
-        // PosQGramArray is sorted in increasing order of location, but we need to sort it in increasing order of frequency
-        // to calculate the prefix length, which is stored in the secod slot of InvertedList
-        qgram_array_x.sort_by_frequency(&inverted_index);
+    let mut pairs: Vec<(ID, ID, usize)> = match &y_records {
+        Some(y_records) => join_records_by(
+            Some(&x_records),
+            y_records,
+            config.q,
+            config.tau,
+            config.metric,
+            &*config.tokenizer,
+        ),
+        None => join_records_by(
+            None,
+            &x_records,
+            config.q,
+            config.tau,
+            config.metric,
+            &*config.tokenizer,
+        ),
+    };
+    let tombstoned = |id: &ID| config.tombstones.contains(id);
+    pairs.retain(|(x_id, y_id, _)| !(tombstoned(y_id) || (self_join && tombstoned(x_id))));
+    if let Some(ratio) = config.tau_ratio {
+        let (mode, records_y): (TokenizerMode, &[String]) = (
+            config.tokenizer.mode(),
+            y_records.as_deref().unwrap_or(&x_records),
+        );
+        pairs.retain(|(x_id, y_id, ed)| {
+            *ed <= ratio_tau(
+                ratio,
+                config.tau,
+                mode.len_of(&x_records[*x_id]),
+                mode.len_of(&records_y[*y_id]),
+            )
+        });
+    }
+    pairs.sort_unstable();
+    let mut grouped: Vec<(ID, Vec<(ID, usize)>)> = Vec::new();
+    for (x_id, y_id, ed) in pairs {
+        match grouped.last_mut() {
+            Some((last, matched)) if *last == x_id => matched.push((y_id, ed)),
+            _ => grouped.push((x_id, vec![(y_id, ed)])),
+        }
+    }
 
-        // calculate a prefix length between `tau + 1` and `q * tau + 1`, by `calc_prefix_len()`
-        let prefix_len: usize = calc_prefix_len(&mut qgram_array_x, q, tau);
+    // transliterated records are returned with their original text
+    Ok(JoinOutput {
+        pairs: grouped,
+        x_records: original_x.unwrap_or(x_records),
+        y_records: original_y.or(y_records),
+        report,
+    })
+}
 
-        let mut candidates: Vec<ID> = qgram_array_x
-            .par_iter()
-            .take(prefix_len)
-            .flat_map(|qgram| {
-                let token_x: Token = qgram.token.clone();
-                let loc_x: Loc = qgram.loc;
+/// Same as [`ed_join_matches`], but the join stops after the prefix filter, and its candidate pairs are returned
+/// without their edit distances being calculated, e.g. as the blocking step of another matcher.
+///
+/// The candidates are those the join would verify, i.e. a superset of its matches, and the same parts of `config` apply.
+/// Records whose candidates couldn't be generated are skipped with a warning, or abort the join, by the error policy.
+///
+/// ```no_run
+/// use ed_join::{config::EdJoinConfig, matching::ed_join_candidates};
+/// use std::{fs::File, path::PathBuf};
+///
+/// let doc = PathBuf::from("records.txt");
+/// let candidates = ed_join_candidates(&doc, &doc, &EdJoinConfig::new(2, 1)).unwrap();
+/// println!("{} candidate pairs", candidates.len());
+/// candidates.write(File::create("candidates.csv").unwrap(), b',').unwrap();
+/// ```
+pub fn ed_join_candidates(
+    doc_x: &PathBuf,
+    doc_y: &PathBuf,
+    config: &EdJoinConfig,
+) -> Result<JoinCandidates> {
+    let PlainInputs {
+        self_join,
+        x_records,
+        original_x,
+        y_records,
+        original_y,
+        mut report,
+    } = read_plain_inputs(doc_x, doc_y, config, "return their candidates")?;
+    let (q, tokenizer): (usize, &dyn Tokenizer) = (config.q, &*config.tokenizer);
+    // the filters bound the Levenshtein distance, so candidates are generated by its threshold
+    let tau: usize = config.metric.filter_tau(config.tau);
+    let records_y: &[String] = y_records.as_deref().unwrap_or(&x_records);
+    let index: InternedIndex = InternedIndex::from(build_inverted_index_with(
+        if self_join { None } else { Some(&x_records) },
+        records_y,
+        q,
+        tokenizer,
+    ));
 
-                // NOTE, the first slot is the inverted list of document y
-                let inverted_list: &Vec<(ID, Loc)> = &inverted_index[&token_x].0;
-                #[cfg(feature = "cli")]
-                trace!(
-                    "**************\nI-list of `{}`: {:?}",
-                    token_x,
-                    inverted_list,
+    let tombstoned = |id: &ID| config.tombstones.contains(id);
+    let generated: Vec<(ID, std::result::Result<Vec<ID>, String>)> = (0..x_records.len())
+        .into_par_iter()
+        .with_min_len(DEFAULT_BATCH_SIZE)
+        .filter(|x_id| !(self_join && tombstoned(x_id)))
+        .map(|x_id| {
+            let result = catch_invariant(|| {
+                let (_, mut y_ids) = generate_candidates_pruned(
+                    x_id,
+                    &x_records[x_id],
+                    records_y,
+                    &index,
+                    q,
+                    tau,
+                    self_join,
+                    &Pruning::default(),
+                    tokenizer,
                 );
+                y_ids.retain(|y_id| !tombstoned(y_id));
+                y_ids.sort_unstable();
+                y_ids
+            });
+            (x_id, result)
+        })
+        .collect();
+    let mut pairs: Vec<(ID, Vec<ID>)> = Vec::with_capacity(generated.len());
+    for (x_id, result) in generated {
+        match result {
+            Ok(y_ids) if y_ids.is_empty() => (),
+            Ok(y_ids) => {
+                report.stats.candidates += y_ids.len();
+                pairs.push((x_id, y_ids));
+            }
+            Err(msg) if config.error_policy == ErrorPolicy::Abort => {
+                bail!(ErrorKind::InvariantViolation(msg))
+            }
+            Err(msg) => report
+                .warnings
+                .push(Warning::SkippedRecord { id: x_id, msg }),
+        }
+    }
 
-                let mut filtered: Vec<ID> = inverted_list
-                    .par_iter()
-                    .filter(|(y_id, _loc_y)| {
-                        // only consider line id greater than current line when self-join
-                        // If doc_x != doc_y => false && not_evaluated || true => true
-                        // If doc_x == doc_y => true && (*id > x_id) || false => (*id > x_id)
-                        (doc_x == doc_y) && (*y_id > x_id) || (doc_x != doc_y)
-                    })
-                    .filter(|(y_id, loc_y)| {
-                        // length filter
-                        (y_vec[*y_id].len() as isize - x_content.len() as isize).abs() <= tau as isize
-                        // position filter
-                            && (loc_x as isize - *loc_y as isize).abs() <= tau as isize
-                    })
-                    .map(|pair| pair.0)
-                    .collect();
-                filtered.par_sort_unstable();
-                filtered.dedup();
-                filtered
-            })
-            .collect();
-        candidates.par_sort_unstable();
-        candidates.dedup();
+    // transliterated records are returned with their original text
+    Ok(JoinCandidates {
+        pairs,
+        x_records: original_x.unwrap_or(x_records),
+        y_records: original_y.or(y_records),
+        report,
+    })
+}
 
-        #[cfg(feature = "cli")]
-        debug!("Candidate of `{}: {}`: {:?}", x_id, x_content, candidates);
+/// The inputs of a join which returns its result, i.e. its records as they're joined, and as they were read if they
+/// differ, with the warnings of reading them.
+struct PlainInputs {
+    self_join: bool,
+    x_records: Vec<String>,
+    original_x: Option<Vec<String>>,
+    /// `None` for a self-join.
+    y_records: Option<Vec<String>>,
+    original_y: Option<Vec<String>>,
+    report: JoinReport,
+}
 
-        let mut verified: Vec<(ID, Vec<(ID, usize)>)> = candidates
-            .par_iter()
-            .map(|y_id| {
-                let y_content = std::str::from_utf8(&y_vec[*y_id]).unwrap();
-                let qgram_array_y = PosQGramArray::from(&y_content, q);
-                (y_id, y_content, qgram_array_y)
-            })
-            .filter_map(|(y_id, y_content, mut qgram_array_y)|{
-                verify(
-                    qgram_array_x.to_vec(),
-                    x_id,
-                    &x_content,
-                    &mut qgram_array_y,
-                    *y_id,
-                    &y_content,
-                    &inverted_index,
-                    q,
-                    tau,
-                )
-            })
-            .collect();
-        verified.par_iter_mut().for_each(|(_x_id, yvec)| yvec.par_sort_unstable_by(|(a_id, _a_ed), (b_id, _b_ed)| a_id.cmp(&b_id)));
+/// Read the inputs of a join which returns its result, which must be a plain join, see [`ed_join_matches`].
+fn read_plain_inputs(
+    doc_x: &PathBuf,
+    doc_y: &PathBuf,
+    config: &EdJoinConfig,
+    returned: &str,
+) -> Result<PlainInputs> {
+    if !config.is_plain() || config.windowing.is_some() || config.delta.is_some() {
+        bail!(
+            "Only plain joins can {}, without any other mode of joining or persisted phases",
+            returned
+        );
+    }
+    let self_join: bool = is_self_join(doc_x, doc_y, config.mode)?;
+    check_inputs(doc_x, doc_y, self_join)?;
+    let mut report: JoinReport = JoinReport::default();
+    let (x_records, original_x, mut warnings_x) =
+        read_input_with_originals(doc_x, Side::X, config)?;
+    report.warnings.append(&mut warnings_x);
+    let (y_records, original_y): (Option<Vec<String>>, Option<Vec<String>>) = if self_join {
+        (None, None)
+    } else {
+        let (y_records, original_y, mut warnings_y) =
+            read_input_with_originals(doc_y, Side::Y, config)?;
+        report.warnings.append(&mut warnings_y);
+        (Some(y_records), original_y)
+    };
 
-        output_s.send(verified).unwrap();
+    Ok(PlainInputs {
+        self_join,
+        x_records,
+        original_x,
+        y_records,
+        original_y,
+        report,
+    })
+}
 
-    });
-    drop(output_s);
+/// Run a panicking computation, and turn a panic into the message it carries.
+///
+/// Panics inside the algorithms indicate violated internal invariants,
+/// which are then handled according to the `ErrorPolicy`.
+fn catch_invariant<T>(f: impl FnOnce() -> T) -> std::result::Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        if let Some(msg) = payload.downcast_ref::<&str>() {
+            msg.to_string()
+        } else if let Some(msg) = payload.downcast_ref::<String>() {
+            msg.clone()
+        } else {
+            "unknown panic".to_string()
+        }
+    })
+}
 
-    while let Ok(mut v) = output_r.recv() {
-        output_vec.append(&mut v);
+/// Same as [`ed_join`], but with all parameters given by an [`EdJoinConfig`].
+///
+/// The join has no state shared with other joins, so several of them can run concurrently in one process,
+/// each with its own thread pool and progress observer.
+pub fn ed_join_with_config(
+    doc_x: &PathBuf,
+    doc_y: &PathBuf,
+    config: &EdJoinConfig,
+) -> Result<JoinReport> {
+    // the name is resolved once, as it may depend on the date
+    let out_name: PathBuf = output_name(doc_x, config);
+    let mut report: JoinReport = match config.num_threads {
+        Some(num_threads) => ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .map_err(|err| Error::from(format!("Failed to build a thread pool: {}", err)))?
+            .install(|| join(doc_x, doc_y, config, &out_name)),
+        None => join(doc_x, doc_y, config, &out_name),
+    }?;
+    if config.clustering.is_some() && config.stop_after.is_none() {
+        report.clusters = Some(clusters_name(&out_name));
     }
-    drop(output_r);
+    report.output = match config.stop_after {
+        Some(phase) => Some(phase_name(&out_name, phase)),
+        // nothing is written to a file of its own with a sink
+        None if config.output_sink.is_some() => None,
+        None => Some(out_name),
+    };
+    Ok(report)
+}
 
-    // sort by line id of doc_x, i.e. the first slot
-    output_vec.par_sort_by_key(|x| x.0);
+/// Path of the result of a join that stopped after `phase`, next to its output and named after it,
+/// i.e. `<doc_x>_out_q<q>_tau<tau>.idx` for the index and `<doc_x>_out_q<q>_tau<tau>.candidates.tsv`
+/// for candidates by default.
+pub fn phase_name(out_name: &Path, phase: Phase) -> PathBuf {
+    match phase {
+        Phase::Index => out_name.with_extension("idx"),
+        Phase::Candidates => out_name.with_extension("candidates.tsv"),
+    }
+}
 
-    #[cfg(feature = "cli")]
-    debug!("Mathes: {:?}", output_vec);
+/// Path of the clusters of a join, next to its output and named after it,
+/// i.e. `<doc_x>_out_q<q>_tau<tau>.clusters.csv` by default.
+pub fn clusters_name(out_name: &Path) -> PathBuf {
+    out_name.with_extension("clusters.csv")
+}
 
-    output_vec.iter().for_each(|(id_x, pairs)| {
-        // first sort the pairs, which is a vector of ID and edit-distance,
-        // by ID, that is the ID from doc_y
-        pairs.iter().for_each(|(id_y, ed)| {
-            writer
-                .write_all(format!("{},{},{}\n", id_x, id_y, ed).as_bytes())
-                .expect("Failed to write to output file.");
-        })
-    });
+/// Path of the output file of a join, named by [`EdJoinConfig::output_naming`] in [`EdJoinConfig::output_dir`],
+/// i.e. `<doc_x>_out_q<q>_tau<tau>.<ext>` in the current directory by default,
+/// where the extension is that of the output format, or else of `doc_x`.
+pub fn output_name(doc_x: &Path, config: &EdJoinConfig) -> PathBuf {
+    let algo: &str = if config.windowing.is_some() {
+        "window"
+    } else if config.delta.is_some() {
+        "delta"
+    } else if config.knn.is_some() {
+        "knn"
+    } else if config.recall.is_some() {
+        "approx"
+    } else {
+        "ed_join"
+    };
+    // names that aren't valid UTF-8 are written lossily
+    let ext: Cow<str> = match config.output_format.extension() {
+        Some(ext) => Cow::Borrowed(ext),
+        // note that extension may be empty
+        None => doc_x
+            .extension()
+            .map_or(Cow::Borrowed("txt"), |ext| ext.to_string_lossy()),
+    };
+    let name: String = config.output_naming.render(
+        &doc_x.file_stem().unwrap_or_default().to_string_lossy(),
+        config.q,
+        config.tau,
+        &ext,
+        algo,
+    );
+    match &config.output_dir {
+        Some(dir) => dir.join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+/// Whether two paths refer to the same file, e.g. through a symlink or a relative path.
+fn same_file(doc_x: &Path, doc_y: &Path) -> bool {
+    doc_x == doc_y
+        || matches!((fs::canonicalize(doc_x), fs::canonicalize(doc_y)), (Ok(x), Ok(y)) if x == y)
+}
+
+/// Whether two files have the same content, compared block by block rather than read as a whole.
+fn same_content(doc_x: &Path, doc_y: &Path) -> Result<bool> {
+    if fs::metadata(doc_x)?.len() != fs::metadata(doc_y)?.len() {
+        return Ok(false);
+    }
+    let (mut reader_x, mut reader_y) = (
+        BufReader::new(File::open(doc_x)?),
+        BufReader::new(File::open(doc_y)?),
+    );
+    loop {
+        let block_x: &[u8] = reader_x.fill_buf()?;
+        let block_y: &[u8] = reader_y.fill_buf()?;
+        let len: usize = min(block_x.len(), block_y.len());
+        if len == 0 {
+            return Ok(block_x.is_empty() && block_y.is_empty());
+        }
+        if block_x[..len] != block_y[..len] {
+            return Ok(false);
+        }
+        reader_x.consume(len);
+        reader_y.consume(len);
+    }
+}
+
+/// Whether a join of `doc_x` and `doc_y` is a self-join, as decided by its [`JoinMode`].
+pub(crate) fn is_self_join(doc_x: &Path, doc_y: &Path, mode: JoinMode) -> Result<bool> {
+    match mode {
+        JoinMode::Auto => Ok(same_file(doc_x, doc_y)),
+        JoinMode::SelfJoin if same_file(doc_x, doc_y) || same_content(doc_x, doc_y)? => Ok(true),
+        JoinMode::SelfJoin => bail!(
+            "{} and {} are different documents, which cannot be self-joined",
+            doc_x.display(),
+            doc_y.display()
+        ),
+        JoinMode::CrossJoin => Ok(false),
+    }
+}
 
+/// Check that `doc_y` has anything to match against, unless it's a self-join.
+///
+/// An empty `doc_x` simply has no matches, and its output is empty, but a zero-length reference file
+/// is more likely a mistake, e.g. a truncated download, than an input that's meant to match nothing.
+pub(crate) fn check_inputs(doc_x: &Path, doc_y: &Path, self_join: bool) -> Result<()> {
+    if !self_join && fs::metadata(doc_y)?.len() == 0 {
+        bail!(ErrorKind::EmptyReference(doc_y.display().to_string()));
+    }
+    if fs::metadata(doc_x)?.len() == 0 {
+        #[cfg(feature = "logging")]
+        info!("{} is empty, so it has no matches", doc_x.display());
+    }
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::qgram::{PosQGram, PosQGramArray};
+/// The body of [`ed_join_with_config`], which runs on the current thread pool.
+///
+/// Unless its output is restored from the cache, a join reads its inputs, builds the index of `doc_y`,
+/// verifies the candidates of each record of `doc_x`, and writes the matches, see [`read_inputs`], [`build_index`],
+/// [`verify`] and [`write_output`]. A join that stops after the index or the candidates persists them instead.
+fn join(
+    doc_x: &PathBuf,
+    doc_y: &PathBuf,
+    config: &EdJoinConfig,
+    out_name: &Path,
+) -> Result<JoinReport> {
+    config.validate()?;
+    let (q, tau) = (config.q, config.tau);
+    // a self-join only reports each pair once, and never a record with itself
+    let self_join: bool = is_self_join(doc_x, doc_y, config.mode)?;
+    let mut report: JoinReport = JoinReport::default();
+    check_inputs(doc_x, doc_y, self_join)?;
+    let clusters_name: PathBuf = clusters_name(out_name);
+    let mut outputs: Vec<(&str, &Path)> = vec![("output", out_name)];
+    if config.clustering.is_some() {
+        outputs.push(("clusters", &clusters_name));
+    }
+    if let Some(candidates_name) = &config.emit_candidates {
+        outputs.push(("candidates", candidates_name));
+    }
+    if let Some(diagnostics_name) = &config.diagnostics {
+        outputs.push(("diagnostics", diagnostics_name));
+    }
 
-    #[test]
-    fn test_min_edit_error() {
-        let qgram_array: PosQGramArray = PosQGramArray::from("hello", 2);
-        assert_eq!(min_edit_errors(&qgram_array, 2), 2);
+    if let Some(windowing) = &config.windowing {
+        return window_join(doc_x, doc_y, out_name, config, windowing);
+    }
+    if let Some(delta) = &config.delta {
+        return delta_join(doc_x, doc_y, out_name, config, delta);
+    }
+
+    // the sidecar is written up front, so that it's there even if the output is restored from cache
+    if config.line_offsets {
+        LineOffsets::load_or_build(doc_y, true)?;
+    }
+
+    // an identical run that was already completed is restored rather than recomputed
+    // but runs of single phases aren't cached, since persisted files aren't part of the key
+    let single_phase: bool = config.stop_after.is_some()
+        || config.index_from.is_some()
+        || config.candidates_from.is_some();
+    let cache_entry: Option<CacheEntry> = match &config.cache_dir {
+        Some(cache_dir) if !single_phase => {
+            Some(CacheEntry::new(cache_dir, doc_x, doc_y, config, self_join)?)
+        }
+        _ => None,
+    };
+    if let Some(cache_entry) = &cache_entry {
+        if let Some(report) = cache_entry.restore(&outputs)? {
+            #[cfg(feature = "logging")]
+            info!("Restored the output of an identical run from cache");
+            return Ok(report);
+        }
+    }
+
+    let warm_entry: Option<WarmEntry> = match &config.warm_dir {
+        Some(warm_dir) => Some(WarmEntry::new(warm_dir, doc_x, doc_y, config, self_join)?),
+        None => None,
+    };
+
+    let inputs: Inputs = read_inputs(doc_x, doc_y, config, self_join, &mut report)?;
+    // a manifest is only left by a join that exceeded a limit, which this one may do again
+    let manifest_name: PathBuf = PathBuf::from(format!("{}.manifest", out_name.display()));
+    let index: JoinIndex = build_index(
+        &inputs,
+        config,
+        self_join,
+        warm_entry.as_ref(),
+        &manifest_name,
+        &mut report,
+    )?;
+    if config.stop_after == Some(Phase::Index) {
+        persist_index(&index.inverted, inputs.y_vec().len(), q, out_name)?;
+        return Ok(report);
+    }
+
+    // an approximate join prunes candidates as much as the recall allows, as estimated on a sample
+    let pruning: Pruning = match config.recall {
+        Some(recall) => {
+            let (pruning, estimate) = tune(
+                &inputs.x_vec,
+                &inputs.skipped_x,
+                inputs.y_vec(),
+                &index.interned,
+                q,
+                tau,
+                self_join,
+                recall,
+                &*config.tokenizer,
+            );
+            report.estimated_recall = estimate;
+            pruning
+        }
+        None => Pruning::default(),
+    };
+    if config.stop_after == Some(Phase::Candidates) {
+        persist_candidates(
+            &inputs,
+            index.backend(),
+            &pruning,
+            config,
+            self_join,
+            out_name,
+            &mut report,
+        )?;
+        return Ok(report);
+    }
+    let persisted: Option<HashMap<ID, Vec<ID>>> = match &config.candidates_from {
+        Some(candidates_name) => Some(read_candidates(candidates_name, q, tau)?),
+        None => None,
+    };
+
+    let matches: Matches = inputs.matches(index.backend(), config, self_join);
+    let mut output: OutputFile = OutputFile::open(out_name, config)?;
+    let verified: Verified = verify(
+        &inputs,
+        index.backend(),
+        &pruning,
+        persisted.as_ref(),
+        warm_entry.as_ref(),
+        matches,
+        config,
+        &mut output,
+        &manifest_name,
+        &mut report,
+    )?;
+    write_output(
+        &verified,
+        matches,
+        config,
+        output,
+        out_name,
+        &clusters_name,
+        &mut report,
+    )?;
+    if let (Some(warm_entry), Some(distances)) = (&warm_entry, &verified.distances) {
+        write_distances(&warm_entry.distances_path(), distances, q)?;
+    }
+    if let Some(cache_entry) = &cache_entry {
+        cache_entry.store(&outputs, &report)?;
+    }
+
+    Ok(report)
+}
+
+/// Records of both inputs of a join, as read and prepared for it by [`read_inputs`].
+struct Inputs<'a> {
+    x_vec: Vec<String>,
+    /// Texts of the records of `doc_x` as they were read, if they differ from `x_vec`, see [`read_input_with_originals`].
+    original_x: Option<Vec<String>>,
+    read_y: ReadRecords<'a>,
+    /// Records of `doc_y` prepared for the join, if they differ from those read, see [`prepare_records`].
+    prepared_y: Option<Vec<String>>,
+    /// Whether records are written with their original texts, as they were transliterated.
+    transliterated: bool,
+    /// Passthrough fields of the rows of `doc_x`, and of `doc_y` unless they're the same, see [`Matches::passthrough`].
+    passthrough: Option<(Vec<Fields>, Option<Vec<Fields>>)>,
+    /// Parts of the merged index of `doc_y`, if matches are written with them, see [`Matches::provenance`].
+    sources: Option<Vec<MergedPart>>,
+    /// Records of `doc_x` which aren't matched, as they were skipped, or tombstoned in a self-join.
+    skipped_x: HashSet<ID>,
+}
+
+/// Where the records of `doc_y` were read from.
+enum ReadRecords<'a> {
+    /// They're those of `doc_x`, of a self-join, or of an identical copy of `doc_x`, which is only read once.
+    X,
+    /// The record store of the join, see [`EdJoinConfig::record_store`].
+    Store(&'a RecordStore),
+    Loaded(RecordStore),
+}
+
+impl Inputs<'_> {
+    /// Records of `doc_y` as they were read.
+    fn read_y(&self) -> &[String] {
+        match &self.read_y {
+            ReadRecords::X => &self.x_vec,
+            ReadRecords::Store(store) => store.records(),
+            ReadRecords::Loaded(store) => store.records(),
+        }
+    }
+
+    /// Records of `doc_y` as they're joined.
+    fn y_vec(&self) -> &[String] {
+        match &self.prepared_y {
+            Some(prepared_y) => prepared_y,
+            None => self.read_y(),
+        }
+    }
+
+    /// Records of `doc_x` as they're written.
+    fn original_x(&self) -> &[String] {
+        self.original_x.as_deref().unwrap_or(&self.x_vec)
+    }
+
+    /// Records of `doc_y` as they're written.
+    fn original_y(&self) -> &[String] {
+        match self.read_y {
+            ReadRecords::X => self.original_x(),
+            _ if self.transliterated => self.read_y(),
+            _ => self.y_vec(),
+        }
+    }
+
+    /// The matches of a join of these records, without any pairs, which are set as they're written.
+    fn matches<'a>(
+        &'a self,
+        index: &'a dyn IndexBackend,
+        config: &'a EdJoinConfig,
+        self_join: bool,
+    ) -> Matches<'a> {
+        Matches {
+            pairs: &[],
+            x_records: self.original_x(),
+            y_records: self.original_y(),
+            self_join,
+            confidence: config.scorer.as_deref().map(|scorer| {
+                (
+                    scorer,
+                    ScoreContext::new(index, config.q, config.tau)
+                        .with_tokenizer(&*config.tokenizer),
+                )
+            }),
+            passthrough: self
+                .passthrough
+                .as_ref()
+                .map(|(x_rows, y_rows)| (x_rows.as_slice(), y_rows.as_deref().unwrap_or(x_rows))),
+            provenance: self.sources.as_deref(),
+            emit_content: config.emit_content,
+            text_mask: config.text_mask.as_ref(),
+            plugins: &config.plugins,
+        }
+    }
+}
+
+/// Read the records of both inputs of a join, and prepare them for it.
+///
+/// Records which are skipped, or too short to have any q-gram, are reported in the warnings of `report`.
+fn read_inputs<'a>(
+    doc_x: &PathBuf,
+    doc_y: &PathBuf,
+    config: &'a EdJoinConfig,
+    self_join: bool,
+    report: &mut JoinReport,
+) -> Result<Inputs<'a>> {
+    let q: usize = config.q;
+    // Read entire `doc_x` and `doc_y` into vectors to reduce IO
+    let read_span: Span = timeline::span("read_inputs");
+    let read_started: Instant = Instant::now();
+    let (x_vec, original_x, mut warnings_x) = read_input_with_originals(doc_x, Side::X, config)?;
+    report.warnings.append(&mut warnings_x);
+    let read_y: ReadRecords = match &config.record_store {
+        // identical copies of `doc_x` are only read once, too
+        _ if self_join || doc_x == doc_y => ReadRecords::X,
+        Some(store) if store.path() != doc_y.as_path() => bail!(
+            "The record store was loaded from {}, not {}",
+            store.path().display(),
+            doc_y.display()
+        ),
+        Some(store) => {
+            report.warnings.extend_from_slice(store.warnings());
+            ReadRecords::Store(store)
+        }
+        None => {
+            let loaded: RecordStore =
+                RecordStore::load_as(doc_y, config.error_policy, &config.input_format)?;
+            report.warnings.extend_from_slice(loaded.warnings());
+            ReadRecords::Loaded(loaded)
+        }
+    };
+    let mut inputs: Inputs = Inputs {
+        x_vec,
+        original_x,
+        read_y,
+        prepared_y: None,
+        transliterated: config.normalization.transliterate,
+        passthrough: None,
+        sources: None,
+        skipped_x: HashSet::new(),
+    };
+    // records of `doc_x` were already prepared when read, but those of a store are kept as they are
+    let as_read: bool = config.normalization.is_identity() && config.alphabet.is_none();
+    if !as_read && !matches!(inputs.read_y, ReadRecords::X) {
+        let mut records: Vec<String> = inputs.read_y().to_vec();
+        report
+            .warnings
+            .append(&mut prepare_records(&mut records, Side::Y, config)?);
+        inputs.prepared_y = Some(records);
+    }
+    // passthrough fields of CSV inputs are only needed for the output, so they're read separately,
+    // but before the join so that a missing column fails early
+    inputs.passthrough = match &config.input_format {
+        InputFormat::Csv(input) if !input.passthrough.is_empty() => {
+            let y_rows: Option<Vec<Fields>> = if self_join || doc_x == doc_y {
+                None
+            } else {
+                Some(read_passthrough(doc_y, input)?)
+            };
+            Some((read_passthrough(doc_x, input)?, y_rows))
+        }
+        _ => None,
+    };
+    // so are the parts of a merged index, so that an index that isn't merged, or not of doc_y, fails early
+    inputs.sources = match &config.index_from {
+        Some(index_from) if config.provenance => {
+            let parts: Vec<MergedPart> = read_sources(index_from)?;
+            let records: usize = parts.last().map_or(0, |part| part.first_id + part.records);
+            if records != inputs.original_y().len() {
+                bail!(
+                    "{} is merged from {} records, not the {} of doc_y",
+                    index_from.display(),
+                    records,
+                    inputs.original_y().len()
+                );
+            }
+            Some(parts)
+        }
+        _ => None,
+    };
+    drop(read_span);
+    report
+        .stats
+        .phases
+        .push(("read_inputs", read_started.elapsed()));
+
+    // records that are not valid UTF-8, reads of a low quality, rows without the join column, malformed records,
+    // or records out of the alphabet that were skipped, are not processed
+    let skipped_ids = |side: Side| -> HashSet<ID> {
+        report
+            .warnings
+            .iter()
+            .filter_map(|warning| match warning {
+                Warning::NonUtf8Record { side: s, id }
+                | Warning::LowQualityRead { side: s, id, .. }
+                | Warning::MissingField { side: s, id, .. }
+                | Warning::MalformedRecord { side: s, id, .. }
+                | Warning::OutOfAlphabet {
+                    side: s,
+                    id,
+                    action: AlphabetAction::Skip,
+                    ..
+                } if *s == side => Some(*id),
+                _ => None,
+            })
+            .collect()
+    };
+    let mut skipped_x: HashSet<ID> = skipped_ids(Side::X);
+    // in a self-join, tombstoned records are deleted from `doc_x` too
+    if self_join {
+        skipped_x.extend(&config.tombstones);
+    }
+    // records that are too short to have any q-gram are reported, as they're never matched
+    let mode: TokenizerMode = config.tokenizer.mode();
+    let mut short_records: Vec<Warning> = inputs
+        .x_vec
+        .iter()
+        .enumerate()
+        .filter(|(x_id, _)| !skipped_x.contains(x_id))
+        .map(|(id, record)| (id, mode.len_of(record)))
+        .filter(|(_, len)| *len < q)
+        .map(|(id, len)| Warning::ShortRecord {
+            side: Side::X,
+            id,
+            len,
+        })
+        .collect();
+    if !self_join {
+        let skipped_y: HashSet<ID> = skipped_ids(Side::Y);
+        short_records.extend(
+            inputs
+                .y_vec()
+                .iter()
+                .enumerate()
+                .filter(|(y_id, _)| !skipped_y.contains(y_id))
+                .map(|(id, record)| (id, mode.len_of(record)))
+                .filter(|(_, len)| *len < q)
+                .map(|(id, len)| Warning::ShortRecord {
+                    side: Side::Y,
+                    id,
+                    len,
+                }),
+        );
+    }
+    report.warnings.append(&mut short_records);
+    inputs.skipped_x = skipped_x;
+
+    Ok(inputs)
+}
+
+/// The inverted index of a join, as built by [`build_index`].
+struct JoinIndex {
+    /// The index as it was built or read, which is only kept if it's persisted, see [`Phase::Index`].
+    inverted: InvertedIndex,
+    /// The index with its tokens interned, unless it's only persisted.
+    interned: InternedIndex,
+    /// The index on disk, if it's built there instead, see [`EdJoinConfig::external_index`].
+    external: Option<ExternalIndex>,
+}
+
+impl JoinIndex {
+    /// The index which candidates are generated from, i.e. the one on disk if any, or else the one in memory.
+    fn backend(&self) -> &dyn IndexBackend {
+        match &self.external {
+            Some(external) => external,
+            None => &self.interned,
+        }
+    }
+}
+
+/// Build the inverted index of a join, or read it from a file or a warm start.
+///
+/// If the index exceeds its limit, a manifest is written to `manifest_name`, see [`EdJoinConfig::limits`].
+fn build_index(
+    inputs: &Inputs,
+    config: &EdJoinConfig,
+    self_join: bool,
+    warm_entry: Option<&WarmEntry>,
+    manifest_name: &Path,
+    report: &mut JoinReport,
+) -> Result<JoinIndex> {
+    let (q, tokenizer): (usize, &dyn Tokenizer) = (config.q, &*config.tokenizer);
+    let (x_vec, y_vec): (&[String], &[String]) = (&inputs.x_vec, inputs.y_vec());
+    let index_span: Span = timeline::span("index");
+    let index_started: Instant = Instant::now();
+    let mut inverted_index: InvertedIndex = match (&config.index_from, warm_entry) {
+        // the index is built on disk instead, below
+        _ if config.external_index.is_some() => InvertedIndex::new(),
+        (Some(index_name), _) => read_index(index_name, q)?,
+        (None, Some(warm_entry)) if warm_entry.index_path().is_file() => {
+            read_index(&warm_entry.index_path(), q)?
+        }
+        _ => {
+            let inverted_index: InvertedIndex = if !self_join {
+                build_inverted_index_with(Some(x_vec), y_vec, q, tokenizer)
+            } else {
+                build_inverted_index_with(None, y_vec, q, tokenizer)
+            };
+            if let Some(warm_entry) = warm_entry {
+                write_index(
+                    &warm_entry.index_path(),
+                    &inverted_index,
+                    q,
+                    Some(y_vec.len()),
+                )?;
+            }
+            inverted_index
+        }
+    };
+    if manifest_name.is_file() {
+        fs::remove_file(manifest_name)?;
+    }
+    if let Some(max) = config.limits.index_bytes {
+        let bytes: usize = index_bytes(&inverted_index);
+        if bytes > max {
+            let manifest: Manifest = Manifest {
+                phase: "index".to_string(),
+                limit: "index_bytes".to_string(),
+                value: bytes,
+                max,
+                completed: report.stats.phases.clone(),
+                records: 0,
+                partial: None,
+            };
+            write_manifest(manifest_name, &manifest)?;
+            bail!(ErrorKind::LimitExceeded(
+                manifest.phase,
+                manifest.limit,
+                bytes,
+                max
+            ));
+        }
+    }
+    let external: Option<ExternalIndex> = match &config.external_index {
+        Some(dir) => Some(ExternalIndex::build(
+            dir,
+            if self_join { None } else { Some(x_vec) },
+            y_vec,
+            q,
+            tokenizer,
+        )?),
+        None => None,
+    };
+    // the tokens of the index in memory are interned, unless it's only written
+    let interned: InternedIndex = match config.stop_after {
+        Some(Phase::Index) => InternedIndex::default(),
+        _ => InternedIndex::from(std::mem::take(&mut inverted_index)),
+    };
+    let index: JoinIndex = JoinIndex {
+        inverted: inverted_index,
+        interned,
+        external,
+    };
+    drop(index_span);
+    report.stats.phases.push(("index", index_started.elapsed()));
+    #[cfg(feature = "logging")]
+    debug!("InvertedList: {:?}", index.backend());
+
+    Ok(index)
+}
+
+/// Persist the index of a join that stops after [`Phase::Index`], see [`phase_name`].
+fn persist_index(
+    inverted_index: &InvertedIndex,
+    records: usize,
+    q: usize,
+    out_name: &Path,
+) -> Result<()> {
+    // the index is written to a partial file first, so that an interrupted run leaves no truncated index
+    let index_name: PathBuf = phase_name(out_name, Phase::Index);
+    let partial_name: PathBuf = PathBuf::from(format!("{}.partial", index_name.display()));
+    write_index(&partial_name, inverted_index, q, Some(records))?;
+    fs::rename(&partial_name, &index_name)?;
+    #[cfg(feature = "logging")]
+    info!("Wrote the inverted index to {}", index_name.display());
+
+    Ok(())
+}
+
+/// Generate the candidates of all records of `doc_x`, and persist them for a join that stops after
+/// [`Phase::Candidates`], see [`phase_name`].
+fn persist_candidates(
+    inputs: &Inputs,
+    index: &dyn IndexBackend,
+    pruning: &Pruning,
+    config: &EdJoinConfig,
+    self_join: bool,
+    out_name: &Path,
+    report: &mut JoinReport,
+) -> Result<()> {
+    let (q, tau, tokenizer): (usize, usize, &dyn Tokenizer) =
+        (config.q, config.tau, &*config.tokenizer);
+    let y_vec: &[String] = inputs.y_vec();
+    let candidates_name: PathBuf = phase_name(out_name, Phase::Candidates);
+    // candidates of each record, and the number of them dropped by the cap of an approximate join
+    type Generated = std::result::Result<(Vec<ID>, usize), String>;
+    let generated: Vec<(ID, Generated)> = inputs
+        .x_vec
+        .par_iter()
+        .enumerate()
+        .with_min_len(config.batch_size.unwrap_or(DEFAULT_BATCH_SIZE))
+        .filter(|(x_id, _)| !inputs.skipped_x.contains(x_id))
+        .map(|(x_id, x_content)| {
+            let result = catch_invariant(|| {
+                let (_, y_ids, dropped) = generate_candidates_capped(
+                    x_id, x_content, y_vec, index, q, tau, self_join, pruning, tokenizer,
+                );
+                let y_ids: Vec<ID> = y_ids
+                    .into_iter()
+                    .filter(|y_id| !config.tombstones.contains(y_id))
+                    .collect();
+                (y_ids, dropped)
+            });
+            (x_id, result)
+        })
+        .collect();
+    let mut candidates: Vec<(ID, Vec<ID>)> = Vec::with_capacity(generated.len());
+    for (x_id, result) in generated {
+        match result {
+            Ok((y_ids, dropped)) => {
+                if dropped > 0 {
+                    report
+                        .warnings
+                        .push(Warning::CappedCandidates { id: x_id, dropped });
+                }
+                report.stats.candidates += y_ids.len();
+                candidates.push((x_id, y_ids));
+            }
+            Err(msg) if config.error_policy == ErrorPolicy::Abort => {
+                bail!(ErrorKind::InvariantViolation(msg))
+            }
+            Err(msg) => report
+                .warnings
+                .push(Warning::SkippedRecord { id: x_id, msg }),
+        }
+    }
+    let partial_name: PathBuf = PathBuf::from(format!("{}.partial", candidates_name.display()));
+    write_candidates(&partial_name, &candidates, q, tau)?;
+    fs::rename(&partial_name, &candidates_name)?;
+    #[cfg(feature = "logging")]
+    {
+        report
+            .warnings
+            .iter()
+            .for_each(|warning| warn!("{}", warning));
+        info!("Wrote candidates to {}", candidates_name.display());
+    }
+
+    Ok(())
+}
+
+/// The output file of a join, as it's written, see [`OutputFile::open`].
+struct OutputFile {
+    partial_name: PathBuf,
+    checkpoint_name: PathBuf,
+    /// The partial file, unless the output goes to a sink, which is written in place.
+    partial: Option<File>,
+    writer: BufWriter<Box<dyn Write + Send>>,
+    /// The first record of `doc_x` whose matches aren't in the partial file yet, if it's resumed.
+    resume_from: ID,
+    /// Whether the matches of a record are written as soon as all records before it are done.
+    streaming: bool,
+}
+
+impl OutputFile {
+    /// Open the output of a join, which is written to a partial file, renamed to `out_name` once the join succeeded,
+    /// unless it goes to a sink. The partial file of an interrupted join is resumed, if it's asked to.
+    fn open(out_name: &Path, config: &EdJoinConfig) -> Result<Self> {
+        let partial_name: PathBuf = PathBuf::from(format!("{}.partial", out_name.display()));
+        let checkpoint_name: PathBuf =
+            PathBuf::from(format!("{}.checkpoint", partial_name.display()));
+        // matches of a record are written as soon as all records before it are done, unless they depend on each other
+        let streaming: bool = config.output_format == OutputFormat::Csv
+            && config.assignment.is_none()
+            && config.clustering.is_none();
+        let mut resume_from: ID = 0;
+        // a sink is written in place, so there's no partial file
+        let partial: Option<File> = if config.output_sink.is_some() {
+            None
+        } else if config.resume && partial_name.is_file() {
+            // a checkpoint knows where the output is complete, even if the records after the last match had none
+            let checkpoint: Option<Checkpoint> = if checkpoint_name.is_file() {
+                Some(read_checkpoint(&checkpoint_name)?)
+            } else {
+                None
+            };
+            match checkpoint {
+                Some(checkpoint) if checkpoint.len <= fs::metadata(&partial_name)?.len() => {
+                    OpenOptions::new()
+                        .write(true)
+                        .open(&partial_name)?
+                        .set_len(checkpoint.len)?;
+                    resume_from = checkpoint.next_x_id;
+                }
+                _ => {
+                    if let Some(x_id) = truncate_partial(&partial_name, config.delimiter)? {
+                        resume_from = x_id;
+                    }
+                }
+            }
+            #[cfg(feature = "logging")]
+            info!(
+                "Resuming {} from line {} of `doc_x`",
+                partial_name.display(),
+                resume_from
+            );
+            Some(OpenOptions::new().append(true).open(&partial_name)?)
+        } else {
+            if checkpoint_name.is_file() {
+                fs::remove_file(&checkpoint_name)?;
+            }
+            Some(File::create(&partial_name)?)
+        };
+        let doc_out: Box<dyn Write + Send> = match &partial {
+            Some(partial) => Box::new(partial.try_clone()?),
+            None => config.open_output(out_name)?,
+        };
+
+        Ok(Self {
+            partial_name,
+            checkpoint_name,
+            partial,
+            writer: BufWriter::new(doc_out),
+            resume_from,
+            streaming,
+        })
+    }
+
+    /// Flush the output, and rename the partial file to `out_name`.
+    fn finish(mut self, out_name: &Path) -> Result<()> {
+        self.writer.flush()?;
+        drop(self.writer);
+        if let Some(partial) = self.partial {
+            partial.sync_all()?;
+            fs::rename(&self.partial_name, out_name)?;
+            if self.checkpoint_name.is_file() {
+                fs::remove_file(&self.checkpoint_name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Matches of a record of `doc_x`, the candidates that passed all filters with their exact edit distances if known,
+/// and the warnings encountered.
+type RecordMatches = (MatchedPairs, FilteredCandidates, Vec<Warning>);
+
+/// Matches single records of `doc_x`, with what all of them share, and adds up their statistics and diagnostics.
+struct RecordMatcher<'a> {
+    config: &'a EdJoinConfig,
+    y_vec: &'a [String],
+    index: &'a dyn IndexBackend,
+    pruning: &'a Pruning,
+    /// Candidates loaded from a file, instead of being generated, see [`EdJoinConfig::candidates_from`].
+    persisted: Option<&'a HashMap<ID, Vec<ID>>>,
+    /// Distances known from earlier joins, if it's warm-started.
+    known: &'a Distances,
+    profile: Option<FilterProfile>,
+    /// Whether the candidates that passed all filters are kept, as they're emitted or warm-started.
+    keep_filtered: bool,
+    bypass_len: usize,
+    /// Candidates generated so far, which are reported to the progress observer.
+    candidates_generated: AtomicUsize,
+    /// Statistics of each record are added up as it's done.
+    stats: Mutex<JoinStats>,
+    /// The strands of a record are matched separately, and their diagnostics merged once all records are done.
+    diagnostics: Mutex<Vec<RecordDiagnostics>>,
+}
+
+impl RecordMatcher<'_> {
+    /// Match a record of `doc_x` with the given threshold. The candidates that passed all filters are only returned
+    /// if they're kept.
+    fn match_record(
+        &self,
+        x_id: ID,
+        x_content: &str,
+        tau: usize,
+        self_join: bool,
+    ) -> Result<RecordMatches> {
+        let (config, index, y_vec) = (self.config, self.index, self.y_vec);
+        let (q, policy) = (config.q, config.error_policy);
+        let tokenizer: &dyn Tokenizer = &*config.tokenizer;
+        let mode: TokenizerMode = tokenizer.mode();
+        let mut warnings: Vec<Warning> = Vec::new();
+        let deadline: Option<Instant> = config
+            .record_timeout
+            .map(|timeout| Instant::now() + timeout);
+        // candidates are generated and counted by the Levenshtein distance, see `DistanceMetric::filter_tau()`
+        let filter_tau: usize = config.metric.filter_tau(tau);
+
+        let candidates = catch_invariant(|| match self.persisted {
+            // the q-grams are sorted as `generate_candidates()` does, since the verification depends on their order
+            Some(persisted) => {
+                let mut qgram_array_x: PosQGramArray =
+                    PosQGramArray::tokenize(x_content, q, tokenizer);
+                qgram_array_x.sort_by_frequency(index);
+                (
+                    qgram_array_x,
+                    persisted.get(&x_id).cloned().unwrap_or_default(),
+                    0,
+                )
+            }
+            None => {
+                let _region = timeline::region(Region::Candidates);
+                generate_candidates_capped(
+                    x_id,
+                    x_content,
+                    y_vec,
+                    index,
+                    q,
+                    filter_tau,
+                    self_join,
+                    self.pruning,
+                    tokenizer,
+                )
+            }
+        });
+        let (qgram_array_x, mut candidates, dropped) = match candidates {
+            Ok(candidates) => candidates,
+            Err(msg) if policy == ErrorPolicy::Abort => bail!(ErrorKind::InvariantViolation(msg)),
+            Err(msg) => {
+                warnings.push(Warning::SkippedRecord { id: x_id, msg });
+                return Ok((Vec::new(), Vec::new(), warnings));
+            }
+        };
+        if dropped > 0 {
+            warnings.push(Warning::CappedCandidates { id: x_id, dropped });
+        }
+
+        // tombstoned records are masked here, whether the candidates were generated or loaded
+        candidates.retain(|y_id| !config.tombstones.contains(y_id));
+        let generated: usize = self
+            .candidates_generated
+            .fetch_add(candidates.len(), Ordering::Relaxed)
+            + candidates.len();
+        if let Some(max) = config.limits.candidates.filter(|max| generated > *max) {
+            bail!(ErrorKind::LimitExceeded(
+                "join".to_string(),
+                "candidates".to_string(),
+                generated,
+                max
+            ));
+        }
+        let mut record_stats: JoinStats = JoinStats {
+            candidates: candidates.len(),
+            ..JoinStats::default()
+        };
+        #[cfg(feature = "logging")]
+        debug!("Candidate of `{}: {}`: {:?}", x_id, x_content, candidates);
+
+        // candidates are verified with one buffer, sized to the longest candidate possible
+        let buffer: &mut EditDistanceBuffer =
+            &mut EditDistanceBuffer::with_capacity(x_content.len() + tau);
+        let verified: Vec<_> = candidates
+            .iter()
+            .map(|y_id| {
+                // candidates not verified yet when the record runs out of time are skipped
+                if deadline.is_some_and(|deadline| Instant::now() > deadline) {
+                    return (*y_id, None, false, false);
+                }
+                let y_content: &str = &y_vec[*y_id];
+                let known_ed: Option<usize> = self.known.get(&x_id).and_then(|pairs| {
+                    pairs
+                        .binary_search_by_key(y_id, |(y_id, _)| *y_id)
+                        .ok()
+                        .map(|i| pairs[i].1)
+                });
+                // the filters are only sound for positional grams
+                let bypassed: bool = known_ed.is_none()
+                    && (!tokenizer.positional()
+                        || cmp::max(mode.len_of(x_content), mode.len_of(y_content))
+                            < self.bypass_len);
+                let result = catch_invariant(|| {
+                    match known_ed {
+                        Some(ed) if ed <= tau => return Verdict::Matched { ed },
+                        Some(ed) => return Verdict::Rejected { ed },
+                        None => (),
+                    }
+                    if bypassed {
+                        let _region = timeline::region(Region::EditDistance);
+                        return match buffer.bounded_distance_in(
+                            x_content,
+                            y_content,
+                            tau,
+                            config.metric,
+                            tokenizer.mode(),
+                        ) {
+                            Some(ed) => Verdict::Matched { ed },
+                            None => Verdict::Rejected { ed: tau + 1 },
+                        };
+                    }
+                    let mut qgram_array_y = PosQGramArray::tokenize(y_content, q, tokenizer);
+                    if config.filters.count && config.count_filter == CountFilter::Exact {
+                        let _region = timeline::region(Region::CountFilter);
+                        let epsilon: usize =
+                            count_mismatch(&qgram_array_x, &qgram_array_y, filter_tau);
+                        if epsilon > q * filter_tau {
+                            return Verdict::CountFilter { epsilon };
+                        }
+                    }
+                    filter_pair_profiled(
+                        qgram_array_x.to_vec(),
+                        x_id,
+                        x_content,
+                        &mut qgram_array_y,
+                        *y_id,
+                        y_content,
+                        index,
+                        q,
+                        tau,
+                        config.metric,
+                        self.profile.as_ref(),
+                        config.filters,
+                        tokenizer.mode(),
+                        buffer,
+                    )
+                });
+                (*y_id, Some(result), bypassed, known_ed.is_none())
+            })
+            .collect();
+
+        let mut matched: MatchedPairs = Vec::new();
+        let mut filtered: FilteredCandidates = Vec::new();
+        let mut timed_out: usize = 0;
+        let mut verified_count: usize = 0;
+        for (y_id, result, bypassed, computed) in verified {
+            if let Some(Ok(Verdict::Matched { .. } | Verdict::Rejected { .. })) = result {
+                verified_count += 1;
+            }
+            if let Some(Ok(verdict)) = &result {
+                record_stats.add_verdict(verdict, computed);
+            }
+            match result {
+                None => timed_out += 1,
+                Some(Ok(Verdict::Matched { ed })) => {
+                    matched.push((x_id, vec![(y_id, ed)]));
+                    filtered.push((y_id, Some(ed)));
+                }
+                // the distance of a bypassed pair is only a lower bound
+                Some(Ok(Verdict::Rejected { ed })) => {
+                    filtered.push((y_id, Some(ed).filter(|_| !bypassed)))
+                }
+                Some(Ok(_)) => (),
+                Some(Err(msg)) => match policy {
+                    ErrorPolicy::Abort => bail!(ErrorKind::InvariantViolation(msg)),
+                    ErrorPolicy::SkipRecord => {
+                        warnings.push(Warning::SkippedRecord { id: x_id, msg });
+                        matched.clear();
+                        filtered.clear();
+                        break;
+                    }
+                    ErrorPolicy::SkipPair => {
+                        warnings.push(Warning::SkippedPair { x_id, y_id, msg })
+                    }
+                },
+            }
+        }
+        if timed_out > 0 {
+            warnings.push(Warning::RecordTimeout {
+                id: x_id,
+                skipped: timed_out,
+            });
+        }
+        *self.stats.lock().unwrap() += &record_stats;
+        if config.diagnostics.is_some() {
+            self.diagnostics.lock().unwrap().push(RecordDiagnostics {
+                x_id,
+                prefix_len: calc_prefix_len(&qgram_array_x, q, filter_tau),
+                candidates: candidates.len(),
+                verified: verified_count,
+                matches: matched.len(),
+            });
+        }
+        matched
+            .iter_mut()
+            .for_each(|(_x_id, yvec)| yvec.sort_unstable_by_key(|(y_id, _ed)| *y_id));
+
+        if !self.keep_filtered {
+            filtered.clear();
+        }
+
+        Ok((matched, filtered, warnings))
+    }
+
+    /// Match a record, and also its reverse complement if it's compared too, keeping the smaller distance of each pair.
+    fn match_strands(
+        &self,
+        x_id: ID,
+        x_content: &str,
+        tau: usize,
+        self_join: bool,
+    ) -> Result<RecordMatches> {
+        let (matched, mut filtered, mut warnings) =
+            self.match_record(x_id, x_content, tau, self_join)?;
+        if !self.config.reverse_complement {
+            return Ok((matched, filtered, warnings));
+        }
+        let (matched_rc, mut filtered_rc, mut warnings_rc) =
+            self.match_record(x_id, &reverse_complement(x_content), tau, self_join)?;
+        warnings.append(&mut warnings_rc);
+
+        let mut eds: BTreeMap<ID, usize> = BTreeMap::new();
+        matched
+            .into_iter()
+            .chain(matched_rc)
+            .flat_map(|(_, yvec)| yvec)
+            .for_each(|(y_id, ed)| {
+                let min_ed: &mut usize = eds.entry(y_id).or_insert(ed);
+                *min_ed = min(*min_ed, ed);
+            });
+        filtered.append(&mut filtered_rc);
+        filtered.sort_unstable_by_key(|(y_id, _)| *y_id);
+        filtered.dedup_by_key(|(y_id, _)| *y_id);
+
+        Ok((
+            eds.into_iter()
+                .map(|(y_id, ed)| (x_id, vec![(y_id, ed)]))
+                .collect(),
+            filtered,
+            warnings,
+        ))
+    }
+}
+
+/// Match a record with its `k` nearest records within `tau`, see [`EdJoinConfig::knn`].
+///
+/// The threshold is widened until `k` matches are found, or `tau` is hit, where `match_within` matches the record
+/// within a threshold, on either side of `x_id` in a self-join. The `k` nearest are kept, ties broken by line number.
+fn match_nearest(
+    x_id: ID,
+    k: usize,
+    tau: usize,
+    self_join: bool,
+    match_within: impl Fn(usize) -> Result<RecordMatches>,
+    within_ratio: impl Fn(&mut MatchedPairs),
+) -> Result<RecordMatches> {
+    let mut knn_tau: usize = cmp::min(1, tau);
+    let (mut matched, filtered, warnings) = loop {
+        let (mut matched, mut filtered, warnings) = match_within(knn_tau)?;
+        matched.retain(|(_, yvec)| yvec.iter().all(|(y_id, _)| !self_join || *y_id != x_id));
+        filtered.retain(|(y_id, _)| !self_join || *y_id != x_id);
+        within_ratio(&mut matched);
+        let skipped: bool = warnings
+            .iter()
+            .any(|warning| matches!(warning, Warning::SkippedRecord { .. }));
+        if matched.len() >= k || knn_tau >= tau || skipped {
+            break (matched, filtered, warnings);
+        }
+        knn_tau += 1;
+    };
+    // keep the `k` nearest, and then restore the order of line numbers
+    if matched.len() > k {
+        matched.select_nth_unstable_by_key(k - 1, |(_, yvec)| (yvec[0].1, yvec[0].0));
+        matched.truncate(k);
+    }
+    matched.sort_by_key(|(_, yvec)| yvec[0].0);
+
+    Ok((matched, filtered, warnings))
+}
+
+/// What the verification of a join found, as returned by [`verify`].
+struct Verified {
+    /// Matches, grouped by line number of `doc_x` in increasing order, unless they were written as they were found.
+    pairs: MatchedPairs,
+    /// Candidates that passed all filters, if they're emitted.
+    emitted: Vec<(ID, Vec<ID>)>,
+    /// Diagnostics of each record, if they're written.
+    diagnostics: Vec<RecordDiagnostics>,
+    /// Distances known from earlier joins, and those verified by this one, if it's warm-started.
+    distances: Option<Distances>,
+}
+
+/// Verify the candidates of every record of `doc_x`.
+///
+/// The matches of a streaming output are written to `output` as they're found, and those of others are returned.
+/// If a limit is exceeded, a manifest of what was done is written to `manifest_name`, see [`EdJoinConfig::limits`].
+#[allow(clippy::too_many_arguments)]
+fn verify(
+    inputs: &Inputs,
+    index: &dyn IndexBackend,
+    pruning: &Pruning,
+    persisted: Option<&HashMap<ID, Vec<ID>>>,
+    warm_entry: Option<&WarmEntry>,
+    matches: Matches,
+    config: &EdJoinConfig,
+    output: &mut OutputFile,
+    manifest_name: &Path,
+    report: &mut JoinReport,
+) -> Result<Verified> {
+    let (q, tau) = (config.q, config.tau);
+    let tokenizer: &dyn Tokenizer = &*config.tokenizer;
+    let mode: TokenizerMode = tokenizer.mode();
+    let self_join: bool = matches.self_join;
+    let (x_vec, y_vec): (&[String], &[String]) = (&inputs.x_vec, inputs.y_vec());
+    // distances known from earlier joins, and those verified by this one, if it's warm-started
+    let known: Distances = match warm_entry {
+        Some(warm_entry) if warm_entry.distances_path().is_file() => {
+            read_distances(&warm_entry.distances_path(), q)?
+        }
+        _ => Distances::new(),
+    };
+    let mut verified_distances: Distances = Distances::new();
+    let matcher: RecordMatcher = RecordMatcher {
+        config,
+        y_vec,
+        index,
+        pruning,
+        persisted,
+        known: &known,
+        profile: config.adaptive_filters.map(FilterProfile::new),
+        keep_filtered: config.emit_candidates.is_some() || warm_entry.is_some(),
+        bypass_len: config.bypass_len.unwrap_or(q * (tau + 2)),
+        candidates_generated: AtomicUsize::new(0),
+        stats: Mutex::new(JoinStats::default()),
+        diagnostics: Mutex::new(Vec::new()),
+    };
+    let mut output_vec: MatchedPairs = Vec::new();
+    // candidates that passed all filters, if they're emitted
+    let mut emitted: Vec<(ID, Vec<ID>)> = Vec::new();
+    let (output_s, output_r) = unbounded::<(ID, MatchedPairs, FilteredCandidates, Vec<Warning>)>();
+
+    // each task matches a batch of records
+    let batch_size: usize = config.batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+    let resume_from: ID = output.resume_from;
+    let x_iter = x_vec
+        .par_iter()
+        .enumerate()
+        .with_min_len(batch_size)
+        .filter(|(x_id, _)| !inputs.skipped_x.contains(x_id) && *x_id >= resume_from);
+    // progressive results process the cheapest records first, and batches are taken in that order as threads become free
+    let file_x_iter = if config.progressive {
+        let mut order: Vec<(usize, ID)> = x_iter
+            .map(|(x_id, x_content)| (probe_cost(x_content, index, q, tau, tokenizer), x_id))
+            .collect();
+        order.par_sort_unstable();
+        let batches: Vec<Vec<ID>> = order
+            .chunks(batch_size)
+            .map(|batch| batch.iter().map(|(_, x_id)| *x_id).collect())
+            .collect();
+        Either::Left(
+            batches
+                .into_iter()
+                .par_bridge()
+                .flat_map_iter(|batch| batch.into_iter().map(|x_id| (x_id, &x_vec[x_id]))),
+        )
+    } else {
+        Either::Right(x_iter)
+    };
+
+    // records that failed and the slowest ones, if a debug sample is taken
+    let collector: Option<SampleCollector> = config
+        .debug_sample
+        .as_ref()
+        .map(|_| SampleCollector::default());
+    // totals of the join so far, which are reported to its progress observer
+    let (records_done, matches_found) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    // records of `doc_x` to be processed, in the order their matches are written
+    let processed: Vec<ID> = (resume_from..x_vec.len())
+        .filter(|x_id| !inputs.skipped_x.contains(x_id))
+        .collect();
+    if let Some(progress) = &config.progress {
+        progress.start(processed.len());
+    }
+
+    let join_span: Span = timeline::span("join");
+    let join_started: Instant = Instant::now();
+    let joined: Result<Vec<(ID, Vec<Warning>)>> =
+        thread::scope(|scope| -> Result<Vec<(ID, Vec<Warning>)>> {
+            let receiver = scope.spawn(|| -> Result<Vec<(ID, Vec<Warning>)>> {
+                let mut join_warnings: Vec<(ID, Vec<Warning>)> = Vec::new();
+                let mut pending: HashMap<ID, MatchedPairs> = HashMap::new();
+                let mut next = processed.iter().peekable();
+                let mut last_checkpoint: Instant = Instant::now();
+                while let Ok((x_id, mut v, filtered, warnings)) = output_r.recv() {
+                    if !warnings.is_empty() {
+                        join_warnings.push((x_id, warnings));
+                    }
+                    if warm_entry.is_some() {
+                        let pairs: Vec<(ID, usize)> = filtered
+                            .iter()
+                            .filter_map(|(y_id, ed)| ed.map(|ed| (*y_id, ed)))
+                            .collect();
+                        if !pairs.is_empty() {
+                            verified_distances.insert(x_id, pairs);
+                        }
+                    }
+                    if config.emit_candidates.is_some() && !filtered.is_empty() {
+                        emitted.push((x_id, filtered.into_iter().map(|(y_id, _)| y_id).collect()));
+                    }
+                    if !output.streaming {
+                        output_vec.append(&mut v);
+                        continue;
+                    }
+                    if config.progressive {
+                        write_matches(
+                            &mut output.writer,
+                            &Matches {
+                                pairs: &v,
+                                ..matches
+                            },
+                            &config.output_format,
+                            config.delimiter,
+                        )?;
+                        output.writer.flush()?;
+                        continue;
+                    }
+                    pending.insert(x_id, v);
+                    while let Some(pairs) = next.peek().and_then(|x_id| pending.remove(x_id)) {
+                        next.next();
+                        write_matches(
+                            &mut output.writer,
+                            &Matches {
+                                pairs: &pairs,
+                                ..matches
+                            },
+                            &config.output_format,
+                            config.delimiter,
+                        )?;
+                    }
+                    if let (Some(interval), Some(partial)) =
+                        (config.checkpoint_interval, &output.partial)
+                    {
+                        if last_checkpoint.elapsed() >= interval {
+                            output.writer.flush()?;
+                            partial.sync_data()?;
+                            let checkpoint: Checkpoint = Checkpoint {
+                                next_x_id: next.peek().map_or(x_vec.len(), |x_id| **x_id),
+                                len: partial.metadata()?.len(),
+                            };
+                            write_checkpoint(&output.checkpoint_name, checkpoint)?;
+                            last_checkpoint = Instant::now();
+                        }
+                    }
+                }
+                Ok(join_warnings)
+            });
+
+            // match a record, and sample it if its join fails
+            let match_sampled = |x_id: ID, x_content: &str, tau: usize, self_join: bool| {
+                let result = matcher.match_strands(x_id, x_content, tau, self_join);
+                if let (Err(_), Some(collector)) = (&result, &collector) {
+                    collector.failed(x_id);
+                }
+                result
+            };
+            let result = file_x_iter.try_for_each(|(x_id, x_content)| -> Result<()> {
+                if config
+                    .cancel
+                    .as_ref()
+                    .is_some_and(|cancel| cancel.load(Ordering::SeqCst))
+                {
+                    bail!(ErrorKind::Cancelled);
+                }
+                let started: Instant = Instant::now();
+                #[cfg(feature = "logging")]
+                trace!(
+                    "=====================\nCurrent line {}: {}",
+                    x_id,
+                    x_content
+                );
+
+                // a record's threshold under a ratio bounds those of all of its pairs, which are checked once matched
+                let x_len: usize = mode.len_of(x_content);
+                let tau: usize = config
+                    .tau_ratio
+                    .map_or(tau, |ratio| record_tau(ratio, tau, x_len));
+                let within_ratio = |matched: &mut MatchedPairs| {
+                    if let Some(ratio) = config.tau_ratio {
+                        matched.iter_mut().for_each(|(_, yvec)| {
+                            yvec.retain(|(y_id, ed)| {
+                                *ed <= ratio_tau(
+                                    ratio,
+                                    config.tau,
+                                    x_len,
+                                    mode.len_of(&y_vec[*y_id]),
+                                )
+                            })
+                        });
+                        matched.retain(|(_, yvec)| !yvec.is_empty());
+                    }
+                };
+                let (matched, filtered, warnings) = match config.knn {
+                    None => {
+                        let (mut matched, filtered, warnings) =
+                            match_sampled(x_id, x_content, tau, self_join)?;
+                        within_ratio(&mut matched);
+                        (matched, filtered, warnings)
+                    }
+                    Some(k) => match_nearest(
+                        x_id,
+                        k,
+                        tau,
+                        self_join,
+                        |tau| match_sampled(x_id, x_content, tau, false),
+                        within_ratio,
+                    )?,
+                };
+
+                if let Some(collector) = &collector {
+                    collector.timed(x_id, started.elapsed());
+                }
+                let found: usize = matched.iter().map(|(_, yvec)| yvec.len()).sum();
+                let matches: usize = matches_found.fetch_add(found, Ordering::Relaxed) + found;
+                if let Some(max) = config.limits.output_rows.filter(|max| matches > *max) {
+                    bail!(ErrorKind::LimitExceeded(
+                        "join".to_string(),
+                        "output_rows".to_string(),
+                        matches,
+                        max
+                    ));
+                }
+                output_s.send((x_id, matched, filtered, warnings)).unwrap();
+                let records: usize = records_done.fetch_add(1, Ordering::Relaxed) + 1;
+                if let Some(progress) = &config.progress {
+                    progress.advance();
+                    progress.progress(ProgressEvent {
+                        x_id,
+                        records,
+                        total: processed.len(),
+                        candidates: matcher.candidates_generated.load(Ordering::Relaxed),
+                        matches,
+                    });
+                }
+                Ok(())
+            });
+            drop(output_s);
+
+            // an error of the writer comes first, as the join may have only failed because of it
+            let join_warnings = receiver.join().unwrap()?;
+            result?;
+            Ok(join_warnings)
+        });
+    // the records done before a limit was exceeded are flushed to the partial output, which the manifest points to
+    if let Some(ErrorKind::LimitExceeded(phase, limit, value, max)) =
+        joined.as_ref().err().map(Error::kind)
+    {
+        output.writer.flush()?;
+        let manifest: Manifest = Manifest {
+            phase: phase.clone(),
+            limit: limit.clone(),
+            value: *value,
+            max: *max,
+            completed: report.stats.phases.clone(),
+            records: records_done.load(Ordering::Relaxed),
+            partial: Some(output.partial_name.clone())
+                .filter(|_| output.streaming && !config.progressive && output.partial.is_some()),
+        };
+        write_manifest(manifest_name, &manifest)?;
+    }
+    // candidates of the records sampled are generated as for the join
+    let take_sample = |dir: &Path, collector: &SampleCollector, warnings: &[Warning]| {
+        let filter_tau: usize = config.metric.filter_tau(tau);
+        write_sample(
+            dir, collector, warnings, x_vec, y_vec, index, q, filter_tau, self_join, tokenizer,
+        )
+    };
+    // a failed join is sampled too, but without the warnings of its records
+    let mut join_warnings: Vec<(ID, Vec<Warning>)> =
+        match (joined, &config.debug_sample, &collector) {
+            (Err(e), Some(dir), Some(collector)) => {
+                take_sample(dir, collector, &report.warnings)?;
+                return Err(e);
+            }
+            (joined, _, _) => joined?,
+        };
+    drop(output_r);
+    if let Some(progress) = &config.progress {
+        progress.finish();
+    }
+    drop(join_span);
+    let RecordMatcher {
+        stats, diagnostics, ..
+    } = matcher;
+    report.stats += &stats.into_inner().unwrap();
+    report.stats.phases.push(("join", join_started.elapsed()));
+
+    // sort by line id of doc_x, i.e. the first slot
+    output_vec.par_sort_by_key(|x| x.0);
+    join_warnings.sort_by_key(|(x_id, _)| *x_id);
+    emitted.sort_unstable_by_key(|(x_id, _)| *x_id);
+    join_warnings
+        .into_iter()
+        .for_each(|(_, mut warnings)| report.warnings.append(&mut warnings));
+    #[cfg(feature = "logging")]
+    report
+        .warnings
+        .iter()
+        .for_each(|warning| warn!("{}", warning));
+    if let (Some(dir), Some(collector)) = (&config.debug_sample, &collector) {
+        let _sampled: usize = take_sample(dir, collector, &report.warnings)?;
+        #[cfg(feature = "logging")]
+        info!(
+            "Wrote a scrambled sample of {} records to {}",
+            _sampled,
+            dir.display()
+        );
+    }
+
+    #[cfg(feature = "logging")]
+    debug!("Mathes: {:?}", output_vec);
+
+    let mut merged: BTreeMap<ID, RecordDiagnostics> = BTreeMap::new();
+    for record in diagnostics.into_inner().unwrap() {
+        let entry: &mut RecordDiagnostics =
+            merged.entry(record.x_id).or_insert(RecordDiagnostics {
+                x_id: record.x_id,
+                ..RecordDiagnostics::default()
+            });
+        entry.prefix_len = cmp::max(entry.prefix_len, record.prefix_len);
+        entry.candidates += record.candidates;
+        entry.verified += record.verified;
+        entry.matches += record.matches;
+    }
+    let distances: Option<Distances> = warm_entry.map(|_| {
+        let mut distances: Distances = known;
+        for (x_id, mut pairs) in verified_distances {
+            let known_pairs: &mut Vec<(ID, usize)> = distances.entry(x_id).or_default();
+            known_pairs.append(&mut pairs);
+            known_pairs.sort_unstable();
+            known_pairs.dedup_by_key(|(y_id, _)| *y_id);
+        }
+        distances
+    });
+
+    Ok(Verified {
+        pairs: output_vec,
+        emitted,
+        diagnostics: merged.into_values().collect(),
+        distances,
+    })
+}
+
+/// Write the matches of a join that weren't written as they were found, and what else it was asked to write,
+/// i.e. their clusters, the candidates that passed all filters, and diagnostics.
+fn write_output(
+    verified: &Verified,
+    matches: Matches,
+    config: &EdJoinConfig,
+    mut output: OutputFile,
+    out_name: &Path,
+    clusters_name: &Path,
+    report: &mut JoinReport,
+) -> Result<()> {
+    let assigned: MatchedPairs;
+    let pairs: &[(ID, Vec<(ID, usize)>)] = match config.assignment {
+        Some(assignment) => {
+            assigned = assign(&verified.pairs, assignment);
+            &assigned
+        }
+        None => &verified.pairs,
+    };
+    let matches: Matches = Matches { pairs, ..matches };
+    let write_span: Span = timeline::span("write_output");
+    let write_started: Instant = Instant::now();
+    if !output.streaming {
+        write_matches(
+            &mut output.writer,
+            &matches,
+            &config.output_format,
+            config.delimiter,
+        )?;
+    }
+    output.finish(out_name)?;
+    drop(write_span);
+    report
+        .stats
+        .phases
+        .push(("write_output", write_started.elapsed()));
+
+    if let Some(clustering) = &config.clustering {
+        write_cluster_assignments(&matches, clustering, clusters_name, config.delimiter)?;
+    }
+    if let Some(candidates_name) = &config.emit_candidates {
+        write_candidate_pairs(
+            BufWriter::new(File::create(candidates_name)?),
+            &verified.emitted,
+            config.delimiter,
+        )?;
+    }
+    if let Some(diagnostics_name) = &config.diagnostics {
+        write_diagnostics(
+            BufWriter::new(File::create(diagnostics_name)?),
+            &verified.diagnostics,
+            config.delimiter,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Cluster the matches of a join, and write the cluster of each record to `clusters_name`, see [`clusters_name`].
+fn write_cluster_assignments(
+    matches: &Matches,
+    clustering: &Clustering,
+    clusters_name: &Path,
+    delimiter: u8,
+) -> Result<()> {
+    write_clusters(
+        BufWriter::new(File::create(clusters_name)?),
+        &cluster(matches, clustering),
+        delimiter,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Filters, Limits, Normalization, Violation};
+    use crate::output::OutputTemplate;
+    use crate::qgram::{PosQGram, PosQGramArray};
+    use crate::sink::MemorySink;
+    use crate::testutil::TempDir;
+    use crate::tokenizer::{CharQGrams, GraphemeQGrams, PackedGrams};
+    use crate::unicode::UnicodeForm;
+    use std::time::Duration;
+
+    #[test]
+    fn test_check_pair() {
+        assert_eq!(
+            check_pair("hello", "hallo", 2, 1),
+            Verdict::Matched { ed: 1 }
+        );
+        assert_eq!(
+            check_pair("hello", "hello world", 2, 1),
+            Verdict::LengthFilter
+        );
+        assert_eq!(check_pair("abcdef", "uvwxyz", 2, 1), Verdict::PrefixFilter);
+        // the q-grams of `abba` are shifted by 2, which is within `tau`, though not within `q`
+        assert_eq!(
+            check_pair("aaabba", "abba", 1, 2),
+            Verdict::Matched { ed: 2 }
+        );
+    }
+
+    #[test]
+    fn test_threshold_above_q() {
+        let dir: TempDir = TempDir::new("threshold_above_q");
+        let doc: PathBuf = dir.join("records.txt");
+        let records: Vec<&str> = vec![
+            "aaabba", "abba", "abab", "baab", "bbaa", "aabb", "ababab", "ba",
+        ];
+        std::fs::write(&doc, records.join("\n") + "\n").unwrap();
+        let mut brute_force: Vec<(ID, ID, usize)> = Vec::new();
+        for (x_id, x) in records.iter().enumerate() {
+            for (y_id, y) in records.iter().enumerate().skip(x_id + 1) {
+                let ed: usize = edit_distance::edit_distance(x, y);
+                if ed <= 2 {
+                    brute_force.push((x_id, y_id, ed));
+                }
+            }
+        }
+
+        // the count filters shift q-grams by `tau`, so a threshold above q loses no pairs, by either count filter
+        for count_filter in [CountFilter::Approximate, CountFilter::Exact] {
+            let sink: MemorySink = MemorySink::default();
+            let config: EdJoinConfig = EdJoinConfig::new(1, 2)
+                .count_filter(count_filter)
+                .output(sink.clone());
+            ed_join_with_config(&doc, &doc, &config).unwrap();
+            let mut joined: Vec<(ID, ID, usize)> = String::from_utf8(sink.contents())
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    let fields: Vec<usize> = line
+                        .split(',')
+                        .map(|field| field.parse().unwrap())
+                        .collect();
+                    (fields[0], fields[1], fields[2])
+                })
+                .collect();
+            joined.sort_unstable();
+            assert_eq!(joined, brute_force, "{:?}", count_filter);
+        }
+    }
+
+    #[test]
+    fn test_join_records() {
+        let records: Vec<String> = ["hello", "hallo", "world", "hello"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            ed_self_join_records(&records, 2, 1),
+            vec![(0, 1, 1), (0, 3, 0), (1, 3, 1)]
+        );
+
+        // the same as a join of files
+        let dir: TempDir = TempDir::new("join_records");
+        let (doc_x, doc_y) = (dir.join("records_x.txt"), dir.join("records_y.txt"));
+        let probes: Vec<String> = ["hullo", "word", "help"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        std::fs::write(&doc_x, probes.join("\n")).unwrap();
+        std::fs::write(&doc_y, records.join("\n")).unwrap();
+        let out_name: PathBuf = dir.join("records_x_out_q2_tau2.txt");
+        ed_join_with_config(
+            &doc_x,
+            &doc_y,
+            &EdJoinConfig::new(2, 2).output_dir(dir.path()),
+        )
+        .unwrap();
+        let written: String = std::fs::read_to_string(&out_name).unwrap();
+        let expected: Vec<String> = ed_join_records(&probes, &records, 2, 2)
+            .iter()
+            .map(|(x_id, y_id, ed)| format!("{},{},{}\n", x_id, y_id, ed))
+            .collect();
+        assert_eq!(written, expected.concat());
+        let output: JoinOutput = ed_join_matches(&doc_x, &doc_y, &EdJoinConfig::new(2, 2)).unwrap();
+        let mut returned: Vec<u8> = Vec::new();
+        output
+            .write(&mut returned, &OutputFormat::Csv, b',')
+            .unwrap();
+        assert_eq!(String::from_utf8(returned).unwrap(), written);
+        let iterated: Vec<(ID, ID, usize)> =
+            EdJoinIter::new(&doc_x, &doc_y, &EdJoinConfig::new(2, 2))
+                .unwrap()
+                .collect();
+        assert_eq!(iterated, ed_join_records(&probes, &records, 2, 2));
+        // batches of records only change how the work is split
+        for batch_size in [1, 2] {
+            let config: EdJoinConfig = EdJoinConfig::new(2, 2).batch_size(batch_size);
+            assert_eq!(
+                EdJoinIter::new(&doc_x, &doc_y, &config)
+                    .unwrap()
+                    .collect::<Vec<_>>(),
+                iterated
+            );
+            let sink: MemorySink = MemorySink::default();
+            ed_join_with_config(&doc_x, &doc_y, &config.output(sink.clone())).unwrap();
+            assert_eq!(String::from_utf8(sink.contents()).unwrap(), written);
+        }
+        assert!(EdJoinIter::new(&doc_x, &doc_y, &EdJoinConfig::new(2, 2).batch_size(0)).is_err());
+        let sink: MemorySink = MemorySink::default();
+        ed_join_with_config(
+            &doc_x,
+            &doc_y,
+            &EdJoinConfig::new(2, 2).output(sink.clone()),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(sink.contents()).unwrap(), written);
+        assert!(ed_join_with_config(
+            &doc_x,
+            &doc_y,
+            &EdJoinConfig::new(2, 2).output(sink).resume()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let dir: TempDir = TempDir::new("checkpoint");
+        let doc: PathBuf = dir.join("records.txt");
+        std::fs::write(&doc, "hello\nhallo\nworld\nword\nhelp\n").unwrap();
+        let (out_name, partial_name, checkpoint_name) = (
+            dir.join("records_out_q2_tau1.txt"),
+            dir.join("records_out_q2_tau1.txt.partial"),
+            dir.join("records_out_q2_tau1.txt.partial.checkpoint"),
+        );
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1)
+            .output_dir(dir.path())
+            .checkpoint_interval(Duration::ZERO);
+        ed_join_with_config(&doc, &doc, &config).unwrap();
+        let expected: String = std::fs::read_to_string(&out_name).unwrap();
+        assert!(!checkpoint_name.exists());
+
+        // an interrupted join resumes from its checkpoint, and the tail after it is dropped, however broken
+        let complete: usize = expected
+            .lines()
+            .take_while(|line| !line.starts_with("2,"))
+            .map(|line| line.len() + 1)
+            .sum();
+        std::fs::write(
+            &partial_name,
+            format!("{}not a line of matches\n", &expected[..complete]),
+        )
+        .unwrap();
+        write_checkpoint(
+            &checkpoint_name,
+            Checkpoint {
+                next_x_id: 2,
+                len: complete as u64,
+            },
+        )
+        .unwrap();
+        ed_join_with_config(&doc, &doc, &config.clone().resume()).unwrap();
+        assert_eq!(std::fs::read_to_string(&out_name).unwrap(), expected);
+        assert!(!checkpoint_name.exists());
+
+        assert!(ed_join_with_config(&doc, &doc, &config.progressive()).is_err());
+    }
+
+    #[test]
+    fn test_unwritable_output() {
+        let dir: TempDir = TempDir::new("unwritable_output");
+        let doc: PathBuf = dir.join("records.txt");
+        std::fs::write(&doc, "hello\nhallo\n").unwrap();
+
+        // the partial output can't be created where a directory is, which is an error rather than a panic
+        std::fs::create_dir_all(dir.join("records_out_q2_tau1.txt.partial")).unwrap();
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1).output_dir(dir.path());
+        assert!(ed_join_with_config(&doc, &doc, &config).is_err());
+    }
+
+    #[test]
+    fn test_stop_after() {
+        let dir: TempDir = TempDir::new("stop_after");
+        let doc: PathBuf = dir.join("records.txt");
+        std::fs::write(&doc, "hello\nhallo\nworld\n").unwrap();
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1).output_dir(dir.path());
+        let matches: String = {
+            let sink: MemorySink = MemorySink::default();
+            ed_join_with_config(&doc, &doc, &config.clone().output(sink.clone())).unwrap();
+            String::from_utf8(sink.contents()).unwrap()
+        };
+
+        let report: JoinReport =
+            ed_join_with_config(&doc, &doc, &config.clone().stop_after(Phase::Index)).unwrap();
+        let index_name: PathBuf = dir.join("records_out_q2_tau1.idx");
+        assert_eq!(report.output.as_deref(), Some(index_name.as_path()));
+        let report: JoinReport =
+            ed_join_with_config(&doc, &doc, &config.clone().stop_after(Phase::Candidates)).unwrap();
+        let candidates_name: PathBuf = dir.join("records_out_q2_tau1.candidates.tsv");
+        assert_eq!(report.output.as_deref(), Some(candidates_name.as_path()));
+        // partial files are renamed, and no output is written
+        assert!(!dir.join("records_out_q2_tau1.idx.partial").exists());
+        assert!(!dir
+            .join("records_out_q2_tau1.candidates.tsv.partial")
+            .exists());
+        assert!(!dir.join("records_out_q2_tau1.txt").exists());
+
+        // either is read back by a later run with the same matches
+        for config in [
+            config.clone().index_from(&index_name),
+            config.clone().candidates_from(&candidates_name),
+        ] {
+            ed_join_with_config(&doc, &doc, &config).unwrap();
+            assert_eq!(
+                std::fs::read_to_string(dir.join("records_out_q2_tau1.txt")).unwrap(),
+                matches
+            );
+        }
+    }
+
+    #[test]
+    fn test_cache() {
+        let dir: TempDir = TempDir::new("cache");
+        let doc: PathBuf = dir.join("records.txt");
+        std::fs::write(&doc, "hello\nhallo\nworld\nword\n").unwrap();
+        let out_name: PathBuf = dir.join("records_out_q2_tau1.txt");
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1)
+            .output_dir(dir.path())
+            .cache_dir(dir.join("cache"));
+
+        let report: JoinReport = ed_join_with_config(&doc, &doc, &config).unwrap();
+        let written: String = std::fs::read_to_string(&out_name).unwrap();
+        std::fs::remove_file(&out_name).unwrap();
+        let restored: JoinReport = ed_join_with_config(&doc, &doc, &config).unwrap();
+        assert_eq!(std::fs::read_to_string(&out_name).unwrap(), written);
+        assert_eq!(restored.stats, report.stats);
+        assert!(restored.stats.candidates > 0 && restored.stats.phases.len() == 4);
+
+        // a tokenizer or scorer of your own, or a time budget, can't be told apart by the cache
+        let custom = crate::tokenizer::FnTokenizer::new("qgrams", |record: &str, q| {
+            QGrams.tokenize(record, q)
+        });
+        for config in [
+            config.clone().tokenizer(custom),
+            config.clone().record_timeout(Duration::from_secs(1)),
+        ] {
+            assert!(ed_join_with_config(&doc, &doc, &config).is_err());
+        }
+    }
+
+    #[test]
+    fn test_clusters_next_to_output() {
+        let dir: TempDir = TempDir::new("clusters");
+        let doc: PathBuf = dir.join("records.txt");
+        std::fs::write(&doc, "hello\nhallo\nworld\n").unwrap();
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1)
+            .output_dir(dir.path())
+            .clustering(crate::cluster::Linkage::Single, 1);
+        let report: JoinReport = ed_join_with_config(&doc, &doc, &config).unwrap();
+        let clusters_name: PathBuf = dir.join("records_out_q2_tau1.clusters.csv");
+        assert_eq!(report.clusters.as_deref(), Some(clusters_name.as_path()));
+        assert!(std::fs::read_to_string(&clusters_name)
+            .unwrap()
+            .starts_with("0,"));
+        // nothing is clustered without clustering
+        let report: JoinReport =
+            ed_join_with_config(&doc, &doc, &EdJoinConfig::new(2, 1).output_dir(dir.path()))
+                .unwrap();
+        assert_eq!(report.clusters, None);
+    }
+
+    #[test]
+    fn test_limits() {
+        let dir: TempDir = TempDir::new("limits");
+        let doc: PathBuf = dir.join("records.txt");
+        std::fs::write(&doc, "hello\nhallo\nworld\nword\nhelp\n").unwrap();
+        let manifest_name: PathBuf = dir.join("records_out_q2_tau1.txt.manifest");
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1).output_dir(dir.path());
+        let limited = |limits: Limits| {
+            ed_join_with_config(&doc, &doc, &config.clone().limits(limits)).unwrap_err()
+        };
+
+        let err: Error = limited(Limits {
+            index_bytes: Some(1),
+            ..Limits::default()
+        });
+        assert!(
+            matches!(err.kind(), ErrorKind::LimitExceeded(phase, limit, _, 1) if phase == "index" && limit == "index_bytes")
+        );
+        let manifest: String = std::fs::read_to_string(&manifest_name).unwrap();
+        assert!(
+            manifest.starts_with("ed_join manifest v1\nphase\tindex\nlimit\tindex_bytes\n"),
+            "{}",
+            manifest
+        );
+        assert!(
+            manifest.contains("\ncompleted\tread_inputs\t") && manifest.ends_with("\nrecords\t0\n")
+        );
+
+        // the pairs are `hello` and `hallo`, and `world` and `word`, of which only one is allowed
+        let err: Error = limited(Limits {
+            output_rows: Some(1),
+            ..Limits::default()
+        });
+        assert!(matches!(err.kind(), ErrorKind::LimitExceeded(phase, _, 2, 1) if phase == "join"));
+        let manifest: String = std::fs::read_to_string(&manifest_name).unwrap();
+        assert!(
+            manifest.contains("\nlimit\toutput_rows\nvalue\t2\nmax\t1\n"),
+            "{}",
+            manifest
+        );
+        assert!(
+            manifest.contains("\ncompleted\tindex\t")
+                && manifest.contains("records_out_q2_tau1.txt.partial\n")
+        );
+        let err: Error = limited(Limits {
+            candidates: Some(1),
+            ..Limits::default()
+        });
+        assert!(
+            matches!(err.kind(), ErrorKind::LimitExceeded(_, limit, _, 1) if limit == "candidates")
+        );
+
+        // a join within its limits leaves no manifest
+        let limits: Limits = Limits {
+            index_bytes: Some(1 << 20),
+            candidates: Some(100),
+            output_rows: Some(2),
+        };
+        ed_join_with_config(&doc, &doc, &config.clone().limits(limits)).unwrap();
+        assert!(!manifest_name.exists());
+        assert!(EdJoinIter::new(&doc, &doc, &config.limits(limits)).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let dir: TempDir = TempDir::new("validate");
+        let doc: PathBuf = dir.join("records.txt");
+        std::fs::write(&doc, "hello\nhallo\n").unwrap();
+
+        // all violations are reported before anything is read, each with a suggestion
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1)
+            .resume()
+            .output_format(OutputFormat::Json)
+            .tau_ratio(1.5)
+            .sliding_windows(1, 1, 0.5)
+            .output(MemorySink::default());
+        let violations: Vec<Violation> = config.violations();
+        let options: Vec<&str> = violations
+            .iter()
+            .map(|violation| violation.options[0])
+            .collect();
+        assert_eq!(
+            options,
+            ["tau_ratio", "output", "resume", "windowing", "windowing"]
+        );
+        let err: Error = ed_join_with_config(&doc, &doc, &config).unwrap_err();
+        let msg: String = err.to_string();
+        assert!(
+            msg.starts_with("invalid configuration, 5 violations:\n  * Not a valid ratio"),
+            "{}",
+            msg
+        );
+        assert!(
+            msg.contains(
+                "Resuming requires CSV output, without assignment or clustering, so drop `resume`"
+            ),
+            "{}",
+            msg
+        );
+        assert!(ed_join_with_config(
+            &doc,
+            &doc,
+            &EdJoinConfig::new(2, 1).output(MemorySink::default())
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_empty_inputs() {
+        let dir: TempDir = TempDir::new("empty_inputs");
+        let (empty, doc) = (dir.join("empty.txt"), dir.join("nonempty.txt"));
+        std::fs::write(&empty, "").unwrap();
+        std::fs::write(&doc, "hello\nhallo\n").unwrap();
+
+        let sink: MemorySink = MemorySink::default();
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1).output(sink.clone());
+        assert!(ed_join_with_config(&empty, &empty, &config)
+            .unwrap()
+            .warnings
+            .is_empty());
+        assert!(ed_join_with_config(&empty, &doc, &config)
+            .unwrap()
+            .warnings
+            .is_empty());
+        assert!(sink.contents().is_empty());
+        let err: Error = ed_join_with_config(&doc, &empty, &config).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::EmptyReference(_)));
+        assert!(ed_join_matches(&doc, &empty, &config).is_err());
+        assert!(ed_join_with_config(&empty, &empty, &config.clone().force_cross_join()).is_err());
+    }
+
+    #[test]
+    fn test_join_mode() {
+        let dir: TempDir = TempDir::new("join_mode");
+        let (doc, copy, other) = (
+            dir.join("records.txt"),
+            dir.join("copy.txt"),
+            dir.join("other.txt"),
+        );
+        std::fs::write(&doc, "hello\nhallo\n").unwrap();
+        std::fs::write(&copy, "hello\nhallo\n").unwrap();
+        std::fs::write(&other, "hello\nhullo\n").unwrap();
+        let same: PathBuf = dir.join(".").join(doc.file_name().unwrap());
+
+        assert!(is_self_join(&doc, &same, JoinMode::Auto).unwrap());
+        assert!(!is_self_join(&doc, &copy, JoinMode::Auto).unwrap());
+        assert!(is_self_join(&doc, &copy, JoinMode::SelfJoin).unwrap());
+        assert!(!is_self_join(&doc, &same, JoinMode::CrossJoin).unwrap());
+        assert!(is_self_join(&doc, &other, JoinMode::SelfJoin).is_err());
+
+        let pairs = |doc_y: &PathBuf, mode: JoinMode| -> Vec<(ID, Vec<(ID, usize)>)> {
+            ed_join_matches(&doc, doc_y, &EdJoinConfig::new(2, 1).mode(mode))
+                .unwrap()
+                .pairs
+        };
+        assert_eq!(pairs(&same, JoinMode::Auto), vec![(0, vec![(1, 1)])]);
+        assert_eq!(pairs(&copy, JoinMode::SelfJoin), vec![(0, vec![(1, 1)])]);
+        assert_eq!(
+            pairs(&copy, JoinMode::Auto),
+            vec![(0, vec![(0, 0), (1, 1)]), (1, vec![(0, 1), (1, 0)])]
+        );
+    }
+
+    #[test]
+    fn test_filters_and_normalization() {
+        let dir: TempDir = TempDir::new("filters_and_normalization");
+        let (doc_x, doc_y) = (dir.join("x.txt"), dir.join("y.txt"));
+        std::fs::write(&doc_x, "  Hello   World\nabcdefgh\n").unwrap();
+        std::fs::write(&doc_y, "hello world\nabcdefgx\nhgfedcba\n").unwrap();
+
+        let pairs = |config: EdJoinConfig| -> String {
+            let sink: MemorySink = MemorySink::default();
+            ed_join_with_config(&doc_x, &doc_y, &config.output(sink.clone())).unwrap();
+            String::from_utf8(sink.contents()).unwrap()
+        };
+        assert_eq!(pairs(EdJoinConfig::new(2, 1)), "1,1,1\n");
+        let normalization: Normalization = Normalization {
+            unicode_form: None,
+            strip_diacritics: false,
+            lowercase: true,
+            trim: true,
+            collapse_whitespace: true,
+            transliterate: false,
+        };
+        assert_eq!(
+            normalization.apply("  Hello   World").unwrap(),
+            "hello world"
+        );
+        // case and accents are ignored alike, whether letters are precomposed or not
+        let insensitive: Normalization = Normalization {
+            unicode_form: Some(UnicodeForm::Nfkc),
+            strip_diacritics: true,
+            ..normalization
+        };
+        #[cfg(feature = "unicode")]
+        assert_eq!(
+            insensitive.apply(" JOSÉ  Mu\u{308}ller ﬁ").unwrap(),
+            insensitive.apply("José Muller fi").unwrap()
+        );
+        #[cfg(not(feature = "unicode"))]
+        assert!(insensitive.apply("José").is_err());
+        let normalized: String = pairs(EdJoinConfig::new(2, 1).normalization(normalization));
+        assert_eq!(normalized, "0,0,0\n1,1,1\n");
+
+        // the filters only save edit distances, so the matches of a join without them are the same
+        let unfiltered: Filters = Filters {
+            count: false,
+            location: false,
+            content: false,
+        };
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1)
+            .normalization(normalization)
+            .filters(unfiltered);
+        assert_eq!(pairs(config), normalized);
+    }
+
+    #[test]
+    fn test_transliteration() {
+        let dir: TempDir = TempDir::new("transliteration");
+        let (doc_x, doc_y) = (dir.join("x.txt"), dir.join("y.txt"));
+        std::fs::write(&doc_x, "Müller\nStraße 5\n").unwrap();
+        std::fs::write(&doc_y, "Mueller\nMuller\nStrasse 5\n").unwrap();
+
+        let normalization: Normalization = Normalization {
+            transliterate: true,
+            ..Normalization::default()
+        };
+        let template: OutputTemplate =
+            OutputTemplate::parse("{x_id},{y_id},{ed},{x_text},{y_text}").unwrap();
+        let sink: MemorySink = MemorySink::default();
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1)
+            .normalization(normalization)
+            .output_format(OutputFormat::Template(template))
+            .output(sink.clone());
+        ed_join_with_config(&doc_x, &doc_y, &config).unwrap();
+        // records are joined transliterated, but written as they are
+        assert_eq!(
+            String::from_utf8(sink.contents()).unwrap(),
+            "0,0,1,Müller,Mueller\n0,1,0,Müller,Muller\n1,2,0,Straße 5,Strasse 5\n"
+        );
+        let output: JoinOutput = ed_join_matches(
+            &doc_x,
+            &doc_y,
+            &EdJoinConfig::new(2, 1).normalization(normalization),
+        )
+        .unwrap();
+        assert_eq!(output.x_records, ["Müller", "Straße 5"]);
+        assert_eq!(output.pairs.len(), 2);
+    }
+
+    #[test]
+    fn test_top_k() {
+        let dir: TempDir = TempDir::new("top_k");
+        let (doc_x, doc_y) = (dir.join("x.txt"), dir.join("y.txt"));
+        std::fs::write(&doc_x, "hello\n").unwrap();
+        std::fs::write(&doc_y, "jello\nhelo\nhello\nhallo\n").unwrap();
+
+        let sink: MemorySink = MemorySink::default();
+        ed_join_with_config(
+            &doc_x,
+            &doc_y,
+            &EdJoinConfig::new(2, 2).knn(2).output(sink.clone()),
+        )
+        .unwrap();
+        // the identical record, and the first of the three at a distance of 1
+        assert_eq!(
+            String::from_utf8(sink.contents()).unwrap(),
+            "0,0,1\n0,2,0\n"
+        );
+
+        // a threshold of 0 is never widened
+        let sink: MemorySink = MemorySink::default();
+        ed_join_with_config(
+            &doc_x,
+            &doc_y,
+            &EdJoinConfig::new(2, 0).knn(2).output(sink.clone()),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(sink.contents()).unwrap(), "0,2,0\n");
+        let config: EdJoinConfig = EdJoinConfig::new(2, 2).knn(0);
+        assert_eq!(config.violations()[0].options, ["knn"]);
+        assert!(ed_join_with_config(&doc_x, &doc_y, &config).is_err());
+    }
+
+    #[test]
+    fn test_tau_ratio() {
+        let dir: TempDir = TempDir::new("tau_ratio");
+        let (doc_x, doc_y) = (dir.join("x.txt"), dir.join("y.txt"));
+        std::fs::write(&doc_x, "hello world\ngood day\n").unwrap();
+        std::fs::write(&doc_y, "hello wrld\nhallo wrld\ngood dai\n").unwrap();
+
+        let sink: MemorySink = MemorySink::default();
+        ed_join_with_config(
+            &doc_x,
+            &doc_y,
+            &EdJoinConfig::new(2, 2).output(sink.clone()),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(sink.contents()).unwrap(),
+            "0,0,1\n0,1,2\n1,2,1\n"
+        );
+        // 5% of 11 characters is 1 edit, and so is 5% of 8, rounded up
+        let sink: MemorySink = MemorySink::default();
+        ed_join_with_config(
+            &doc_x,
+            &doc_y,
+            &EdJoinConfig::new(2, 2).tau_ratio(0.05).output(sink.clone()),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(sink.contents()).unwrap(),
+            "0,0,1\n1,2,1\n"
+        );
+        let output: JoinOutput =
+            ed_join_matches(&doc_x, &doc_y, &EdJoinConfig::new(2, 2).tau_ratio(0.05)).unwrap();
+        assert_eq!(output.pairs, [(0, vec![(0, 1)]), (1, vec![(2, 1)])]);
+        assert!(
+            ed_join_with_config(&doc_x, &doc_y, &EdJoinConfig::new(2, 2).tau_ratio(1.5)).is_err()
+        );
+    }
+
+    #[test]
+    fn test_metric() {
+        let dir: TempDir = TempDir::new("metric");
+        let doc: PathBuf = dir.join("records.txt");
+        std::fs::write(
+            &doc,
+            "Jonathan Smith
+Jonahtan Smiht
+Jonathan Smyth
+",
+        )
+        .unwrap();
+
+        let config = EdJoinConfig::new(2, 2);
+        let output: JoinOutput = ed_join_matches(&doc, &doc, &config).unwrap();
+        assert_eq!(output.pairs, [(0, vec![(2, 1)])]);
+        // two transpositions, which are four edits of the Levenshtein distance, whether filtered or bypassed
+        for config in [config.clone().bypass_len(0), config.clone()] {
+            let sink: MemorySink = MemorySink::default();
+            let config = config
+                .metric(DistanceMetric::DamerauLevenshtein)
+                .output(sink.clone());
+            ed_join_with_config(&doc, &doc, &config).unwrap();
+            assert_eq!(
+                String::from_utf8(sink.contents()).unwrap(),
+                "0,1,2\n0,2,1\n"
+            );
+        }
+        let config = config.metric(DistanceMetric::DamerauLevenshtein);
+        let output: JoinOutput = ed_join_matches(&doc, &doc, &config).unwrap();
+        assert_eq!(output.pairs, [(0, vec![(1, 2), (2, 1)])]);
+        assert!(ed_join_with_config(&doc, &doc, &config.approximate(0.9)).is_err());
+    }
+
+    #[test]
+    fn test_damerau_threshold_above_q() {
+        // the optimal string alignment distance, by brute force
+        let osa = |s: &str, t: &str| -> usize {
+            let (s, t): (Vec<char>, Vec<char>) = (s.chars().collect(), t.chars().collect());
+            let mut d: Vec<Vec<usize>> = vec![vec![0; t.len() + 1]; s.len() + 1];
+            for i in 0..=s.len() {
+                for j in 0..=t.len() {
+                    d[i][j] = if i == 0 || j == 0 {
+                        i + j
+                    } else {
+                        let mut best = (d[i - 1][j] + 1)
+                            .min(d[i][j - 1] + 1)
+                            .min(d[i - 1][j - 1] + (s[i - 1] != t[j - 1]) as usize);
+                        if i > 1 && j > 1 && s[i - 1] == t[j - 2] && s[i - 2] == t[j - 1] {
+                            best = best.min(d[i - 2][j - 2] + 1);
+                        }
+                        best
+                    };
+                }
+            }
+            d[s.len()][t.len()]
+        };
+        let dir: TempDir = TempDir::new("damerau_threshold_above_q");
+        let doc: PathBuf = dir.join("records.txt");
+        let records: Vec<&str> = vec![
+            "abcdefgh",
+            "badcefgh",
+            "cdefghab",
+            "abdcfegh",
+            "aaabba",
+            "abba",
+            "baab",
+            "ccabcdefgh",
+            "abcdef",
+        ];
+        std::fs::write(&doc, records.join("\n") + "\n").unwrap();
+
+        // q < 2 * tau, so the q-grams of a pair within the filter threshold may be shifted by more than q
+        let tau: usize = 2;
+        let mut brute_force: Vec<(ID, ID, usize)> = Vec::new();
+        for (x_id, x) in records.iter().enumerate() {
+            for (y_id, y) in records.iter().enumerate().skip(x_id + 1) {
+                let d: usize = osa(x, y);
+                if d <= tau {
+                    brute_force.push((x_id, y_id, d));
+                }
+            }
+        }
+        for q in [1, 2] {
+            // the records are short enough to bypass the filters, unless the bypass is disabled
+            let config: EdJoinConfig = EdJoinConfig::new(q, tau)
+                .metric(DistanceMetric::DamerauLevenshtein)
+                .bypass_len(0);
+            let sink: MemorySink = MemorySink::default();
+            ed_join_with_config(&doc, &doc, &config.clone().output(sink.clone())).unwrap();
+            let mut joined: Vec<(ID, ID, usize)> = String::from_utf8(sink.contents())
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    let fields: Vec<usize> = line
+                        .split(',')
+                        .map(|field| field.parse().unwrap())
+                        .collect();
+                    (fields[0], fields[1], fields[2])
+                })
+                .collect();
+            joined.sort_unstable();
+            assert_eq!(joined, brute_force, "q = {}", q);
+            let output: JoinOutput = ed_join_matches(&doc, &doc, &config).unwrap();
+            let matched: Vec<(ID, ID, usize)> = output
+                .pairs
+                .iter()
+                .flat_map(|(x_id, pairs)| pairs.iter().map(move |(y_id, d)| (*x_id, *y_id, *d)))
+                .collect();
+            assert_eq!(matched, brute_force, "q = {}", q);
+        }
+    }
+
+    #[test]
+    fn test_tombstones() {
+        let dir: TempDir = TempDir::new("tombstones");
+        let doc: PathBuf = dir.join("records.txt");
+        std::fs::write(&doc, "hello\nhallo\njello\nhello\n").unwrap();
+
+        // `hallo` is deleted, and so is the copy of `hello` after it, in a self-join
+        let config = |sink: &MemorySink| {
+            EdJoinConfig::new(2, 1)
+                .tombstones(vec![1, 3])
+                .output(sink.clone())
+        };
+        let sink: MemorySink = MemorySink::default();
+        ed_join_with_config(&doc, &doc, &config(&sink)).unwrap();
+        assert_eq!(String::from_utf8(sink.contents()).unwrap(), "0,2,1\n");
+        let output: JoinOutput =
+            ed_join_matches(&doc, &doc, &EdJoinConfig::new(2, 1).tombstones(vec![1, 3])).unwrap();
+        assert_eq!(output.pairs, [(0, vec![(2, 1)])]);
+    }
+
+    #[test]
+    fn test_provenance() {
+        let dir: TempDir = TempDir::new("provenance");
+        let doc: PathBuf = dir.join("records.txt");
+        std::fs::write(&doc, "hello\nhallo\nyellow\nhelp\nfellow\n").unwrap();
+        let records: Vec<String> = ["hello", "hallo", "yellow", "help", "fellow"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let names: Vec<PathBuf> = vec![dir.join("a.index"), dir.join("b.index")];
+        for (name, part) in names.iter().zip(&[&records[..2], &records[2..]]) {
+            write_index(
+                name,
+                &build_inverted_index(None, part, 2),
+                2,
+                Some(part.len()),
+            )
+            .unwrap();
+        }
+        let merged: PathBuf = dir.join("merged.index");
+        crate::persist::merge_indexes(&names, &merged).unwrap();
+
+        // `fellow` is the third record of the second part
+        let sink: MemorySink = MemorySink::default();
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1)
+            .index_from(&merged)
+            .provenance()
+            .output_format(OutputFormat::CsvHeader)
+            .output(sink.clone());
+        ed_join_with_config(&doc, &doc, &config).unwrap();
+        assert_eq!(
+            String::from_utf8(sink.contents()).unwrap(),
+            format!(
+                "x_id,y_id,ed,y_source,y_line\n0,1,1,{},1\n2,4,1,{},2\n",
+                names[0].display(),
+                names[1].display()
+            )
+        );
+
+        // an index that isn't merged has no parts
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1)
+            .index_from(&names[0])
+            .provenance()
+            .output(MemorySink::default());
+        assert!(ed_join_with_config(&doc, &doc, &config).is_err());
+        let options = |config: EdJoinConfig| -> Vec<&'static str> {
+            config
+                .violations()
+                .iter()
+                .map(|violation| violation.options[0])
+                .collect()
+        };
+        assert_eq!(
+            options(EdJoinConfig::new(2, 1).provenance()),
+            ["provenance"]
+        );
+        assert_eq!(
+            options(
+                EdJoinConfig::new(2, 1)
+                    .index_from(&merged)
+                    .provenance()
+                    .output_format(OutputFormat::Dot)
+            ),
+            ["provenance"]
+        );
+    }
+
+    #[test]
+    fn test_ed_join_candidates() {
+        let dir: TempDir = TempDir::new("ed_join_candidates");
+        let doc: PathBuf = dir.join("records.txt");
+        std::fs::write(&doc, "hello\nhallo\nworld\nword\nhelp\n").unwrap();
+
+        // the candidates are a superset of the matches, without their edit distances
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1);
+        let candidates: JoinCandidates = ed_join_candidates(&doc, &doc, &config).unwrap();
+        let matches: JoinOutput = ed_join_matches(&doc, &doc, &config).unwrap();
+        assert!(!matches.pairs.is_empty());
+        for (x_id, pairs) in &matches.pairs {
+            let y_ids: &Vec<ID> = &candidates
+                .pairs
+                .iter()
+                .find(|(id, _)| id == x_id)
+                .unwrap()
+                .1;
+            assert!(
+                pairs.iter().all(|(y_id, _)| y_ids.contains(y_id)),
+                "{:?}",
+                candidates.pairs
+            );
+        }
+        assert_eq!(candidates.report.stats.candidates, candidates.len());
+        let mut written: Vec<u8> = Vec::new();
+        candidates.write(&mut written, b',').unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap().lines().count(),
+            candidates.len()
+        );
+        assert!(
+            ed_join_candidates(&doc, &doc, &config.stop_after(crate::persist::Phase::Index))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_progress() {
+        let dir: TempDir = TempDir::new("progress");
+        let doc: PathBuf = dir.join("records.txt");
+        std::fs::write(&doc, "hello\nhallo\njello\nworld\n").unwrap();
+
+        let events: Arc<Mutex<Vec<ProgressEvent>>> = Arc::default();
+        let seen: Arc<Mutex<Vec<ProgressEvent>>> = Arc::clone(&events);
+        let sink: MemorySink = MemorySink::default();
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1)
+            .output(sink.clone())
+            .on_progress(move |event| seen.lock().unwrap().push(event));
+        ed_join_with_config(&doc, &doc, &config).unwrap();
+
+        // every record is counted once, and the last one counted has the totals of the join
+        let mut events: Vec<ProgressEvent> = events.lock().unwrap().clone();
+        events.sort_by_key(|event| event.records);
+        assert_eq!(
+            events.iter().map(|event| event.records).collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+        let last: ProgressEvent = events[3];
+        assert_eq!(last.total, 4);
+        assert_eq!(
+            last.matches,
+            String::from_utf8(sink.contents()).unwrap().lines().count()
+        );
+        assert!(last.matches > 0 && last.candidates >= last.matches);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let dir: TempDir = TempDir::new("diagnostics");
+        let (doc_x, doc_y, diagnostics) = (
+            dir.join("x.txt"),
+            dir.join("y.txt"),
+            dir.join("diagnostics.csv"),
+        );
+        std::fs::write(&doc_x, "hello\nworld\nspam\n").unwrap();
+        std::fs::write(&doc_y, "hallo\nhelps\nwords\n").unwrap();
+
+        let config: EdJoinConfig = EdJoinConfig::new(2, 1)
+            .output(MemorySink::default())
+            .diagnostics(&diagnostics);
+        ed_join_with_config(&doc_x, &doc_y, &config).unwrap();
+        // `hallo` and `helps` share a q-gram of the prefix of `hello`, but only `hallo` is within `tau`
+        assert_eq!(
+            std::fs::read_to_string(&diagnostics).unwrap(),
+            "x_id,prefix_len,candidates,verified,matches\n0,3,2,2,1\n1,3,1,1,0\n2,3,0,0,0\n"
+        );
+    }
+
+    #[test]
+    fn test_unicode_join() {
+        let dir: TempDir = TempDir::new("unicode_join");
+        let doc: PathBuf = dir.join("records.txt");
+        // `é` is a single character in the first records, but `e` and a combining accent in the last one
+        let records: Vec<&str> = vec![
+            "café crème",
+            "cafe creme",
+            "caffè crema",
+            "ça va",
+            "ça vá",
+            "cafe\u{301} cre\u{300}me",
+            "Müller Hans",
+            "Muller Hans",
+        ];
+        std::fs::write(&doc, records.join("\n")).unwrap();
+
+        // byte grams split characters, which mustn't panic
+        let packed: PackedGrams = PackedGrams::new(b"aceflmr").unwrap();
+        let tokenizers: Vec<Arc<dyn Tokenizer>> = vec![
+            Arc::new(CharQGrams),
+            Arc::new(GraphemeQGrams),
+            Arc::new(QGrams),
+            Arc::new(packed),
+        ];
+        for tokenizer in tokenizers {
+            let mode: TokenizerMode = tokenizer.mode();
+            let mut buffer: EditDistanceBuffer = EditDistanceBuffer::default();
+            let mut expected: String = String::new();
+            for x_id in 0..records.len() {
+                for y_id in x_id + 1..records.len() {
+                    let ed: usize = buffer.edit_distance_in(records[x_id], records[y_id], mode);
+                    if ed <= 3 {
+                        expected.push_str(&format!("{},{},{}\n", x_id, y_id, ed));
+                    }
+                }
+            }
+            let sink: MemorySink = MemorySink::default();
+            let mut config: EdJoinConfig = EdJoinConfig::new(2, 3).output(sink.clone());
+            config.tokenizer = tokenizer;
+            ed_join_with_config(&doc, &doc, &config).unwrap();
+            assert_eq!(
+                String::from_utf8(sink.contents()).unwrap(),
+                expected,
+                "{:?}",
+                mode
+            );
+        }
+        // a character with a combining accent is a single grapheme
+        let mut buffer: EditDistanceBuffer = EditDistanceBuffer::default();
+        assert_eq!(
+            buffer.edit_distance_in(records[0], records[5], TokenizerMode::Chars),
+            4
+        );
+        assert_eq!(
+            buffer.edit_distance_in(records[0], records[5], TokenizerMode::Graphemes),
+            2
+        );
+    }
+
+    #[test]
+    fn test_bypass_units() {
+        let dir: TempDir = TempDir::new("bypass_units");
+        let doc: PathBuf = dir.join("records.txt");
+        // 3 characters, but 4 bytes each, and the count filter would prune the pair,
+        // while the last record is too long to be a candidate, but makes `ä` the rarest q-gram
+        std::fs::write(&doc, "äbc\näxy\nbcxybcxy\n").unwrap();
+
+        // records are as short as the bypass by their characters, rather than their bytes
+        let mut config: EdJoinConfig = EdJoinConfig::new(1, 1)
+            .bypass_len(4)
+            .output(MemorySink::default());
+        config.tokenizer = TokenizerMode::Chars.tokenizer();
+        let stats: JoinStats = ed_join_with_config(&doc, &doc, &config).unwrap().stats;
+        assert_eq!(stats.candidates, 1);
+        assert_eq!(stats.edit_distances, 1);
+    }
+
+    #[test]
+    fn test_token_join() {
+        let dir: TempDir = TempDir::new("token_join");
+        let doc: PathBuf = dir.join("records.txt");
+        let records: Vec<&str> = vec![
+            "221B Baker Street, London",
+            "221B Baker St., London",
+            "Baker Street 221B London",
+            "10 Downing Street, London",
+            "221B Baker Street London NW1",
+        ];
+        std::fs::write(&doc, records.join("\n")).unwrap();
+
+        let mode: TokenizerMode = TokenizerMode::Words { punctuation: true };
+        let mut buffer: EditDistanceBuffer = EditDistanceBuffer::default();
+        let mut expected: String = String::new();
+        for x_id in 0..records.len() {
+            for y_id in x_id + 1..records.len() {
+                let ed: usize = buffer.edit_distance_in(records[x_id], records[y_id], mode);
+                if ed <= 2 {
+                    expected.push_str(&format!("{},{},{}\n", x_id, y_id, ed));
+                }
+            }
+        }
+        assert_eq!(expected, "0,1,1\n0,2,2\n0,3,2\n0,4,1\n1,4,2\n");
+        let sink: MemorySink = MemorySink::default();
+        let mut config: EdJoinConfig = EdJoinConfig::new(1, 2).output(sink.clone());
+        config.tokenizer = mode.tokenizer();
+        ed_join_with_config(&doc, &doc, &config).unwrap();
+        assert_eq!(String::from_utf8(sink.contents()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_count_mismatch() {
+        let (x, y) = (
+            PosQGramArray::from("abcab", 2),
+            PosQGramArray::from("abcb", 2),
+        );
+        // `ab` at 0 and `bc` at 1 have counterparts, while `ca` and `ab` at 3 of the longer `x` don't
+        assert_eq!(count_mismatch(&x, &y, 1), 2);
+        assert_eq!(count_mismatch(&y, &x, 1), 2);
+        assert_eq!(count_mismatch(&x, &x, 0), 0);
+    }
+
+    #[test]
+    fn test_min_edit_error() {
+        let qgram_array: PosQGramArray = PosQGramArray::from("hello", 2);
+        assert_eq!(min_edit_errors(&qgram_array, 2), 2);
+        // the same in any order, and the q-gram at location 0 also needs an edit
+        let unsorted: Vec<PosQGram> = ["lo", "he"]
+            .iter()
+            .zip(&[3, 0])
+            .map(|(token, loc)| PosQGram {
+                token: token.to_string(),
+                loc: *loc,
+            })
+            .collect();
+        assert_eq!(min_edit_errors(&unsorted, 2), 2);
+        assert_eq!(min_edit_errors(&[], 2), 0);
     }
 
     #[test]
     fn test_calc_prefix_len() {
-        let mut qgram_array: PosQGramArray = PosQGramArray::from_vec(vec![
+        let qgram_array: PosQGramArray = PosQGramArray::from_vec(vec![
             PosQGram {
                 token: "lo".to_string(),
                 loc: 3,
@@ -301,7 +3618,9 @@ mod tests {
                 loc: 2,
             },
         ]);
-        let result = calc_prefix_len(&mut qgram_array, 2, 2);
+        let result = calc_prefix_len(&qgram_array, 2, 2);
         assert_eq!(result, 4);
+        // at most the number of q-grams, when even all of them incur too few errors
+        assert_eq!(calc_prefix_len(&qgram_array[..2], 2, 2), 2);
     }
 }
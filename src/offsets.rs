@@ -0,0 +1,178 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::errors::*;
+use crate::qgram::ID;
+
+/// Byte offsets of the lines of a document, to read any record by its line number without scanning the file.
+///
+/// Lines are split the same way as `read_records()` does, so the ID of a record is its index here.
+/// The offsets can be persisted to a sidecar file next to the document, see [`LineOffsets::sidecar`].
+///
+/// ```no_run
+/// use ed_join::offsets::LineOffsets;
+/// use std::{fs::File, path::Path};
+///
+/// let doc = Path::new("reference.txt");
+/// let offsets = LineOffsets::load_or_build(doc, true).unwrap();
+/// let record = offsets.fetch(&mut File::open(doc).unwrap(), 41).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineOffsets {
+    /// Offset of the start of each line.
+    offsets: Vec<u64>,
+    /// Length of the document, i.e. the end of the last line, which also tells whether a sidecar is stale.
+    len: u64,
+}
+
+impl LineOffsets {
+    /// Scan a document for the offsets of its lines.
+    pub fn build(path: &Path) -> Result<Self> {
+        let mut reader: BufReader<File> = BufReader::new(File::open(path)?);
+        let mut offsets: Vec<u64> = Vec::new();
+        let mut line: Vec<u8> = Vec::new();
+        let mut len: u64 = 0;
+        loop {
+            line.clear();
+            let n: usize = reader.read_until(b'\n', &mut line)?;
+            if n == 0 {
+                break;
+            }
+            offsets.push(len);
+            len += n as u64;
+        }
+
+        Ok(Self { offsets, len })
+    }
+
+    /// Path of the sidecar file of a document, i.e. the path with `.offsets` appended.
+    pub fn sidecar(path: &Path) -> PathBuf {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(".offsets");
+        PathBuf::from(sidecar)
+    }
+
+    /// Write the offsets to a sidecar file, one per line, after a header with the length of the document.
+    pub fn write(&self, sidecar: &Path) -> Result<()> {
+        let mut writer: BufWriter<File> = BufWriter::new(File::create(sidecar)?);
+        writeln!(writer, "ed_join offsets v1 len={}", self.len)?;
+        for offset in &self.offsets {
+            writeln!(writer, "{}", offset)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Read offsets written by [`LineOffsets::write`], or `None` if they're not of a document of length `len`,
+    /// e.g. because it changed since.
+    pub fn read(sidecar: &Path, len: u64) -> Result<Option<Self>> {
+        let mut reader: BufReader<File> = BufReader::new(File::open(sidecar)?);
+        let mut header: String = String::new();
+        reader.read_line(&mut header)?;
+        if header.trim_end() != format!("ed_join offsets v1 len={}", len) {
+            return Ok(None);
+        }
+
+        let offsets: Vec<u64> = reader
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                line?.parse().map_err(|_| {
+                    Error::from(format!(
+                        "Invalid line {} of offsets {}",
+                        i + 2,
+                        sidecar.display()
+                    ))
+                })
+            })
+            .collect::<Result<Vec<u64>>>()?;
+
+        Ok(Some(Self { offsets, len }))
+    }
+
+    /// Read the offsets of a document from its sidecar if it's up to date, or scan the document otherwise,
+    /// and then write the sidecar if `persist` is set.
+    pub fn load_or_build(path: &Path, persist: bool) -> Result<Self> {
+        let sidecar: PathBuf = Self::sidecar(path);
+        if sidecar.is_file() {
+            if let Some(offsets) = Self::read(&sidecar, path.metadata()?.len())? {
+                return Ok(offsets);
+            }
+        }
+        let offsets: Self = Self::build(path)?;
+        if persist {
+            offsets.write(&sidecar)?;
+        }
+
+        Ok(offsets)
+    }
+
+    /// Number of lines of the document.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Read a record of the document, by its line number, or `None` if there's no such line.
+    ///
+    /// The line break is stripped, and so is the carriage return of CRLF line endings.
+    pub fn fetch(&self, file: &mut File, id: ID) -> Result<Option<String>> {
+        let start: u64 = match self.offsets.get(id) {
+            Some(start) => *start,
+            None => return Ok(None),
+        };
+        let end: u64 = self.offsets.get(id + 1).copied().unwrap_or(self.len);
+
+        let mut line: Vec<u8> = vec![0; (end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut line)?;
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+
+        Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn fetch_lines() {
+        let dir: TempDir = TempDir::new("offsets");
+        let doc: PathBuf = dir.join("records.txt");
+        std::fs::write(&doc, "hello\r\n\nworld").unwrap();
+
+        let offsets: LineOffsets = LineOffsets::load_or_build(&doc, true).unwrap();
+        assert_eq!(offsets.len(), 3);
+        let mut file: File = File::open(&doc).unwrap();
+        assert_eq!(
+            offsets.fetch(&mut file, 0).unwrap().as_deref(),
+            Some("hello")
+        );
+        assert_eq!(offsets.fetch(&mut file, 1).unwrap().as_deref(), Some(""));
+        assert_eq!(
+            offsets.fetch(&mut file, 2).unwrap().as_deref(),
+            Some("world")
+        );
+        assert_eq!(offsets.fetch(&mut file, 3).unwrap(), None);
+
+        // the sidecar is reused as long as the document is unchanged
+        let sidecar: PathBuf = LineOffsets::sidecar(&doc);
+        assert_eq!(LineOffsets::read(&sidecar, 13).unwrap(), Some(offsets));
+        std::fs::write(&doc, "hello\n").unwrap();
+        assert_eq!(LineOffsets::load_or_build(&doc, false).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,1547 @@
+use std::{
+    borrow::Cow,
+    cmp,
+    fmt::{self, Debug},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::csv::Fields;
+use crate::errors::*;
+#[cfg(feature = "hashing")]
+use crate::hash::{hmac_sha256, to_hex, Sha256};
+use crate::persist::MergedPart;
+use crate::plugin::MatchPlugin;
+use crate::qgram::ID;
+use crate::report::{JoinReport, Side};
+use crate::scoring::{ScoreContext, Scorer};
+
+/// All matches of a join, together with the records they refer to.
+#[derive(Clone, Copy, Debug)]
+pub struct Matches<'a> {
+    /// Matched pairs, grouped by line number of `doc_x`, in the same layout as [`crate::verification::verify`] returns.
+    pub pairs: &'a [(ID, Vec<(ID, usize)>)],
+    pub x_records: &'a [String],
+    pub y_records: &'a [String],
+    /// Whether `doc_x` and `doc_y` are the same document, so their line numbers refer to the same records.
+    pub self_join: bool,
+    /// If set, every match is scored by this scorer, see [`MatchRecord::confidence`].
+    pub confidence: Option<(&'a dyn Scorer, ScoreContext<'a>)>,
+    /// If set, the passthrough fields of the rows of `doc_x` and `doc_y`, see [`MatchRecord::x_fields`].
+    pub passthrough: Option<(&'a [Fields], &'a [Fields])>,
+    /// If set, the parts of the merged index of `doc_y`, whose records are written with the part they're from,
+    /// see [`Matches::source`].
+    pub provenance: Option<&'a [MergedPart]>,
+    /// Whether the matched records themselves are written too, by the formats that can, see [`OutputFormat::emits_content`].
+    pub emit_content: bool,
+    /// If set, records are written as their salted hashes rather than their texts, see [`TextMask`].
+    pub text_mask: Option<&'a TextMask>,
+    /// Plugins which every match is passed through before it's written, in order, see [`MatchPlugin`].
+    pub plugins: &'a [Arc<dyn MatchPlugin>],
+}
+
+/// Matches of a join returned to its caller, along with the records they refer to, see [`crate::matching::ed_join_matches`].
+///
+/// Nothing is written by such a join, but the matches can still be written in any format with [`JoinOutput::write`].
+#[derive(Clone, Debug, Default)]
+pub struct JoinOutput {
+    /// Matched pairs, grouped by line number of `doc_x` in increasing order, with the line numbers of `doc_y` in increasing order.
+    pub pairs: Vec<(ID, Vec<(ID, usize)>)>,
+    pub x_records: Vec<String>,
+    /// Records of `doc_y`, or `None` for a self-join, whose line numbers refer to `x_records`.
+    pub y_records: Option<Vec<String>>,
+    pub report: JoinReport,
+}
+
+impl JoinOutput {
+    /// The matches, as a join would write them.
+    pub fn matches(&self) -> Matches<'_> {
+        Matches {
+            pairs: &self.pairs,
+            x_records: &self.x_records,
+            y_records: self.y_records.as_deref().unwrap_or(&self.x_records),
+            self_join: self.y_records.is_none(),
+            confidence: None,
+            passthrough: None,
+            provenance: None,
+            emit_content: false,
+            text_mask: None,
+            plugins: &[],
+        }
+    }
+
+    /// Write the matches in the given format, see [`write_matches`].
+    pub fn write<W: Write>(
+        &self,
+        mut writer: W,
+        format: &OutputFormat,
+        delimiter: u8,
+    ) -> Result<()> {
+        write_matches(&mut writer, &self.matches(), format, delimiter)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Candidate pairs of a join which stopped before verifying them, along with the records they refer to,
+/// see [`crate::matching::ed_join_candidates`].
+#[derive(Clone, Debug, Default)]
+pub struct JoinCandidates {
+    /// Candidates of the prefix filter, grouped by line number of `doc_x` in increasing order, with the line numbers
+    /// of `doc_y` in increasing order.
+    pub pairs: Vec<(ID, Vec<ID>)>,
+    pub x_records: Vec<String>,
+    /// Records of `doc_y`, or `None` for a self-join, whose line numbers refer to `x_records`.
+    pub y_records: Option<Vec<String>>,
+    pub report: JoinReport,
+}
+
+impl JoinCandidates {
+    /// Number of candidate pairs.
+    pub fn len(&self) -> usize {
+        self.pairs.iter().map(|(_, y_ids)| y_ids.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the candidate pairs, one per row of line numbers, as [`write_candidate_pairs`] does.
+    pub fn write<W: Write>(&self, writer: W, delimiter: u8) -> Result<()> {
+        write_candidate_pairs(writer, &self.pairs, delimiter)
+    }
+}
+
+impl<'a> Matches<'a> {
+    /// Iterate over all matched pairs that the plugins keep, in the order they're stored.
+    pub fn iter(&self) -> impl Iterator<Item = MatchRecord<'a>> + 'a {
+        let (x_records, y_records, confidence, passthrough) = (
+            self.x_records,
+            self.y_records,
+            self.confidence,
+            self.passthrough,
+        );
+        let plugins: &'a [Arc<dyn MatchPlugin>] = self.plugins;
+        // rows after a read error have no fields
+        let fields = |rows: &'a [Fields], id: ID| -> &'a [String] {
+            rows.get(id).map_or(&[], Vec::as_slice)
+        };
+        self.pairs.iter().flat_map(move |(x_id, pairs)| {
+            pairs.iter().filter_map(move |(y_id, ed)| {
+                let mut m = MatchRecord {
+                    x_id: *x_id,
+                    y_id: *y_id,
+                    ed: *ed,
+                    x_text: &x_records[*x_id],
+                    y_text: &y_records[*y_id],
+                    confidence: None,
+                    x_fields: passthrough.map_or(&[], |(rows, _)| fields(rows, *x_id)),
+                    y_fields: passthrough.map_or(&[], |(_, rows)| fields(rows, *y_id)),
+                };
+                m.confidence = confidence.map(|(scorer, context)| scorer.score(&m, &context));
+                plugins
+                    .iter()
+                    .all(|plugin| plugin.apply(&mut m))
+                    .then_some(m)
+            })
+        })
+    }
+
+    /// The part of the merged index a record of `doc_y` is from, and its line number in that part, counted from 0
+    /// as `y_id` is, or `None` without [`Self::provenance`].
+    pub fn source(&self, y_id: ID) -> Option<(&'a Path, ID)> {
+        let parts: &'a [MergedPart] = self.provenance?;
+        // parts are contiguous and in order of their first IDs
+        let part: &'a MergedPart = &parts[parts
+            .partition_point(|part| part.first_id <= y_id)
+            .checked_sub(1)?];
+        Some((part.path.as_path(), y_id - part.first_id))
+    }
+
+    /// A record as it's written, which is its salted hash if records are masked.
+    pub(crate) fn text<'t>(&self, text: &'t str) -> Cow<'t, str> {
+        match self.text_mask {
+            Some(mask) => Cow::Owned(mask.hash(text)),
+            None => Cow::Borrowed(text),
+        }
+    }
+
+    /// Name of a node in a match graph.
+    ///
+    /// For a self-join, nodes are simply line numbers. Otherwise they're prefixed by the document, e.g. `x:0` and `y:3`.
+    pub(crate) fn node_name(&self, side: Side, id: ID) -> String {
+        if self.self_join {
+            id.to_string()
+        } else {
+            format!("{}:{}", side, id)
+        }
+    }
+
+    /// All nodes involved in at least one match, `doc_x` first, in increasing line numbers.
+    fn nodes(&self) -> Vec<String> {
+        let mut nodes_x: Vec<ID> = Vec::new();
+        let mut nodes_y: Vec<ID> = Vec::new();
+        self.iter().for_each(|m| {
+            nodes_x.push(m.x_id);
+            nodes_y.push(m.y_id);
+        });
+        if self.self_join {
+            nodes_x.append(&mut nodes_y);
+        }
+        nodes_x.sort_unstable();
+        nodes_x.dedup();
+        nodes_y.sort_unstable();
+        nodes_y.dedup();
+
+        nodes_x
+            .into_iter()
+            .map(|id| self.node_name(Side::X, id))
+            .chain(nodes_y.into_iter().map(|id| self.node_name(Side::Y, id)))
+            .collect()
+    }
+}
+
+/// Salted hashes which records are written as instead of their texts, so that matches of sensitive records can be shared
+/// without the records themselves, see [`crate::config::EdJoinConfig::mask_texts`].
+///
+/// A record is written as the hexadecimal HMAC-SHA256 of its text under the salt, by the `hmac` and `sha2` crates.
+/// Equal records have equal hashes, and whoever holds the salt can hash their own records to join them back,
+/// while without it, records can't be recovered by hashing guesses of them. Line numbers, lengths and passthrough
+/// fields are written as they are.
+///
+/// ```
+/// use ed_join::output::TextMask;
+///
+/// let mask = TextMask::new("pepper").unwrap();
+/// assert_eq!(mask.hash("hello"), mask.hash("hello"));
+/// assert_ne!(mask.hash("hello"), TextMask::new("salt").unwrap().hash("hello"));
+/// assert_eq!(mask.hash("hello").len(), 64);
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct TextMask {
+    salt: Vec<u8>,
+    /// A mask can't be created without the `hashing` feature.
+    #[cfg(not(feature = "hashing"))]
+    never: std::convert::Infallible,
+}
+
+impl TextMask {
+    /// A mask under the given salt, or an error if the crate is built without the `hashing` feature.
+    pub fn new(salt: impl Into<Vec<u8>>) -> Result<Self> {
+        #[cfg(feature = "hashing")]
+        {
+            Ok(Self { salt: salt.into() })
+        }
+        #[cfg(not(feature = "hashing"))]
+        {
+            let _ = salt;
+            bail!("Masking texts needs the `hashing` feature of ed_join")
+        }
+    }
+
+    /// The hash a record is written as.
+    pub fn hash(&self, text: &str) -> String {
+        #[cfg(feature = "hashing")]
+        {
+            to_hex(&hmac_sha256(&self.salt, text.as_bytes()))
+        }
+        #[cfg(not(feature = "hashing"))]
+        {
+            let _ = text;
+            match self.never {}
+        }
+    }
+}
+
+impl Debug for TextMask {
+    // the salt is secret, but its checksum tells masks apart, e.g. for cache entries
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[cfg(feature = "hashing")]
+        {
+            let mut hasher: Sha256 = Sha256::new().map_err(|_| fmt::Error)?;
+            hasher.update(&self.salt);
+            f.debug_struct("TextMask")
+                .field("salt_sha256", &hasher.hex_digest())
+                .finish()
+        }
+        #[cfg(not(feature = "hashing"))]
+        {
+            let _ = f;
+            match self.never {}
+        }
+    }
+}
+
+/// A matched pair of records, with everything an output line may need.
+#[derive(Clone, Copy, Debug)]
+pub struct MatchRecord<'a> {
+    pub x_id: ID,
+    pub y_id: ID,
+    pub ed: usize,
+    pub x_text: &'a str,
+    pub y_text: &'a str,
+    /// How likely the pair is a true duplicate, in `[0, 1]`, if scoring is enabled, see [`crate::scoring::Scorer`].
+    pub confidence: Option<f64>,
+    /// Passthrough fields of the row in `doc_x`, if it's a CSV input, see [`crate::csv::CsvInput::passthrough`].
+    pub x_fields: &'a [String],
+    /// Passthrough fields of the row in `doc_y`.
+    pub y_fields: &'a [String],
+}
+
+impl<'a> MatchRecord<'a> {
+    /// Edit distance normalized by the length of the longer record, as a similarity in `[0, 1]`.
+    pub fn score(&self) -> f64 {
+        let max_len: usize =
+            std::cmp::max(self.x_text.chars().count(), self.y_text.chars().count());
+        if max_len == 0 {
+            1.0
+        } else {
+            1.0 - self.ed as f64 / max_len as f64
+        }
+    }
+}
+
+/// A field that can be referred to in an [`OutputTemplate`], as `{name}`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TemplateField {
+    /// `{x_id}`, line number of the record in `doc_x`.
+    XId,
+    /// `{y_id}`, line number of the record in `doc_y`.
+    YId,
+    /// `{ed}`, edit distance of the pair.
+    Ed,
+    /// `{score}`, see [`MatchRecord::score`].
+    Score,
+    /// `{x_text}`, content of the record in `doc_x`.
+    XText,
+    /// `{y_text}`, content of the record in `doc_y`.
+    YText,
+    /// `{x_len}`, number of characters of the record in `doc_x`.
+    XLen,
+    /// `{y_len}`, number of characters of the record in `doc_y`.
+    YLen,
+    /// `{confidence}`, see [`MatchRecord::confidence`]. It's empty unless scoring is enabled.
+    Confidence,
+}
+
+impl TemplateField {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "x_id" => Some(TemplateField::XId),
+            "y_id" => Some(TemplateField::YId),
+            "ed" => Some(TemplateField::Ed),
+            "score" => Some(TemplateField::Score),
+            "x_text" => Some(TemplateField::XText),
+            "y_text" => Some(TemplateField::YText),
+            "x_len" => Some(TemplateField::XLen),
+            "y_len" => Some(TemplateField::YLen),
+            "confidence" => Some(TemplateField::Confidence),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TemplatePart {
+    Literal(String),
+    Field(TemplateField),
+}
+
+/// A user-defined format of output lines, e.g. `{x_id}\t{y_id}\t{ed}\t{x_text}`.
+///
+/// Fields are written as `{name}`, see [`TemplateField`] for the available ones.
+/// `{{` and `}}` are literal braces, and `\t`, `\n` and `\\` are unescaped,
+/// so templates can be given verbatim on the command line. Each match is written on its own line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+impl OutputTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts: Vec<TemplatePart> = Vec::new();
+        let mut literal: String = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name: String = String::new();
+                    let mut closed: bool = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(c);
+                    }
+                    if !closed {
+                        bail!(ErrorKind::InvalidTemplate("unmatched `{`".to_string()));
+                    }
+                    let field = TemplateField::from_name(&name).ok_or_else(|| {
+                        Error::from(ErrorKind::InvalidTemplate(format!(
+                            "unknown field `{{{}}}`",
+                            name
+                        )))
+                    })?;
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(TemplatePart::Field(field));
+                }
+                '}' => bail!(ErrorKind::InvalidTemplate("unmatched `}`".to_string())),
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(c) => {
+                        literal.push('\\');
+                        literal.push(c);
+                    }
+                    None => literal.push('\\'),
+                },
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// Render a matched pair, without the trailing line break.
+    pub fn render(&self, m: &MatchRecord) -> String {
+        self.render_masked(m, None)
+    }
+
+    /// Render a matched pair as [`OutputTemplate::render`] does, but with `{x_text}` and `{y_text}` as their hashes
+    /// if records are masked.
+    pub fn render_masked(&self, m: &MatchRecord, mask: Option<&TextMask>) -> String {
+        let text = |text: &str| mask.map_or_else(|| text.to_string(), |mask| mask.hash(text));
+        let mut line: String = String::new();
+        self.parts.iter().for_each(|part| match part {
+            TemplatePart::Literal(s) => line.push_str(s),
+            TemplatePart::Field(TemplateField::XId) => line.push_str(&m.x_id.to_string()),
+            TemplatePart::Field(TemplateField::YId) => line.push_str(&m.y_id.to_string()),
+            TemplatePart::Field(TemplateField::Ed) => line.push_str(&m.ed.to_string()),
+            TemplatePart::Field(TemplateField::Score) => {
+                line.push_str(&format!("{:.4}", m.score()))
+            }
+            TemplatePart::Field(TemplateField::XText) => line.push_str(&text(m.x_text)),
+            TemplatePart::Field(TemplateField::YText) => line.push_str(&text(m.y_text)),
+            TemplatePart::Field(TemplateField::XLen) => {
+                line.push_str(&m.x_text.chars().count().to_string())
+            }
+            TemplatePart::Field(TemplateField::YLen) => {
+                line.push_str(&m.y_text.chars().count().to_string())
+            }
+            TemplatePart::Field(TemplateField::Confidence) => {
+                if let Some(confidence) = m.confidence {
+                    line.push_str(&format!("{:.4}", confidence))
+                }
+            }
+        });
+        line
+    }
+}
+
+/// A placeholder of an [`OutputNaming`], as `{name}`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NamingField {
+    /// `{stem}`, file name of `doc_x` without its extension.
+    Stem,
+    /// `{q}`, length of q-grams.
+    Q,
+    /// `{tau}`, threshold of the join.
+    Tau,
+    /// `{ext}`, extension of the output format, or else of `doc_x`, or `txt` if it has none.
+    Ext,
+    /// `{date}`, the date of the join, as `YYYY-MM-DD` in UTC.
+    Date,
+    /// `{algo}`, how the join is run, `ed_join`, `approx`, `knn`, `window` or `delta`.
+    Algo,
+}
+
+impl NamingField {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "stem" => Some(NamingField::Stem),
+            "q" => Some(NamingField::Q),
+            "tau" => Some(NamingField::Tau),
+            "ext" => Some(NamingField::Ext),
+            "date" => Some(NamingField::Date),
+            "algo" => Some(NamingField::Algo),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum NamingPart {
+    Literal(String),
+    Field(NamingField),
+}
+
+/// A template of the name of the output file, e.g. `{stem}_{algo}_{date}.{ext}`, see [`NamingField`].
+///
+/// `{{` and `}}` are literal braces. The default is `{stem}_out_q{q}_tau{tau}.{ext}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputNaming {
+    parts: Vec<NamingPart>,
+}
+
+impl Default for OutputNaming {
+    fn default() -> Self {
+        Self::parse("{stem}_out_q{q}_tau{tau}.{ext}").unwrap()
+    }
+}
+
+impl OutputNaming {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts: Vec<NamingPart> = Vec::new();
+        let mut literal: String = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                    let field = NamingField::from_name(&name).ok_or_else(|| {
+                        Error::from(ErrorKind::InvalidTemplate(format!(
+                            "unknown placeholder `{{{}}}` of output names",
+                            name
+                        )))
+                    })?;
+                    if !literal.is_empty() {
+                        parts.push(NamingPart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(NamingPart::Field(field));
+                }
+                '}' => bail!(ErrorKind::InvalidTemplate(
+                    "unmatched `}` of output names".to_string()
+                )),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(NamingPart::Literal(literal));
+        }
+        if parts.is_empty() {
+            bail!(ErrorKind::InvalidTemplate("empty output names".to_string()));
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// Render the name of the output of a join, given the values of all placeholders but the date, which is today's.
+    pub fn render(&self, stem: &str, q: usize, tau: usize, ext: &str, algo: &str) -> String {
+        let mut name: String = String::new();
+        self.parts.iter().for_each(|part| match part {
+            NamingPart::Literal(s) => name.push_str(s),
+            NamingPart::Field(NamingField::Stem) => name.push_str(stem),
+            NamingPart::Field(NamingField::Q) => name.push_str(&q.to_string()),
+            NamingPart::Field(NamingField::Tau) => name.push_str(&tau.to_string()),
+            NamingPart::Field(NamingField::Ext) => name.push_str(ext),
+            NamingPart::Field(NamingField::Date) => name.push_str(&utc_date()),
+            NamingPart::Field(NamingField::Algo) => name.push_str(algo),
+        });
+        name
+    }
+}
+
+/// Today's date in UTC, as `YYYY-MM-DD`.
+fn utc_date() -> String {
+    let secs: u64 = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    // days since 1970-01-01 to a civil date, after Howard Hinnant's `civil_from_days`
+    let z: i64 = (secs / 86_400) as i64 + 719_468;
+    let era: i64 = z.div_euclid(146_097);
+    let doe: i64 = z.rem_euclid(146_097);
+    let yoe: i64 = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy: i64 = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp: i64 = (5 * doy + 2) / 153;
+    let day: i64 = doy - (153 * mp + 2) / 5 + 1;
+    let month: i64 = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year: i64 = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Format of the output file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// `x_id,y_id,ed` lines, with the configured delimiter, and `confidence` as the fourth column if scoring is enabled,
+    /// followed by the records if their content is emitted.
+    #[default]
+    Csv,
+    /// The same as `Csv`, after a header row of `x_id`, `y_id`, `ed`, `confidence`, `x_content`, `y_content`,
+    /// `y_source` and `y_line`, and `x_field_0`, `x_field_1`, ... and `y_field_0`, ... for the passthrough fields, if any.
+    CsvHeader,
+    /// The same as `CsvHeader`, but tab-separated whichever delimiter is configured.
+    Tsv,
+    /// Lines in a user-defined format.
+    Template(OutputTemplate),
+    /// JSON Lines, i.e. a `{"x_id":0,"y_id":3,"ed":1}` object per match, with `confidence` too if scoring is enabled,
+    /// `x_content` and `y_content` if the records are emitted, and `y_source` and `y_line` if their provenance is,
+    /// as read by `jq`.
+    JsonLines,
+    /// A single JSON array of the objects of `JsonLines`, one per line.
+    Json,
+    /// A Parquet file of a single row group, with the columns of `CsvHeader`, of which the ids and distances are `INT64`,
+    /// the confidence is an optional `DOUBLE`, and the records and passthrough fields are UTF-8 strings.
+    ///
+    /// It's only written when built with the `parquet` feature.
+    Parquet,
+    /// Weighted edge list of the match graph, i.e. `x_id y_id ed` lines, as read by `networkx.read_weighted_edgelist`.
+    EdgeList,
+    /// The match graph in Graphviz DOT format, with edit distances as edge weights.
+    Dot,
+    /// The match graph in GraphML, with edit distances as edge weights, as read by Gephi and NetworkX.
+    GraphMl,
+    /// A sparse matrix in Matrix Market coordinate format, as read by `scipy.io.mmread`.
+    ///
+    /// Rows are records of `doc_x` and columns are records of `doc_y`, both 1-based as required by the format.
+    /// The matrix of a self-join is `symmetric`, of which only the lower triangle is written, as the format requires.
+    MatrixMarket(MatrixValue),
+    /// A sparse matrix as COO triplets, i.e. `row,col,value` lines with a header, and 0-based rows and columns.
+    ///
+    /// The configured delimiter is used, so that it can be loaded with e.g. `pandas.read_csv` and passed to
+    /// `scipy.sparse.coo_matrix`.
+    Coo(MatrixValue),
+}
+
+/// Value of the entries in a sparse matrix output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MatrixValue {
+    /// Edit distance of the pair.
+    ///
+    /// Note that identical records are stored as explicit zeros, which some consumers drop.
+    #[default]
+    Distance,
+    /// Similarity of the pair, see [`MatchRecord::score`].
+    Similarity,
+}
+
+impl MatrixValue {
+    /// Parse the name of a value, i.e. `distance` or `similarity`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "distance" => Some(MatrixValue::Distance),
+            "similarity" => Some(MatrixValue::Similarity),
+            _ => None,
+        }
+    }
+
+    fn render(self, m: &MatchRecord) -> String {
+        match self {
+            MatrixValue::Distance => m.ed.to_string(),
+            MatrixValue::Similarity => m.score().to_string(),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Parse the name of a format, i.e. `csv`, `csv-header`, `tsv`, `jsonl`, `json`, `parquet`, `edgelist`, `dot`,
+    /// `graphml`, `mtx` or `coo`.
+    ///
+    /// Sparse matrices hold edit distances by default.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(OutputFormat::Csv),
+            "csv-header" => Some(OutputFormat::CsvHeader),
+            "tsv" => Some(OutputFormat::Tsv),
+            "jsonl" => Some(OutputFormat::JsonLines),
+            "json" => Some(OutputFormat::Json),
+            "parquet" => Some(OutputFormat::Parquet),
+            "edgelist" => Some(OutputFormat::EdgeList),
+            "dot" => Some(OutputFormat::Dot),
+            "graphml" => Some(OutputFormat::GraphMl),
+            "mtx" => Some(OutputFormat::MatrixMarket(MatrixValue::default())),
+            "coo" => Some(OutputFormat::Coo(MatrixValue::default())),
+            _ => None,
+        }
+    }
+
+    /// File extension that's conventional for this format, if there's any.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            OutputFormat::Tsv => Some("tsv"),
+            OutputFormat::JsonLines => Some("jsonl"),
+            OutputFormat::Json => Some("json"),
+            OutputFormat::Parquet => Some("parquet"),
+            OutputFormat::Dot => Some("dot"),
+            OutputFormat::GraphMl => Some("graphml"),
+            OutputFormat::MatrixMarket(_) => Some("mtx"),
+            _ => None,
+        }
+    }
+
+    /// Whether the format writes the matched records if [`Matches::emit_content`] is set.
+    ///
+    /// Templates always can, by `{x}` and `{y}`, and formats of match graphs and matrices never do.
+    pub fn emits_content(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Csv
+                | OutputFormat::CsvHeader
+                | OutputFormat::Tsv
+                | OutputFormat::JsonLines
+                | OutputFormat::Json
+                | OutputFormat::Parquet
+        )
+    }
+}
+
+/// Write all matches in the given format.
+///
+/// # Parameters
+///
+///  * `writer`: Where the matches are written to.
+///  * `matches`: All matches of a join.
+///  * `format`: Format of the output.
+///  * `delimiter`: Field delimiter, only used by `OutputFormat::Csv`, `OutputFormat::CsvHeader` and `OutputFormat::Coo`.
+pub fn write_matches<W: Write>(
+    writer: &mut W,
+    matches: &Matches,
+    format: &OutputFormat,
+    delimiter: u8,
+) -> Result<()> {
+    match format {
+        OutputFormat::Csv => write_delimited(writer, matches, delimiter, false)?,
+        OutputFormat::CsvHeader => write_delimited(writer, matches, delimiter, true)?,
+        OutputFormat::Tsv => write_delimited(writer, matches, b'\t', true)?,
+        OutputFormat::Template(template) => {
+            for m in matches.iter() {
+                writeln!(writer, "{}", template.render_masked(&m, matches.text_mask))?;
+            }
+        }
+        OutputFormat::JsonLines => {
+            for m in matches.iter() {
+                writeln!(writer, "{}", json_object(&m, matches))?;
+            }
+        }
+        OutputFormat::Json => {
+            write!(writer, "[")?;
+            for (i, m) in matches.iter().enumerate() {
+                write!(
+                    writer,
+                    "{}\n{}",
+                    if i == 0 { "" } else { "," },
+                    json_object(&m, matches)
+                )?;
+            }
+            writeln!(writer, "\n]")?;
+        }
+        OutputFormat::Parquet => write_parquet(writer, matches)?,
+        OutputFormat::EdgeList => {
+            for m in matches.iter() {
+                writeln!(
+                    writer,
+                    "{} {} {}",
+                    matches.node_name(Side::X, m.x_id),
+                    matches.node_name(Side::Y, m.y_id),
+                    m.ed
+                )?;
+            }
+        }
+        OutputFormat::Dot => {
+            writeln!(writer, "graph matches {{")?;
+            for node in matches.nodes() {
+                writeln!(writer, "    \"{}\";", node)?;
+            }
+            for m in matches.iter() {
+                writeln!(
+                    writer,
+                    "    \"{}\" -- \"{}\" [weight={}];",
+                    matches.node_name(Side::X, m.x_id),
+                    matches.node_name(Side::Y, m.y_id),
+                    m.ed
+                )?;
+            }
+            writeln!(writer, "}}")?;
+        }
+        OutputFormat::GraphMl => {
+            writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+            writeln!(
+                writer,
+                "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">"
+            )?;
+            writeln!(
+                writer,
+                "  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"int\"/>"
+            )?;
+            writeln!(
+                writer,
+                "  <graph id=\"matches\" edgedefault=\"undirected\">"
+            )?;
+            for node in matches.nodes() {
+                writeln!(writer, "    <node id=\"{}\"/>", node)?;
+            }
+            for m in matches.iter() {
+                writeln!(
+                    writer,
+                    "    <edge source=\"{}\" target=\"{}\"><data key=\"weight\">{}</data></edge>",
+                    matches.node_name(Side::X, m.x_id),
+                    matches.node_name(Side::Y, m.y_id),
+                    m.ed
+                )?;
+            }
+            writeln!(writer, "  </graph>")?;
+            writeln!(writer, "</graphml>")?;
+        }
+        OutputFormat::MatrixMarket(value) => {
+            let field: &str = match value {
+                MatrixValue::Distance => "integer",
+                MatrixValue::Similarity => "real",
+            };
+            // a self-join has each pair once, which is an entry of the lower triangle of a symmetric matrix
+            let symmetry: &str = if matches.self_join {
+                "symmetric"
+            } else {
+                "general"
+            };
+            writeln!(
+                writer,
+                "%%MatrixMarket matrix coordinate {} {}",
+                field, symmetry
+            )?;
+            writeln!(
+                writer,
+                "{} {} {}",
+                matches.x_records.len(),
+                matches.y_records.len(),
+                matches.iter().count()
+            )?;
+            for m in matches.iter() {
+                let (row, col): (ID, ID) = if matches.self_join {
+                    (cmp::max(m.x_id, m.y_id), cmp::min(m.x_id, m.y_id))
+                } else {
+                    (m.x_id, m.y_id)
+                };
+                writeln!(writer, "{} {} {}", row + 1, col + 1, value.render(&m))?;
+            }
+        }
+        OutputFormat::Coo(value) => {
+            let mut csv_writer: CsvWriter<&mut W> = CsvWriter::new(writer, delimiter);
+            csv_writer.write_record(&["row", "col", "value"])?;
+            for m in matches.iter() {
+                csv_writer.write_record(&[
+                    m.x_id.to_string(),
+                    m.y_id.to_string(),
+                    value.render(&m),
+                ])?;
+            }
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Write matches as `x_id,y_id,ed` rows, with their confidence, content, provenance and passthrough fields,
+/// optionally after a header row.
+fn write_delimited<W: Write>(
+    writer: &mut W,
+    matches: &Matches,
+    delimiter: u8,
+    header: bool,
+) -> Result<()> {
+    let mut csv_writer: CsvWriter<&mut W> = CsvWriter::new(writer, delimiter);
+    if header {
+        let mut names: Vec<String> = vec!["x_id".to_string(), "y_id".to_string(), "ed".to_string()];
+        if matches.confidence.is_some() {
+            names.push("confidence".to_string());
+        }
+        if matches.emit_content {
+            names.extend(vec!["x_content".to_string(), "y_content".to_string()]);
+        }
+        if matches.provenance.is_some() {
+            names.extend(vec!["y_source".to_string(), "y_line".to_string()]);
+        }
+        // passthrough rows of a side all have the same fields
+        if let Some((x_rows, y_rows)) = matches.passthrough {
+            let width = |rows: &[Fields]| rows.first().map_or(0, Vec::len);
+            names.extend((0..width(x_rows)).map(|i| format!("x_field_{}", i)));
+            names.extend((0..width(y_rows)).map(|i| format!("y_field_{}", i)));
+        }
+        csv_writer.write_record(&names)?;
+    }
+    for m in matches.iter() {
+        let mut record: Vec<String> =
+            vec![m.x_id.to_string(), m.y_id.to_string(), m.ed.to_string()];
+        if let Some(confidence) = m.confidence {
+            record.push(format!("{:.4}", confidence));
+        }
+        if matches.emit_content {
+            record.extend(vec![
+                matches.text(m.x_text).into_owned(),
+                matches.text(m.y_text).into_owned(),
+            ]);
+        }
+        if let Some((source, line)) = matches.source(m.y_id) {
+            record.extend(vec![source.display().to_string(), line.to_string()]);
+        }
+        record.extend(m.x_fields.iter().chain(m.y_fields).cloned());
+        csv_writer.write_record(&record)?;
+    }
+
+    Ok(())
+}
+
+/// A column of a Parquet output.
+#[cfg(feature = "parquet")]
+enum ParquetColumn {
+    Int64(Vec<i64>),
+    /// Values which may be null, e.g. confidences which a plugin cleared.
+    Double(Vec<Option<f64>>),
+    Utf8(Vec<parquet::data_type::ByteArray>),
+}
+
+/// Write matches as a Parquet file, with the columns of [`write_delimited`].
+///
+/// The file is written to a buffer first, since its footer refers to the offsets of its columns.
+#[cfg(feature = "parquet")]
+fn write_parquet<W: Write>(writer: &mut W, matches: &Matches) -> Result<()> {
+    use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+    use parquet::file::{properties::WriterProperties, writer::SerializedFileWriter};
+    use parquet::schema::parser::parse_message_type;
+
+    let rows: Vec<MatchRecord> = matches.iter().collect();
+    let ints = |f: &dyn Fn(&MatchRecord) -> usize| {
+        ParquetColumn::Int64(rows.iter().map(|m| f(m) as i64).collect())
+    };
+    let texts = |f: &dyn Fn(&MatchRecord) -> String| {
+        ParquetColumn::Utf8(
+            rows.iter()
+                .map(|m| ByteArray::from(f(m).as_str()))
+                .collect(),
+        )
+    };
+    let mut columns: Vec<(String, ParquetColumn)> = vec![
+        ("x_id".to_string(), ints(&|m| m.x_id)),
+        ("y_id".to_string(), ints(&|m| m.y_id)),
+        ("ed".to_string(), ints(&|m| m.ed)),
+    ];
+    if matches.confidence.is_some() {
+        let confidences: Vec<Option<f64>> = rows.iter().map(|m| m.confidence).collect();
+        columns.push(("confidence".to_string(), ParquetColumn::Double(confidences)));
+    }
+    if matches.emit_content {
+        columns.push((
+            "x_content".to_string(),
+            texts(&|m| matches.text(m.x_text).into_owned()),
+        ));
+        columns.push((
+            "y_content".to_string(),
+            texts(&|m| matches.text(m.y_text).into_owned()),
+        ));
+    }
+    if matches.provenance.is_some() {
+        let source = |m: &MatchRecord| matches.source(m.y_id).unwrap_or((Path::new(""), 0));
+        columns.push((
+            "y_source".to_string(),
+            texts(&|m| source(m).0.display().to_string()),
+        ));
+        columns.push(("y_line".to_string(), ints(&|m| source(m).1)));
+    }
+    // passthrough rows of a side all have the same fields
+    if let Some((x_rows, y_rows)) = matches.passthrough {
+        let width = |rows: &[Fields]| rows.first().map_or(0, Vec::len);
+        for i in 0..width(x_rows) {
+            columns.push((format!("x_field_{}", i), texts(&|m| m.x_fields[i].clone())));
+        }
+        for i in 0..width(y_rows) {
+            columns.push((format!("y_field_{}", i), texts(&|m| m.y_fields[i].clone())));
+        }
+    }
+
+    let schema: String = format!(
+        "message matches {{ {} }}",
+        columns
+            .iter()
+            .map(|(name, column)| match column {
+                ParquetColumn::Int64(_) => format!("required int64 {};", name),
+                ParquetColumn::Double(_) => format!("optional double {};", name),
+                ParquetColumn::Utf8(_) => format!("required binary {} (UTF8);", name),
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    );
+    let written = || -> parquet::errors::Result<Vec<u8>> {
+        let mut file_writer: SerializedFileWriter<Vec<u8>> = SerializedFileWriter::new(
+            Vec::new(),
+            Arc::new(parse_message_type(&schema)?),
+            Arc::new(WriterProperties::builder().build()),
+        )?;
+        let mut row_group = file_writer.next_row_group()?;
+        for (_, column) in &columns {
+            let mut column_writer = row_group.next_column()?.expect("a column of the schema");
+            match column {
+                ParquetColumn::Int64(values) => {
+                    column_writer
+                        .typed::<Int64Type>()
+                        .write_batch(values, None, None)?;
+                }
+                ParquetColumn::Double(values) => {
+                    let levels: Vec<i16> =
+                        values.iter().map(|value| value.is_some() as i16).collect();
+                    let values: Vec<f64> = values.iter().flatten().copied().collect();
+                    column_writer.typed::<DoubleType>().write_batch(
+                        &values,
+                        Some(&levels),
+                        None,
+                    )?;
+                }
+                ParquetColumn::Utf8(values) => {
+                    column_writer
+                        .typed::<ByteArrayType>()
+                        .write_batch(values, None, None)?;
+                }
+            }
+            column_writer.close()?;
+        }
+        row_group.close()?;
+        file_writer.into_inner()
+    };
+    let buffer: Vec<u8> =
+        written().map_err(|e| Error::from(format!("Cannot write the output as Parquet: {}", e)))?;
+    writer.write_all(&buffer)?;
+
+    Ok(())
+}
+
+/// Parquet output needs the `parquet` feature, which [`crate::config::EdJoinConfig::violations`] reports before the join.
+#[cfg(not(feature = "parquet"))]
+fn write_parquet<W: Write>(_writer: &mut W, _matches: &Matches) -> Result<()> {
+    bail!("Parquet output needs the `parquet` feature of ed_join")
+}
+
+/// A match as a JSON object, with `confidence` if it's scored, the records if their content is emitted,
+/// and the source of the record of `doc_y` if its provenance is.
+fn json_object(m: &MatchRecord, matches: &Matches) -> String {
+    let mut object: String = format!("{{\"x_id\":{},\"y_id\":{},\"ed\":{}", m.x_id, m.y_id, m.ed);
+    if let Some(confidence) = m.confidence {
+        object.push_str(&format!(",\"confidence\":{:.4}", confidence));
+    }
+    if matches.emit_content {
+        object.push_str(&format!(
+            ",\"x_content\":\"{}\",\"y_content\":\"{}\"",
+            escape_json(&matches.text(m.x_text)),
+            escape_json(&matches.text(m.y_text))
+        ));
+    }
+    if let Some((source, line)) = matches.source(m.y_id) {
+        object.push_str(&format!(
+            ",\"y_source\":\"{}\",\"y_line\":{}",
+            escape_json(&source.display().to_string()),
+            line
+        ));
+    }
+    object.push('}');
+    object
+}
+
+/// Escape a string so it can be embedded in a JSON string literal.
+pub(crate) fn escape_json(s: &str) -> String {
+    let mut escaped: String = String::with_capacity(s.len());
+    s.chars().for_each(|c| match c {
+        '"' => escaped.push_str("\\\""),
+        '\\' => escaped.push_str("\\\\"),
+        '\n' => escaped.push_str("\\n"),
+        '\r' => escaped.push_str("\\r"),
+        '\t' => escaped.push_str("\\t"),
+        c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+        c => escaped.push(c),
+    });
+    escaped
+}
+
+/// Write candidate pairs that passed all filters, i.e. whose edit distance is calculated, as `x_id,y_id` lines.
+///
+/// # Parameters
+///
+///  * `writer`: Where the pairs are written to.
+///  * `candidates`: Line numbers of records of `doc_x`, and of their candidates in `doc_y`.
+///  * `delimiter`: Field delimiter.
+pub fn write_candidate_pairs<W: Write>(
+    writer: W,
+    candidates: &[(ID, Vec<ID>)],
+    delimiter: u8,
+) -> Result<()> {
+    let mut csv_writer: CsvWriter<W> = CsvWriter::new(writer, delimiter);
+    for (x_id, y_ids) in candidates {
+        for y_id in y_ids {
+            csv_writer.write_record(&[x_id.to_string(), y_id.to_string()])?;
+        }
+    }
+    csv_writer.flush()?;
+
+    Ok(())
+}
+
+/// How much work the matching of a record of `doc_x` took, to find the records that dominate the runtime of a join.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RecordDiagnostics {
+    pub x_id: ID,
+    /// Number of q-grams in the prefix of the record, which are probed in the inverted index.
+    pub prefix_len: usize,
+    /// Number of candidates generated by the prefix filter.
+    pub candidates: usize,
+    /// Number of candidates that passed all filters, whose edit distance is calculated.
+    pub verified: usize,
+    /// Number of matches found.
+    pub matches: usize,
+}
+
+/// Write the diagnostics of records, as an `x_id,prefix_len,candidates,verified,matches` header and one line per record.
+pub fn write_diagnostics<W: Write>(
+    writer: W,
+    diagnostics: &[RecordDiagnostics],
+    delimiter: u8,
+) -> Result<()> {
+    let mut csv_writer: CsvWriter<W> = CsvWriter::new(writer, delimiter);
+    csv_writer.write_record(&["x_id", "prefix_len", "candidates", "verified", "matches"])?;
+    for record in diagnostics {
+        csv_writer.write_record(&[
+            record.x_id.to_string(),
+            record.prefix_len.to_string(),
+            record.candidates.to_string(),
+            record.verified.to_string(),
+            record.matches.to_string(),
+        ])?;
+    }
+    csv_writer.flush()?;
+
+    Ok(())
+}
+
+/// Recover the partial CSV output of an interrupted join, so that the join can be resumed.
+///
+/// The partially written last line is truncated, and so are all lines of the last record of `doc_x` in the file,
+/// as its matches may be incomplete.
+///
+/// # Return
+///
+/// Line number of the record of `doc_x` which the join should resume from, or `None` if no line was complete.
+pub fn truncate_partial(path: &Path, delimiter: u8) -> Result<Option<ID>> {
+    let content: Vec<u8> = fs::read(path)?;
+    let complete: usize = content
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map_or(0, |i| i + 1);
+
+    // line number of the last record of `doc_x`, and offset of its first line
+    let mut last: Option<(ID, usize)> = None;
+    let mut offset: usize = 0;
+    for line in content[..complete].split_inclusive(|b| *b == b'\n') {
+        let x_id: ID = line
+            .split(|b| *b == delimiter)
+            .next()
+            .and_then(|field| std::str::from_utf8(field).ok())
+            .and_then(|field| field.parse::<ID>().ok())
+            .filter(|x_id| last.map_or(true, |(last_id, _)| *x_id >= last_id))
+            .ok_or_else(|| {
+                Error::from(format!(
+                    "Not a partial output of this join: {}",
+                    path.display()
+                ))
+            })?;
+        if last.map_or(true, |(last_id, _)| x_id != last_id) {
+            last = Some((x_id, offset));
+        }
+        offset += line.len();
+    }
+
+    OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_len(last.map_or(0, |(_, offset)| offset as u64))?;
+
+    Ok(last.map(|(x_id, _)| x_id))
+}
+
+/// A minimal CSV writer, which quotes fields following RFC 4180.
+///
+/// A field is quoted when it contains the delimiter, a double quote, or a line break,
+/// and double quotes within a quoted field are escaped by doubling them.
+pub struct CsvWriter<W: Write> {
+    inner: W,
+    delimiter: u8,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(inner: W, delimiter: u8) -> Self {
+        Self { inner, delimiter }
+    }
+
+    /// Write a single record, terminated by a line feed.
+    pub fn write_record<S: AsRef<str>>(&mut self, fields: &[S]) -> io::Result<()> {
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                self.inner.write_all(&[self.delimiter])?;
+            }
+            self.write_field(field.as_ref())?;
+        }
+        self.inner.write_all(b"\n")
+    }
+
+    fn write_field(&mut self, field: &str) -> io::Result<()> {
+        let needs_quotes: bool = field
+            .bytes()
+            .any(|b| b == self.delimiter || b == b'"' || b == b'\n' || b == b'\r');
+        if needs_quotes {
+            self.inner.write_all(b"\"")?;
+            self.inner
+                .write_all(field.replace('"', "\"\"").as_bytes())?;
+            self.inner.write_all(b"\"")
+        } else {
+            self.inner.write_all(field.as_bytes())
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scoring::DefaultScorer;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn csv_quoting() {
+        let mut buffer: Vec<u8> = Vec::new();
+        {
+            let mut writer = CsvWriter::new(&mut buffer, b',');
+            writer.write_record(&["0", "a,b", "say \"hi\""]).unwrap();
+            writer.write_record(&["1", "a\tb", "line\nbreak"]).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "0,\"a,b\",\"say \"\"hi\"\"\"\n1,a\tb,\"line\nbreak\"\n"
+        );
+    }
+
+    #[test]
+    fn output_template() {
+        let template =
+            OutputTemplate::parse("{x_id}\\t{y_id}\\t{ed}\\t{score} {{{x_text}}}").unwrap();
+        let m = MatchRecord {
+            x_id: 0,
+            y_id: 2,
+            ed: 1,
+            x_text: "hello",
+            y_text: "hell",
+            confidence: None,
+            x_fields: &[],
+            y_fields: &[],
+        };
+        assert_eq!(template.render(&m), "0\t2\t1\t0.8000 {hello}");
+        assert!(OutputTemplate::parse("{x_id},{id}").is_err());
+    }
+
+    #[test]
+    fn output_naming() {
+        assert_eq!(
+            OutputNaming::default().render("doc", 2, 1, "csv", "ed_join"),
+            "doc_out_q2_tau1.csv"
+        );
+        let naming = OutputNaming::parse("{{{stem}}}-{algo}-{date}.{ext}").unwrap();
+        let name: String = naming.render("doc", 2, 1, "txt", "approx");
+        assert!(name.starts_with("{doc}-approx-20") && name.ends_with(".txt"));
+        assert_eq!(name.len(), "{doc}-approx-YYYY-MM-DD.txt".len());
+        assert!(OutputNaming::parse("{stem}_{time}").is_err());
+        assert!(OutputNaming::parse("").is_err());
+    }
+
+    #[test]
+    fn partial_output() {
+        let dir: TempDir = TempDir::new("partial");
+        let path = dir.join("records_out_q2_tau1.csv.partial");
+        fs::write(&path, "0,1,1\n0,2,1\n3,4,0\n3,5,1\n3,6").unwrap();
+        assert_eq!(truncate_partial(&path, b',').unwrap(), Some(3));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "0,1,1\n0,2,1\n");
+
+        fs::write(&path, "0,1").unwrap();
+        assert_eq!(truncate_partial(&path, b',').unwrap(), None);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        fs::write(&path, "3,4,0\n0,1,1\n").unwrap();
+        assert!(truncate_partial(&path, b',').is_err());
+    }
+
+    #[test]
+    fn graph_formats() {
+        let records: Vec<String> =
+            vec!["hello".to_string(), "hell".to_string(), "hallo".to_string()];
+        let pairs = vec![(0, vec![(1, 1), (2, 1)])];
+        let matches = Matches {
+            pairs: &pairs,
+            x_records: &records,
+            y_records: &records,
+            self_join: false,
+            confidence: None,
+            passthrough: None,
+            provenance: None,
+            emit_content: false,
+            text_mask: None,
+            plugins: &[],
+        };
+
+        let mut buffer: Vec<u8> = Vec::new();
+        write_matches(&mut buffer, &matches, &OutputFormat::EdgeList, b',').unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap(), "x:0 y:1 1\nx:0 y:2 1\n");
+
+        let mut buffer: Vec<u8> = Vec::new();
+        write_matches(&mut buffer, &matches, &OutputFormat::Dot, b',').unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "graph matches {\n    \"x:0\";\n    \"y:1\";\n    \"y:2\";\n    \"x:0\" -- \"y:1\" [weight=1];\n    \"x:0\" -- \"y:2\" [weight=1];\n}\n"
+        );
+    }
+
+    #[test]
+    fn json_lines() {
+        let records: Vec<String> = vec!["hello".to_string(), "hell".to_string()];
+        let pairs = vec![(0, vec![(1, 1)])];
+        let matches = Matches {
+            pairs: &pairs,
+            x_records: &records,
+            y_records: &records,
+            self_join: true,
+            confidence: None,
+            passthrough: None,
+            provenance: None,
+            emit_content: false,
+            text_mask: None,
+            plugins: &[],
+        };
+
+        let mut buffer: Vec<u8> = Vec::new();
+        write_matches(&mut buffer, &matches, &OutputFormat::JsonLines, b',').unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "{\"x_id\":0,\"y_id\":1,\"ed\":1}\n"
+        );
+
+        let mut buffer: Vec<u8> = Vec::new();
+        write_matches(&mut buffer, &matches, &OutputFormat::Json, b',').unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "[\n{\"x_id\":0,\"y_id\":1,\"ed\":1}\n]\n"
+        );
+        let fields: Vec<Fields> = vec![vec!["a".to_string()], vec!["b".to_string()]];
+        let matches = Matches {
+            passthrough: Some((&fields, &fields)),
+            provenance: None,
+            ..matches
+        };
+        let mut buffer: Vec<u8> = Vec::new();
+        write_matches(
+            &mut buffer,
+            &Matches {
+                emit_content: true,
+                ..matches
+            },
+            &OutputFormat::JsonLines,
+            b',',
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "{\"x_id\":0,\"y_id\":1,\"ed\":1,\"x_content\":\"hello\",\"y_content\":\"hell\"}\n"
+        );
+        let mut buffer: Vec<u8> = Vec::new();
+        write_matches(&mut buffer, &matches, &OutputFormat::Tsv, b',').unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "x_id\ty_id\ted\tx_field_0\ty_field_0\n0\t1\t1\ta\tb\n"
+        );
+
+        // masked records are written as their hashes, in every format that has them, and the rest as they are
+        #[cfg(feature = "hashing")]
+        {
+            let mask: TextMask = TextMask::new("pepper").unwrap();
+            let (hello, hell) = (mask.hash("hello"), mask.hash("hell"));
+            let masked = Matches {
+                emit_content: true,
+                text_mask: Some(&mask),
+                ..matches
+            };
+            let mut buffer: Vec<u8> = Vec::new();
+            write_matches(&mut buffer, &masked, &OutputFormat::JsonLines, b',').unwrap();
+            assert_eq!(
+                String::from_utf8(buffer).unwrap(),
+                format!(
+                    "{{\"x_id\":0,\"y_id\":1,\"ed\":1,\"x_content\":\"{}\",\"y_content\":\"{}\"}}\n",
+                    hello, hell
+                )
+            );
+            let mut buffer: Vec<u8> = Vec::new();
+            write_matches(&mut buffer, &masked, &OutputFormat::Tsv, b',').unwrap();
+            assert!(String::from_utf8(buffer)
+                .unwrap()
+                .ends_with(&format!("\n0\t1\t1\t{}\t{}\ta\tb\n", hello, hell)));
+            let template: OutputTemplate = OutputTemplate::parse("{x_text} {y_len}").unwrap();
+            let mut buffer: Vec<u8> = Vec::new();
+            write_matches(
+                &mut buffer,
+                &masked,
+                &OutputFormat::Template(template),
+                b',',
+            )
+            .unwrap();
+            assert_eq!(String::from_utf8(buffer).unwrap(), format!("{} 4\n", hello));
+            assert!(!format!("{:?}", mask).contains("pepper"));
+        }
+    }
+
+    /// Clears the confidence of pairs at a distance above 1.
+    #[derive(Debug)]
+    struct ClearFar;
+
+    impl MatchPlugin for ClearFar {
+        fn apply(&self, m: &mut MatchRecord) -> bool {
+            if m.ed > 1 {
+                m.confidence = None;
+            }
+            true
+        }
+    }
+
+    #[test]
+    fn parquet() {
+        let records: Vec<String> =
+            vec!["hello".to_string(), "hell".to_string(), "help".to_string()];
+        let pairs = vec![(0, vec![(1, 1), (2, 2)])];
+        let fields: Vec<Fields> = vec![vec!["a".to_string()]; 3];
+        let index = crate::qgram::build_inverted_index(None, &records, 2);
+        let plugins: Vec<Arc<dyn MatchPlugin>> = vec![Arc::new(ClearFar)];
+        let matches = Matches {
+            pairs: &pairs,
+            x_records: &records,
+            y_records: &records,
+            self_join: true,
+            confidence: Some((&DefaultScorer, ScoreContext::new(&index, 2, 2))),
+            passthrough: Some((&fields, &fields)),
+            provenance: None,
+            emit_content: true,
+            text_mask: None,
+            plugins: &plugins,
+        };
+
+        let mut buffer: Vec<u8> = Vec::new();
+        let written: Result<()> =
+            write_matches(&mut buffer, &matches, &OutputFormat::Parquet, b',');
+        #[cfg(not(feature = "parquet"))]
+        assert!(written.is_err());
+        #[cfg(feature = "parquet")]
+        {
+            use parquet::file::reader::{FileReader, SerializedFileReader};
+
+            written.unwrap();
+            let dir: TempDir = TempDir::new("parquet");
+            let path = dir.join("matches.parquet");
+            fs::write(&path, &buffer).unwrap();
+            let reader = SerializedFileReader::new(fs::File::open(&path).unwrap()).unwrap();
+            let rows: Vec<String> = reader
+                .get_row_iter(None)
+                .unwrap()
+                .map(|row| row.unwrap().to_string())
+                .collect();
+            // a confidence cleared by a plugin is null
+            assert!(rows[0].starts_with("{x_id: 0, y_id: 1, ed: 1, confidence: 0."));
+            assert!(rows[0].ends_with(
+                ", x_content: \"hello\", y_content: \"hell\", x_field_0: \"a\", y_field_0: \"a\"}"
+            ));
+            assert_eq!(
+                rows[1],
+                "{x_id: 0, y_id: 2, ed: 2, confidence: null, x_content: \"hello\", y_content: \"help\", \
+                 x_field_0: \"a\", y_field_0: \"a\"}"
+            );
+        }
+    }
+
+    #[test]
+    fn sparse_matrix_formats() {
+        let records: Vec<String> =
+            vec!["hello".to_string(), "hell".to_string(), "hallo".to_string()];
+        let pairs = vec![(0, vec![(1, 1), (2, 1)])];
+        let matches = Matches {
+            pairs: &pairs,
+            x_records: &records,
+            y_records: &records,
+            self_join: true,
+            confidence: None,
+            passthrough: None,
+            provenance: None,
+            emit_content: false,
+            text_mask: None,
+            plugins: &[],
+        };
+
+        let mut buffer: Vec<u8> = Vec::new();
+        write_matches(
+            &mut buffer,
+            &matches,
+            &OutputFormat::MatrixMarket(MatrixValue::Distance),
+            b',',
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "%%MatrixMarket matrix coordinate integer symmetric\n3 3 2\n2 1 1\n3 1 1\n"
+        );
+        // the matrix of two documents isn't symmetric
+        let mut buffer: Vec<u8> = Vec::new();
+        write_matches(
+            &mut buffer,
+            &Matches {
+                self_join: false,
+                ..matches
+            },
+            &OutputFormat::MatrixMarket(MatrixValue::Distance),
+            b',',
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "%%MatrixMarket matrix coordinate integer general\n3 3 2\n1 2 1\n1 3 1\n"
+        );
+
+        let mut buffer: Vec<u8> = Vec::new();
+        write_matches(
+            &mut buffer,
+            &matches,
+            &OutputFormat::Coo(MatrixValue::Similarity),
+            b'\t',
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "row\tcol\tvalue\n0\t1\t0.8\n0\t2\t0.8\n"
+        );
+    }
+}
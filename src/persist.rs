@@ -0,0 +1,983 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    convert::TryFrom,
+    ffi::OsString,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::errors::*;
+use crate::hash::Sha256;
+use crate::qgram::{InvertedIndex, InvertedList, ID};
+
+/// A phase of a join, after which a run can stop and persist its intermediate results.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Construction of the inverted index, see [`write_index`].
+    Index,
+    /// Candidate generation by the prefix filter, see [`write_candidates`].
+    Candidates,
+}
+
+impl Phase {
+    /// Parse the name of a phase, i.e. `index` or `candidates`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "index" => Some(Phase::Index),
+            "candidates" => Some(Phase::Candidates),
+            _ => None,
+        }
+    }
+}
+
+/// Escape backslashes, tabs and line breaks, so that a string can be a field of a tab-separated line.
+pub(crate) fn escape_field(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Undo `escape_field()`, or `None` if the escaping is invalid.
+pub(crate) fn unescape_field(s: &str) -> Option<String> {
+    let mut unescaped: String = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next()? {
+                't' => unescaped.push('\t'),
+                'n' => unescaped.push('\n'),
+                'r' => unescaped.push('\r'),
+                c => unescaped.push(c),
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    Some(unescaped)
+}
+
+/// Read the header line of a persisted file, and check that it was written with the same format and parameters.
+fn check_header(reader: &mut impl BufRead, path: &Path, expected: &str) -> Result<()> {
+    let mut header: String = String::new();
+    reader.read_line(&mut header)?;
+    if header.trim_end() != expected {
+        bail!(
+            "{} was not written with the same parameters, expected `{}` but found `{}`",
+            path.display(),
+            expected,
+            header.trim_end()
+        );
+    }
+    Ok(())
+}
+
+/// First bytes of an inverted index written by [`write_index`], before its `q`.
+const INDEX_MAGIC: &[u8] = b"ed_join index v4\n";
+/// First bytes of an inverted index written by the previous version of [`write_index`], which had no number of records.
+const INDEX_MAGIC_V3: &[u8] = b"ed_join index v3\n";
+/// First bytes of an inverted index written by the previous version of [`write_index`], which had no checksums.
+const INDEX_MAGIC_V2: &[u8] = b"ed_join index v2\n";
+/// First line of an inverted index written as text, by the first version of [`write_index`], before its `q`.
+const TEXT_INDEX_HEADER: &str = "ed_join index v1 q=";
+/// Number of q-grams in each segment of an inverted index, which is checksummed separately.
+const INDEX_SEGMENT_LEN: usize = 4096;
+/// Length of the checksum of a segment, i.e. of the prefix of its SHA-256 which is kept.
+const CHECKSUM_LEN: usize = 8;
+
+/// Append an integer as a LEB128 varint, i.e. 7 bits per byte, least significant first.
+pub(crate) fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+/// Read a varint written by `write_varint()`, or `None` if the input ends or it overflows.
+pub(crate) fn read_varint(bytes: &mut std::slice::Iter<u8>) -> Option<u64> {
+    let mut value: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let byte: u8 = *bytes.next()?;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte < 0x80 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Checksum of a segment of an inverted index.
+fn checksum(bytes: &[u8]) -> Result<[u8; CHECKSUM_LEN]> {
+    let mut hasher: Sha256 = Sha256::new()?;
+    hasher.update(bytes);
+    let mut checksum: [u8; CHECKSUM_LEN] = [0; CHECKSUM_LEN];
+    checksum.copy_from_slice(&hasher.digest()[..CHECKSUM_LEN]);
+    Ok(checksum)
+}
+
+/// Write an inverted index in a compact binary format, so that joins of the same inputs can skip building it.
+///
+/// After a header of the format, `q`, the number of records of `doc_y` plus one, or 0 if it's not known, and the numbers
+/// of q-grams and segments, the q-grams are written in segments of up to 4096 each,
+/// which are their length in bytes, the q-grams, and a checksum of them. The header has a checksum too,
+/// so that a corrupt index is detected when it's read, see [`verify_index`]. Checksums are truncated SHA-256,
+/// so indexes are only written and read with the `hashing` feature, except those of version 2, which have none.
+///
+/// Each q-gram is its token, number of occurences and inverted list, all as varints,
+/// where IDs are the differences from the previous entry's and tokens are their length and bytes.
+/// Tokens are written in sorted order, so that the file is the same across runs.
+/// An index is only valid for the same inputs and `q`, and only `q` is checked when it's read back.
+pub fn write_index(
+    path: &Path,
+    inverted_index: &InvertedIndex,
+    q: usize,
+    records: Option<usize>,
+) -> Result<()> {
+    let mut writer: BufWriter<File> = BufWriter::new(File::create(path)?);
+    let mut tokens: Vec<&String> = inverted_index.keys().collect();
+    tokens.sort_unstable();
+
+    let mut buffer: Vec<u8> = INDEX_MAGIC.to_vec();
+    write_varint(&mut buffer, q as u64);
+    write_varint(&mut buffer, records.map_or(0, |records| records as u64 + 1));
+    write_varint(&mut buffer, tokens.len() as u64);
+    write_varint(&mut buffer, tokens.chunks(INDEX_SEGMENT_LEN).len() as u64);
+    writer.write_all(&buffer)?;
+    writer.write_all(&checksum(&buffer)?)?;
+
+    let mut segment: Vec<u8> = Vec::new();
+    for chunk in tokens.chunks(INDEX_SEGMENT_LEN) {
+        segment.clear();
+        for token in chunk {
+            let (inverted_list, count) = &inverted_index[*token];
+            write_varint(&mut segment, token.len() as u64);
+            segment.extend_from_slice(token.as_bytes());
+            write_varint(&mut segment, *count as u64);
+            write_varint(&mut segment, inverted_list.len() as u64);
+            // IDs are in increasing order, but differences are zigzag-encoded in case they're not
+            let mut previous: i64 = 0;
+            for (id, loc) in inverted_list {
+                let delta: i64 = *id as i64 - previous;
+                write_varint(&mut segment, ((delta << 1) ^ (delta >> 63)) as u64);
+                write_varint(&mut segment, *loc as u64);
+                previous = *id as i64;
+            }
+        }
+        buffer.clear();
+        write_varint(&mut buffer, segment.len() as u64);
+        writer.write_all(&buffer)?;
+        writer.write_all(&segment)?;
+        writer.write_all(&checksum(&segment)?)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// What [`verify_index`] found in a persisted inverted index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexSummary {
+    /// Version of the format the index was written in, where only version 3 and later have checksums.
+    pub version: u32,
+    /// `q` of the q-grams of the index.
+    pub q: usize,
+    /// Number of records of `doc_y`, if it's known, which it's only for version 4 and later.
+    pub records: Option<usize>,
+    /// Number of distinct q-grams.
+    pub qgrams: usize,
+    /// Number of segments whose checksums were checked, besides that of the header.
+    pub segments: usize,
+}
+
+/// Read an inverted index written by [`write_index`], or by earlier versions of it.
+///
+/// It's an error if `q` is not that of the index, or if the checksum of any segment doesn't match.
+pub fn read_index(path: &Path, q: usize) -> Result<InvertedIndex> {
+    let (summary, inverted_index) = load_index(path)?;
+    if summary.q != q {
+        bail!(
+            "{} was not written with the same parameters, expected q={} but found q={}",
+            path.display(),
+            q,
+            summary.q
+        );
+    }
+
+    Ok(inverted_index)
+}
+
+/// Check that an inverted index can be read, i.e. that it's well-formed and the checksums of all its segments match,
+/// without knowing its `q`.
+///
+/// Indexes written before checksums were added are only checked to be well-formed.
+pub fn verify_index(path: &Path) -> Result<IndexSummary> {
+    Ok(load_index(path)?.0)
+}
+
+/// Copy an inverted index from `from` to `to`, e.g. to replicate it to another host, after verifying it.
+///
+/// The copy is written in the current format, so it also upgrades indexes written before checksums were added.
+/// It's written to `<to>.partial` and verified before it's renamed to `to`, so `to` is never a partial copy.
+pub fn copy_index(from: &Path, to: &Path) -> Result<IndexSummary> {
+    let (summary, inverted_index) = load_index(from)?;
+    write_verified_index(to, &inverted_index, summary.q, summary.records)
+}
+
+/// Write an inverted index to `<to>.partial`, and verify it before it's renamed to `to`.
+fn write_verified_index(
+    to: &Path,
+    inverted_index: &InvertedIndex,
+    q: usize,
+    records: Option<usize>,
+) -> Result<IndexSummary> {
+    let mut partial_name: OsString = to.as_os_str().to_os_string();
+    partial_name.push(".partial");
+    let partial_name: PathBuf = PathBuf::from(partial_name);
+    write_index(&partial_name, inverted_index, q, records)?;
+    let written: IndexSummary = match load_index(&partial_name) {
+        Ok((written, _)) => written,
+        Err(e) => {
+            std::fs::remove_file(&partial_name)?;
+            return Err(e);
+        }
+    };
+    std::fs::rename(&partial_name, to)?;
+
+    Ok(written)
+}
+
+/// A part of an index merged by [`merge_indexes`], and where its records are in the merged index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergedPart {
+    /// File of the index of the part.
+    pub path: PathBuf,
+    /// ID of the first record of the part in the merged index, i.e. the number of records of the parts before it.
+    pub first_id: ID,
+    /// Number of records of the part.
+    pub records: usize,
+}
+
+/// Merge the persisted indexes of parts of a corpus into the index of all of them, in the given order,
+/// e.g. of shards that were indexed in parallel on separate machines.
+///
+/// The merged index is that of the parts concatenated, so a join loads it by `--index-from` with the concatenated parts
+/// as `doc_y`. The IDs of the records of each part are offset by the number of records of the parts before it, so their
+/// inverted lists are appended in order, and the numbers of occurrences are summed. Those are exact for indexes of
+/// self-joins. For others, occurrences in `doc_x` are counted once for each part, which only changes the order
+/// of q-grams, not the matches.
+///
+/// All indexes must be of the same `q`, and of version 4 or later, which know their number of records.
+/// The merged index is written and verified as by [`copy_index`], and the parts are written to `<to>.sources`,
+/// as tab-separated lines of their paths, first IDs and numbers of records, after a header, see [`read_sources`].
+pub fn merge_indexes(parts: &[PathBuf], to: &Path) -> Result<(IndexSummary, Vec<MergedPart>)> {
+    let mut merged: InvertedIndex = HashMap::new();
+    let mut merged_parts: Vec<MergedPart> = Vec::with_capacity(parts.len());
+    let mut q: Option<usize> = None;
+    let mut first_id: ID = 0;
+    for path in parts {
+        let (summary, inverted_index) = load_index(path)?;
+        if q.is_some_and(|q| q != summary.q) {
+            bail!(
+                "{} is an index with q={}, not q={} as the indexes before it",
+                path.display(),
+                summary.q,
+                q.unwrap()
+            );
+        }
+        q = Some(summary.q);
+        let records: usize = match summary.records {
+            Some(records) => records,
+            None => bail!(
+                "{} doesn't know its number of records, as it's of version {}, so it must be built again to be merged",
+                path.display(),
+                summary.version
+            ),
+        };
+        for (token, (inverted_list, count)) in inverted_index {
+            let (merged_list, merged_count) = merged.entry(token).or_insert((Vec::new(), 0));
+            merged_list.extend(
+                inverted_list
+                    .into_iter()
+                    .map(|(id, loc)| (first_id + id, loc)),
+            );
+            *merged_count += count;
+        }
+        merged_parts.push(MergedPart {
+            path: path.clone(),
+            first_id,
+            records,
+        });
+        first_id += records;
+    }
+    let q: usize = match q {
+        Some(q) => q,
+        None => bail!("No indexes to merge"),
+    };
+
+    let summary: IndexSummary = write_verified_index(to, &merged, q, Some(first_id))?;
+    let mut writer: BufWriter<File> = BufWriter::new(File::create(sources_path(to))?);
+    writeln!(writer, "{}", SOURCES_HEADER)?;
+    for part in &merged_parts {
+        writeln!(
+            writer,
+            "{}\t{}\t{}",
+            escape_field(&part.path.to_string_lossy()),
+            part.first_id,
+            part.records
+        )?;
+    }
+    writer.flush()?;
+
+    Ok((summary, merged_parts))
+}
+
+/// Header of the parts of a merged index, see [`merge_indexes`].
+const SOURCES_HEADER: &str = "index\tfirst_id\trecords";
+
+/// Path of the parts of a merged index, i.e. `<index>.sources`.
+fn sources_path(index: &Path) -> PathBuf {
+    let mut sources_name: OsString = index.as_os_str().to_os_string();
+    sources_name.push(".sources");
+    PathBuf::from(sources_name)
+}
+
+/// Read the parts of an index merged by [`merge_indexes`], in order, from `<index>.sources`.
+pub fn read_sources(index: &Path) -> Result<Vec<MergedPart>> {
+    let sources_name: PathBuf = sources_path(index);
+    let corrupted = || {
+        Error::from(format!(
+            "Corrupted sources of a merged index: {}",
+            sources_name.display()
+        ))
+    };
+    let file: File = File::open(&sources_name).map_err(|e| {
+        Error::from(format!(
+            "{} is not a merged index, as {} cannot be read: {}",
+            index.display(),
+            sources_name.display(),
+            e
+        ))
+    })?;
+    let mut lines = BufReader::new(file).lines();
+    if lines.next().transpose()?.as_deref() != Some(SOURCES_HEADER) {
+        return Err(corrupted());
+    }
+    let mut parts: Vec<MergedPart> = Vec::new();
+    for line in lines {
+        let line: String = line?;
+        let part: MergedPart = match line.split('\t').collect::<Vec<&str>>()[..] {
+            [path, first_id, records] => MergedPart {
+                path: PathBuf::from(unescape_field(path).ok_or_else(corrupted)?),
+                first_id: first_id.parse().map_err(|_| corrupted())?,
+                records: records.parse().map_err(|_| corrupted())?,
+            },
+            _ => return Err(corrupted()),
+        };
+        // the parts are contiguous, so that the part of a record can be found by its ID
+        if part.first_id != parts.last().map_or(0, |last| last.first_id + last.records) {
+            return Err(corrupted());
+        }
+        parts.push(part);
+    }
+
+    Ok(parts)
+}
+
+/// Read an inverted index of any version, and its summary.
+fn load_index(path: &Path) -> Result<(IndexSummary, InvertedIndex)> {
+    let mut reader: BufReader<File> = BufReader::new(File::open(path)?);
+    let version: u32 = match reader.fill_buf()? {
+        magic if magic.starts_with(INDEX_MAGIC) => 4,
+        magic if magic.starts_with(INDEX_MAGIC_V3) => 3,
+        magic if magic.starts_with(INDEX_MAGIC_V2) => 2,
+        _ => return read_text_index(reader, path),
+    };
+    let mut data: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    let invalid = || Error::from(format!("Invalid index {}", path.display()));
+    let mut bytes = data[INDEX_MAGIC.len()..].iter();
+    let q: usize = read_varint(&mut bytes).ok_or_else(invalid)? as usize;
+    let records: Option<usize> = match version {
+        4 => read_varint(&mut bytes)
+            .ok_or_else(invalid)?
+            .checked_sub(1)
+            .map(|records| records as usize),
+        _ => None,
+    };
+    let qgrams: usize = read_varint(&mut bytes).ok_or_else(invalid)? as usize;
+    let mut inverted_index: InvertedIndex = HashMap::new();
+    let mut summary: IndexSummary = IndexSummary {
+        version,
+        q,
+        records,
+        qgrams,
+        segments: 0,
+    };
+    if version == 2 {
+        read_qgrams(&mut bytes, &mut inverted_index, Some(qgrams)).ok_or_else(invalid)?;
+    } else {
+        let segments: usize = read_varint(&mut bytes).ok_or_else(invalid)? as usize;
+        let header_len: usize = data.len() - bytes.len();
+        if bytes.as_slice().get(..CHECKSUM_LEN) != Some(&checksum(&data[..header_len])?[..]) {
+            bail!(
+                "The header of index {} is corrupt, as its checksum doesn't match",
+                path.display()
+            );
+        }
+        bytes = data[header_len + CHECKSUM_LEN..].iter();
+        for i in 0..segments {
+            let len: usize = read_varint(&mut bytes).ok_or_else(invalid)? as usize;
+            let rest: &[u8] = bytes.as_slice();
+            let (segment, expected): (&[u8], &[u8]) =
+                match (rest.get(..len), rest.get(len..len + CHECKSUM_LEN)) {
+                    (Some(segment), Some(expected)) => (segment, expected),
+                    _ => bail!("Segment {} of index {} is truncated", i, path.display()),
+                };
+            if checksum(segment)? != expected {
+                bail!(
+                    "Segment {} of index {} is corrupt, as its checksum doesn't match",
+                    i,
+                    path.display()
+                );
+            }
+            read_qgrams(&mut segment.iter(), &mut inverted_index, None).ok_or_else(invalid)?;
+            bytes = rest[len + CHECKSUM_LEN..].iter();
+        }
+        summary.segments = segments;
+    }
+    if bytes.next().is_some() || inverted_index.len() != qgrams {
+        return Err(invalid());
+    }
+
+    Ok((summary, inverted_index))
+}
+
+/// Read `count` q-grams, or all of them until the bytes end if it's `None`, or `None` if they're invalid.
+fn read_qgrams(
+    bytes: &mut std::slice::Iter<u8>,
+    inverted_index: &mut InvertedIndex,
+    count: Option<usize>,
+) -> Option<()> {
+    let mut read: usize = 0;
+    while count.map_or(!bytes.as_slice().is_empty(), |count| read < count) {
+        let len: usize = read_varint(bytes)? as usize;
+        let rest: &[u8] = bytes.as_slice();
+        let token: String = String::from_utf8(rest.get(..len)?.to_vec()).ok()?;
+        *bytes = rest[len..].iter();
+        let count: usize = read_varint(bytes)? as usize;
+        let entries: usize = read_varint(bytes)? as usize;
+        let mut inverted_list: InvertedList = Vec::with_capacity(entries.min(bytes.len()));
+        let mut previous: i64 = 0;
+        for _ in 0..entries {
+            let zigzag: u64 = read_varint(bytes)?;
+            let id: i64 = previous + ((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64));
+            let loc: u64 = read_varint(bytes)?;
+            inverted_list.push((usize::try_from(id).ok()?, loc as usize));
+            previous = id;
+        }
+        inverted_index.insert(token, (inverted_list, count));
+        read += 1;
+    }
+    Some(())
+}
+
+/// Read an inverted index written as text, as the first version of [`write_index`] did, one q-gram per line.
+fn read_text_index(
+    mut reader: BufReader<File>,
+    path: &Path,
+) -> Result<(IndexSummary, InvertedIndex)> {
+    let mut header: String = String::new();
+    reader.read_line(&mut header)?;
+    let q: usize = match header
+        .trim_end()
+        .strip_prefix(TEXT_INDEX_HEADER)
+        .map(str::parse)
+    {
+        Some(Ok(q)) => q,
+        _ => bail!(
+            "{} is not an index, its header is `{}`",
+            path.display(),
+            header.trim_end()
+        ),
+    };
+
+    let mut inverted_index: InvertedIndex = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line: String = line?;
+        let invalid = || {
+            Error::from(format!(
+                "Invalid line {} of index {}",
+                i + 1,
+                path.display()
+            ))
+        };
+        let fields: Vec<&str> = line.splitn(3, '\t').collect();
+        if fields.len() != 3 {
+            return Err(invalid());
+        }
+        let token: String = unescape_field(fields[0]).ok_or_else(invalid)?;
+        let count: usize = fields[1].parse().map_err(|_| invalid())?;
+        let inverted_list: InvertedList = fields[2]
+            .split(' ')
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (id, loc) = entry.split_once(':')?;
+                Some((id.parse().ok()?, loc.parse().ok()?))
+            })
+            .collect::<Option<InvertedList>>()
+            .ok_or_else(invalid)?;
+        inverted_index.insert(token, (inverted_list, count));
+    }
+    let summary: IndexSummary = IndexSummary {
+        version: 1,
+        q,
+        records: None,
+        qgrams: inverted_index.len(),
+        segments: 0,
+    };
+
+    Ok((summary, inverted_index))
+}
+
+/// Write an inverted index for inspection, as tab-separated lines after a header, in sorted order of tokens.
+///
+/// Each line holds the token, its number of occurences, the number of distinct records and length of its inverted list,
+/// and the inverted list itself as `id:loc` entries if `postings` is set. Unlike [`write_index`], it can't be read back.
+pub fn dump_index<W: Write>(
+    writer: W,
+    inverted_index: &InvertedIndex,
+    postings: bool,
+) -> Result<()> {
+    let mut writer: BufWriter<W> = BufWriter::new(writer);
+    write!(writer, "token\tfrequency\trecords\tpostings")?;
+    if postings {
+        write!(writer, "\tlist")?;
+    }
+    writeln!(writer)?;
+
+    let mut tokens: Vec<&String> = inverted_index.keys().collect();
+    tokens.sort_unstable();
+    for token in tokens {
+        let (inverted_list, count) = &inverted_index[token];
+        // inverted lists are in increasing order of IDs, so the entries of a record are adjacent
+        let mut records: Vec<ID> = inverted_list.iter().map(|(id, _)| *id).collect();
+        records.dedup();
+        write!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            escape_field(token),
+            count,
+            records.len(),
+            inverted_list.len()
+        )?;
+        if postings {
+            // but locations within a record are in no particular order
+            let mut inverted_list: InvertedList = inverted_list.clone();
+            inverted_list.sort_unstable();
+            let list: Vec<String> = inverted_list
+                .iter()
+                .map(|(id, loc)| format!("{}:{}", id, loc))
+                .collect();
+            write!(writer, "\t{}", list.join(" "))?;
+        }
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Write candidates, one record of `doc_x` per line, as its line number and those of its candidates in `doc_y`.
+pub fn write_candidates(
+    path: &Path,
+    candidates: &[(ID, Vec<ID>)],
+    q: usize,
+    tau: usize,
+) -> Result<()> {
+    let mut writer: BufWriter<File> = BufWriter::new(File::create(path)?);
+    writeln!(writer, "ed_join candidates v1 q={} tau={}", q, tau)?;
+    for (x_id, y_ids) in candidates {
+        let y_ids: Vec<String> = y_ids.iter().map(|y_id| y_id.to_string()).collect();
+        writeln!(writer, "{}\t{}", x_id, y_ids.join(","))?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Read candidates written by [`write_candidates`], keyed by line number of `doc_x`.
+pub fn read_candidates(path: &Path, q: usize, tau: usize) -> Result<HashMap<ID, Vec<ID>>> {
+    let mut reader: BufReader<File> = BufReader::new(File::open(path)?);
+    check_header(
+        &mut reader,
+        path,
+        &format!("ed_join candidates v1 q={} tau={}", q, tau),
+    )?;
+
+    let mut candidates: HashMap<ID, Vec<ID>> = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line: String = line?;
+        let parsed: Option<(ID, Vec<ID>)> = line.split_once('\t').and_then(|(x_id, y_ids)| {
+            let y_ids: Option<Vec<ID>> = y_ids
+                .split(',')
+                .filter(|y_id| !y_id.is_empty())
+                .map(|y_id| y_id.parse().ok())
+                .collect();
+            Some((x_id.parse().ok()?, y_ids?))
+        });
+        let (x_id, y_ids) = parsed.ok_or_else(|| {
+            Error::from(format!(
+                "Invalid line {} of candidates {}",
+                i + 1,
+                path.display()
+            ))
+        })?;
+        candidates.insert(x_id, y_ids);
+    }
+
+    Ok(candidates)
+}
+
+/// Edit distances of verified pairs, keyed by line number of `doc_x`, with the line numbers of `doc_y` in increasing order.
+pub type Distances = HashMap<ID, Vec<(ID, usize)>>;
+
+/// Write edit distances of verified pairs, one record of `doc_x` per line, as its line number and `y_id:ed` of its pairs.
+///
+/// Exact distances don't depend on `tau`, so they're valid for any later join of the same inputs.
+pub fn write_distances(path: &Path, distances: &Distances, q: usize) -> Result<()> {
+    let mut writer: BufWriter<File> = BufWriter::new(File::create(path)?);
+    writeln!(writer, "ed_join distances v1 q={}", q)?;
+
+    let mut x_ids: Vec<&ID> = distances.keys().collect();
+    x_ids.sort_unstable();
+    for x_id in x_ids {
+        let pairs: Vec<String> = distances[x_id]
+            .iter()
+            .map(|(y_id, ed)| format!("{}:{}", y_id, ed))
+            .collect();
+        writeln!(writer, "{}\t{}", x_id, pairs.join(","))?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Read edit distances written by [`write_distances`].
+pub fn read_distances(path: &Path, q: usize) -> Result<Distances> {
+    let mut reader: BufReader<File> = BufReader::new(File::open(path)?);
+    check_header(&mut reader, path, &format!("ed_join distances v1 q={}", q))?;
+
+    let mut distances: Distances = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line: String = line?;
+        let parsed: Option<(ID, Vec<(ID, usize)>)> =
+            line.split_once('\t').and_then(|(x_id, pairs)| {
+                let pairs: Option<Vec<(ID, usize)>> = pairs
+                    .split(',')
+                    .filter(|pair| !pair.is_empty())
+                    .map(|pair| {
+                        let (y_id, ed) = pair.split_once(':')?;
+                        Some((y_id.parse().ok()?, ed.parse().ok()?))
+                    })
+                    .collect();
+                Some((x_id.parse().ok()?, pairs?))
+            });
+        let (x_id, pairs) = parsed.ok_or_else(|| {
+            Error::from(format!(
+                "Invalid line {} of distances {}",
+                i + 1,
+                path.display()
+            ))
+        })?;
+        distances.insert(x_id, pairs);
+    }
+
+    Ok(distances)
+}
+
+/// A point of the partial output of a join, up to which it's complete, see [`crate::config::EdJoinConfig::checkpoint_interval`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Line number of the first record of `doc_x` whose matches aren't written, or the number of records if all are.
+    pub next_x_id: ID,
+    /// Length of the partial output in bytes, after the matches of all records before `next_x_id`.
+    pub len: u64,
+}
+
+/// Write a checkpoint to `<path>.tmp` first, and rename it to `path`, so that a crash leaves the previous one intact.
+pub fn write_checkpoint(path: &Path, checkpoint: Checkpoint) -> Result<()> {
+    let mut tmp_name: OsString = path.as_os_str().to_os_string();
+    tmp_name.push(".tmp");
+    let mut file: File = File::create(&tmp_name)?;
+    write!(
+        file,
+        "ed_join checkpoint v1\n{}\t{}\n",
+        checkpoint.next_x_id, checkpoint.len
+    )?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_name, path)?;
+
+    Ok(())
+}
+
+/// Read a checkpoint written by [`write_checkpoint`].
+pub fn read_checkpoint(path: &Path) -> Result<Checkpoint> {
+    let mut reader: BufReader<File> = BufReader::new(File::open(path)?);
+    check_header(&mut reader, path, "ed_join checkpoint v1")?;
+
+    let mut line: String = String::new();
+    reader.read_line(&mut line)?;
+    let parsed: Option<Checkpoint> =
+        line.trim_end()
+            .split_once('\t')
+            .and_then(|(next_x_id, len)| {
+                Some(Checkpoint {
+                    next_x_id: next_x_id.parse().ok()?,
+                    len: len.parse().ok()?,
+                })
+            });
+    parsed.ok_or_else(|| Error::from(format!("Invalid checkpoint {}", path.display())))
+}
+
+/// What a join completed before it exceeded one of its limits, see [`crate::config::EdJoinConfig::limits`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Manifest {
+    /// The phase that exceeded the limit, by its name in a timeline, e.g. `index`.
+    pub phase: String,
+    /// The limit exceeded, by its name in [`crate::config::Limits`], e.g. `candidates`, with its value and maximum.
+    pub limit: String,
+    pub value: usize,
+    pub max: usize,
+    /// The phases completed before, with their wall time, see [`crate::report::JoinStats::phases`].
+    pub completed: Vec<(&'static str, Duration)>,
+    /// Number of records of `doc_x` whose matches were found.
+    pub records: usize,
+    /// The partial output, with the matches of records done in the order of `doc_x`, as `--resume` continues it.
+    pub partial: Option<PathBuf>,
+}
+
+/// Write a manifest as `key\tvalue` lines after a header, with a `completed` line of each phase in seconds.
+pub fn write_manifest(path: &Path, manifest: &Manifest) -> Result<()> {
+    let mut writer: BufWriter<File> = BufWriter::new(File::create(path)?);
+    writeln!(writer, "ed_join manifest v1")?;
+    writeln!(
+        writer,
+        "phase\t{}\nlimit\t{}\nvalue\t{}\nmax\t{}",
+        manifest.phase, manifest.limit, manifest.value, manifest.max
+    )?;
+    for (phase, elapsed) in &manifest.completed {
+        writeln!(writer, "completed\t{}\t{:.3}", phase, elapsed.as_secs_f64())?;
+    }
+    writeln!(writer, "records\t{}", manifest.records)?;
+    if let Some(partial) = &manifest.partial {
+        writeln!(
+            writer,
+            "partial\t{}",
+            escape_field(&partial.display().to_string())
+        )?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Read a list of tombstones, i.e. line numbers of records of `doc_y` that are deleted, one per line,
+/// see [`crate::config::EdJoinConfig::tombstones`].
+///
+/// Unlike the other files here, it has no header, so that it can be written by any tool. Empty lines are ignored.
+pub fn read_tombstones(path: &Path) -> Result<BTreeSet<ID>> {
+    let reader: BufReader<File> = BufReader::new(File::open(path)?);
+    let mut tombstones: BTreeSet<ID> = BTreeSet::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line: String = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match line.trim().parse::<ID>() {
+            Ok(y_id) => tombstones.insert(y_id),
+            Err(_) => bail!(
+                "Line {} of tombstones {} is not a line number: {}",
+                i + 1,
+                path.display(),
+                line
+            ),
+        };
+    }
+
+    Ok(tombstones)
+}
+
+#[cfg(all(test, feature = "hashing"))]
+mod tests {
+    use super::*;
+    use crate::qgram::build_inverted_index;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn round_trip() {
+        let dir: TempDir = TempDir::new("round_trip");
+        let (index_name, candidates_name) = (dir.join("index"), dir.join("candidates"));
+
+        let records: Vec<String> = ["hello", "he\tlo", "hallo"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let inverted_index: InvertedIndex = build_inverted_index(None, &records, 2);
+        write_index(&index_name, &inverted_index, 2, Some(records.len())).unwrap();
+        assert_eq!(read_index(&index_name, 2).unwrap(), inverted_index);
+        assert!(read_index(&index_name, 3).is_err());
+        std::fs::write(&index_name, "ed_join index v1 q=2\nhe\t2\t0:0 1:0\n").unwrap();
+        assert_eq!(
+            read_index(&index_name, 2).unwrap()["he"],
+            (vec![(0, 0), (1, 0)], 2)
+        );
+        std::fs::write(&index_name, b"ed_join index v2\n\x02\x01\x05he").unwrap();
+        assert!(read_index(&index_name, 2).is_err());
+
+        let candidates = vec![(0, vec![1, 2]), (1, vec![])];
+        write_candidates(&candidates_name, &candidates, 2, 1).unwrap();
+        let read = read_candidates(&candidates_name, 2, 1).unwrap();
+        assert_eq!(read[&0], vec![1, 2]);
+        assert_eq!(read[&1], Vec::<ID>::new());
+        assert!(read_candidates(&candidates_name, 2, 2).is_err());
+
+        let distances: Distances = vec![(0, vec![(1, 1), (2, 3)]), (2, vec![])]
+            .into_iter()
+            .collect();
+        write_distances(&candidates_name, &distances, 2).unwrap();
+        assert_eq!(read_distances(&candidates_name, 2).unwrap(), distances);
+        assert!(read_distances(&candidates_name, 3).is_err());
+
+        let checkpoint: Checkpoint = Checkpoint {
+            next_x_id: 7,
+            len: 120,
+        };
+        write_checkpoint(&candidates_name, checkpoint).unwrap();
+        assert_eq!(read_checkpoint(&candidates_name).unwrap(), checkpoint);
+        std::fs::write(&candidates_name, "ed_join checkpoint v1\n7\n").unwrap();
+        assert!(read_checkpoint(&candidates_name).is_err());
+
+        std::fs::write(&candidates_name, "3\n\n 1\n").unwrap();
+        assert_eq!(
+            read_tombstones(&candidates_name).unwrap(),
+            [1, 3].iter().copied().collect::<BTreeSet<ID>>()
+        );
+        std::fs::write(&candidates_name, "3\nx\n").unwrap();
+        assert!(read_tombstones(&candidates_name).is_err());
+
+        let mut dump: Vec<u8> = Vec::new();
+        dump_index(&mut dump, &inverted_index, true).unwrap();
+        let dump: String = String::from_utf8(dump).unwrap();
+        assert!(dump.starts_with("token\tfrequency\trecords\tpostings\tlist\n"));
+        assert!(dump.contains("\nlo\t3\t3\t3\t0:3 1:3 2:3\n"));
+    }
+
+    #[test]
+    fn index_checksums() {
+        let dir: TempDir = TempDir::new("index_checksums");
+        let (index_name, copy_name) = (dir.join("index"), dir.join("copy"));
+        // enough distinct q-grams for a few segments
+        let records: Vec<String> = (0..3000u64)
+            .map(|i| format!("{:08x}", i.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32))
+            .collect();
+        let inverted_index: InvertedIndex = build_inverted_index(None, &records, 4);
+        write_index(&index_name, &inverted_index, 4, Some(records.len())).unwrap();
+        let summary: IndexSummary = verify_index(&index_name).unwrap();
+        assert_eq!(
+            (summary.version, summary.q, summary.records, summary.qgrams),
+            (4, 4, Some(3000), inverted_index.len())
+        );
+        assert_eq!(
+            summary.segments,
+            inverted_index.len().div_ceil(INDEX_SEGMENT_LEN)
+        );
+        assert!(summary.segments > 1);
+
+        assert_eq!(copy_index(&index_name, &copy_name).unwrap(), summary);
+        assert_eq!(read_index(&copy_name, 4).unwrap(), inverted_index);
+        // a flipped bit in the last q-gram is caught by the checksum of its segment
+        let mut data: Vec<u8> = std::fs::read(&index_name).unwrap();
+        let last: usize = data.len() - CHECKSUM_LEN - 1;
+        data[last] ^= 1;
+        std::fs::write(&index_name, &data).unwrap();
+        let corrupt: String = verify_index(&index_name).unwrap_err().to_string();
+        assert!(
+            corrupt.contains(&format!("Segment {} of index", summary.segments - 1)),
+            "{}",
+            corrupt
+        );
+        assert!(copy_index(&index_name, &copy_name).is_err());
+        assert_eq!(read_index(&copy_name, 4).unwrap(), inverted_index);
+
+        // an index written as text is upgraded by copying it
+        std::fs::write(&index_name, "ed_join index v1 q=2\nhe\t2\t0:0 1:0\n").unwrap();
+        assert_eq!(verify_index(&index_name).unwrap().version, 1);
+        assert_eq!(copy_index(&index_name, &copy_name).unwrap().version, 4);
+        assert_eq!(
+            read_index(&copy_name, 2).unwrap()["he"],
+            (vec![(0, 0), (1, 0)], 2)
+        );
+    }
+
+    #[test]
+    fn index_merge() {
+        let dir: TempDir = TempDir::new("index_merge");
+        let names: Vec<PathBuf> = (0..3).map(|i| dir.join(format!("part{}", i))).collect();
+        let merged_name: PathBuf = dir.join("merged");
+        let records: Vec<String> = ["hello", "hallo", "yellow", "help", "fellow"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        for (name, part) in names.iter().zip(&[&records[..2], &records[2..]]) {
+            write_index(
+                name,
+                &build_inverted_index(None, part, 2),
+                2,
+                Some(part.len()),
+            )
+            .unwrap();
+        }
+
+        // the merged index of a self-join is the index of the concatenated parts
+        let (summary, parts) = merge_indexes(&names[..2], &merged_name).unwrap();
+        assert_eq!(summary.records, Some(records.len()));
+        assert_eq!(
+            parts
+                .iter()
+                .map(|part| (part.first_id, part.records))
+                .collect::<Vec<_>>(),
+            vec![(0, 2), (2, 3)]
+        );
+        assert_eq!(
+            read_index(&merged_name, 2).unwrap(),
+            build_inverted_index(None, &records, 2)
+        );
+        let sources: String = std::fs::read_to_string(sources_path(&merged_name)).unwrap();
+        assert_eq!(
+            sources.lines().nth(2),
+            Some(format!("{}\t2\t3", names[1].display()).as_str())
+        );
+        assert_eq!(read_sources(&merged_name).unwrap(), parts);
+        assert!(read_sources(&names[0]).is_err());
+
+        // indexes of another q, or which don't know their number of records, can't be merged
+        write_index(
+            &names[2],
+            &build_inverted_index(None, &records, 3),
+            3,
+            Some(records.len()),
+        )
+        .unwrap();
+        assert!(merge_indexes(&names, &merged_name).is_err());
+        std::fs::write(&names[2], "ed_join index v1 q=2\nhe\t2\t0:0 1:0\n").unwrap();
+        assert!(merge_indexes(&names[1..], &merged_name)
+            .unwrap_err()
+            .to_string()
+            .contains("version 1"));
+        assert!(merge_indexes(&[], &merged_name).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt::Debug;
+use std::path::Path;
+#[cfg(all(feature = "plugins", unix))]
+use std::{
+    ffi::{c_void, CStr, CString},
+    os::unix::ffi::OsStrExt,
+};
+
+use crate::errors::*;
+use crate::output::MatchRecord;
+
+/// A post-processing step of matches at output time, which filters them or changes their confidence,
+/// e.g. to apply the business rules of a site.
+///
+/// Plugins are applied in the order they're added, see [`crate::config::EdJoinConfig::plugin`], after scoring,
+/// and a match dropped by a plugin is not passed to those after it. Only written matches are post-processed,
+/// so the matches returned by [`crate::matching::ed_join_matches`] are not.
+///
+/// ```
+/// use ed_join::output::MatchRecord;
+/// use ed_join::plugin::MatchPlugin;
+///
+/// // drop pairs of records that are too short to be told apart
+/// #[derive(Debug)]
+/// struct MinLength(usize);
+///
+/// impl MatchPlugin for MinLength {
+///     fn apply(&self, m: &mut MatchRecord) -> bool {
+///         m.x_text.len() >= self.0 && m.y_text.len() >= self.0
+///     }
+/// }
+/// ```
+pub trait MatchPlugin: Debug + Send + Sync {
+    /// Whether a match is written, after the plugin may have changed it.
+    fn apply(&self, m: &mut MatchRecord) -> bool;
+}
+
+/// Version of the interface of [`DylibPlugin`]s, which a plugin returns from `ed_join_plugin_version`.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// A match as passed to a [`DylibPlugin`], whose texts are UTF-8 and not terminated by a nul.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PluginMatch {
+    pub x_id: u64,
+    pub y_id: u64,
+    pub ed: u64,
+    pub x_text: *const u8,
+    pub x_len: usize,
+    pub y_text: *const u8,
+    pub y_len: usize,
+    /// Confidence of the match, or NaN if it's not scored. The plugin may overwrite it, and a NaN clears it.
+    pub confidence: f64,
+}
+
+/// `ed_join_plugin_version` of a [`DylibPlugin`].
+#[cfg(all(feature = "plugins", unix))]
+type VersionFn = unsafe extern "C" fn() -> u32;
+/// `ed_join_plugin_apply` of a [`DylibPlugin`].
+#[cfg(all(feature = "plugins", unix))]
+type ApplyFn = unsafe extern "C" fn(*mut PluginMatch) -> i32;
+
+/// A [`MatchPlugin`] loaded from a dynamic library, so that it can be deployed without rebuilding the tool.
+///
+/// The library exports two functions, with the C calling convention:
+///
+///  * `uint32_t ed_join_plugin_version(void)`, which returns [`PLUGIN_ABI_VERSION`].
+///  * `int32_t ed_join_plugin_apply(PluginMatch *m)`, which returns non-zero to keep the match.
+///    It may be called from any thread, but never with the same match twice.
+///
+/// Dynamic plugins are only loaded on Unix, when built with the `plugins` feature.
+#[derive(Debug)]
+pub struct DylibPlugin {
+    #[cfg(all(feature = "plugins", unix))]
+    handle: *mut c_void,
+    #[cfg(all(feature = "plugins", unix))]
+    apply: ApplyFn,
+}
+
+// the handle is only closed on drop, and plugins must be callable from any thread, as documented
+#[cfg(all(feature = "plugins", unix))]
+unsafe impl Send for DylibPlugin {}
+#[cfg(all(feature = "plugins", unix))]
+unsafe impl Sync for DylibPlugin {}
+
+/// The message of the last error of the dynamic linker.
+#[cfg(all(feature = "plugins", unix))]
+fn dl_error() -> String {
+    let msg: *const libc::c_char = unsafe { libc::dlerror() };
+    if msg.is_null() {
+        "unknown error".to_string()
+    } else {
+        unsafe { CStr::from_ptr(msg) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl DylibPlugin {
+    /// Load a plugin from a dynamic library, whose version must be [`PLUGIN_ABI_VERSION`].
+    ///
+    /// The library is run as it's loaded, so only load plugins that are trusted.
+    pub fn load(path: &Path) -> Result<Self> {
+        #[cfg(all(feature = "plugins", unix))]
+        {
+            let c_path: CString = match CString::new(path.as_os_str().as_bytes()) {
+                Ok(c_path) => c_path,
+                Err(_) => bail!("Not a valid path of a plugin: {}", path.display()),
+            };
+            let handle: *mut c_void =
+                unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+            if handle.is_null() {
+                bail!("Cannot load the plugin {}: {}", path.display(), dl_error());
+            }
+            let symbol = |name: &[u8]| -> Result<*mut c_void> {
+                let symbol: *mut c_void =
+                    unsafe { libc::dlsym(handle, name.as_ptr() as *const libc::c_char) };
+                if symbol.is_null() {
+                    let name: &str = std::str::from_utf8(&name[..name.len() - 1]).unwrap();
+                    bail!("The plugin {} has no function `{}`", path.display(), name);
+                }
+                Ok(symbol)
+            };
+            let checked = || -> Result<ApplyFn> {
+                let version: VersionFn = unsafe {
+                    std::mem::transmute::<*mut c_void, VersionFn>(symbol(
+                        b"ed_join_plugin_version\0",
+                    )?)
+                };
+                let version: u32 = unsafe { version() };
+                if version != PLUGIN_ABI_VERSION {
+                    bail!(
+                        "The plugin {} is of version {} of the interface, not {}",
+                        path.display(),
+                        version,
+                        PLUGIN_ABI_VERSION
+                    );
+                }
+                Ok(unsafe {
+                    std::mem::transmute::<*mut c_void, ApplyFn>(symbol(b"ed_join_plugin_apply\0")?)
+                })
+            };
+            match checked() {
+                Ok(apply) => Ok(Self { handle, apply }),
+                Err(e) => {
+                    unsafe { libc::dlclose(handle) };
+                    Err(e)
+                }
+            }
+        }
+        #[cfg(not(all(feature = "plugins", unix)))]
+        {
+            bail!(
+                "Cannot load the plugin {}: plugins are only loaded on Unix, when built with the `plugins` feature",
+                path.display()
+            )
+        }
+    }
+}
+
+impl MatchPlugin for DylibPlugin {
+    fn apply(&self, m: &mut MatchRecord) -> bool {
+        #[cfg(all(feature = "plugins", unix))]
+        {
+            let mut plugin_match: PluginMatch = PluginMatch {
+                x_id: m.x_id as u64,
+                y_id: m.y_id as u64,
+                ed: m.ed as u64,
+                x_text: m.x_text.as_ptr(),
+                x_len: m.x_text.len(),
+                y_text: m.y_text.as_ptr(),
+                y_len: m.y_text.len(),
+                confidence: m.confidence.unwrap_or(f64::NAN),
+            };
+            let keep: bool = unsafe { (self.apply)(&mut plugin_match) } != 0;
+            m.confidence = Some(plugin_match.confidence).filter(|confidence| !confidence.is_nan());
+            keep
+        }
+        // a plugin is never loaded without the feature
+        #[cfg(not(all(feature = "plugins", unix)))]
+        {
+            let _ = m;
+            true
+        }
+    }
+}
+
+#[cfg(all(feature = "plugins", unix))]
+impl Drop for DylibPlugin {
+    fn drop(&mut self) {
+        unsafe { libc::dlclose(self.handle) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Matches;
+    use std::sync::Arc;
+
+    /// Drops pairs at a distance, and marks those it keeps as certain.
+    #[derive(Debug)]
+    struct ExactOnly;
+
+    impl MatchPlugin for ExactOnly {
+        fn apply(&self, m: &mut MatchRecord) -> bool {
+            m.confidence = Some(1.0);
+            m.ed == 0
+        }
+    }
+
+    #[test]
+    fn plugins() {
+        let records: Vec<String> = ["abc", "abc", "abd"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let pairs = vec![(0, vec![(1, 0), (2, 1)]), (1, vec![(2, 1)])];
+        let plugins: Vec<Arc<dyn MatchPlugin>> = vec![Arc::new(ExactOnly)];
+        let matches = Matches {
+            pairs: &pairs,
+            x_records: &records,
+            y_records: &records,
+            self_join: true,
+            confidence: None,
+            passthrough: None,
+            provenance: None,
+            emit_content: false,
+            text_mask: None,
+            plugins: &plugins,
+        };
+        let kept: Vec<(usize, usize, Option<f64>)> = matches
+            .iter()
+            .map(|m| (m.x_id, m.y_id, m.confidence))
+            .collect();
+        assert_eq!(kept, [(0, 1, Some(1.0))]);
+
+        let missing = DylibPlugin::load(Path::new("/nonexistent/libplugin.so")).unwrap_err();
+        assert!(missing.to_string().contains("/nonexistent/libplugin.so"));
+        // the C library is loaded, but it's not a plugin
+        #[cfg(all(feature = "plugins", target_os = "linux"))]
+        assert!(DylibPlugin::load(Path::new("libc.so.6"))
+            .unwrap_err()
+            .to_string()
+            .contains("no function `ed_join_plugin_version`"));
+    }
+}
@@ -0,0 +1,21 @@
+//! The types and functions most library users need, for a single glob import.
+//!
+//! ```
+//! use ed_join::prelude::*;
+//!
+//! fn is_match(x: &str, y: &str) -> Result<bool> {
+//!     let config: EdJoinConfig = EdJoinConfig::new(2, 1).error_policy(ErrorPolicy::Abort);
+//!     Ok(matches!(check_pair(x, y, config.q, config.tau), Verdict::Matched { .. }))
+//! }
+//!
+//! assert!(is_match("hello", "hallo").unwrap());
+//! ```
+//!
+//! Errors are the `Error` and `ErrorKind` of this crate, so matching on them needs no dependency on `error_chain`.
+
+pub use crate::api::{
+    check_pair, ed_join, ed_join_with_config, CountFilter, EdJoinConfig, Error, ErrorKind,
+    ErrorPolicy, JoinReport, OutputFormat, Phase, ProgressObserver, RecordStore, Result, Side,
+    Verdict, Warning,
+};
+pub use crate::qgram::{build_inverted_index, InvertedIndex};
@@ -0,0 +1,85 @@
+use std::fmt::{self, Debug};
+
+use crate::qgram::ID;
+
+/// Receives the progress of a join, e.g. to draw a progress bar.
+///
+/// Every join reports to the observer of its own `EdJoinConfig`, if any, so joins running concurrently in one process,
+/// each with its own thread pool, don't interfere with each other:
+///
+/// ```no_run
+/// use ed_join::{config::EdJoinConfig, matching::ed_join_with_config, progress::ProgressObserver};
+/// use std::{path::PathBuf, sync::atomic::{AtomicUsize, Ordering}, thread};
+///
+/// #[derive(Debug, Default)]
+/// struct Counter(AtomicUsize);
+///
+/// impl ProgressObserver for Counter {
+///     fn start(&self, _total: usize) {}
+///     fn advance(&self) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let (doc_x, doc_y) = (PathBuf::from("x.txt"), PathBuf::from("y.txt"));
+/// thread::scope(|scope| {
+///     for tau in 1..=2 {
+///         let config = EdJoinConfig::new(2, tau).num_threads(2).progress(Counter::default());
+///         let (doc_x, doc_y) = (&doc_x, &doc_y);
+///         scope.spawn(move || ed_join_with_config(doc_x, doc_y, &config).unwrap());
+///     }
+/// });
+/// ```
+pub trait ProgressObserver: Debug + Send + Sync {
+    /// Called once before matching, with the number of records of `doc_x` to be processed.
+    fn start(&self, total: usize);
+    /// Called whenever a record of `doc_x` is done, from any thread of the join.
+    fn advance(&self) {}
+    /// Called whenever a record of `doc_x` is done, after [`ProgressObserver::advance`], with the totals of the join so far.
+    fn progress(&self, _event: ProgressEvent) {}
+    /// Called once all records are done.
+    fn finish(&self) {}
+}
+
+/// The progress of a join when a record of `doc_x` is done, see [`ProgressObserver::progress`].
+///
+/// Records are done by many threads at once, so events may arrive out of order, and the totals of an event may already
+/// include records done after it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProgressEvent {
+    /// Line number of the record that's done.
+    pub x_id: ID,
+    /// Number of records done so far.
+    pub records: usize,
+    /// Number of records to be processed, as passed to [`ProgressObserver::start`].
+    pub total: usize,
+    /// Number of candidates generated so far, before they're verified.
+    pub candidates: usize,
+    /// Number of matches found so far.
+    pub matches: usize,
+}
+
+/// An observer which is only called with the events of a join, e.g. for a GUI or a service to surface them,
+/// see [`crate::config::EdJoinConfig::on_progress`].
+///
+/// ```
+/// use ed_join::progress::{ProgressEvent, ProgressFn, ProgressObserver};
+///
+/// let observer = ProgressFn(|event: ProgressEvent| assert!(event.records <= event.total));
+/// observer.progress(ProgressEvent { x_id: 0, records: 1, total: 2, candidates: 3, matches: 1 });
+/// ```
+pub struct ProgressFn<F: Fn(ProgressEvent) + Send + Sync>(pub F);
+
+impl<F: Fn(ProgressEvent) + Send + Sync> Debug for ProgressFn<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressFn").finish_non_exhaustive()
+    }
+}
+
+impl<F: Fn(ProgressEvent) + Send + Sync> ProgressObserver for ProgressFn<F> {
+    fn start(&self, _total: usize) {}
+
+    fn progress(&self, event: ProgressEvent) {
+        (self.0)(event)
+    }
+}
@@ -1,16 +1,26 @@
-use crossbeam_channel::unbounded;
 use rayon::prelude::*;
 use std::{
+    borrow::Cow,
     cmp::Ordering,
-    collections::HashMap,
-    fmt::{Display, Formatter},
+    collections::{hash_map::Entry, HashMap},
+    fmt::{Debug, Display, Formatter},
     fs::File,
     io::{prelude::*, BufReader},
+    mem,
     ops::{Deref, DerefMut},
     path::PathBuf,
+    sync::Arc,
 };
 
+use crate::alphabet::AlphabetAction;
+use crate::config::{EdJoinConfig, ErrorPolicy, InputFormat};
+use crate::csv::read_csv;
 use crate::errors::*;
+use crate::fastq::read_fastq;
+use crate::intern::{Interner, TokenId};
+use crate::jsonl::read_jsonl;
+use crate::report::{Side, Warning};
+use crate::tokenizer::{QGrams, Tokenizer, TokenizerMode};
 
 /// A symbol, such as a q-gram
 pub(crate) type Token = String;
@@ -27,7 +37,7 @@ pub struct PosQGram {
 }
 
 impl PosQGram {
-    fn from(t: Token, l: Loc) -> Self {
+    pub fn from(t: Token, l: Loc) -> Self {
         Self { token: t, loc: l }
     }
 
@@ -37,7 +47,7 @@ impl PosQGram {
         match len_a.cmp(&len_b) {
             Ordering::Greater => Ordering::Greater,
             Ordering::Less => Ordering::Less,
-            Ordering::Equal => self.token.as_bytes().cmp(&other.token.as_bytes()),
+            Ordering::Equal => self.token.as_bytes().cmp(other.token.as_bytes()),
         }
     }
 }
@@ -97,22 +107,14 @@ impl PosQGramArray {
     /// Given a string and a given `q`, generate a PosQGramArray.
     // NOTE: The position QGramArray is sorted in increasing order of location.
     pub fn from(s: &str, q: usize) -> Self {
-        let slice: Vec<String> = Vec::from(s)
-            .par_windows(q)
-            .map(|ngrams| {
-                std::str::from_utf8(ngrams)
-                    .expect("Error when parsing ngrams")
-                    .to_string()
-            })
-            .collect();
-
-        let mut inner: Vec<PosQGram> = Vec::new();
+        Self::tokenize(s, q, &QGrams)
+    }
 
-        slice.into_iter().enumerate().for_each(|(pos, key)| {
-            inner.push(PosQGram::from(key.to_string(), pos));
-        });
+    /// Same as `from()`, but with the grams generated by the given tokenizer.
+    pub fn tokenize(s: &str, q: usize, tokenizer: &dyn Tokenizer) -> Self {
+        let mut inner: Vec<PosQGram> = tokenizer.tokenize(s, q);
         // sort in increasing order of location
-        inner.par_sort_unstable_by_key(|qgram| qgram.loc);
+        inner.sort_unstable_by_key(|qgram| qgram.loc);
 
         Self { inner }
     }
@@ -121,20 +123,31 @@ impl PosQGramArray {
     ///
     /// - Firstly, in decreasing order of frequency
     /// - Secondly, in lexicographical order of token name
-    pub fn sort_by_frequency(&mut self, inverted: &InvertedIndex) {
-        self.par_sort_unstable_by(|a, b| {
-            let len_a: usize = inverted.get(&a.token).unwrap().1;
-            let len_b: usize = inverted.get(&b.token).unwrap().1;
+    pub fn sort_by_frequency(&mut self, inverted: &dyn IndexBackend) {
+        // IDs of an interner are in this order, so each gram is looked up once rather than on every comparison
+        if let Some(interner) = inverted.interner() {
+            self.sort_by_cached_key(|qgram| interner.get(&qgram.token).unwrap());
+            return;
+        }
+        self.sort_unstable_by(|a, b| {
+            let len_a: usize = inverted.count(&a.token).unwrap();
+            let len_b: usize = inverted.count(&b.token).unwrap();
             match len_a.cmp(&len_b) {
                 Ordering::Greater => Ordering::Greater,
                 Ordering::Less => Ordering::Less,
-                Ordering::Equal => a.token.as_bytes().cmp(&b.token.as_bytes()),
+                Ordering::Equal => a.token.as_bytes().cmp(b.token.as_bytes()),
             }
         });
     }
 
     pub fn sort_by_location(&mut self) {
-        self.par_sort_unstable_by(|a, b| a.loc.cmp(&b.loc))
+        self.sort_unstable_by_key(|qgram| qgram.loc)
+    }
+}
+
+impl Default for PosQGramArray {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -176,106 +189,492 @@ pub type InvertedList = Vec<(ID, Loc)>;
 ///
 pub type InvertedIndex = HashMap<Token, (InvertedList, usize)>;
 
-/// This function reads an entire file into a string, count q-grams by parallel iterators,
-/// and returns a hashmap where the keys are q-gram tokens, and values are a vector of line-position pair.
+/// The lookups of an inverted index that a join makes, so that the index can be held in memory, as an [`InvertedIndex`],
+/// or on disk, as an [`crate::external::ExternalIndex`].
+pub trait IndexBackend: Debug + Sync {
+    /// The number of occurences of a token in both documents, or `None` if it's in neither of them.
+    fn count(&self, token: &str) -> Option<usize>;
+
+    /// The inverted list of a token in the second document, in increasing order of ID and location,
+    /// or `None` if it's in neither of them.
+    fn postings(&self, token: &str) -> Option<Cow<'_, [(ID, Loc)]>>;
+
+    /// The number of occurences of all tokens.
+    fn total_count(&self) -> usize;
+
+    /// The interner of the tokens of the index, if their IDs are in the order of
+    /// [`PosQGramArray::sort_by_frequency`], i.e. increasing number of occurences, and then bytes.
+    fn interner(&self) -> Option<&Interner> {
+        None
+    }
+}
+
+impl IndexBackend for InvertedIndex {
+    fn count(&self, token: &str) -> Option<usize> {
+        self.get(token).map(|(_, count)| *count)
+    }
+
+    fn postings(&self, token: &str) -> Option<Cow<'_, [(ID, Loc)]>> {
+        self.get(token)
+            .map(|(inverted_list, _)| Cow::Borrowed(inverted_list.as_slice()))
+    }
+
+    fn total_count(&self) -> usize {
+        self.values().map(|(_, count)| count).sum()
+    }
+}
+
+/// Estimated bytes of an inverted index in memory, i.e. of its tokens and postings, and of an entry of its map per token.
+pub fn index_bytes(inverted_index: &InvertedIndex) -> usize {
+    let entry: usize = mem::size_of::<(Token, (InvertedList, usize))>();
+    inverted_index
+        .iter()
+        .map(|(token, (inverted_list, _))| {
+            entry + token.len() + inverted_list.len() * mem::size_of::<(ID, Loc)>()
+        })
+        .sum()
+}
+
+/// An [`InvertedIndex`] whose tokens are interned, so that each of them is stored once, and its inverted list is
+/// looked up by its [`TokenId`].
 ///
-/// NOTE: If the input file is larger, then simply divide the input to small slices in pre-processing. This would be implemented in the future.
+/// IDs are assigned in the order of [`PosQGramArray::sort_by_frequency`], so that grams are sorted and compared by
+/// their IDs, rather than by looking up the number of occurences of their tokens on every comparison.
+#[derive(Debug, Default)]
+pub struct InternedIndex {
+    interner: Interner,
+    /// Inverted lists and numbers of occurences, indexed by the IDs of their tokens.
+    lists: Vec<(InvertedList, usize)>,
+}
+
+impl InternedIndex {
+    /// The inverted list of the token of an ID, and its number of occurences.
+    pub fn get(&self, id: TokenId) -> &(InvertedList, usize) {
+        &self.lists[id as usize]
+    }
+
+    /// Tokens with their inverted lists and numbers of occurences, in increasing order of ID.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &(InvertedList, usize))> + '_ {
+        self.interner.tokens().zip(&self.lists)
+    }
+
+    /// Number of distinct tokens.
+    pub fn len(&self) -> usize {
+        self.lists.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lists.is_empty()
+    }
+}
+
+impl From<InvertedIndex> for InternedIndex {
+    fn from(inverted_index: InvertedIndex) -> Self {
+        let mut entries: Vec<(Token, (InvertedList, usize))> = inverted_index.into_iter().collect();
+        entries.par_sort_unstable_by(|(token_a, (_, count_a)), (token_b, (_, count_b))| {
+            count_a
+                .cmp(count_b)
+                .then_with(|| token_a.as_bytes().cmp(token_b.as_bytes()))
+        });
+        let (tokens, lists): (Vec<Token>, Vec<(InvertedList, usize)>) = entries.into_iter().unzip();
+        Self {
+            interner: tokens.into_iter().collect(),
+            lists,
+        }
+    }
+}
+
+impl IndexBackend for InternedIndex {
+    fn count(&self, token: &str) -> Option<usize> {
+        self.interner.get(token).map(|id| self.get(id).1)
+    }
+
+    fn postings(&self, token: &str) -> Option<Cow<'_, [(ID, Loc)]>> {
+        self.interner
+            .get(token)
+            .map(|id| Cow::Borrowed(self.get(id).0.as_slice()))
+    }
+
+    fn total_count(&self) -> usize {
+        self.lists.iter().map(|(_, count)| count).sum()
+    }
+
+    fn interner(&self) -> Option<&Interner> {
+        Some(&self.interner)
+    }
+}
+
+/// Number of chunks per thread that inputs are split into when they're read and indexed,
+/// so that threads that finish early can pick up more work.
+const CHUNKS_PER_THREAD: usize = 4;
+
+/// Read all records, i.e. lines, of a file.
+///
+/// Lines that are not valid UTF-8 are kept as empty records, so that line numbers are preserved,
+/// and a warning is emitted for each of them.
+///
+/// The file is read at once, and split into chunks of whole lines, which are parsed in parallel.
+///
+/// # Args
+///
+/// * `path`: Path, absolute or relative, to the document to be read.
+/// * `side`: Which side of the join this document is on, used for reporting.
+/// * `policy`: How read errors and records that are not valid UTF-8 are handled.
+///
+/// # Returns
+///
+/// * When succesful, returns all the records and the warnings encountered while reading.
+pub fn read_records(
+    path: &PathBuf,
+    side: Side,
+    policy: ErrorPolicy,
+) -> Result<(Vec<String>, Vec<Warning>)> {
+    let mut data: Vec<u8> = Vec::new();
+    if let Err(e) = File::open(path)?.read_to_end(&mut data) {
+        if policy == ErrorPolicy::Abort {
+            return Err(e.into());
+        }
+        // the records before the error are only known by reading line by line
+        return read_records_sequentially(path, side, policy);
+    }
+
+    let chunks: Vec<&[u8]> = line_chunks(&data, rayon::current_num_threads() * CHUNKS_PER_THREAD);
+    let parsed: Vec<Vec<Option<String>>> =
+        chunks.par_iter().map(|chunk| parse_lines(chunk)).collect();
+    let mut records: Vec<String> = Vec::with_capacity(parsed.iter().map(Vec::len).sum());
+    let mut warnings: Vec<Warning> = Vec::new();
+    for record in parsed.into_iter().flatten() {
+        let id: ID = records.len();
+        match record {
+            Some(record) => records.push(record),
+            None if policy == ErrorPolicy::Abort => bail!(ErrorKind::NonUtf8Record(side, id)),
+            None => {
+                warnings.push(Warning::NonUtf8Record { side, id });
+                records.push(String::new());
+            }
+        }
+    }
+
+    Ok((records, warnings))
+}
+
+/// Split `data` into about `n` chunks of whole lines, each but the last one ending with its newline.
+fn line_chunks(data: &[u8], n: usize) -> Vec<&[u8]> {
+    let size: usize = (data.len() / n.max(1)).max(1);
+    let mut chunks: Vec<&[u8]> = Vec::with_capacity(n + 1);
+    let mut start: usize = 0;
+    while start < data.len() {
+        let from: usize = (start + size - 1).min(data.len());
+        let end: usize = data[from..]
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map_or(data.len(), |i| from + i + 1);
+        chunks.push(&data[start..end]);
+        start = end;
+    }
+    chunks
+}
+
+/// Parse a chunk of whole lines, as records or `None` for those that are not valid UTF-8.
+fn parse_lines(chunk: &[u8]) -> Vec<Option<String>> {
+    let chunk: &[u8] = chunk.strip_suffix(b"\n").unwrap_or(chunk);
+    chunk
+        .split(|byte| *byte == b'\n')
+        // same as `BufRead::lines()`, strip the carriage return of CRLF line endings
+        .map(|line| String::from_utf8(line.strip_suffix(b"\r").unwrap_or(line).to_vec()).ok())
+        .collect()
+}
+
+/// Same as [`read_records`], but a line at a time, so that the records before an I/O error are kept.
+fn read_records_sequentially(
+    path: &PathBuf,
+    side: Side,
+    policy: ErrorPolicy,
+) -> Result<(Vec<String>, Vec<Warning>)> {
+    let reader: BufReader<File> = BufReader::new(File::open(path)?);
+    let mut records: Vec<String> = Vec::new();
+    let mut warnings: Vec<Warning> = Vec::new();
+
+    for (id, line) in reader.split(b'\n').enumerate() {
+        let mut line: Vec<u8> = match line {
+            Ok(line) => line,
+            Err(e) if policy == ErrorPolicy::Abort => return Err(e.into()),
+            Err(e) => {
+                // the reader cannot be resynchronised with line boundaries after an error
+                warnings.push(Warning::ReadError {
+                    side,
+                    id,
+                    msg: e.to_string(),
+                });
+                break;
+            }
+        };
+        // same as `BufRead::lines()`, strip the carriage return of CRLF line endings
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        match String::from_utf8(line) {
+            Ok(record) => records.push(record),
+            Err(_) if policy == ErrorPolicy::Abort => bail!(ErrorKind::NonUtf8Record(side, id)),
+            Err(_) => {
+                warnings.push(Warning::NonUtf8Record { side, id });
+                records.push(String::new());
+            }
+        }
+    }
+
+    Ok((records, warnings))
+}
+
+/// Read all records of a file in the given format, see [`read_records`], [`crate::fastq::read_fastq`],
+/// [`crate::csv::read_csv`] and [`crate::jsonl::read_jsonl`].
+pub fn read_records_as(
+    path: &PathBuf,
+    side: Side,
+    policy: ErrorPolicy,
+    format: &InputFormat,
+) -> Result<(Vec<String>, Vec<Warning>)> {
+    match format {
+        InputFormat::Lines => read_records(path, side, policy),
+        InputFormat::Fastq(filter) => read_fastq(path, side, policy, filter),
+        InputFormat::Csv(input) => read_csv(path, side, policy, input),
+        InputFormat::JsonLines(input) => read_jsonl(path, side, policy, input),
+    }
+}
+
+/// Read all records of an input of a join, in its format and with its error policy, and prepared as it says,
+/// see [`prepare_records`].
+pub fn read_input(
+    path: &PathBuf,
+    side: Side,
+    config: &EdJoinConfig,
+) -> Result<(Vec<String>, Vec<Warning>)> {
+    let (mut records, mut warnings) =
+        read_records_as(path, side, config.error_policy, &config.input_format)?;
+    warnings.append(&mut prepare_records(&mut records, side, config)?);
+    Ok((records, warnings))
+}
+
+/// Read all records of an input of a join as [`read_input`] does, along with their original text if they're transliterated,
+/// which is what's written in the output, see [`crate::config::Normalization::transliterate`].
+#[allow(clippy::type_complexity)]
+pub fn read_input_with_originals(
+    path: &PathBuf,
+    side: Side,
+    config: &EdJoinConfig,
+) -> Result<(Vec<String>, Option<Vec<String>>, Vec<Warning>)> {
+    let (mut records, mut warnings) =
+        read_records_as(path, side, config.error_policy, &config.input_format)?;
+    let originals: Option<Vec<String>> = if config.normalization.transliterate {
+        Some(records.clone())
+    } else {
+        None
+    };
+    warnings.append(&mut prepare_records(&mut records, side, config)?);
+    Ok((records, originals, warnings))
+}
+
+/// Normalize records as a join says, and check them against its alphabet, if any.
+///
+/// Records with characters out of the alphabet are reported as warnings, in order,
+/// and skipped ones are left empty, so that they still occupy their IDs.
+pub fn prepare_records(
+    records: &mut [String],
+    side: Side,
+    config: &EdJoinConfig,
+) -> Result<Vec<Warning>> {
+    if !config.normalization.is_identity() {
+        records.iter_mut().try_for_each(|record| -> Result<()> {
+            *record = config.normalization.apply(record)?;
+            Ok(())
+        })?;
+    }
+    let mut warnings: Vec<Warning> = Vec::new();
+    if let Some(alphabet) = &config.alphabet {
+        let action: AlphabetAction = config.alphabet_action;
+        for (id, record) in records.iter_mut().enumerate() {
+            let c: char = match alphabet.first_outside(record) {
+                Some(c) => c,
+                None => continue,
+            };
+            match action {
+                AlphabetAction::Abort => bail!(ErrorKind::OutOfAlphabet(side, id, c)),
+                AlphabetAction::Skip => record.clear(),
+                AlphabetAction::Transliterate => *record = alphabet.transliterate(record),
+                AlphabetAction::Report => (),
+            }
+            warnings.push(Warning::OutOfAlphabet {
+                side,
+                id,
+                c,
+                action,
+            });
+        }
+    }
+    Ok(warnings)
+}
+
+/// This function reads two input files and builds the inverted index for them, see [`build_inverted_index`].
+///
+/// Both files are read at once, each in parallel chunks of lines, see [`read_records`].
 ///
 /// # Args
 ///
 /// * `doc_x` and `doc_y`: Path, absolute or relative, to documents to be processed.
-/// * `file_x_len` and `file_y_len`: Number of lines for each file. This is used
 /// * `q`: A tuning parameter used to generate the `q`-grams.
+/// * `mode`: The units the `q`-grams are windows of.
 ///
 /// # Returns
 ///
-/// * When succesful, returns a B-Tree map, where keys are numbers of occurences of all token, i.e. a q-gram,
-///   and values are vectors of tokens that have that number of occurences.
+/// * When succesful, returns the inverted index of `doc_y`.
 pub fn generate_inverted_index(
     doc_x: &PathBuf,
     doc_y: &PathBuf,
     q: usize,
+    mode: TokenizerMode,
 ) -> Result<InvertedIndex> {
-    let reader_y: BufReader<File> = BufReader::new(File::open(doc_y)?);
-    let mut ngram_map: InvertedIndex = HashMap::new();
+    let tokenizer: Arc<dyn Tokenizer> = mode.tokenizer();
+    if doc_x != doc_y {
+        let (read_x, read_y) = rayon::join(
+            || read_records(doc_x, Side::X, ErrorPolicy::default()),
+            || read_records(doc_y, Side::Y, ErrorPolicy::default()),
+        );
+        let ((records_x, _), (records_y, _)) = (read_x?, read_y?);
+        Ok(build_inverted_index_with(
+            Some(&records_x),
+            &records_y,
+            q,
+            &*tokenizer,
+        ))
+    } else {
+        let (records_y, _) = read_records(doc_y, Side::Y, ErrorPolicy::default())?;
+        Ok(build_inverted_index_with(None, &records_y, q, &*tokenizer))
+    }
+}
 
-    // first collect ngrams for document_y
-    let (map_y_s, map_y_r) = unbounded::<(Token, (ID, Loc))>();
-    reader_y
-        .lines()
+/// This function counts q-grams of records by parallel iterators,
+/// and returns a hashmap where the keys are q-gram tokens, and values are a vector of line-position pair.
+///
+/// Records are split into chunks, whose grams are collected in parallel and merged, so it scales with the number of threads.
+///
+/// # Args
+///
+/// * `records_x`: Records of the first document, or `None` when it's a self-join.
+/// * `records_y`: Records of the second document, which the inverted lists are built for.
+/// * `q`: A tuning parameter used to generate the `q`-grams.
+///
+/// # Returns
+///
+/// * A hashmap, where keys are q-gram tokens, and values are the inverted list of `records_y`
+///   and the number of occurences of that token in both documents.
+pub fn build_inverted_index(
+    records_x: Option<&[String]>,
+    records_y: &[String],
+    q: usize,
+) -> InvertedIndex {
+    build_inverted_index_with(records_x, records_y, q, &QGrams)
+}
+
+/// Same as [`build_inverted_index`], but with the grams generated by the given tokenizer.
+pub fn build_inverted_index_with(
+    records_x: Option<&[String]>,
+    records_y: &[String],
+    q: usize,
+    tokenizer: &dyn Tokenizer,
+) -> InvertedIndex {
+    // first collect ngrams for document_y, a chunk of records per task, and merge the maps of chunks
+    let chunk_len_y: usize = chunk_len(records_y.len());
+    let lists_y: HashMap<Token, InvertedList> = records_y
+        .par_chunks(chunk_len_y)
         .enumerate()
-        .for_each(|(line_id, line_result)| {
-            let map_y_s_clone = map_y_s.clone();
-            // `par_windows()` creates a parallel iterator on ovelapping slices of the input
-            let slice: Vec<_> = Vec::from(line_result.unwrap())
-                .par_windows(q)
-                // convert u8 to &[str], and then String, so we can use enumerate method on each qgram
-                .map(|qgrams| {
-                    std::str::from_utf8(qgrams)
-                        .expect("Error when parsing ngrams")
-                        .to_string()
-                })
-                .collect();
-            slice.into_par_iter().enumerate().for_each(|(pos, key)| {
-                map_y_s_clone.send((key, (line_id, pos))).unwrap();
-            });
+        .map(|(chunk, records)| {
+            let mut lists: HashMap<Token, InvertedList> = HashMap::new();
+            for (i, record) in records.iter().enumerate() {
+                let line_id: ID = chunk * chunk_len_y + i;
+                for qgram in tokenizer.tokenize(record, q) {
+                    lists
+                        .entry(qgram.token)
+                        .or_default()
+                        .push((line_id, qgram.loc));
+                }
+            }
+            lists
+        })
+        .reduce(HashMap::new, |a, b| {
+            merge_maps(a, b, |list, mut other| list.append(&mut other))
         });
-    drop(map_y_s);
-
-    while let Ok((key, (line_id, pos))) = map_y_r.recv() {
-        ngram_map
-            .entry(key)
-            .or_insert((Vec::new(), 0))
-            .0
-            .push((line_id, pos));
-    }
-    drop(map_y_r);
 
     // then count the occurences for doc_y only, and store it in the second slot
-    ngram_map
-        .values_mut()
-        .par_bridge()
-        .for_each(|value| value.1 = value.0.len());
+    let mut ngram_map: InvertedIndex = lists_y
+        .into_par_iter()
+        .map(|(token, list)| {
+            let count: usize = list.len();
+            (token, (list, count))
+        })
+        .collect();
 
     // Only process doc_x when it's not self-join
     // but only add the count to the second slot of the value
-    // And the channel only sends the Token
-    if doc_x != doc_y {
-        let reader_x: BufReader<File> = BufReader::new(File::open(doc_x)?);
-        let (map_x_s, map_x_r) = unbounded::<Token>();
-
-        reader_x.lines().for_each(|line_result| {
-            let map_x_s_clone = map_x_s.clone();
-            let slice: Vec<_> = Vec::from(line_result.unwrap())
-                .par_windows(q)
-                .map(|qgrams| {
-                    std::str::from_utf8(qgrams)
-                        .expect("Error when parsing ngrams")
-                        .to_string()
-                })
-                .collect();
-
-            slice.into_par_iter().for_each(|key| {
-                map_x_s_clone.send(key).unwrap();
-            });
-        });
-        drop(map_x_s);
-
-        while let Ok(key) = map_x_r.recv() {
+    if let Some(records_x) = records_x {
+        for (key, count_x) in count_qgrams(records_x, q, tokenizer) {
             let (_list_y, count) = ngram_map.entry(key).or_insert((Vec::new(), 0));
-            *count += 1;
+            *count += count_x;
         }
-        drop(map_x_r);
     }
 
-    // sort values by ID (line number)
+    // sort values by ID (line number), and then location, as chunks are merged in any order
     ngram_map.par_iter_mut().for_each(|(_, (list_y, _count))| {
-        list_y.par_sort_unstable_by_key(|(id_y, _loc_y)| {
-            *id_y //sort by id
-        });
+        list_y.par_sort_unstable();
     });
 
-    Ok(ngram_map)
+    ngram_map
+}
+
+/// Count the occurences of the grams of records, a chunk of records per task.
+pub(crate) fn count_qgrams(
+    records: &[String],
+    q: usize,
+    tokenizer: &dyn Tokenizer,
+) -> HashMap<Token, usize> {
+    records
+        .par_chunks(chunk_len(records.len()))
+        .map(|records| {
+            let mut counts: HashMap<Token, usize> = HashMap::new();
+            for record in records {
+                for qgram in tokenizer.tokenize(record, q) {
+                    *counts.entry(qgram.token).or_insert(0) += 1;
+                }
+            }
+            counts
+        })
+        .reduce(HashMap::new, |a, b| {
+            merge_maps(a, b, |count, other| *count += other)
+        })
+}
+
+/// Number of records of each chunk that an input is split into, so that idle threads can pick up chunks of others.
+fn chunk_len(records: usize) -> usize {
+    (records / (rayon::current_num_threads() * CHUNKS_PER_THREAD)).max(1)
+}
+
+/// Merge two maps of grams, the smaller into the larger one, merging values of the same gram by `merge`.
+fn merge_maps<V>(
+    mut a: HashMap<Token, V>,
+    mut b: HashMap<Token, V>,
+    merge: impl Fn(&mut V, V),
+) -> HashMap<Token, V> {
+    if a.len() < b.len() {
+        std::mem::swap(&mut a, &mut b);
+    }
+    for (key, value) in b {
+        match a.entry(key) {
+            Entry::Occupied(mut entry) => merge(entry.get_mut(), value),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+        }
+    }
+    a
 }
 
 #[cfg(test)]
@@ -297,11 +696,113 @@ mod tests {
         let testfile: PathBuf = PathBuf::from("./testset/sample_test1.txt".to_string());
         let result: String = format!(
             "{:?}",
-            generate_inverted_index(&testfile, &testfile, 2)
+            generate_inverted_index(&testfile, &testfile, 2, TokenizerMode::Bytes)
                 .unwrap()
                 .get("he")
         );
 
         assert_eq!(result, format!("{:?}", Some(([(0, 0), (1, 0), (2, 0)], 3))));
     }
+
+    #[test]
+    fn interned_index() {
+        let records: Vec<String> = ["hello", "help", "yellow", "jello"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let inverted_index: InvertedIndex = build_inverted_index(None, &records, 2);
+        let interned: InternedIndex = InternedIndex::from(inverted_index.clone());
+        assert_eq!(interned.len(), inverted_index.len());
+        assert_eq!(interned.total_count(), inverted_index.total_count());
+        for (token, (inverted_list, count)) in &inverted_index {
+            assert_eq!(interned.count(token), Some(*count));
+            assert_eq!(interned.postings(token).unwrap(), inverted_list.as_slice());
+        }
+        assert_eq!(interned.count("zz"), None);
+
+        // grams are sorted by their IDs as they are by looking up their numbers of occurences
+        for record in &records {
+            let mut sorted: PosQGramArray = PosQGramArray::from(record, 2);
+            let mut by_id: PosQGramArray = PosQGramArray::from(record, 2);
+            sorted.sort_by_frequency(&inverted_index);
+            by_id.sort_by_frequency(&interned);
+            assert_eq!(by_id.to_string(), sorted.to_string());
+        }
+
+        // dumps of the same index are the same, whatever order its maps are in
+        let rebuilt: InternedIndex = InternedIndex::from(build_inverted_index(None, &records, 2));
+        assert_eq!(format!("{:?}", rebuilt), format!("{:?}", interned));
+    }
+
+    #[test]
+    fn chunked_records() {
+        let data: &[u8] = b"hello\r\n\nw\xffrld\nspam\r\nlast";
+        for n in 1..=data.len() + 1 {
+            let chunks: Vec<&[u8]> = line_chunks(data, n);
+            assert_eq!(chunks.concat(), data);
+            assert!(chunks[..chunks.len() - 1]
+                .iter()
+                .all(|chunk| chunk.ends_with(b"\n")));
+            let records: Vec<Option<String>> =
+                chunks.iter().flat_map(|chunk| parse_lines(chunk)).collect();
+            assert_eq!(
+                records,
+                [
+                    Some("hello".to_string()),
+                    Some(String::new()),
+                    None,
+                    Some("spam".to_string()),
+                    Some("last".to_string())
+                ]
+            );
+        }
+        assert!(line_chunks(b"", 4).is_empty());
+        assert_eq!(
+            parse_lines(b"a\n\n"),
+            [Some("a".to_string()), Some(String::new())]
+        );
+    }
+
+    #[test]
+    fn non_utf8_records() {
+        let testfile: PathBuf = PathBuf::from("./testset/sample_non_utf8.txt".to_string());
+        let (records, warnings) =
+            read_records(&testfile, Side::X, ErrorPolicy::SkipRecord).unwrap();
+
+        assert_eq!(records, vec!["hello", "", "hallo"]);
+        assert_eq!(
+            warnings,
+            vec![Warning::NonUtf8Record {
+                side: Side::X,
+                id: 1
+            }]
+        );
+        assert!(read_records(&testfile, Side::X, ErrorPolicy::Abort).is_err());
+    }
+
+    #[test]
+    fn out_of_alphabet_records() {
+        let alphabet = crate::alphabet::Alphabet::parse("[a-z]").unwrap();
+        let prepare = |action: AlphabetAction| {
+            let config: EdJoinConfig = EdJoinConfig::new(2, 1).alphabet(alphabet.clone(), action);
+            let mut records: Vec<String> = vec!["hello".to_string(), "hé_llo".to_string()];
+            let warnings: Vec<Warning> = prepare_records(&mut records, Side::Y, &config).unwrap();
+            assert_eq!(
+                warnings,
+                vec![Warning::OutOfAlphabet {
+                    side: Side::Y,
+                    id: 1,
+                    c: 'é',
+                    action
+                }]
+            );
+            records
+        };
+        assert_eq!(prepare(AlphabetAction::Report), vec!["hello", "hé_llo"]);
+        assert_eq!(prepare(AlphabetAction::Skip), vec!["hello", ""]);
+        assert_eq!(
+            prepare(AlphabetAction::Transliterate),
+            vec!["hello", "hello"]
+        );
+    }
 }
@@ -0,0 +1,341 @@
+use std::{
+    fmt::{Display, Formatter},
+    ops::AddAssign,
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::alphabet::AlphabetAction;
+use crate::qgram::ID;
+use crate::verification::Verdict;
+
+/// Which input document a record comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Side {
+    /// The document which matches are generated for, i.e. `doc_x`.
+    X,
+    /// The document which matches come from, i.e. `doc_y`.
+    Y,
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Side::X => write!(f, "x"),
+            Side::Y => write!(f, "y"),
+        }
+    }
+}
+
+/// A data-quality issue that was encountered during a join, but didn't abort it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Warning {
+    /// A record that is not valid UTF-8. It's skipped, but still occupies its line number.
+    NonUtf8Record { side: Side, id: ID },
+    /// A record shorter than `q`. It has no q-gram, and thus can never be a candidate.
+    ShortRecord { side: Side, id: ID, len: usize },
+    /// A read of a FASTQ file whose mean quality, rounded down, is below the threshold. It's skipped, but still occupies its ID.
+    LowQualityRead { side: Side, id: ID, mean: usize },
+    /// A row of a CSV input of only `fields` fields, which has no join column. It's skipped, but still occupies its ID.
+    MissingField { side: Side, id: ID, fields: usize },
+    /// A record that could not be parsed, e.g. a line of a JSON Lines input without the join field.
+    /// It's skipped, but still occupies its ID.
+    MalformedRecord { side: Side, id: ID, msg: String },
+    /// Reading the input failed at this record. It and all following records are skipped.
+    ReadError { side: Side, id: ID, msg: String },
+    /// A record with a character `c` out of the alphabet, which is the first one of it, and what was done about it.
+    OutOfAlphabet {
+        side: Side,
+        id: ID,
+        c: char,
+        action: AlphabetAction,
+    },
+    /// Processing this record of `doc_x` violated an internal invariant, and it's skipped.
+    SkippedRecord { id: ID, msg: String },
+    /// This record of `doc_x` ran out of its time budget, and its `skipped` candidates that were left were not verified,
+    /// so some of its matches may be missing.
+    RecordTimeout { id: ID, skipped: usize },
+    /// The candidates of this record of `doc_x` were capped by an approximate join, and `dropped` of them were not
+    /// verified, so some of its matches may be missing.
+    CappedCandidates { id: ID, dropped: usize },
+    /// Verifying this pair violated an internal invariant, and it's skipped.
+    SkippedPair { x_id: ID, y_id: ID, msg: String },
+}
+
+impl Warning {
+    /// A short, stable name of the kind of this warning.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Warning::NonUtf8Record { .. } => "non_utf8_record",
+            Warning::ShortRecord { .. } => "short_record",
+            Warning::LowQualityRead { .. } => "low_quality_read",
+            Warning::MissingField { .. } => "missing_field",
+            Warning::MalformedRecord { .. } => "malformed_record",
+            Warning::ReadError { .. } => "read_error",
+            Warning::OutOfAlphabet { .. } => "out_of_alphabet",
+            Warning::SkippedRecord { .. } => "skipped_record",
+            Warning::RecordTimeout { .. } => "record_timeout",
+            Warning::CappedCandidates { .. } => "capped_candidates",
+            Warning::SkippedPair { .. } => "skipped_pair",
+        }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::NonUtf8Record { side, id } => {
+                write!(
+                    f,
+                    "{},{},{},skipped record that is not valid UTF-8",
+                    self.kind(),
+                    side,
+                    id
+                )
+            }
+            Warning::ShortRecord { side, id, len } => write!(
+                f,
+                "{},{},{},record of length {} has no q-gram and is never matched",
+                self.kind(),
+                side,
+                id,
+                len
+            ),
+            Warning::LowQualityRead { side, id, mean } => {
+                write!(
+                    f,
+                    "{},{},{},skipped read of mean quality {}",
+                    self.kind(),
+                    side,
+                    id,
+                    mean
+                )
+            }
+            Warning::MissingField { side, id, fields } => {
+                write!(
+                    f,
+                    "{},{},{},skipped row of {} fields without the join column",
+                    self.kind(),
+                    side,
+                    id,
+                    fields
+                )
+            }
+            Warning::MalformedRecord { side, id, msg } => {
+                write!(
+                    f,
+                    "{},{},{},skipped malformed record: {}",
+                    self.kind(),
+                    side,
+                    id,
+                    msg
+                )
+            }
+            Warning::ReadError { side, id, msg } => write!(
+                f,
+                "{},{},{},skipped the rest of the input after a read error: {}",
+                self.kind(),
+                side,
+                id,
+                msg
+            ),
+            Warning::OutOfAlphabet {
+                side,
+                id,
+                c,
+                action,
+            } => {
+                let done: &str = match action {
+                    AlphabetAction::Skip => ", skipped record",
+                    AlphabetAction::Transliterate => ", transliterated record",
+                    AlphabetAction::Report | AlphabetAction::Abort => "",
+                };
+                write!(
+                    f,
+                    "{},{},{},record has {:?}, which is out of the alphabet{}",
+                    self.kind(),
+                    side,
+                    id,
+                    c,
+                    done
+                )
+            }
+            Warning::SkippedRecord { id, msg } => {
+                write!(f, "{},x,{},skipped record: {}", self.kind(), id, msg)
+            }
+            Warning::RecordTimeout { id, skipped } => write!(
+                f,
+                "{},x,{},skipped {} candidates after running out of time",
+                self.kind(),
+                id,
+                skipped
+            ),
+            Warning::CappedCandidates { id, dropped } => write!(
+                f,
+                "{},x,{},dropped {} candidates beyond the cap of the approximate join",
+                self.kind(),
+                id,
+                dropped
+            ),
+            Warning::SkippedPair { x_id, y_id, msg } => {
+                write!(
+                    f,
+                    "{},x,{},skipped pair with y {}: {}",
+                    self.kind(),
+                    x_id,
+                    y_id,
+                    msg
+                )
+            }
+        }
+    }
+}
+
+/// What a join reports back to its caller, besides the matches.
+#[derive(Clone, Debug, Default)]
+pub struct JoinReport {
+    /// Warnings about the inputs come first, in the order of input records, `doc_x` first.
+    pub warnings: Vec<Warning>,
+    /// If the join was approximate, its recall, as estimated on a sample of `doc_x`.
+    pub estimated_recall: Option<f64>,
+    /// Path of the output file, as resolved from [`crate::config::EdJoinConfig::output_naming`],
+    /// unless it went to a sink, or of the result of the phase the join stopped after,
+    /// see [`crate::matching::phase_name`].
+    pub output: Option<PathBuf>,
+    /// Path of the clusters of the matches, if they were clustered, see [`crate::matching::clusters_name`].
+    pub clusters: Option<PathBuf>,
+    /// Statistics of the work the join did, or the run it was restored from the cache of,
+    /// which are empty if its matches were returned by [`crate::matching::ed_join_matches`].
+    pub stats: JoinStats,
+}
+
+impl JoinReport {
+    /// Number of records skipped, from either input.
+    pub fn skipped_records(&self) -> usize {
+        self.warnings
+            .iter()
+            .filter(|warning| {
+                matches!(
+                    warning,
+                    Warning::NonUtf8Record { .. }
+                        | Warning::LowQualityRead { .. }
+                        | Warning::MissingField { .. }
+                        | Warning::MalformedRecord { .. }
+                        | Warning::ReadError { .. }
+                        | Warning::SkippedRecord { .. }
+                        | Warning::OutOfAlphabet {
+                            action: AlphabetAction::Skip,
+                            ..
+                        }
+                )
+            })
+            .count()
+    }
+
+    /// Number of records with characters out of the alphabet, from either input, whether they were skipped or not.
+    pub fn out_of_alphabet(&self) -> usize {
+        self.warnings
+            .iter()
+            .filter(|warning| matches!(warning, Warning::OutOfAlphabet { .. }))
+            .count()
+    }
+
+    /// Number of reads skipped for their low quality, from either input. They're also counted by [`Self::skipped_records`].
+    pub fn low_quality_reads(&self) -> usize {
+        self.warnings
+            .iter()
+            .filter(|warning| matches!(warning, Warning::LowQualityRead { .. }))
+            .count()
+    }
+
+    /// Number of pairs of records skipped.
+    pub fn skipped_pairs(&self) -> usize {
+        self.warnings
+            .iter()
+            .filter(|warning| matches!(warning, Warning::SkippedPair { .. }))
+            .count()
+    }
+}
+
+/// Statistics of the work a join did, e.g. to tune `q` and `tau` by, or to compare joins.
+///
+/// A candidate is counted as passing a filter unless the filter pruned it, whether the filter was applied or not,
+/// e.g. for pairs of short records, which bypass the filters. Candidates whose verification was skipped, as the record
+/// ran out of time or the pair failed, pass none of them. With reverse complements, each strand is counted separately.
+///
+/// ```
+/// use ed_join::config::EdJoinConfig;
+/// use ed_join::matching::ed_join_with_config;
+/// use ed_join::report::JoinStats;
+/// use ed_join::sink::MemorySink;
+/// # let doc = std::env::temp_dir().join(format!("ed_join_stats_doctest_{}.txt", std::process::id()));
+/// # std::fs::write(&doc, "Hello World\nHallo World\nHello Word\n").unwrap();
+///
+/// let report = ed_join_with_config(&doc, &doc, &EdJoinConfig::new(2, 1).output(MemorySink::default())).unwrap();
+/// let stats: &JoinStats = &report.stats;
+/// assert!(stats.candidates >= stats.passed_count_filter && stats.passed_content_filter >= 2);
+/// assert_eq!(stats.edit_distances, stats.passed_content_filter);
+/// assert_eq!(stats.phases.iter().map(|(name, _)| *name).collect::<Vec<_>>(), ["read_inputs", "index", "join", "write_output"]);
+/// # std::fs::remove_file(&doc).unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JoinStats {
+    /// Candidates generated, or loaded, after tombstoned records were masked.
+    pub candidates: usize,
+    /// Candidates which the count filter didn't prune.
+    pub passed_count_filter: usize,
+    /// Candidates which neither the count filter nor the location-based one pruned.
+    pub passed_location_filter: usize,
+    /// Candidates which no filter pruned, i.e. whose edit distance was computed or known.
+    pub passed_content_filter: usize,
+    /// Exact edit distances computed, i.e. those of candidates which passed all filters,
+    /// but not those known from earlier joins of a warm start.
+    pub edit_distances: usize,
+    /// Wall time of each phase of the join, in the order they ran, by their names in a timeline, e.g. `index`.
+    pub phases: Vec<(&'static str, Duration)>,
+}
+
+impl JoinStats {
+    /// Count a candidate by the verdict of its verification, where `computed` is whether its edit distance was computed.
+    pub(crate) fn add_verdict(&mut self, verdict: &Verdict, computed: bool) {
+        let (count, location, content): (bool, bool, bool) = match verdict {
+            Verdict::LengthFilter | Verdict::PrefixFilter | Verdict::CountFilter { .. } => {
+                (false, false, false)
+            }
+            Verdict::LocationFilter { .. } => (true, false, false),
+            Verdict::ContentFilter { .. } => (true, true, false),
+            Verdict::Rejected { .. } | Verdict::Matched { .. } => (true, true, true),
+        };
+        self.passed_count_filter += usize::from(count);
+        self.passed_location_filter += usize::from(location);
+        self.passed_content_filter += usize::from(content);
+        self.edit_distances += usize::from(content && computed);
+    }
+}
+
+/// Counts are added, and phases appended.
+impl AddAssign<&JoinStats> for JoinStats {
+    fn add_assign(&mut self, other: &JoinStats) {
+        self.candidates += other.candidates;
+        self.passed_count_filter += other.passed_count_filter;
+        self.passed_location_filter += other.passed_location_filter;
+        self.passed_content_filter += other.passed_content_filter;
+        self.edit_distances += other.edit_distances;
+        self.phases.extend_from_slice(&other.phases);
+    }
+}
+
+/// One statistic per line, e.g. `candidates: 42`, and phases in seconds, e.g. `index: 0.012s`.
+impl Display for JoinStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "candidates: {}", self.candidates)?;
+        writeln!(f, "passed count filter: {}", self.passed_count_filter)?;
+        writeln!(f, "passed location filter: {}", self.passed_location_filter)?;
+        writeln!(f, "passed content filter: {}", self.passed_content_filter)?;
+        write!(f, "edit distances: {}", self.edit_distances)?;
+        for (name, elapsed) in &self.phases {
+            write!(f, "\n{}: {:.3}s", name, elapsed.as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap},
+    fs::{self, File},
+    io::{BufWriter, Write},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::approx::Pruning;
+use crate::errors::*;
+use crate::matching::generate_candidates_pruned;
+use crate::persist::escape_field;
+use crate::qgram::{IndexBackend, ID};
+use crate::report::{Side, Warning};
+use crate::tokenizer::Tokenizer;
+
+/// Number of the slowest records of `doc_x` in a debug sample, besides those of warnings.
+pub const SLOWEST_RECORDS: usize = 10;
+
+/// Scrambles records such that they can't be read, but are still joined as the originals are.
+///
+/// Characters are replaced consistently, i.e. equal characters by equal ones and different ones by different ones,
+/// so edit distances and shared q-grams of scrambled records are those of the originals. ASCII lowercase and uppercase
+/// letters and digits are permuted randomly within their class. Other letters and digits are replaced by letters of the
+/// same length in UTF-8, in the order they're first seen, and everything else, e.g. spaces and punctuation, is kept.
+///
+/// ```
+/// use ed_join::sample::Scrambler;
+///
+/// let mut scrambler = Scrambler::new(42);
+/// let (hello, hallo) = (scrambler.scramble("Hello, 42!"), scrambler.scramble("Hallo, 42!"));
+/// assert_ne!(hello, "Hello, 42!");
+/// assert_eq!((&hello[..1], &hello[2..], &hello[5..7]), (&hallo[..1], &hallo[2..], ", "));
+/// assert!(hello.as_bytes()[0].is_ascii_uppercase() && hello.as_bytes()[7].is_ascii_digit());
+/// ```
+#[derive(Clone, Debug)]
+pub struct Scrambler {
+    lower: [u8; 26],
+    upper: [u8; 26],
+    digits: [u8; 10],
+    /// Replacements of other letters and digits.
+    others: HashMap<char, char>,
+    /// The next code point which a letter of each length in UTF-8 may be replaced by.
+    next: [u32; 5],
+}
+
+impl Scrambler {
+    /// A scrambler whose permutations are drawn from a seed, which the same seed draws the same.
+    pub fn new(seed: u64) -> Self {
+        // splitmix64, which is plenty for shuffling a few letters
+        let mut state: u64 = seed;
+        let mut shuffle = |slice: &mut [u8]| {
+            for i in (1..slice.len()).rev() {
+                state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+                let mut z: u64 = state;
+                z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+                slice.swap(i, ((z ^ (z >> 31)) % (i as u64 + 1)) as usize);
+            }
+        };
+        let mut lower: [u8; 26] = *b"abcdefghijklmnopqrstuvwxyz";
+        let mut upper: [u8; 26] = *b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+        let mut digits: [u8; 10] = *b"0123456789";
+        shuffle(&mut lower);
+        shuffle(&mut upper);
+        shuffle(&mut digits);
+        Self {
+            lower,
+            upper,
+            digits,
+            others: HashMap::new(),
+            // Latin Extended-A, CJK Unified Ideographs, and CJK Extension B
+            next: [0, 0, 0x100, 0x4e00, 0x2_0000],
+        }
+    }
+
+    /// Scramble a record, consistently with all records scrambled before it.
+    pub fn scramble(&mut self, text: &str) -> String {
+        text.chars().map(|c| self.scramble_char(c)).collect()
+    }
+
+    fn scramble_char(&mut self, c: char) -> char {
+        match c {
+            'a'..='z' => self.lower[c as usize - 'a' as usize] as char,
+            'A'..='Z' => self.upper[c as usize - 'A' as usize] as char,
+            '0'..='9' => self.digits[c as usize - '0' as usize] as char,
+            c if c.is_alphanumeric() => {
+                if let Some(replacement) = self.others.get(&c) {
+                    return *replacement;
+                }
+                let len: usize = c.len_utf8();
+                let replacement: char = loop {
+                    let code: u32 = self.next[len];
+                    self.next[len] += 1;
+                    match char::from_u32(code) {
+                        Some(replacement)
+                            if replacement.is_alphabetic() && replacement.len_utf8() == len =>
+                        {
+                            break replacement
+                        }
+                        // the ranges of a length run out after thousands of distinct letters, which are kept
+                        _ if code >= [0, 0, 0x800, 0x1_0000, 0x11_0000][len] => break c,
+                        _ => (),
+                    }
+                };
+                self.others.insert(c, replacement);
+                replacement
+            }
+            c => c,
+        }
+    }
+}
+
+/// Records of a join which are sampled as it goes, i.e. the slowest ones and those whose join failed,
+/// see [`crate::config::EdJoinConfig::debug_sample`].
+#[derive(Debug, Default)]
+pub(crate) struct SampleCollector {
+    slowest: Mutex<BinaryHeap<Reverse<(Duration, ID)>>>,
+    failed: Mutex<BTreeSet<ID>>,
+}
+
+impl SampleCollector {
+    /// Record how long a record of `doc_x` took, which is kept if it's one of the slowest.
+    pub(crate) fn timed(&self, x_id: ID, elapsed: Duration) {
+        let mut slowest = self.slowest.lock().unwrap();
+        slowest.push(Reverse((elapsed, x_id)));
+        if slowest.len() > SLOWEST_RECORDS {
+            slowest.pop();
+        }
+    }
+
+    /// Record a record of `doc_x` whose join failed, and aborted the join.
+    pub(crate) fn failed(&self, x_id: ID) {
+        self.failed.lock().unwrap().insert(x_id);
+    }
+}
+
+/// Write a debug sample of a join to a directory, which is created if it doesn't exist.
+///
+/// The records of `doc_x` sampled are those of the [`SampleCollector`], and those of the warnings of records that were
+/// skipped or ran out of time, and which pairs were skipped. Each one comes with all of its candidates in `doc_y`,
+/// generated again, so that the sample reproduces how they were joined. All records are scrambled by a [`Scrambler`]
+/// with a random seed, as they were joined, i.e. after parsing and normalization.
+///
+/// The sample is `records.txt` for a self-join, and `x.txt` and `y.txt` otherwise, and `sample.tsv` has the line numbers
+/// of the records in them and in the inputs, and why they're sampled.
+///
+/// # Return
+///
+/// The number of records sampled.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn write_sample(
+    dir: &Path,
+    collector: &SampleCollector,
+    warnings: &[Warning],
+    x_vec: &[String],
+    y_vec: &[String],
+    index: &dyn IndexBackend,
+    q: usize,
+    tau: usize,
+    self_join: bool,
+    tokenizer: &dyn Tokenizer,
+) -> Result<usize> {
+    // why each record is sampled, keyed by its side and line number, where a self-join has only one side
+    let y_side: Side = if self_join { Side::X } else { Side::Y };
+    let mut sampled: BTreeMap<(Side, ID), Vec<String>> = BTreeMap::new();
+    let mut sample =
+        |side: Side, id: ID, reason: String| sampled.entry((side, id)).or_default().push(reason);
+    for x_id in collector.failed.lock().unwrap().iter() {
+        sample(Side::X, *x_id, "failed".to_string());
+    }
+    for Reverse((elapsed, x_id)) in collector.slowest.lock().unwrap().iter() {
+        sample(
+            Side::X,
+            *x_id,
+            format!("slow, {:.3} ms", elapsed.as_secs_f64() * 1000.0),
+        );
+    }
+    for warning in warnings {
+        match warning {
+            Warning::SkippedRecord { id, .. } | Warning::RecordTimeout { id, .. } => {
+                sample(Side::X, *id, warning.kind().to_string())
+            }
+            Warning::SkippedPair { x_id, y_id, .. } => {
+                sample(Side::X, *x_id, warning.kind().to_string());
+                sample(y_side, *y_id, format!("{} of x:{}", warning.kind(), x_id));
+            }
+            _ => (),
+        }
+    }
+
+    let x_ids: Vec<ID> = sampled
+        .keys()
+        .filter(|(side, _)| *side == Side::X)
+        .map(|(_, id)| *id)
+        .collect();
+    for x_id in x_ids {
+        // the candidates of a record that failed may fail again, and then it has none
+        let candidates: Vec<ID> = panic::catch_unwind(AssertUnwindSafe(|| {
+            generate_candidates_pruned(
+                x_id,
+                &x_vec[x_id],
+                y_vec,
+                index,
+                q,
+                tau,
+                self_join,
+                &Pruning::default(),
+                tokenizer,
+            )
+            .1
+        }))
+        .unwrap_or_default();
+        for y_id in candidates {
+            sampled
+                .entry((y_side, y_id))
+                .or_default()
+                .push(format!("candidate of x:{}", x_id));
+        }
+    }
+
+    fs::create_dir_all(dir)?;
+    let seed: u64 = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_nanos() as u64)
+        ^ u64::from(std::process::id());
+    let mut scrambler: Scrambler = Scrambler::new(seed);
+    // a self-join has all records on the side of `doc_x`
+    let names: &[&str] = if self_join {
+        &["records.txt"]
+    } else {
+        &["x.txt", "y.txt"]
+    };
+    let mut files: Vec<BufWriter<File>> = names
+        .iter()
+        .map(|name| File::create(dir.join(name)).map(BufWriter::new))
+        .collect::<std::io::Result<_>>()?;
+    let mut lines: [ID; 2] = [0, 0];
+    let mut index_file: BufWriter<File> = BufWriter::new(File::create(dir.join("sample.tsv"))?);
+    writeln!(index_file, "file\tline\tside\tid\treason")?;
+    for ((side, id), reasons) in &sampled {
+        let (i, text): (usize, &str) = match side {
+            Side::X => (0, &x_vec[*id]),
+            Side::Y => (1, &y_vec[*id]),
+        };
+        writeln!(files[i], "{}", scrambler.scramble(text))?;
+        writeln!(
+            index_file,
+            "{}\t{}\t{}\t{}\t{}",
+            names[i],
+            lines[i],
+            side,
+            id,
+            escape_field(&reasons.join("; "))
+        )?;
+        lines[i] += 1;
+    }
+    files.iter_mut().try_for_each(|file| file.flush())?;
+    index_file.flush()?;
+
+    Ok(sampled.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::EdJoinConfig;
+    use crate::matching::ed_join_matches;
+    use crate::testutil::TempDir;
+    use std::path::PathBuf;
+
+    #[test]
+    fn debug_sample() {
+        let temp: TempDir = TempDir::new("debug_sample");
+        let (dir, doc): (PathBuf, PathBuf) = (temp.join("sample"), temp.join("records.txt"));
+        std::fs::write(&doc, "Hello World\nHallo World\nJello Word\nzzzz\n").unwrap();
+
+        // with fewer records than the slowest kept, all are sampled, and the sample joins as the records did
+        let config: EdJoinConfig = EdJoinConfig::new(2, 2).debug_sample(&dir);
+        let joined = ed_join_matches(&doc, &doc, &EdJoinConfig::new(2, 2)).unwrap();
+        crate::matching::ed_join_with_config(
+            &doc,
+            &doc,
+            &config.output(crate::sink::MemorySink::default()),
+        )
+        .unwrap();
+        let records: String = std::fs::read_to_string(dir.join("records.txt")).unwrap();
+        // the permutations are random, but each character keeps its class
+        let class = |c: char| (c.is_ascii_lowercase(), c.is_ascii_uppercase(), c == ' ');
+        let original: String = std::fs::read_to_string(&doc).unwrap();
+        assert_eq!(
+            records.chars().map(class).collect::<Vec<_>>(),
+            original.chars().map(class).collect::<Vec<_>>()
+        );
+        let sampled = ed_join_matches(
+            &dir.join("records.txt"),
+            &dir.join("records.txt"),
+            &EdJoinConfig::new(2, 2),
+        )
+        .unwrap();
+        assert_eq!(sampled.pairs, joined.pairs);
+
+        let index: String = std::fs::read_to_string(dir.join("sample.tsv")).unwrap();
+        assert!(
+            index.starts_with("file\tline\tside\tid\treason\nrecords.txt\t0\tx\t0\tslow, "),
+            "{}",
+            index
+        );
+        assert_eq!(index.lines().count(), 5);
+    }
+}
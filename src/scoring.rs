@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use crate::output::MatchRecord;
+use crate::qgram::{IndexBackend, PosQGramArray, Token};
+use crate::tokenizer::{QGrams, Tokenizer};
+
+/// What a [`Scorer`] may use besides the matched pair itself.
+#[derive(Clone, Copy, Debug)]
+pub struct ScoreContext<'a> {
+    /// The inverted index of the join, which holds the number of occurences of every q-gram.
+    pub inverted_index: &'a dyn IndexBackend,
+    /// Total number of occurences of all q-grams.
+    pub total_qgrams: usize,
+    pub q: usize,
+    pub tau: usize,
+    /// The tokenizer the index was built with.
+    pub tokenizer: &'a dyn Tokenizer,
+}
+
+impl<'a> ScoreContext<'a> {
+    pub fn new(inverted_index: &'a dyn IndexBackend, q: usize, tau: usize) -> Self {
+        Self {
+            inverted_index,
+            total_qgrams: inverted_index.total_count(),
+            q,
+            tau,
+            tokenizer: &QGrams,
+        }
+    }
+
+    /// Use the given tokenizer instead of [`QGrams`], which must be the one the index was built with.
+    pub fn with_tokenizer(mut self, tokenizer: &'a dyn Tokenizer) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    /// Inverse document frequency of a q-gram, i.e. how rare it is, as `ln(1 + total / count)`.
+    ///
+    /// A q-gram that isn't in the index, e.g. one of a skipped record, is as rare as one occuring once.
+    pub fn idf(&self, token: &str) -> f64 {
+        let count: usize = self.inverted_index.count(token).unwrap_or(1).max(1);
+        (1.0 + self.total_qgrams as f64 / count as f64).ln()
+    }
+}
+
+/// A function that scores how likely a matched pair is a true duplicate, as a confidence in `[0, 1]`.
+///
+/// Implement this to replace the [`DefaultScorer`], e.g. with a model calibrated on labelled pairs.
+pub trait Scorer: Debug + Send + Sync {
+    fn score(&self, m: &MatchRecord, context: &ScoreContext) -> f64;
+
+    /// The name of the scorer and its parameters, which is part of the key of cached runs,
+    /// see [`crate::cache::CacheEntry`].
+    ///
+    /// It's `None` by default, so that runs with a scorer of your own aren't cached,
+    /// since the cache cannot tell when its code changed.
+    fn cache_key(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Scores a pair by its similarity and the rarity of the q-grams the records share.
+///
+/// The confidence is [`MatchRecord::score`] multiplied by the fraction of the information of the q-grams of `x`,
+/// weighted by [`ScoreContext::idf`], which is also in `y`. Pairs which only share common q-grams,
+/// such as short records differing in a rare character, are thus ranked below pairs that share rare ones.
+///
+/// It's a heuristic rather than a calibrated probability, but it orders matches better than the edit distance alone.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultScorer;
+
+impl Scorer for DefaultScorer {
+    fn score(&self, m: &MatchRecord, context: &ScoreContext) -> f64 {
+        let mut qgrams_y: HashMap<Token, usize> = HashMap::new();
+        PosQGramArray::tokenize(m.y_text, context.q, context.tokenizer)
+            .iter()
+            .for_each(|qgram| {
+                *qgrams_y.entry(qgram.token.clone()).or_insert(0) += 1;
+            });
+
+        let (mut shared, mut total): (f64, f64) = (0.0, 0.0);
+        PosQGramArray::tokenize(m.x_text, context.q, context.tokenizer)
+            .iter()
+            .for_each(|qgram| {
+                let idf: f64 = context.idf(&qgram.token);
+                total += idf;
+                if let Some(count) = qgrams_y.get_mut(&qgram.token).filter(|count| **count > 0) {
+                    *count -= 1;
+                    shared += idf;
+                }
+            });
+        let overlap: f64 = if total > 0.0 { shared / total } else { 1.0 };
+
+        m.score() * overlap
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some("default".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qgram::{build_inverted_index, InvertedIndex};
+
+    #[test]
+    fn default_scorer() {
+        let records: Vec<String> = ["hello", "hallo", "jello", "yellow"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let inverted_index: InvertedIndex = build_inverted_index(None, &records, 2);
+        let context: ScoreContext = ScoreContext::new(&inverted_index, 2, 1);
+        let pair = |x: usize, y: usize| MatchRecord {
+            x_id: x,
+            y_id: y,
+            ed: 1,
+            x_text: &records[x],
+            y_text: &records[y],
+            confidence: None,
+            x_fields: &[],
+            y_fields: &[],
+        };
+
+        let identical = DefaultScorer.score(
+            &MatchRecord {
+                ed: 0,
+                ..pair(0, 0)
+            },
+            &context,
+        );
+        assert!((identical - 1.0).abs() < 1e-9);
+        // `hello` and `jello` share `ello`, which is common, while `hallo` differs in the middle
+        let (jello, hallo) = (
+            DefaultScorer.score(&pair(0, 2), &context),
+            DefaultScorer.score(&pair(0, 1), &context),
+        );
+        assert!(0.0 < hallo && hallo < jello && jello < 1.0);
+    }
+}
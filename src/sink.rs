@@ -0,0 +1,515 @@
+use std::{
+    fmt::{self, Debug},
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use crate::hash::Sha256;
+use crate::output::OutputFormat;
+use crate::persist::escape_field;
+use crate::qgram::ID;
+
+/// Where the output of a join is written, instead of a file named after `doc_x` in the current directory.
+///
+/// A sink is opened once per join, which then writes all of its output to the writer and flushes it:
+///
+/// ```
+/// use ed_join::sink::{MemorySink, OutputSink};
+/// use std::io::Write;
+///
+/// let sink = MemorySink::default();
+/// sink.open().unwrap().write_all(b"0,1,1\n").unwrap();
+/// assert_eq!(sink.contents(), b"0,1,1\n");
+/// ```
+pub trait OutputSink: Debug + Send + Sync {
+    /// Open the writer which the output of a join is written to.
+    fn open(&self) -> io::Result<Box<dyn Write + Send>>;
+
+    /// Open the writer of a join, which tells the sink about its output, see [`SinkContext`]. It's [`Self::open`] by default.
+    fn open_join(&self, context: &SinkContext) -> io::Result<Box<dyn Write + Send>> {
+        let _ = context;
+        self.open()
+    }
+}
+
+/// What a join tells the sink it writes to about its output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SinkContext {
+    /// Hash of the parameters that affect the output, or `None` if they can't be told apart,
+    /// e.g. with a tokenizer of your own, see [`crate::tokenizer::Tokenizer::cache_key`].
+    pub fingerprint: Option<String>,
+    /// Format of the output.
+    pub format: OutputFormat,
+    /// Field delimiter of the output, see [`crate::config::EdJoinConfig::delimiter`].
+    pub delimiter: u8,
+}
+
+impl SinkContext {
+    /// The output of a plain join, as [`RetrySink`] assumes if it's opened outside of a join:
+    /// CSV rows of `x_id,y_id,ed`, without a fingerprint.
+    fn csv() -> Self {
+        Self {
+            fingerprint: None,
+            format: OutputFormat::Csv,
+            delimiter: b',',
+        }
+    }
+}
+
+/// Write the output to a file at the given path, which is created or truncated, e.g. `/dev/stdout`.
+///
+/// Unlike the default output, the file is written in place, rather than renamed once the join succeeded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileSink(pub PathBuf);
+
+impl OutputSink for FileSink {
+    fn open(&self) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(File::create(&self.0)?))
+    }
+}
+
+/// Write the output to the standard output of the process, e.g. for piping.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdoutSink;
+
+impl OutputSink for StdoutSink {
+    fn open(&self) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(io::stdout()))
+    }
+}
+
+/// Keep the output in memory, e.g. for tests. Clones share the same buffer, and every join appends to it.
+#[derive(Clone, Debug, Default)]
+pub struct MemorySink(Arc<Mutex<Vec<u8>>>);
+
+impl MemorySink {
+    /// All bytes written so far.
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// The writer of a [`MemorySink`], appending to its shared buffer.
+struct MemoryWriter(Arc<Mutex<Vec<u8>>>);
+
+impl Write for MemoryWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn open(&self) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(MemoryWriter(Arc::clone(&self.0))))
+    }
+}
+
+/// Write the output to any writer, e.g. a socket or a compressor.
+///
+/// The writer is handed over to the first join that opens the sink, and opening it again is an error.
+pub struct WriteSink<W: Write + Send + 'static>(Mutex<Option<W>>);
+
+impl<W: Write + Send + 'static> WriteSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self(Mutex::new(Some(writer)))
+    }
+}
+
+impl<W: Write + Send + 'static> Debug for WriteSink<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteSink").finish_non_exhaustive()
+    }
+}
+
+impl<W: Write + Send + 'static> OutputSink for WriteSink<W> {
+    fn open(&self) -> io::Result<Box<dyn Write + Send>> {
+        match self.0.lock().unwrap().take() {
+            Some(writer) => Ok(Box::new(writer)),
+            None => Err(io::Error::other(
+                "The writer of the sink was already taken by another join",
+            )),
+        }
+    }
+}
+
+/// A sink of rows, e.g. a table of a database or a topic of a queue, which is sent each line of the output separately,
+/// see [`RetrySink`].
+pub trait RowSink: Debug + Send + Sync {
+    /// Send a row, without its line break, under a key which identifies it, so that a row sent again under the same key
+    /// can replace the one sent before, e.g. by an upsert, rather than duplicate it.
+    fn send(&self, key: &str, row: &[u8]) -> io::Result<()>;
+
+    /// Send a line of the output that's not a match, e.g. a header or a bracket of a JSON array, without its line break.
+    /// It's dropped by default, and isn't retried.
+    fn send_unkeyed(&self, line: &[u8]) -> io::Result<()> {
+        let _ = line;
+        Ok(())
+    }
+}
+
+/// Write the output to a [`RowSink`], retrying the rows whose sending fails, so that failures of the network in the
+/// middle of a join don't drop its matches.
+///
+/// Each match of the output is a row, whose idempotency key is the SHA-256 of the fingerprint of the parameters
+/// of the join, and the line numbers of its pair. So a row that's retried, or sent again by a rerun of the same join,
+/// has the same key, even if its other fields changed. The fingerprint is the hash of the parameters of the join,
+/// see [`SinkContext::fingerprint`], unless it's given to [`RetrySink::params`], which is required if the join has none.
+/// Lines that aren't matches, such as a header, are sent by [`RowSink::send_unkeyed`].
+///
+/// A row that's failed is retried after a backoff, which doubles after every attempt. Once all attempts failed,
+/// the row is appended to the dead-letter file as its key and the escaped row, separated by a tab,
+/// or the join fails if there's none. Keys are hashed with the `hashing` feature, without which no row can be sent.
+/// Only output of a match per line can be sent, i.e. CSV, TSV, COO, JSON lines or JSON.
+///
+/// ```
+/// use ed_join::sink::{OutputSink, RetrySink, RowSink};
+/// use std::{io::{self, Write}, sync::Mutex, time::Duration};
+///
+/// // a table that's unavailable every other time
+/// #[derive(Debug, Default)]
+/// struct Table(Mutex<(usize, Vec<String>)>);
+///
+/// impl RowSink for Table {
+///     fn send(&self, _key: &str, row: &[u8]) -> io::Result<()> {
+///         let mut table = self.0.lock().unwrap();
+///         table.0 += 1;
+///         if table.0 % 2 == 1 {
+///             return Err(io::Error::other("unavailable"));
+///         }
+///         table.1.push(String::from_utf8_lossy(row).into_owned());
+///         Ok(())
+///     }
+/// }
+///
+/// let sink = RetrySink::new(Table::default()).retry(2, Duration::ZERO).params("q=2 tau=1");
+/// sink.open().unwrap().write_all(b"0,1,1\n0,3,0\n").unwrap();
+/// assert_eq!(sink.row_sink().0.lock().unwrap().1, ["0,1,1", "0,3,0"]);
+/// ```
+#[derive(Debug)]
+pub struct RetrySink<S: RowSink> {
+    sink: Arc<S>,
+    attempts: usize,
+    backoff: Duration,
+    params: Option<String>,
+    dead_letter: Option<PathBuf>,
+}
+
+impl<S: RowSink> RetrySink<S> {
+    /// Send rows to a sink, with 5 attempts per row and a first backoff of 100 ms, and no dead-letter file.
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink: Arc::new(sink),
+            attempts: 5,
+            backoff: Duration::from_millis(100),
+            params: None,
+            dead_letter: None,
+        }
+    }
+
+    /// Attempt to send each row this many times, which is at least once, waiting `backoff` after the first failure.
+    pub fn retry(mut self, attempts: usize, backoff: Duration) -> Self {
+        self.attempts = attempts.max(1);
+        self.backoff = backoff;
+        self
+    }
+
+    /// Fingerprint of the parameters of the join, e.g. `q=2 tau=1`, which the idempotency keys of rows depend on,
+    /// so that rows of joins of different parameters in the same sink don't replace each other.
+    /// It's the one of the join by default, and must not be empty.
+    pub fn params(mut self, params: impl Into<String>) -> Self {
+        self.params = Some(params.into());
+        self
+    }
+
+    /// Append rows of which all attempts failed to this file, instead of failing the join.
+    pub fn dead_letter(mut self, path: impl Into<PathBuf>) -> Self {
+        self.dead_letter = Some(path.into());
+        self
+    }
+
+    /// The sink the rows are sent to.
+    pub fn row_sink(&self) -> &S {
+        &self.sink
+    }
+}
+
+impl<S: RowSink + 'static> OutputSink for RetrySink<S> {
+    /// Open the sink outside of a join, for CSV rows of `x_id,y_id,ed`, which needs [`RetrySink::params`].
+    fn open(&self) -> io::Result<Box<dyn Write + Send>> {
+        self.open_join(&SinkContext::csv())
+    }
+
+    fn open_join(&self, context: &SinkContext) -> io::Result<Box<dyn Write + Send>> {
+        let params: String =
+            match self.params.as_ref().or(context.fingerprint.as_ref()) {
+                Some(params) if !params.is_empty() => params.clone(),
+                Some(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "The parameters of a RetrySink must not be empty",
+                    ))
+                }
+                None => return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The join has no fingerprint of its parameters, so a RetrySink needs `params`",
+                )),
+            };
+        let rows: RowLayout = match context.format {
+            OutputFormat::Csv | OutputFormat::CsvHeader | OutputFormat::Coo(_) => {
+                RowLayout::Delimited(context.delimiter)
+            }
+            OutputFormat::Tsv => RowLayout::Delimited(b'\t'),
+            OutputFormat::JsonLines | OutputFormat::Json => RowLayout::Json,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "A RetrySink only sends CSV, TSV, COO, JSON lines or JSON output, of a match per line",
+                ))
+            }
+        };
+        Ok(Box::new(RetryWriter {
+            sink: Arc::clone(&self.sink),
+            attempts: self.attempts,
+            backoff: self.backoff,
+            params,
+            rows,
+            dead_letter: self.dead_letter.clone(),
+            line: Vec::new(),
+        }))
+    }
+}
+
+/// Where the line numbers of the pair of a match are in its line of output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RowLayout {
+    /// The first two fields, e.g. `x_id,y_id,ed`.
+    Delimited(u8),
+    /// The first two members of an object, e.g. `{"x_id":0,"y_id":1,...}`, which may be followed by a comma.
+    Json,
+}
+
+impl RowLayout {
+    /// The line numbers of the pair of a line of output, or `None` if it's not a match, e.g. a header.
+    fn pair(self, line: &[u8]) -> Option<(ID, ID)> {
+        let id = |field: &[u8]| -> Option<ID> { std::str::from_utf8(field).ok()?.parse().ok() };
+        match self {
+            RowLayout::Delimited(delimiter) => {
+                let mut fields = line.split(|b| *b == delimiter);
+                Some((id(fields.next()?)?, id(fields.next()?)?))
+            }
+            RowLayout::Json => {
+                let digits = |s: &[u8]| s.iter().take_while(|b| b.is_ascii_digit()).count();
+                let rest: &[u8] = line.strip_prefix(b"{\"x_id\":")?;
+                let (x_id, rest) = rest.split_at(digits(rest));
+                let rest: &[u8] = rest.strip_prefix(b",\"y_id\":")?;
+                Some((id(x_id)?, id(&rest[..digits(rest)])?))
+            }
+        }
+    }
+}
+
+/// The writer of a [`RetrySink`], which sends every complete line of a match as a row.
+struct RetryWriter<S: RowSink> {
+    sink: Arc<S>,
+    attempts: usize,
+    backoff: Duration,
+    /// Fingerprint of the parameters of the join, which is never empty.
+    params: String,
+    rows: RowLayout,
+    dead_letter: Option<PathBuf>,
+    /// The line written so far, without its line break.
+    line: Vec<u8>,
+}
+
+impl<S: RowSink> RetryWriter<S> {
+    /// Send the current line, retrying it if it's a match, or write it to the dead-letter file once all attempts failed.
+    fn send_line(&mut self) -> io::Result<()> {
+        let (x_id, y_id): (ID, ID) = match self.rows.pair(&self.line) {
+            Some(pair) => pair,
+            None => {
+                self.sink.send_unkeyed(&self.line)?;
+                self.line.clear();
+                return Ok(());
+            }
+        };
+        let mut hasher: Sha256 =
+            Sha256::new().map_err(|e| io::Error::new(io::ErrorKind::Unsupported, e.to_string()))?;
+        hasher.update(self.params.as_bytes());
+        hasher.update(format!("\n{}\t{}", x_id, y_id).as_bytes());
+        let key: String = hasher.hex_digest();
+
+        let mut backoff: Duration = self.backoff;
+        let mut result: io::Result<()> = self.sink.send(&key, &self.line);
+        for _ in 1..self.attempts {
+            if result.is_ok() {
+                break;
+            }
+            thread::sleep(backoff);
+            backoff *= 2;
+            result = self.sink.send(&key, &self.line);
+        }
+        match (result, &self.dead_letter) {
+            (Ok(()), _) => (),
+            (Err(e), None) => return Err(e),
+            (Err(_e), Some(dead_letter)) => {
+                #[cfg(feature = "logging")]
+                warn!(
+                    "A row failed {} times, the last by {}, and is written to {}",
+                    self.attempts,
+                    _e,
+                    dead_letter.display()
+                );
+                let mut file: File = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(dead_letter)?;
+                writeln!(
+                    file,
+                    "{}\t{}",
+                    key,
+                    escape_field(&String::from_utf8_lossy(&self.line))
+                )?;
+            }
+        }
+        self.line.clear();
+        Ok(())
+    }
+}
+
+impl<S: RowSink> Write for RetryWriter<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for line in buf.split_inclusive(|b| *b == b'\n') {
+            match line.strip_suffix(b"\n") {
+                Some(line) => {
+                    self.line.extend_from_slice(line);
+                    self.send_line()?;
+                }
+                None => self.line.extend_from_slice(line),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: RowSink> Drop for RetryWriter<S> {
+    // the output ends with a line break, so this is only a last line written without one
+    fn drop(&mut self) {
+        if !self.line.is_empty() {
+            let _ = self.send_line();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "hashing"))]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    /// A table which always rejects rows containing `bad`, and keeps the keys and rows of the others,
+    /// and the lines that aren't rows.
+    #[derive(Debug, Default)]
+    struct Table(Mutex<Vec<(String, String)>>, Mutex<Vec<String>>);
+
+    impl RowSink for Table {
+        fn send(&self, key: &str, row: &[u8]) -> io::Result<()> {
+            let row: String = String::from_utf8_lossy(row).into_owned();
+            if row.contains("bad") {
+                return Err(io::Error::other("rejected"));
+            }
+            self.0.lock().unwrap().push((key.to_string(), row));
+            Ok(())
+        }
+
+        fn send_unkeyed(&self, line: &[u8]) -> io::Result<()> {
+            self.1
+                .lock()
+                .unwrap()
+                .push(String::from_utf8_lossy(line).into_owned());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn retry_sink() {
+        let dir: TempDir = TempDir::new("dead_letter");
+        let dead_letter: PathBuf = dir.join("dead_letter.tsv");
+        let sink = RetrySink::new(Table::default())
+            .retry(3, Duration::ZERO)
+            .params("q=2 tau=1")
+            .dead_letter(&dead_letter);
+        let mut writer = sink.open().unwrap();
+        writer.write_all(b"0,1,1\n0,2,bad").unwrap();
+        writer.write_all(b"\t3\n0,1,0\n1,3,").unwrap();
+        drop(writer);
+
+        // rows are keyed by their pair, and the last line is sent once the writer is dropped
+        let rows = sink.row_sink().0.lock().unwrap().clone();
+        assert_eq!(
+            rows.iter().map(|(_, row)| row.as_str()).collect::<Vec<_>>(),
+            ["0,1,1", "0,1,0", "1,3,"]
+        );
+        assert_eq!(rows[0].0, rows[1].0);
+        assert_ne!(rows[0].0, rows[2].0);
+        let dead: String = std::fs::read_to_string(&dead_letter).unwrap();
+        assert!(dead.ends_with("\t0,2,bad\\t3\n"), "{}", dead);
+        assert_eq!(dead.len(), 64 + "\t0,2,bad\\t3\n".len());
+
+        // keys depend on the parameters, and a failing row fails the join without a dead-letter file
+        let other = RetrySink::new(Table::default())
+            .retry(3, Duration::ZERO)
+            .params("q=3 tau=1");
+        other.open().unwrap().write_all(b"0,1,1\n").unwrap();
+        assert_ne!(other.row_sink().0.lock().unwrap()[0].0, rows[0].0);
+        assert!(other.open().unwrap().write_all(b"0,2,bad\n").is_err());
+    }
+
+    #[test]
+    fn retry_sink_of_join() {
+        let context = |format: OutputFormat| SinkContext {
+            fingerprint: Some("0123abcd".to_string()),
+            format,
+            delimiter: b';',
+        };
+        // headers and brackets aren't keyed, and the fingerprint of the join is the default of the parameters
+        let sink = RetrySink::new(Table::default());
+        let mut writer = sink.open_join(&context(OutputFormat::CsvHeader)).unwrap();
+        writer.write_all(b"x_id;y_id;ed\n0;1;1\n").unwrap();
+        drop(writer);
+        let mut writer = sink.open_join(&context(OutputFormat::Json)).unwrap();
+        writer
+            .write_all(
+                b"[\n{\"x_id\":0,\"y_id\":1,\"ed\":1},\n{\"x_id\":2,\"y_id\":10,\"ed\":0}\n]\n",
+            )
+            .unwrap();
+        drop(writer);
+        let rows = sink.row_sink().0.lock().unwrap().clone();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].0, rows[1].0);
+        assert_ne!(rows[1].0, rows[2].0);
+        assert_eq!(
+            *sink.row_sink().1.lock().unwrap(),
+            ["x_id;y_id;ed", "[", "]"]
+        );
+        let explicit = RetrySink::new(Table::default()).params("0123abcd");
+        explicit.open().unwrap().write_all(b"0,1,1\n").unwrap();
+        assert_eq!(explicit.row_sink().0.lock().unwrap()[0].0, rows[0].0);
+
+        // parameters are required, and output without a match per line can't be sent
+        assert!(RetrySink::new(Table::default()).open().is_err());
+        assert!(RetrySink::new(Table::default()).params("").open().is_err());
+        assert!(sink.open_join(&context(OutputFormat::Dot)).is_err());
+    }
+}
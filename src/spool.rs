@@ -0,0 +1,416 @@
+use std::{
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::config::EdJoinConfig;
+use crate::errors::*;
+use crate::matching::ed_join_with_config;
+
+/// Extension of job files waiting in a spool directory.
+const JOB: &str = "job";
+/// Extension of the directory which a job writes its output to, before it's moved.
+const WORK: &str = "work";
+/// How often a request to shut down is checked for, while a job runs or the spool is idle.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
+/// The status of a job, as `key = value` fields.
+type Status = Vec<(&'static str, String)>;
+
+/// How a daemon shuts down gracefully, e.g. on SIGTERM before its pod is killed, see [`run_spool_until`].
+#[derive(Clone, Copy, Debug)]
+pub struct Shutdown<'a> {
+    /// Set once the daemon is to shut down, e.g. by a handler of the signal.
+    pub requested: &'a AtomicBool,
+    /// How long the jobs running then have to finish, before they're interrupted.
+    pub grace: Duration,
+}
+
+impl Shutdown<'_> {
+    fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+}
+
+/// A join described by a job file of a spool directory.
+///
+/// A job file holds one `key = value` per line, and blank lines and lines starting with `#` are ignored:
+///
+/// ```text
+/// doc_x = probes.txt
+/// # optional, a self-join of doc_x by default
+/// doc_y = reference.txt
+/// q = 3
+/// tau = 1
+/// # optional, <job>.out in the spool directory by default
+/// output = matches.csv
+/// ```
+///
+/// Relative paths are relative to the spool directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Job {
+    pub doc_x: PathBuf,
+    pub doc_y: PathBuf,
+    pub q: usize,
+    pub tau: usize,
+    /// Where the output is moved to once the join is done.
+    pub output: PathBuf,
+}
+
+impl Job {
+    /// Parse the job file at `path`.
+    pub fn parse(path: &Path) -> Result<Self> {
+        let spool: &Path = path.parent().unwrap_or_else(|| Path::new("."));
+        let (mut doc_x, mut doc_y, mut q, mut tau, mut output) = (None, None, None, None, None);
+        for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line: &str = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid =
+                || Error::from(format!("Invalid line {} of job {}", i + 1, path.display()));
+            let (key, value) = line.split_once('=').ok_or_else(invalid)?;
+            let value: &str = value.trim();
+            match key.trim() {
+                "doc_x" => doc_x = Some(spool.join(value)),
+                "doc_y" => doc_y = Some(spool.join(value)),
+                "q" => q = Some(value.parse::<usize>().map_err(|_| invalid())?),
+                "tau" => tau = Some(value.parse::<usize>().map_err(|_| invalid())?),
+                "output" => output = Some(spool.join(value)),
+                _ => return Err(invalid()),
+            }
+        }
+
+        let missing = |key: &str| Error::from(format!("Job {} has no `{}`", path.display(), key));
+        let doc_x: PathBuf = doc_x.ok_or_else(|| missing("doc_x"))?;
+        Ok(Self {
+            doc_y: doc_y.unwrap_or_else(|| doc_x.clone()),
+            doc_x,
+            q: q.ok_or_else(|| missing("q"))?,
+            tau: tau.ok_or_else(|| missing("tau"))?,
+            output: output.unwrap_or_else(|| path.with_extension("out")),
+        })
+    }
+}
+
+/// Write the status of a job next to its job file, as `key = value` lines, the same as the job file itself.
+fn write_status(job_name: &Path, fields: &[(&str, String)]) -> Result<()> {
+    let status: String = fields
+        .iter()
+        .map(|(key, value)| format!("{} = {}\n", key, value))
+        .collect();
+    fs::write(job_name.with_extension("status"), status)?;
+    Ok(())
+}
+
+/// Move a file, even across file systems.
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Whether a job was interrupted by a shutdown before, according to its status.
+fn was_interrupted(job_name: &Path) -> bool {
+    fs::read_to_string(job_name.with_extension("status"))
+        .is_ok_and(|status| status.starts_with("state = interrupted\n"))
+}
+
+/// Run a single job, whose file has already been claimed, and return the status to be written for it.
+///
+/// The output of the join is written to the work directory of the job, `<name>.work`, before it's moved,
+/// and an interrupted job is resumed from the partial output it left there, see [`EdJoinConfig::resume`].
+fn run(running: &Path, resume: bool, cancel: Arc<AtomicBool>) -> Result<Status> {
+    let job: Job = Job::parse(running)?;
+    let work: PathBuf = running.with_extension(WORK);
+    fs::create_dir_all(&work)?;
+    let mut config: EdJoinConfig = EdJoinConfig::new(job.q, job.tau)
+        .output_dir(&work)
+        .cancel_on(cancel);
+    if resume {
+        config = config.resume();
+    }
+    let report = ed_join_with_config(&job.doc_x, &job.doc_y, &config)?;
+    if let Some(output) = &report.output {
+        move_file(output, &job.output)?;
+    }
+    Ok(vec![
+        ("state", "done".to_string()),
+        ("output", job.output.display().to_string()),
+        ("warnings", report.warnings.len().to_string()),
+        ("skipped_records", report.skipped_records().to_string()),
+        ("skipped_pairs", report.skipped_pairs().to_string()),
+    ])
+}
+
+/// Write the status of a job which ended, and rename it by how it ended.
+///
+/// A job cancelled by a shutdown is put back in the spool, along with its work directory, to be resumed.
+fn finish(job: &Path, result: Result<Status>) -> Result<()> {
+    let running: PathBuf = job.with_extension("running");
+    if let Err(ErrorKind::Cancelled) = result.as_ref().map_err(Error::kind) {
+        #[cfg(feature = "logging")]
+        warn!(
+            "Interrupted job {}, which is put back in the spool",
+            job.display()
+        );
+        write_status(job, &[("state", "interrupted".to_string())])?;
+        fs::rename(&running, job)?;
+        return Ok(());
+    }
+
+    let work: PathBuf = job.with_extension(WORK);
+    if work.is_dir() {
+        fs::remove_dir_all(&work)?;
+    }
+    let (status, finished): (Status, PathBuf) = match result {
+        Ok(status) => (status, job.with_extension("done")),
+        Err(err) => (
+            vec![
+                ("state", "failed".to_string()),
+                ("error", err.to_string().replace('\n', " ")),
+            ],
+            job.with_extension("failed"),
+        ),
+    };
+    write_status(job, &status)?;
+    fs::rename(&running, &finished)?;
+    Ok(())
+}
+
+/// A job running on a thread of its own.
+struct Worker {
+    job: PathBuf,
+    /// Set to cancel the join of the job.
+    cancel: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// Run all jobs waiting in a spool directory, in order of their names, at most `workers` at a time.
+///
+/// A job `<name>.job` is claimed by renaming it to `<name>.running`, and `<name>.status` holds its state,
+/// which is `running`, `done`, `failed` or `interrupted`, along with its warnings or error. A finished job is renamed to
+/// `<name>.done` or `<name>.failed`. Each job writes its output to a directory of its own, `<name>.work`, from which
+/// it's moved to the output of the job, and which is removed once the job is finished.
+///
+/// # Return
+///
+/// The number of jobs run, whether they failed or not. Only errors of the spool directory itself are returned.
+pub fn run_spool(spool: &Path, workers: usize) -> Result<usize> {
+    run_jobs(spool, workers, None)
+}
+
+/// Same as [`run_spool`], but shutting down gracefully once a shutdown is requested.
+///
+/// No job is claimed after that, and the jobs running then have the grace period to finish. Those that don't are
+/// cancelled, see [`EdJoinConfig::cancel_on`], and waited for: their state is `interrupted`, and they're put back in
+/// the spool as `<name>.job`. The next run of the spool resumes each of them from the partial output it left in its
+/// work directory, rather than starting over.
+pub fn run_spool_until(spool: &Path, workers: usize, shutdown: Shutdown) -> Result<usize> {
+    run_jobs(spool, workers, Some(shutdown))
+}
+
+fn run_jobs(spool: &Path, workers: usize, shutdown: Option<Shutdown>) -> Result<usize> {
+    if workers == 0 {
+        bail!("Not a valid positive integer: workers = 0");
+    }
+    let mut jobs: Vec<PathBuf> = fs::read_dir(spool)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<PathBuf>>>()?
+        .into_iter()
+        .filter(|path| path.is_file() && path.extension().is_some_and(|extension| extension == JOB))
+        .collect();
+    jobs.sort_unstable();
+
+    let (sender, receiver) = mpsc::channel::<(PathBuf, Result<Status>)>();
+    let mut running: Vec<Worker> = Vec::with_capacity(workers);
+    let mut jobs = jobs.into_iter();
+    let mut deadline: Option<Instant> = None;
+    let mut ran: usize = 0;
+    loop {
+        let requested: bool = shutdown.is_some_and(|shutdown| shutdown.is_requested());
+        while !requested && running.len() < workers {
+            let Some(job) = jobs.next() else {
+                break;
+            };
+            let claimed: PathBuf = job.with_extension("running");
+            // another daemon on the same spool may have claimed it first
+            if fs::rename(&job, &claimed).is_err() {
+                continue;
+            }
+            let resume: bool = was_interrupted(&job);
+            write_status(&job, &[("state", "running".to_string())])?;
+            #[cfg(feature = "logging")]
+            info!("Running job {}", job.display());
+            ran += 1;
+
+            let cancel: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+            let thread: JoinHandle<()> = {
+                let (sender, job, cancel) = (sender.clone(), job.clone(), Arc::clone(&cancel));
+                thread::spawn(move || {
+                    let result: Result<Status> =
+                        panic::catch_unwind(AssertUnwindSafe(|| run(&claimed, resume, cancel)))
+                            .unwrap_or_else(|_| Err("The join panicked".into()));
+                    let _ = sender.send((job, result));
+                })
+            };
+            running.push(Worker {
+                job,
+                cancel,
+                thread,
+            });
+        }
+        if running.is_empty() {
+            break;
+        }
+
+        if let Ok((job, result)) = receiver.recv_timeout(SHUTDOWN_POLL) {
+            let i: usize = running.iter().position(|worker| worker.job == job).unwrap();
+            let _ = running.swap_remove(i).thread.join();
+            finish(&job, result)?;
+        }
+        if let Some(shutdown) = shutdown.filter(|shutdown| shutdown.is_requested()) {
+            if Instant::now() >= *deadline.get_or_insert_with(|| Instant::now() + shutdown.grace) {
+                // the jobs which finish before they notice are finished as usual
+                running
+                    .iter()
+                    .for_each(|worker| worker.cancel.store(true, Ordering::SeqCst));
+                for worker in running.drain(..) {
+                    let _ = worker.thread.join();
+                }
+                for (job, result) in receiver.try_iter() {
+                    finish(&job, result)?;
+                }
+                break;
+            }
+        }
+    }
+
+    Ok(ran)
+}
+
+/// Watch a spool directory, and run the jobs that appear in it, checking for new ones every `interval`.
+pub fn watch(spool: &Path, interval: Duration, workers: usize) -> Result<()> {
+    loop {
+        run_spool(spool, workers)?;
+        thread::sleep(interval);
+    }
+}
+
+/// Same as [`watch`], but returning once a shutdown is requested, after shutting down gracefully as
+/// [`run_spool_until`] does.
+pub fn watch_until(
+    spool: &Path,
+    interval: Duration,
+    workers: usize,
+    shutdown: Shutdown,
+) -> Result<()> {
+    loop {
+        run_spool_until(spool, workers, shutdown)?;
+        let wake: Instant = Instant::now() + interval;
+        while !shutdown.is_requested() && Instant::now() < wake {
+            thread::sleep(SHUTDOWN_POLL.min(wake.saturating_duration_since(Instant::now())));
+        }
+        if shutdown.is_requested() {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn spool() {
+        let dir: TempDir = TempDir::new("spool");
+        let spool: &Path = dir.path();
+        fs::write(spool.join("spool_records.txt"), "hello\nhallo\nworld\n").unwrap();
+        fs::write(
+            spool.join("a.job"),
+            "# a self-join\ndoc_x = spool_records.txt\nq = 2\ntau = 1\n",
+        )
+        .unwrap();
+        fs::write(spool.join("b.job"), "doc_x = spool_records.txt\ntau = 1\n").unwrap();
+
+        let job: Job = Job::parse(&spool.join("a.job")).unwrap();
+        assert_eq!(job.doc_y, spool.join("spool_records.txt"));
+        assert_eq!(job.output, spool.join("a.out"));
+
+        assert!(run_spool(spool, 0).is_err());
+        assert_eq!(run_spool(spool, 2).unwrap(), 2);
+        assert_eq!(fs::read_to_string(spool.join("a.out")).unwrap(), "0,1,1\n");
+        assert!(fs::read_to_string(spool.join("a.status"))
+            .unwrap()
+            .starts_with("state = done\n"));
+        assert!(spool.join("a.done").is_file());
+        let status: String = fs::read_to_string(spool.join("b.status")).unwrap();
+        assert!(status.starts_with("state = failed\nerror = ") && status.contains("no `q`"));
+        assert!(spool.join("b.failed").is_file());
+        assert!(!spool.join("a.work").exists() && !spool.join("b.work").exists());
+        assert_eq!(run_spool(spool, 2).unwrap(), 0);
+
+        // a job interrupted by a shutdown resumes from its partial output, of which the last record is redone
+        fs::write(
+            spool.join("c.job"),
+            "doc_x = spool_records.txt\nq = 2\ntau = 1\noutput = c.out\n",
+        )
+        .unwrap();
+        fs::write(spool.join("c.status"), "state = interrupted\n").unwrap();
+        fs::create_dir_all(spool.join("c.work")).unwrap();
+        fs::write(
+            spool
+                .join("c.work")
+                .join("spool_records_out_q2_tau1.txt.partial"),
+            "0,2,9\n1,2,9\n",
+        )
+        .unwrap();
+        let requested: AtomicBool = AtomicBool::new(false);
+        let shutdown: Shutdown = Shutdown {
+            requested: &requested,
+            grace: Duration::ZERO,
+        };
+        assert_eq!(run_spool_until(spool, 1, shutdown).unwrap(), 1);
+        assert_eq!(fs::read_to_string(spool.join("c.out")).unwrap(), "0,2,9\n");
+
+        // a job cancelled by a shutdown is put back in the spool, along with its work directory
+        fs::write(
+            spool.join("e.running"),
+            "doc_x = spool_records.txt\nq = 2\ntau = 1\n",
+        )
+        .unwrap();
+        let result: Result<Status> = run(
+            &spool.join("e.running"),
+            false,
+            Arc::new(AtomicBool::new(true)),
+        );
+        assert!(matches!(
+            result.as_ref().map_err(Error::kind),
+            Err(ErrorKind::Cancelled)
+        ));
+        finish(&spool.join("e.job"), result).unwrap();
+        assert!(was_interrupted(&spool.join("e.job")));
+        assert!(spool.join("e.job").is_file() && spool.join("e.work").is_dir());
+
+        // and no job is claimed once a shutdown is requested
+        fs::write(
+            spool.join("d.job"),
+            "doc_x = spool_records.txt\nq = 2\ntau = 1\n",
+        )
+        .unwrap();
+        requested.store(true, Ordering::SeqCst);
+        assert_eq!(run_spool_until(spool, 1, shutdown).unwrap(), 0);
+        watch_until(spool, Duration::from_secs(60), 1, shutdown).unwrap();
+        assert!(spool.join("d.job").is_file());
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    path::{Path, PathBuf},
+};
+
+use crate::config::{ErrorPolicy, InputFormat};
+use crate::errors::*;
+use crate::qgram::{read_records, read_records_as};
+use crate::report::{Side, Warning};
+
+/// The records of a reference document, i.e. `doc_y`, loaded once and shared read-only by any number of joins.
+///
+/// Wrap it in an `Arc` and give it to [`crate::config::EdJoinConfig::record_store`],
+/// e.g. to join several probe files against the same reference without reading it again for each of them.
+///
+/// ```no_run
+/// use ed_join::{config::{EdJoinConfig, ErrorPolicy}, matching::ed_join_with_config, store::RecordStore};
+/// use std::{path::PathBuf, sync::Arc};
+///
+/// let reference = PathBuf::from("reference.txt");
+/// let store = Arc::new(RecordStore::load(&reference, ErrorPolicy::default()).unwrap());
+/// for probe in &["a.txt", "b.txt"] {
+///     let config = EdJoinConfig::new(2, 1).record_store(Arc::clone(&store));
+///     ed_join_with_config(&PathBuf::from(probe), &reference, &config).unwrap();
+/// }
+/// ```
+pub struct RecordStore {
+    path: PathBuf,
+    records: Vec<String>,
+    warnings: Vec<Warning>,
+}
+
+impl RecordStore {
+    /// Read all records of a document, as `read_records()` does for `doc_y`.
+    pub fn load(path: &PathBuf, policy: ErrorPolicy) -> Result<Self> {
+        let (records, warnings) = read_records(path, Side::Y, policy)?;
+        Ok(Self {
+            path: path.clone(),
+            records,
+            warnings,
+        })
+    }
+
+    /// Read all records of a document in the given format, as `read_records_as()` does for `doc_y`.
+    pub fn load_as(path: &PathBuf, policy: ErrorPolicy, format: &InputFormat) -> Result<Self> {
+        let (records, warnings) = read_records_as(path, Side::Y, policy, format)?;
+        Ok(Self {
+            path: path.clone(),
+            records,
+            warnings,
+        })
+    }
+
+    /// Path of the document the records were read from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// All records, indexed by line number. Records that are not valid UTF-8 are empty.
+    pub fn records(&self) -> &[String] {
+        &self.records
+    }
+
+    /// Warnings encountered while reading the records, which are reported by every join using them.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+}
+
+// the records are left out, so that a config holding a store is still small when printed
+impl Debug for RecordStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordStore")
+            .field("path", &self.path)
+            .field("records", &self.records.len())
+            .field("warnings", &self.warnings.len())
+            .finish()
+    }
+}
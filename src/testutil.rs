@@ -0,0 +1,38 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A directory of a test for its inputs and outputs, which is removed with its contents when dropped,
+/// so that it's cleaned up even if the test fails.
+#[derive(Debug)]
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create an empty directory, whose name is unique to the test and the process.
+    pub fn new(name: &str) -> Self {
+        let path: PathBuf =
+            std::env::temp_dir().join(format!("ed_join_{}_{}", name, std::process::id()));
+        // left over by a run that was killed
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path of a file in the directory.
+    pub fn join<P: AsRef<Path>>(&self, name: P) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
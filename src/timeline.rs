@@ -0,0 +1,252 @@
+use std::path::Path;
+#[cfg(feature = "timeline")]
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Instant,
+};
+
+use crate::errors::*;
+
+/// A region of the matching of every candidate pair, whose time is summed over all pairs and threads,
+/// rather than timed as a [`Span`] each, which would be far too many for a timeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    /// Generating the candidates of a record from the inverted index.
+    Candidates,
+    CountFilter,
+    LocationFilter,
+    ContentFilter,
+    EditDistance,
+}
+
+impl Region {
+    #[cfg(feature = "timeline")]
+    const ALL: [Region; 5] = [
+        Region::Candidates,
+        Region::CountFilter,
+        Region::LocationFilter,
+        Region::ContentFilter,
+        Region::EditDistance,
+    ];
+
+    /// The name of the region in a timeline, e.g. `count_filter`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Region::Candidates => "candidates",
+            Region::CountFilter => "count_filter",
+            Region::LocationFilter => "location_filter",
+            Region::ContentFilter => "content_filter",
+            Region::EditDistance => "edit_distance",
+        }
+    }
+}
+
+/// A named phase of a run, timed from its creation until it's dropped, see [`span`].
+///
+/// Without the `timeline` feature, it's empty and records nothing.
+#[must_use = "a span is timed until it's dropped"]
+#[derive(Debug)]
+pub struct Span {
+    #[cfg(feature = "timeline")]
+    name: &'static str,
+    #[cfg(feature = "timeline")]
+    start: Instant,
+}
+
+/// Time spent in a [`Region`], added to its total when it's dropped, see [`region`].
+#[must_use = "a region is timed until it's dropped"]
+#[derive(Debug)]
+pub struct RegionTimer {
+    #[cfg(feature = "timeline")]
+    region: Region,
+    #[cfg(feature = "timeline")]
+    start: Instant,
+}
+
+/// A completed span, in microseconds since the timeline was started.
+#[cfg(feature = "timeline")]
+struct Event {
+    name: &'static str,
+    thread: u64,
+    start: f64,
+    duration: f64,
+}
+
+#[cfg(feature = "timeline")]
+struct Timeline {
+    epoch: Instant,
+    events: Mutex<Vec<Event>>,
+    region_nanos: [AtomicU64; 5],
+    region_calls: [AtomicU64; 5],
+}
+
+#[cfg(feature = "timeline")]
+static TIMELINE: OnceLock<Timeline> = OnceLock::new();
+
+#[cfg(feature = "timeline")]
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(1);
+
+#[cfg(feature = "timeline")]
+thread_local! {
+    /// Number of the current thread in the timeline, as `std::thread::ThreadId` has no stable number.
+    static THREAD: u64 = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Start recording spans and regions, which are only recorded once the timeline is started.
+///
+/// It's an error if the crate was built without the `timeline` feature.
+pub fn start() -> Result<()> {
+    #[cfg(feature = "timeline")]
+    {
+        TIMELINE.get_or_init(|| Timeline {
+            epoch: Instant::now(),
+            events: Mutex::new(Vec::new()),
+            region_nanos: Default::default(),
+            region_calls: Default::default(),
+        });
+        Ok(())
+    }
+    #[cfg(not(feature = "timeline"))]
+    bail!("Timelines are only recorded when built with the `timeline` feature")
+}
+
+/// Time a phase of a run until the returned span is dropped, e.g. `let _span = timeline::span("index");`.
+pub fn span(name: &'static str) -> Span {
+    #[cfg(feature = "timeline")]
+    return Span {
+        name,
+        start: Instant::now(),
+    };
+    #[cfg(not(feature = "timeline"))]
+    {
+        let _ = name;
+        Span {}
+    }
+}
+
+/// Time a region of the matching of a pair until the returned timer is dropped.
+pub fn region(region: Region) -> RegionTimer {
+    #[cfg(feature = "timeline")]
+    return RegionTimer {
+        region,
+        start: Instant::now(),
+    };
+    #[cfg(not(feature = "timeline"))]
+    {
+        let _ = region;
+        RegionTimer {}
+    }
+}
+
+#[cfg(feature = "timeline")]
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(timeline) = TIMELINE.get() {
+            let event: Event = Event {
+                name: self.name,
+                thread: THREAD.with(|thread| *thread),
+                start: self
+                    .start
+                    .saturating_duration_since(timeline.epoch)
+                    .as_secs_f64()
+                    * 1e6,
+                duration: self.start.elapsed().as_secs_f64() * 1e6,
+            };
+            timeline.events.lock().unwrap().push(event);
+        }
+    }
+}
+
+#[cfg(feature = "timeline")]
+impl Drop for RegionTimer {
+    fn drop(&mut self) {
+        if let Some(timeline) = TIMELINE.get() {
+            let i: usize = Region::ALL
+                .iter()
+                .position(|region| *region == self.region)
+                .unwrap();
+            timeline.region_nanos[i]
+                .fetch_add(self.start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+            timeline.region_calls[i].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Write the spans recorded so far as a timeline in the Trace Event Format, as loaded by `chrome://tracing` or Perfetto.
+///
+/// Spans are complete events on the thread they were timed on. The total of each region is an event too, on a thread
+/// of its own, and they're laid end to end from the start of the timeline, with the number of times each was entered.
+pub fn write(path: &Path) -> Result<()> {
+    #[cfg(feature = "timeline")]
+    {
+        let timeline: &Timeline = match TIMELINE.get() {
+            Some(timeline) => timeline,
+            None => bail!("The timeline was never started"),
+        };
+        let mut writer: BufWriter<File> = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{{\"displayTimeUnit\":\"ms\",\"traceEvents\":[")?;
+        writeln!(
+            writer,
+            "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":0,\"args\":{{\"name\":\"regions, summed over threads\"}}}}"
+        )?;
+        for event in timeline.events.lock().unwrap().iter() {
+            writeln!(
+                writer,
+                ",{{\"name\":\"{}\",\"cat\":\"phase\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":{}}}",
+                event.name, event.start, event.duration, event.thread
+            )?;
+        }
+        let mut start: f64 = 0.0;
+        for (i, region) in Region::ALL.iter().enumerate() {
+            let calls: u64 = timeline.region_calls[i].load(Ordering::Relaxed);
+            if calls == 0 {
+                continue;
+            }
+            let duration: f64 = timeline.region_nanos[i].load(Ordering::Relaxed) as f64 / 1e3;
+            writeln!(
+                writer,
+                ",{{\"name\":\"{}\",\"cat\":\"region\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":0,\"args\":{{\"calls\":{}}}}}",
+                region.name(),
+                start,
+                duration,
+                calls
+            )?;
+            start += duration;
+        }
+        writeln!(writer, "]}}")?;
+        writer.flush()?;
+        Ok(())
+    }
+    #[cfg(not(feature = "timeline"))]
+    {
+        let _ = path;
+        bail!("Timelines are only recorded when built with the `timeline` feature")
+    }
+}
+
+#[cfg(all(test, feature = "timeline"))]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn timeline() {
+        start().unwrap();
+        {
+            let _span = span("phase");
+            let _region = region(Region::EditDistance);
+        }
+        let dir: TempDir = TempDir::new("timeline");
+        let path = dir.join("timeline.json");
+        write(&path).unwrap();
+        let written: String = std::fs::read_to_string(&path).unwrap();
+        assert!(written.contains("\"name\":\"phase\",\"cat\":\"phase\",\"ph\":\"X\""));
+        assert!(written.contains("\"name\":\"edit_distance\",\"cat\":\"region\""));
+        assert!(written.trim_end().ends_with("]}"));
+    }
+}
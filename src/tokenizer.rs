@@ -0,0 +1,832 @@
+use std::{
+    convert::TryInto,
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+};
+
+use crate::qgram::PosQGram;
+
+/// Generates the grams of records, which the inverted index and the filters work on.
+///
+/// The filters bound the edit distance by assuming that every gram is the window of `q` units at its location, bytes
+/// unless [`Tokenizer::mode`] says otherwise, possibly mapped to another token, so that an edit destroys at most `q` grams.
+/// Tokenizers for which that holds are positional, and keep the join exact. For others, e.g. [`WordQGrams`], candidates
+/// are still generated from the grams, but verified by their edit distance alone, and matches whose records share no gram
+/// of the prefix are missed.
+///
+/// ```
+/// use ed_join::tokenizer::{CanonicalKmers, Tokenizer};
+///
+/// let grams: Vec<String> = CanonicalKmers.tokenize("ACGT", 3).into_iter().map(|gram| gram.token).collect();
+/// assert_eq!(grams, ["ACG", "ACG"]);
+/// ```
+pub trait Tokenizer: Debug + Send + Sync {
+    /// The grams of a record, with their locations, in increasing order of location.
+    fn tokenize(&self, record: &str, q: usize) -> Vec<PosQGram>;
+
+    /// Whether every gram is the window of `q` units at its location, or a function of it.
+    fn positional(&self) -> bool {
+        false
+    }
+
+    /// The units which locations count, and which the filters and edit distances compare records by.
+    fn mode(&self) -> TokenizerMode {
+        TokenizerMode::Bytes
+    }
+
+    /// The name of the tokenizer and its parameters, which is part of the key of cached runs,
+    /// see [`crate::cache::CacheEntry`].
+    ///
+    /// It's `None` by default, so that runs with a tokenizer of your own aren't cached,
+    /// since the cache cannot tell when its code changed.
+    fn cache_key(&self) -> Option<String> {
+        None
+    }
+}
+
+/// The units of records which q-grams are windows of.
+///
+/// Locations of grams, lengths of records and edit distances all count these units, so a join is exact for any UTF-8 input,
+/// though `Bytes` counts the bytes of records that aren't ASCII rather than their characters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TokenizerMode {
+    /// Bytes, as the paper does. Edit distances count bytes too, which are characters for ASCII records,
+    /// and a window that splits a multi-byte character is a token of its bytes escaped.
+    #[default]
+    Bytes,
+    /// Characters, i.e. Unicode scalar values.
+    Chars,
+    /// Grapheme clusters, i.e. what's displayed as a single character, such as `e` with a combining accent.
+    ///
+    /// Clusters are approximated as a character with the combining marks, variation selectors, emoji modifiers and tags
+    /// that follow it, characters joined by zero-width joiners, pairs of regional indicators, and CRLF.
+    /// Unlike the full rules of Unicode, conjoining Hangul jamo and spacing marks are clusters of their own.
+    Graphemes,
+    /// Words separated by whitespace, and by ASCII punctuation too if `punctuation` is set, which are dropped.
+    ///
+    /// This is a token join, e.g. of names or addresses: an edit inserts, deletes or substitutes a whole word,
+    /// so `tau` is a number of words, and so is `q`.
+    Words { punctuation: bool },
+}
+
+impl TokenizerMode {
+    /// Parse the name of a mode, i.e. `bytes`, `chars`, `graphemes`, `words`, or `words-punct` for words separated by
+    /// punctuation too.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bytes" => Some(TokenizerMode::Bytes),
+            "chars" => Some(TokenizerMode::Chars),
+            "graphemes" => Some(TokenizerMode::Graphemes),
+            "words" => Some(TokenizerMode::Words { punctuation: false }),
+            "words-punct" => Some(TokenizerMode::Words { punctuation: true }),
+            _ => None,
+        }
+    }
+
+    /// The q-grams of this mode, i.e. [`QGrams`], [`CharQGrams`], [`GraphemeQGrams`] or [`TokenQGrams`].
+    pub fn tokenizer(&self) -> Arc<dyn Tokenizer> {
+        match self {
+            TokenizerMode::Bytes => Arc::new(QGrams),
+            TokenizerMode::Chars => Arc::new(CharQGrams),
+            TokenizerMode::Graphemes => Arc::new(GraphemeQGrams),
+            TokenizerMode::Words { punctuation } => Arc::new(TokenQGrams {
+                punctuation: *punctuation,
+            }),
+        }
+    }
+
+    /// Byte spans of the units of a record, in order. They're adjacent, except for the separators between words.
+    ///
+    /// ```
+    /// use ed_join::tokenizer::TokenizerMode;
+    ///
+    /// assert_eq!(TokenizerMode::Chars.spans("e\u{301}h"), [(0, 1), (1, 3), (3, 4)]);
+    /// assert_eq!(TokenizerMode::Graphemes.spans("e\u{301}h"), [(0, 3), (3, 4)]);
+    /// assert_eq!(TokenizerMode::Words { punctuation: true }.spans("Doe, J."), [(0, 3), (5, 6)]);
+    /// ```
+    pub fn spans(&self, record: &str) -> Vec<(usize, usize)> {
+        match self {
+            TokenizerMode::Bytes => (0..record.len()).map(|i| (i, i + 1)).collect(),
+            TokenizerMode::Chars => record
+                .char_indices()
+                .map(|(i, c)| (i, i + c.len_utf8()))
+                .collect(),
+            TokenizerMode::Graphemes => grapheme_boundaries(record)
+                .windows(2)
+                .map(|span| (span[0], span[1]))
+                .collect(),
+            TokenizerMode::Words { punctuation } => {
+                let is_separator =
+                    |c: char| c.is_whitespace() || (*punctuation && c.is_ascii_punctuation());
+                record
+                    .split(is_separator)
+                    .filter(|word| !word.is_empty())
+                    .map(|word| {
+                        let start: usize = word.as_ptr() as usize - record.as_ptr() as usize;
+                        (start, start + word.len())
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Number of units of a record.
+    pub fn len_of(&self, record: &str) -> usize {
+        match self {
+            TokenizerMode::Bytes => record.len(),
+            TokenizerMode::Chars => record.chars().count(),
+            _ => self.spans(record).len(),
+        }
+    }
+
+    /// The units `lo..hi` of a record, or those of them it has.
+    pub fn units<'a>(&self, record: &'a str, lo: usize, hi: usize) -> Vec<&'a str> {
+        let spans: Vec<(usize, usize)> = self.spans(record);
+        let (lo, hi): (usize, usize) = (lo.min(spans.len()), hi.min(spans.len()));
+        spans[lo..hi]
+            .iter()
+            .map(|(start, end)| &record[*start..*end])
+            .collect()
+    }
+}
+
+/// Whether a character is part of the grapheme cluster of the character before it, see [`TokenizerMode::Graphemes`].
+fn extends_grapheme(c: char) -> bool {
+    matches!(
+        c as u32,
+        // combining diacritical marks, and their extensions and supplement
+        0x300..=0x36F | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF
+        // combining marks for symbols, and combining half marks
+        | 0x20D0..=0x20FF | 0xFE20..=0xFE2F
+        // zero-width joiner, and variation selectors
+        | 0x200D | 0xFE00..=0xFE0F | 0xE0100..=0xE01EF
+        // emoji modifiers, and tags
+        | 0x1F3FB..=0x1F3FF | 0xE0020..=0xE007F
+    )
+}
+
+/// Whether a character is a regional indicator, pairs of which are flags.
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+/// Byte offsets of the boundaries between the grapheme clusters of a record, see [`TokenizerMode::Graphemes`].
+fn grapheme_boundaries(record: &str) -> Vec<usize> {
+    let mut boundaries: Vec<usize> = Vec::with_capacity(record.len() + 1);
+    let mut prev: Option<char> = None;
+    // regional indicators of the current cluster, which holds at most two of them
+    let mut indicators: usize = 0;
+    for (i, c) in record.char_indices() {
+        let joined: bool = match prev {
+            None => false,
+            Some(prev) => {
+                extends_grapheme(c)
+                    || prev == '\u{200D}'
+                    || (prev == '\r' && c == '\n')
+                    || (indicators == 1 && is_regional_indicator(c))
+            }
+        };
+        if !joined {
+            boundaries.push(i);
+            indicators = 0;
+        }
+        if is_regional_indicator(c) {
+            indicators += 1;
+        }
+        prev = Some(c);
+    }
+    boundaries.push(record.len());
+    boundaries
+}
+
+/// Overlapping windows of `q` units of a record, located at the index of their first unit.
+///
+/// Windows of adjacent units are the slice of the record they cover, and those of words are the words joined by single spaces.
+fn unit_windows(record: &str, q: usize, mode: TokenizerMode) -> Vec<PosQGram> {
+    let spans: Vec<(usize, usize)> = mode.spans(record);
+    spans
+        .windows(q)
+        .enumerate()
+        .map(|(loc, window)| {
+            let token: String = match mode {
+                TokenizerMode::Words { .. } => window
+                    .iter()
+                    .map(|(start, end)| &record[*start..*end])
+                    .collect::<Vec<&str>>()
+                    .join(" "),
+                _ => record[window[0].0..window[q - 1].1].to_string(),
+            };
+            PosQGram::from(token, loc)
+        })
+        .collect()
+}
+
+/// Parse the name of a built-in tokenizer, i.e. `qgrams`, `words` or `kmers`, see [`TokenizerMode`] for q-grams of other units.
+pub fn from_name(name: &str) -> Option<Arc<dyn Tokenizer>> {
+    match name {
+        "qgrams" => Some(Arc::new(QGrams)),
+        "words" => Some(Arc::new(WordQGrams)),
+        "kmers" => Some(Arc::new(CanonicalKmers)),
+        _ => None,
+    }
+}
+
+/// Evaluate `$fixed` with the const `$Q` bound to `q` if it's 2, 3 or 4, the most common values of q, and `$dynamic`
+/// otherwise.
+///
+/// Byte windows of the fixed path are arrays, see [`fixed_grams`], so that their loops are monomorphized for these lengths.
+macro_rules! specialize_q {
+    ($q:expr, |$Q:ident| $fixed:expr, $dynamic:expr) => {
+        match $q {
+            2 => {
+                const $Q: usize = 2;
+                $fixed
+            }
+            3 => {
+                const $Q: usize = 3;
+                $fixed
+            }
+            4 => {
+                const $Q: usize = 4;
+                $fixed
+            }
+            _ => $dynamic,
+        }
+    };
+}
+
+/// The grams of the windows of `Q` bytes of a record, as tokens of the windows given as arrays.
+fn fixed_grams<const Q: usize>(record: &str, token: impl Fn(&[u8; Q]) -> String) -> Vec<PosQGram> {
+    record
+        .as_bytes()
+        .windows(Q)
+        .map(|window| token(window.try_into().expect("Windows are of length Q")))
+        .enumerate()
+        .map(|(loc, token)| PosQGram::from(token, loc))
+        .collect()
+}
+
+/// First code point of the escapes of [`byte_token`], i.e. Latin Extended-A and B, of two bytes each in UTF-8.
+const ESCAPED_BYTE: u32 = 0x100;
+
+/// The token of a window of bytes, which is the window itself if it's valid UTF-8, or else every byte escaped as
+/// a character of its own, e.g. a window that splits the two bytes of `ü`.
+///
+/// It's lossless, i.e. equal windows have equal tokens and different windows have different ones: an escaped token
+/// is twice as long as its window in UTF-8, and a token kept as is has its window's length, so they never collide.
+pub(crate) fn byte_token(window: &[u8]) -> String {
+    match std::str::from_utf8(window) {
+        Ok(token) => token.to_string(),
+        Err(_) => window
+            .iter()
+            .map(|byte| char::from_u32(ESCAPED_BYTE + u32::from(*byte)).unwrap())
+            .collect(),
+    }
+}
+
+/// Overlapping windows of `q` bytes, i.e. the q-grams of the paper. This is the default.
+///
+/// Windows within a multi-byte character are escaped by [`byte_token`], so records of any UTF-8 are tokenized,
+/// though their distances count bytes rather than characters, see [`CharQGrams`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QGrams;
+
+impl Tokenizer for QGrams {
+    fn tokenize(&self, record: &str, q: usize) -> Vec<PosQGram> {
+        specialize_q!(
+            q,
+            |Q| fixed_grams::<Q>(record, |ngrams| byte_token(ngrams)),
+            record
+                .as_bytes()
+                .windows(q)
+                .map(byte_token)
+                .enumerate()
+                .map(|(loc, token)| PosQGram::from(token, loc))
+                .collect()
+        )
+    }
+
+    fn positional(&self) -> bool {
+        true
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some("qgrams".to_string())
+    }
+}
+
+/// Overlapping windows of `q` characters, which are valid for any UTF-8 input, unlike [`QGrams`].
+///
+/// ```
+/// use ed_join::tokenizer::{CharQGrams, Tokenizer};
+///
+/// let grams: Vec<String> = CharQGrams.tokenize("ça va", 2).into_iter().map(|gram| gram.token).collect();
+/// assert_eq!(grams, ["ça", "a ", " v", "va"]);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CharQGrams;
+
+impl Tokenizer for CharQGrams {
+    fn tokenize(&self, record: &str, q: usize) -> Vec<PosQGram> {
+        unit_windows(record, q, TokenizerMode::Chars)
+    }
+
+    fn positional(&self) -> bool {
+        true
+    }
+
+    fn mode(&self) -> TokenizerMode {
+        TokenizerMode::Chars
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some("qgrams chars".to_string())
+    }
+}
+
+/// Overlapping windows of `q` grapheme clusters, so that e.g. a letter with a combining accent is a single unit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GraphemeQGrams;
+
+impl Tokenizer for GraphemeQGrams {
+    fn tokenize(&self, record: &str, q: usize) -> Vec<PosQGram> {
+        unit_windows(record, q, TokenizerMode::Graphemes)
+    }
+
+    fn positional(&self) -> bool {
+        true
+    }
+
+    fn mode(&self) -> TokenizerMode {
+        TokenizerMode::Graphemes
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some("qgrams graphemes".to_string())
+    }
+}
+
+/// Overlapping windows of `q` words, for token joins, see [`TokenizerMode::Words`].
+///
+/// Unlike [`WordQGrams`], this is positional, as words are the units of locations and edit distances too,
+/// so the join is exact at the level of words.
+///
+/// ```
+/// use ed_join::tokenizer::{TokenQGrams, Tokenizer};
+///
+/// let grams: Vec<String> = TokenQGrams { punctuation: true }.tokenize("Doe, John A.", 2).into_iter().map(|gram| gram.token).collect();
+/// assert_eq!(grams, ["Doe John", "John A"]);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TokenQGrams {
+    /// Whether ASCII punctuation separates words too.
+    pub punctuation: bool,
+}
+
+impl Tokenizer for TokenQGrams {
+    fn tokenize(&self, record: &str, q: usize) -> Vec<PosQGram> {
+        unit_windows(record, q, self.mode())
+    }
+
+    fn positional(&self) -> bool {
+        true
+    }
+
+    fn mode(&self) -> TokenizerMode {
+        TokenizerMode::Words {
+            punctuation: self.punctuation,
+        }
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some(if self.punctuation {
+            "qgrams words-punct".to_string()
+        } else {
+            "qgrams words".to_string()
+        })
+    }
+}
+
+/// Sequences of `q` consecutive words, separated by whitespace, located at the first byte of their first word.
+///
+/// The tokens are the words joined by single spaces, so that records differing in whitespace only share all grams.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WordQGrams;
+
+impl Tokenizer for WordQGrams {
+    fn tokenize(&self, record: &str, q: usize) -> Vec<PosQGram> {
+        let words: Vec<(usize, &str)> = record
+            .split_whitespace()
+            .map(|word| (word.as_ptr() as usize - record.as_ptr() as usize, word))
+            .collect();
+        words
+            .windows(q)
+            .map(|window| {
+                let token: String = window
+                    .iter()
+                    .map(|(_, word)| *word)
+                    .collect::<Vec<&str>>()
+                    .join(" ");
+                PosQGram::from(token, window[0].0)
+            })
+            .collect()
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some("words".to_string())
+    }
+}
+
+/// Complement of a base of DNA. Bases other than `A`, `C`, `G` and `T`, in either case, are their own complement.
+fn complement(base: u8) -> u8 {
+    match base {
+        b'A' => b'T',
+        b'T' => b'A',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'a' => b't',
+        b't' => b'a',
+        b'c' => b'g',
+        b'g' => b'c',
+        base => base,
+    }
+}
+
+/// Reverse complement of a sequence of DNA, i.e. the same sequence read from the opposite strand.
+///
+/// ```
+/// assert_eq!(ed_join::tokenizer::reverse_complement("AACGt"), "aCGTT");
+/// ```
+pub fn reverse_complement(sequence: &str) -> String {
+    // complements are ASCII, and other bytes are kept, but the order of bytes of other characters must be too
+    sequence
+        .chars()
+        .rev()
+        .map(|c| {
+            if c.is_ascii() {
+                complement(c as u8) as char
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// The lesser of a k-mer and its reverse complement.
+fn canonical_kmer(kmer: &[u8]) -> Vec<u8> {
+    let reverse: Vec<u8> = kmer.iter().rev().map(|base| complement(*base)).collect();
+    if reverse.as_slice() < kmer {
+        reverse
+    } else {
+        kmer.to_vec()
+    }
+}
+
+/// The lesser of a k-mer of `Q` bases and its reverse complement, as [`canonical_kmer`] on the stack.
+fn canonical_kmer_fixed<const Q: usize>(kmer: &[u8; Q]) -> [u8; Q] {
+    let mut reverse: [u8; Q] = *kmer;
+    reverse.reverse();
+    reverse
+        .iter_mut()
+        .for_each(|base| *base = complement(*base));
+    if reverse < *kmer {
+        reverse
+    } else {
+        *kmer
+    }
+}
+
+/// Windows of `q` bases of DNA, i.e. k-mers, as the lesser of themselves and their reverse complement.
+///
+/// A k-mer and its reverse complement are the same sequence read from either strand, so they're the same token,
+/// see [`reverse_complement`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CanonicalKmers;
+
+impl Tokenizer for CanonicalKmers {
+    fn tokenize(&self, record: &str, q: usize) -> Vec<PosQGram> {
+        specialize_q!(
+            q,
+            |Q| fixed_grams::<Q>(record, |kmer| byte_token(&canonical_kmer_fixed(kmer))),
+            record
+                .as_bytes()
+                .windows(q)
+                .map(|kmer| byte_token(&canonical_kmer(kmer)))
+                .enumerate()
+                .map(|(loc, token)| PosQGram::from(token, loc))
+                .collect()
+        )
+    }
+
+    fn positional(&self) -> bool {
+        true
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some("kmers".to_string())
+    }
+}
+
+/// Largest alphabet which [`PackedGrams`] packs, so that every symbol takes at most 3 bits, i.e. 2 symbols per character.
+const MAX_PACKED_SYMBOLS: usize = 8;
+/// First character of packed tokens, and of the 64 characters that hold 6 bits each.
+const PACKED_BASE: u8 = b'0';
+/// Prefix of windows with symbols outside the alphabet, which are kept as they are, and never collide with packed tokens.
+const UNPACKED: char = '~';
+
+/// Windows of `q` bytes of a small alphabet, e.g. `ACGT` of DNA, packed into a few bits per symbol.
+///
+/// Each symbol takes `ceil(log2(n))` bits of an alphabet of `n` symbols, and each character of a token holds 6 bits,
+/// so a k-mer of DNA is a third of its length, which shrinks the index and speeds up comparing tokens.
+/// Windows with a symbol outside the alphabet are kept unpacked. It's positional, and thus keeps the join exact.
+///
+/// ```
+/// use ed_join::tokenizer::{PackedGrams, Tokenizer};
+///
+/// let records = vec!["ACGTACGTAC".to_string(), "TTGCA".to_string()];
+/// let packed = PackedGrams::detect(&records).unwrap();
+/// assert_eq!(packed.tokenize("ACGTAC", 6)[0].token.len(), 2);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackedGrams {
+    /// Symbols of the alphabet, in increasing order.
+    symbols: Vec<u8>,
+    /// Each symbol's code, or `None` if it's not in the alphabet.
+    codes: Vec<Option<u8>>,
+    bits: u32,
+    canonical: bool,
+}
+
+impl PackedGrams {
+    /// Pack windows of the given alphabet, or `None` if it has more than 8 symbols, which don't pack well.
+    pub fn new(alphabet: &[u8]) -> Option<Self> {
+        let mut symbols: Vec<u8> = alphabet.to_vec();
+        symbols.sort_unstable();
+        symbols.dedup();
+        if symbols.is_empty() || symbols.len() > MAX_PACKED_SYMBOLS {
+            return None;
+        }
+        let mut codes: Vec<Option<u8>> = vec![None; 256];
+        symbols
+            .iter()
+            .enumerate()
+            .for_each(|(code, symbol)| codes[*symbol as usize] = Some(code as u8));
+        let bits: u32 = (usize::BITS - (symbols.len() - 1).leading_zeros()).max(1);
+
+        Some(Self {
+            symbols,
+            codes,
+            bits,
+            canonical: false,
+        })
+    }
+
+    /// Detect the alphabet of the records, and pack it if it's small enough, see [`PackedGrams::new`].
+    pub fn detect(records: &[String]) -> Option<Self> {
+        let mut seen: [bool; 256] = [false; 256];
+        records
+            .iter()
+            .flat_map(|record| record.bytes())
+            .for_each(|byte| seen[byte as usize] = true);
+        let alphabet: Vec<u8> = (0..=255).filter(|byte| seen[*byte as usize]).collect();
+        Self::new(&alphabet)
+    }
+
+    /// Pack the lesser of each window and its reverse complement, as [`CanonicalKmers`] does.
+    pub fn canonical(mut self) -> Self {
+        self.canonical = true;
+        self
+    }
+
+    fn pack(&self, window: &[u8]) -> String {
+        let codes: Option<Vec<u8>> = window
+            .iter()
+            .map(|symbol| self.codes[*symbol as usize])
+            .collect();
+        match codes {
+            Some(codes) => self.encode(&codes),
+            None => unpacked(window),
+        }
+    }
+
+    /// Pack a window of `Q` symbols, as [`PackedGrams::pack`] without collecting its codes on the heap.
+    fn pack_fixed<const Q: usize>(&self, window: &[u8; Q]) -> String {
+        let mut codes: [u8; Q] = [0; Q];
+        for (code, symbol) in codes.iter_mut().zip(window) {
+            match self.codes[*symbol as usize] {
+                Some(symbol_code) => *code = symbol_code,
+                None => return unpacked(window),
+            }
+        }
+        self.encode(&codes)
+    }
+
+    /// The token of the codes of a window's symbols.
+    fn encode(&self, codes: &[u8]) -> String {
+        let per_char: usize = (6 / self.bits) as usize;
+        codes
+            .chunks(per_char)
+            .map(|chunk| {
+                let value: u8 = chunk
+                    .iter()
+                    .fold(0, |value, code| (value << self.bits) | code);
+                (PACKED_BASE + value) as char
+            })
+            .collect()
+    }
+}
+
+/// A window with a symbol outside the alphabet, as it is, or escaped if it splits a character, see [`byte_token`].
+fn unpacked(window: &[u8]) -> String {
+    let mut unpacked: String = UNPACKED.to_string();
+    unpacked.push_str(&byte_token(window));
+    unpacked
+}
+
+impl Tokenizer for PackedGrams {
+    fn tokenize(&self, record: &str, q: usize) -> Vec<PosQGram> {
+        specialize_q!(
+            q,
+            |Q| fixed_grams::<Q>(record, |window| {
+                if self.canonical {
+                    self.pack_fixed(&canonical_kmer_fixed(window))
+                } else {
+                    self.pack_fixed(window)
+                }
+            }),
+            record
+                .as_bytes()
+                .windows(q)
+                .map(|window| if self.canonical {
+                    self.pack(&canonical_kmer(window))
+                } else {
+                    self.pack(window)
+                })
+                .enumerate()
+                .map(|(loc, token)| PosQGram::from(token, loc))
+                .collect()
+        )
+    }
+
+    fn positional(&self) -> bool {
+        true
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some(format!(
+            "{} symbols={}",
+            if self.canonical {
+                "packed-kmers"
+            } else {
+                "packed"
+            },
+            self.symbols.escape_ascii()
+        ))
+    }
+}
+
+/// A tokenizer given by a closure, e.g. `FnTokenizer::new("lowercase", |record, q| ...)`.
+///
+/// Its name is what it's printed as. Runs with it aren't cached, since the cache cannot tell when the closure changed.
+pub struct FnTokenizer<F> {
+    name: String,
+    positional: bool,
+    f: F,
+}
+
+impl<F: Fn(&str, usize) -> Vec<PosQGram> + Send + Sync> FnTokenizer<F> {
+    pub fn new(name: impl Into<String>, f: F) -> Self {
+        Self {
+            name: name.into(),
+            positional: false,
+            f,
+        }
+    }
+
+    /// Declare that the closure is positional, see [`Tokenizer::positional`], which keeps the join exact.
+    pub fn positional(mut self) -> Self {
+        self.positional = true;
+        self
+    }
+}
+
+impl<F: Fn(&str, usize) -> Vec<PosQGram> + Send + Sync> Tokenizer for FnTokenizer<F> {
+    fn tokenize(&self, record: &str, q: usize) -> Vec<PosQGram> {
+        (self.f)(record, q)
+    }
+
+    fn positional(&self) -> bool {
+        self.positional
+    }
+}
+
+impl<F> Debug for FnTokenizer<F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("FnTokenizer").field(&self.name).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenizers() {
+        let tokens = |tokenizer: &dyn Tokenizer, record: &str, q: usize| -> Vec<(String, usize)> {
+            tokenizer
+                .tokenize(record, q)
+                .into_iter()
+                .map(|gram| (gram.token, gram.loc))
+                .collect()
+        };
+        assert_eq!(
+            tokens(&QGrams, "hello", 3),
+            [
+                ("hel".to_string(), 0),
+                ("ell".to_string(), 1),
+                ("llo".to_string(), 2)
+            ]
+        );
+        assert_eq!(
+            tokens(&WordQGrams, " the  quick fox", 2),
+            [("the quick".to_string(), 1), ("quick fox".to_string(), 6)]
+        );
+        assert!(WordQGrams.tokenize("fox", 2).is_empty());
+        assert_eq!(
+            tokens(&CharQGrams, "héé", 2),
+            [("hé".to_string(), 0), ("éé".to_string(), 1)]
+        );
+        // a flag of two regional indicators, and an accent that combines with `e`
+        let graphemes: &str = "\u{1F1E9}\u{1F1EA}e\u{301}!";
+        assert_eq!(
+            tokens(&GraphemeQGrams, graphemes, 2),
+            [
+                ("\u{1F1E9}\u{1F1EA}e\u{301}".to_string(), 0),
+                ("e\u{301}!".to_string(), 1)
+            ]
+        );
+        assert_eq!(TokenizerMode::Graphemes.len_of(graphemes), 3);
+        assert_eq!(
+            TokenizerMode::Chars.units(graphemes, 2, 9),
+            ["e", "\u{301}", "!"]
+        );
+        let words: TokenQGrams = TokenQGrams { punctuation: false };
+        assert_eq!(
+            tokens(&words, " the  quick fox", 2),
+            [("the quick".to_string(), 0), ("quick fox".to_string(), 1)]
+        );
+        assert_eq!(words.mode().len_of("Doe, J."), 2);
+        // the window of `M` and the first byte of `ü` is escaped byte by byte, while `ü` itself is kept
+        assert_eq!(
+            tokens(&QGrams, "Mü", 2),
+            [("\u{14d}\u{1c3}".to_string(), 0), ("ü".to_string(), 1)]
+        );
+        assert_ne!(byte_token(&[0xc3]), byte_token(&[0xbc]));
+        // `TTG` is the reverse complement of `CAA`
+        assert_eq!(
+            tokens(&CanonicalKmers, "TTGc", 3),
+            [("CAA".to_string(), 0), ("TGc".to_string(), 1)]
+        );
+
+        let packed: PackedGrams = PackedGrams::new(b"TGCA").unwrap();
+        let packed_tokens: Vec<(String, usize)> = tokens(&packed, "ACGTAN", 4);
+        // `ACG` and `T` are codes 0, 1, 2 and 3 of 2 bits each, and `N` isn't in the alphabet
+        assert_eq!(packed_tokens[0], ("63".to_string(), 0));
+        assert_eq!(packed_tokens[2], ("~GTAN".to_string(), 2));
+        assert_eq!(
+            tokens(&packed.clone().canonical(), "TTGC", 4),
+            tokens(&packed, "GCAA", 4)
+        );
+        assert!(PackedGrams::new(b"ABCDEFGHI").is_none());
+        // the fixed paths of the common values of q tokenize as the dynamic path does
+        let record: &str = "ACGTTGCANAcGTü";
+        for q in 1..=6 {
+            let dynamic = |token: &dyn Fn(&[u8]) -> String| -> Vec<String> {
+                record.as_bytes().windows(q).map(token).collect()
+            };
+            let tokens = |tokenizer: &dyn Tokenizer| -> Vec<String> {
+                tokenizer
+                    .tokenize(record, q)
+                    .into_iter()
+                    .map(|gram| gram.token)
+                    .collect()
+            };
+            assert_eq!(tokens(&QGrams), dynamic(&byte_token));
+            assert_eq!(
+                tokens(&CanonicalKmers),
+                dynamic(&|kmer| byte_token(&canonical_kmer(kmer)))
+            );
+            assert_eq!(tokens(&packed), dynamic(&|window| packed.pack(window)));
+            assert_eq!(
+                tokens(&packed.clone().canonical()),
+                dynamic(&|window| packed.pack(&canonical_kmer(window)))
+            );
+        }
+
+        let lowercase = FnTokenizer::new("lowercase", |record: &str, q| {
+            QGrams.tokenize(&record.to_lowercase(), q)
+        });
+        assert_eq!(tokens(&lowercase, "AB", 2), [("ab".to_string(), 0)]);
+        assert_eq!(format!("{:?}", lowercase), "FnTokenizer(\"lowercase\")");
+    }
+}
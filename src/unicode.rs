@@ -0,0 +1,170 @@
+#[cfg(feature = "unicode")]
+use unicode_normalization::UnicodeNormalization;
+
+use crate::errors::*;
+
+/// A Unicode normalization form, which records are brought into before they're joined, so that the same text is
+/// always the same units, e.g. `é` as one character or as `e` and a combining acute accent.
+///
+/// Records are normalized by the `unicode-normalization` crate, and thus need the `unicode` feature.
+///
+/// ```
+/// use ed_join::unicode::{normalize, UnicodeForm};
+///
+/// assert_eq!(normalize("Mu\u{308}ller", UnicodeForm::Nfc).unwrap(), "Müller");
+/// assert_eq!(normalize("ﬁancé", UnicodeForm::Nfkd).unwrap(), "fiance\u{301}");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnicodeForm {
+    /// Canonical decomposition, then canonical composition.
+    Nfc,
+    /// Canonical decomposition, e.g. `é` into `e` and a combining acute accent.
+    Nfd,
+    /// Compatibility decomposition, then canonical composition, e.g. `ﬁ` into `fi` and `ｅ` into `e`.
+    Nfkc,
+    /// Compatibility decomposition.
+    Nfkd,
+}
+
+impl UnicodeForm {
+    /// Parse the name of a form, i.e. `nfc`, `nfd`, `nfkc` or `nfkd`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nfc" => Some(UnicodeForm::Nfc),
+            "nfd" => Some(UnicodeForm::Nfd),
+            "nfkc" => Some(UnicodeForm::Nfkc),
+            "nfkd" => Some(UnicodeForm::Nfkd),
+            _ => None,
+        }
+    }
+
+    /// The name of the form, as parsed by [`Self::from_name`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            UnicodeForm::Nfc => "nfc",
+            UnicodeForm::Nfd => "nfd",
+            UnicodeForm::Nfkc => "nfkc",
+            UnicodeForm::Nfkd => "nfkd",
+        }
+    }
+}
+
+/// Bring a record into a normalization form, see [`UnicodeForm`], or an error if the crate is built without the
+/// `unicode` feature.
+pub fn normalize(record: &str, form: UnicodeForm) -> Result<String> {
+    #[cfg(feature = "unicode")]
+    {
+        // ASCII is the same in every form
+        if record.is_ascii() {
+            return Ok(record.to_string());
+        }
+        Ok(match form {
+            UnicodeForm::Nfc => record.nfc().collect(),
+            UnicodeForm::Nfd => record.nfd().collect(),
+            UnicodeForm::Nfkc => record.nfkc().collect(),
+            UnicodeForm::Nfkd => record.nfkd().collect(),
+        })
+    }
+    #[cfg(not(feature = "unicode"))]
+    {
+        let _ = (record, form);
+        bail!("Unicode normalization needs the `unicode` feature of ed_join")
+    }
+}
+
+/// Strip the diacritics of a record, e.g. `Dvořák` into `Dvorak`, whether the letters are precomposed or not,
+/// or an error if the crate is built without the `unicode` feature.
+///
+/// Diacritics are the marks of the combining diacritical marks blocks, so that e.g. the vowel signs of Indic scripts
+/// are kept. Unlike [`crate::alphabet::to_ascii`], letters without a diacritic and other characters are kept,
+/// e.g. `ß` or `“`.
+///
+/// ```
+/// use ed_join::unicode::strip_diacritics;
+///
+/// assert_eq!(strip_diacritics("Dvořák, Straße").unwrap(), "Dvorak, Straße");
+/// ```
+pub fn strip_diacritics(record: &str) -> Result<String> {
+    #[cfg(feature = "unicode")]
+    {
+        if record.is_ascii() {
+            return Ok(record.to_string());
+        }
+        Ok(record.nfd().filter(|c| !is_diacritic(*c)).nfc().collect())
+    }
+    #[cfg(not(feature = "unicode"))]
+    {
+        let _ = record;
+        bail!("Stripping diacritics needs the `unicode` feature of ed_join")
+    }
+}
+
+/// Whether a character is in one of the combining diacritical marks blocks.
+#[cfg(feature = "unicode")]
+fn is_diacritic(c: char) -> bool {
+    matches!(
+        c,
+        '\u{300}'..='\u{36f}'
+            | '\u{1ab0}'..='\u{1aff}'
+            | '\u{1dc0}'..='\u{1dff}'
+            | '\u{20d0}'..='\u{20ff}'
+            | '\u{fe20}'..='\u{fe2f}'
+    )
+}
+
+#[cfg(all(test, feature = "unicode"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unicode_forms() {
+        let normalized = |record: &str, form: UnicodeForm| normalize(record, form).unwrap();
+        // the marks of `ệ` in the wrong order, which are reordered before they're composed
+        assert_eq!(normalized("e\u{302}\u{323}", UnicodeForm::Nfc), "\u{1ec7}");
+        assert_eq!(normalized("\u{1ec7}", UnicodeForm::Nfd), "e\u{323}\u{302}");
+        // a mark of the same class in between blocks the composition
+        assert_eq!(
+            normalized("a\u{301}\u{301}", UnicodeForm::Nfc),
+            "\u{e1}\u{301}"
+        );
+        assert_eq!(
+            normalized("Ａｂｃ\u{3000}½ ﬁancé", UnicodeForm::Nfkc),
+            "Abc 1\u{2044}2 fiancé"
+        );
+        assert_eq!(normalized("x²", UnicodeForm::Nfc), "x²");
+        assert_eq!(normalized("x²", UnicodeForm::Nfkc), "x2");
+        // scripts other than Latin
+        assert_eq!(normalized("\u{438}\u{306}", UnicodeForm::Nfc), "\u{439}");
+        assert_eq!(normalized("\u{3b1}\u{301}", UnicodeForm::Nfc), "\u{3ac}");
+        assert_eq!(
+            normalized("\u{1112}\u{1161}\u{11ab}", UnicodeForm::Nfc),
+            "\u{d55c}"
+        );
+        assert_eq!(
+            normalized("\u{d55c}", UnicodeForm::Nfd),
+            "\u{1112}\u{1161}\u{11ab}"
+        );
+        // the angstrom sign is canonically equivalent to `Å`
+        assert_eq!(normalized("\u{212b}", UnicodeForm::Nfc), "\u{c5}");
+        assert_eq!(normalized("①", UnicodeForm::Nfc), "①");
+        assert_eq!(normalized("①", UnicodeForm::Nfkc), "1");
+
+        let stripped = |record: &str| strip_diacritics(record).unwrap();
+        assert_eq!(
+            stripped("Nguyễn Thị Minh Khai, ﬁ"),
+            "Nguyen Thi Minh Khai, ﬁ"
+        );
+        assert_eq!(stripped("Mu\u{308}ller"), "Muller");
+        assert_eq!(stripped("\u{439}\u{3ac}"), "\u{438}\u{3b1}");
+        // vowel signs aren't diacritics
+        assert_eq!(stripped("\u{915}\u{93f}"), "\u{915}\u{93f}");
+        for form in [
+            UnicodeForm::Nfc,
+            UnicodeForm::Nfd,
+            UnicodeForm::Nfkc,
+            UnicodeForm::Nfkd,
+        ] {
+            assert_eq!(UnicodeForm::from_name(form.name()), Some(form));
+        }
+    }
+}
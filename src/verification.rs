@@ -1,221 +1,293 @@
-use edit_distance::edit_distance;
-use rayon::prelude::*;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
 
-use crate::matching::min_edit_errors;
+use crate::config::Filters;
+use crate::filters::{
+    compare_qgrams, CandidatePair, ContentFiltering, CountFiltering, Filter, LocationFiltering,
+};
 use crate::qgram::*;
+use crate::timeline::{self, Region, RegionTimer};
+use crate::tokenizer::TokenizerMode;
 
-type RightError = usize;
-type SuffixSumArray = Vec<(Loc, RightError)>;
+/// The edit distance which pairs of records are matched within `tau` by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// Insertions, deletions and substitutions of units.
+    #[default]
+    Levenshtein,
+    /// Also transpositions of adjacent units, each as one edit, where no unit is edited more than once,
+    /// i.e. the optimal string alignment distance, so that `Jhon` is within 1 of `John`.
+    DamerauLevenshtein,
+}
 
-// Algorithm 8
-/// Given two sorted q-gram arrays, in increasing order of location,
-/// find the set of loosely mismatching q-grams and the number of strictly mismatching q-grams.
-///
-/// # Parameters
-///
-///  * `x` and `y`: PosQGramArrays as `source` and `target` for matching.
-///  * `invert`: The inverted index.
-///  * `tau`: A positive integer as the tuning parameter for threshold for matching.
-///
-/// # Return
-///
-///  * A set of loosely mismatching q-grams from `x` to `y`.
-///  * The number of strictly mismatching q-grams from `x` to `y`.
-fn compare_qgrams(
-    x: &PosQGramArray,
-    y: &PosQGramArray,
-    invert: &InvertedIndex,
-    tau: usize,
-) -> (PosQGramArray, usize) {
-    let mut i: usize = 0;
-    let mut j: usize = 0;
-    let mut epsilon: usize = 0;
-    let mut loose_mismatch: PosQGramArray = PosQGramArray::new();
-
-    let comparator = |x: &PosQGramArray,
-                      y: &PosQGramArray,
-                      i: &mut usize,
-                      j: usize,
-                      epsilon: &mut usize,
-                      loose_mismatch: &mut PosQGramArray| {
-        if ((*i >= 1) && (x[*i].token != x[*i - 1].token))
-            || ((j >= 1) && (x[*i].token != y[j - 1].token))
-            || ((j >= 1) && ((x[*i].loc as isize - y[j - 1].loc as isize).abs() > tau as isize))
-        {
-            loose_mismatch.push(x[*i].clone());
+impl DistanceMetric {
+    /// Parse the name of a metric, i.e. `levenshtein` or `damerau`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "levenshtein" => Some(DistanceMetric::Levenshtein),
+            "damerau" => Some(DistanceMetric::DamerauLevenshtein),
+            _ => None,
         }
-        *i += 1;
-        *epsilon += 1;
-    };
+    }
 
-    let get_len = |token_x: &Token| invert.get(token_x).unwrap().1;
-
-    while i < x.len() && j < y.len() {
-        if x[i].token == y[j].token {
-            if (x[i].loc as isize - y[j].loc as isize).abs() <= tau as isize {
-                i += 1;
-                j += 1;
-            } else if x[i].loc < y[j].loc {
-                comparator(x, y, &mut i, j, &mut epsilon, &mut loose_mismatch);
-            } else {
-                j += 1;
-            }
-        } else if (get_len(&x[i].token) < get_len(&y[j].token))
-            || ((get_len(&x[i].token) == get_len(&y[j].token))
-                & ((x[i].token.as_bytes()) < (y[j].token.as_bytes())))
-        {
-            comparator(x, y, &mut i, j, &mut epsilon, &mut loose_mismatch);
-        } else {
-            j += 1;
+    /// The name of the metric, as parsed by [`Self::from_name`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            DistanceMetric::Levenshtein => "levenshtein",
+            DistanceMetric::DamerauLevenshtein => "damerau",
         }
     }
-    while i < x.len() {
-        comparator(x, y, &mut i, j, &mut epsilon, &mut loose_mismatch);
-    }
-
-    loose_mismatch.sort_by_location();
 
-    (loose_mismatch, epsilon)
+    /// The threshold of the Levenshtein distance which the filters prune pairs by, for a threshold `tau` of this metric.
+    ///
+    /// A transposition is two substitutions, so pairs within `tau` transpositions are within `2 * tau` of Levenshtein.
+    pub fn filter_tau(&self, tau: usize) -> usize {
+        match self {
+            DistanceMetric::Levenshtein => tau,
+            DistanceMetric::DamerauLevenshtein => 2 * tau,
+        }
+    }
 }
 
-// Based on Algorithm 2
-/// Given a set of q-grams, find the minimum number of edit operations in the suffix that destroys all q-grams.
-///
-/// # Parameters
-///
-///  * `qgram_array`: A PosQGramArray, i.e. a set of positional q-grams.
-///  * `q`: A positive integer as the tuning parameter for length of q-grams.
-///
-/// # Return
+/// Reusable rows and units of the dynamic programming of edit distances,
+/// so that verifying many pairs doesn't allocate for each of them.
 ///
-/// The minimum number of edit operations on the suffix that destroy all q-grams.
-fn sum_right_errors(qgram_array: &mut PosQGramArray, q: usize) -> Option<SuffixSumArray> {
-    if qgram_array.len() == 0 {
-        None
-    } else {
-        qgram_array.reverse();
-        let mut cnt: usize = 0;
-        let mut loc: usize = qgram_array[0].loc + 1;
-
-        let mut suffix_sum: SuffixSumArray = Vec::new();
-
-        qgram_array.iter().for_each(|qgram| {
-            if qgram.loc < loc {
-                cnt += 1;
-                suffix_sum.push((qgram.loc, cnt));
-                if qgram.loc + 1 >= q {
-                    loc = qgram.loc + 1 - q;
-                } else {
-                    loc = 0;
+/// Verifying all candidates of a record with one buffer, sized to the longest of them, allocates only once.
+#[derive(Clone, Debug, Default)]
+pub struct EditDistanceBuffer {
+    s: Vec<u32>,
+    t: Vec<u32>,
+    prev: Vec<usize>,
+    curr: Vec<usize>,
+    /// The row before `prev`, for transpositions.
+    prev2: Vec<usize>,
+    /// IDs of the grapheme clusters of more than one character and of words, above those of all characters.
+    clusters: HashMap<String, u32>,
+}
+
+/// Fill `units` with the units of a record, as characters, or IDs of grapheme clusters of more than one character and words.
+fn fill_units(
+    units: &mut Vec<u32>,
+    record: &str,
+    mode: TokenizerMode,
+    clusters: &mut HashMap<String, u32>,
+) {
+    units.clear();
+    match mode {
+        TokenizerMode::Bytes => units.extend(record.bytes().map(u32::from)),
+        TokenizerMode::Chars => units.extend(record.chars().map(u32::from)),
+        _ => units.extend(mode.spans(record).into_iter().map(|(start, end)| {
+            let cluster: &str = &record[start..end];
+            let mut chars = cluster.chars();
+            match (chars.next(), chars.next()) {
+                // a word of a single character is an ID of its own, so that it differs from a word like `a1`
+                (Some(c), None) if mode == TokenizerMode::Graphemes => u32::from(c),
+                _ => {
+                    let next: u32 = u32::from(char::MAX) + 1 + clusters.len() as u32;
+                    *clusters.entry(cluster.to_string()).or_insert(next)
                 }
             }
-        });
-
-        qgram_array.reverse();
-        Some(suffix_sum)
+        })),
     }
 }
 
-fn frequency_histogram(s: &str) -> HashMap<char, usize> {
-    let mut map: HashMap<char, usize> = HashMap::new();
+impl EditDistanceBuffer {
+    /// A buffer for strings of up to `len` characters, which still grows for longer ones.
+    pub fn with_capacity(len: usize) -> Self {
+        Self {
+            s: Vec::with_capacity(len),
+            t: Vec::with_capacity(len),
+            prev: Vec::with_capacity(len + 1),
+            curr: Vec::with_capacity(len + 1),
+            prev2: Vec::with_capacity(len + 1),
+            clusters: HashMap::new(),
+        }
+    }
 
-    s.chars().for_each(|c| {
-        map.entry(c).and_modify(|v| *v += 1).or_insert(1);
-    });
+    /// Edit distance of two strings, by characters, the same as `edit_distance::edit_distance()`.
+    pub fn edit_distance(&mut self, s: &str, t: &str) -> usize {
+        self.edit_distance_in(s, t, TokenizerMode::Chars)
+    }
 
-    map
-}
+    /// Edit distance of two strings, by the units of `mode`, e.g. bytes for [`TokenizerMode::Bytes`].
+    pub fn edit_distance_in(&mut self, s: &str, t: &str, mode: TokenizerMode) -> usize {
+        self.distance_in(s, t, DistanceMetric::Levenshtein, mode)
+    }
 
-// Algorithm 6
-/// Given two strings, calculate their L1 distance.
-///
-/// # Parameters
-///
-///  * `s` and `t`: (Sub-)String that is under probing window.
-///  * `lo` and `hi`: Indicates the start and end point of the probing window.
-///
-/// # Return
-///
-/// L1 distance of the two given strings with given probing window.
-fn l1_distance(s: &str, t: &str, lo: usize, hi: usize) -> usize {
-    let h_s: HashMap<char, usize> = frequency_histogram(&s[lo..hi]);
-    let h_t: HashMap<char, usize> = frequency_histogram(&t[lo..hi]);
-
-    let mut keys: Vec<&char> = h_s.keys().collect::<Vec<&char>>();
-    keys.append(&mut h_t.keys().collect::<Vec<&char>>());
-    keys.par_sort();
-    keys.dedup();
-
-    let mut v_s: Vec<usize> = Vec::with_capacity(keys.len());
-    let mut v_t: Vec<usize> = Vec::with_capacity(keys.len());
-
-    keys.iter().for_each(|k| {
-        v_s.push(*h_s.get(k).unwrap_or(&0));
-        v_t.push(*h_t.get(k).unwrap_or(&0));
-    });
-
-    let distance: usize = v_s
-        .par_iter()
-        .zip_eq(v_t.par_iter())
-        .map(|(a, b)| (*a as isize - *b as isize).abs() as usize)
-        .sum();
-    distance
-}
+    /// Distance of two strings by `metric`, by the units of `mode`, see [`Self::edit_distance_in`].
+    pub fn distance_in(
+        &mut self,
+        s: &str,
+        t: &str,
+        metric: DistanceMetric,
+        mode: TokenizerMode,
+    ) -> usize {
+        let Self {
+            s: sc,
+            t: tc,
+            prev,
+            curr,
+            prev2,
+            clusters,
+        } = self;
+        clusters.clear();
+        fill_units(sc, s, mode, clusters);
+        fill_units(tc, t, mode, clusters);
+        prev.clear();
+        prev.extend(0..=tc.len());
+        curr.clear();
+        curr.resize(tc.len() + 1, 0);
+        prev2.clear();
+        prev2.resize(tc.len() + 1, 0);
+        let transpositions: bool = metric == DistanceMetric::DamerauLevenshtein;
 
-// Algorithm 5
-/// Content-based mismatch filtering by combining L1-distance and minimum edit errors in the suffix to the probing window.
-///
-/// # Parameters
-///
-///  * `from` and `to`: (Sub-)String that is under probing window.
-///  * `mismatch`: A PosQGramArray with loosely mismatching q-grams from `s` to `t`.
-///  * `suffix_sum`: A condensed suffix sum array.
-///  * `q`: A positive integer as the tuning parameter for length of q-grams.
-///  * `tau`: A positive integer as the tuning parameter for threshold for matching.
-///
-/// # Return
-///
-/// A lower bound of the edit distance from `s` to `t`.
-fn content_filter(
-    from: &str,
-    to: &str,
-    mismatch: PosQGramArray,
-    suffix_sum: SuffixSumArray,
-    q: usize,
-    tau: usize,
-) -> Option<usize> {
-    let mut i: usize = 1;
-    let mut j: usize = 0;
-    let mut epsilon: usize;
-
-    let epsi = |s, t, mismatch: &PosQGramArray, q, ii: usize, jj: usize| {
-        let l1 = l1_distance(s, t, mismatch[jj].loc, mismatch[ii - 1].loc + q - 1);
-        let right_error = suffix_sum
-            .par_iter()
-            .find_first(|e| e.0 >= mismatch[ii - 1].loc + q) // e is a PosQGram, e.0 is location
-            .unwrap_or(&(0, 0)) // returns (Loc, RightError)
-            .1; // returns RightError
-        l1 / 2 + right_error // NOTE: I believe author had a typo here and I fixed it
-    };
+        for i in 1..=sc.len() {
+            curr[0] = i;
+            for j in 1..=tc.len() {
+                let substitution: usize = prev[j - 1] + (sc[i - 1] != tc[j - 1]) as usize;
+                curr[j] = substitution.min(prev[j] + 1).min(curr[j - 1] + 1);
+                if transpositions
+                    && i > 1
+                    && j > 1
+                    && sc[i - 1] == tc[j - 2]
+                    && sc[i - 2] == tc[j - 1]
+                {
+                    curr[j] = curr[j].min(prev2[j - 2] + 1);
+                }
+            }
+            std::mem::swap(prev2, prev);
+            std::mem::swap(prev, curr);
+        }
+
+        prev[tc.len()]
+    }
 
-    // otherwise index is out-of-bound
-    if mismatch.len() >= 2 {
-        while i < mismatch.len() {
-            if mismatch[i].loc - mismatch[i - 1].loc > 1 {
-                epsilon = epsi(from, to, &mismatch, q, i, j);
-                if epsilon > tau {
-                    return Some(2 * tau + 1);
+    /// Edit distance of two strings, if it's at most `tau`, by dynamic programming restricted to a band of `2 * tau + 1` diagonals.
+    ///
+    /// This takes `O(tau * len)` time, and stops as soon as a row of the band exceeds `tau`,
+    /// so it's cheaper than the filters for short records, see [`crate::config::EdJoinConfig::bypass_len`].
+    pub fn bounded_edit_distance(&mut self, s: &str, t: &str, tau: usize) -> Option<usize> {
+        self.bounded_edit_distance_in(s, t, tau, TokenizerMode::Chars)
+    }
+
+    /// Same as [`Self::bounded_edit_distance`], but by the units of `mode`, see [`Self::edit_distance_in`].
+    pub fn bounded_edit_distance_in(
+        &mut self,
+        s: &str,
+        t: &str,
+        tau: usize,
+        mode: TokenizerMode,
+    ) -> Option<usize> {
+        self.bounded_distance_in(s, t, tau, DistanceMetric::Levenshtein, mode)
+    }
+
+    /// Same as [`Self::bounded_edit_distance_in`], but by `metric`.
+    pub fn bounded_distance_in(
+        &mut self,
+        s: &str,
+        t: &str,
+        tau: usize,
+        metric: DistanceMetric,
+        mode: TokenizerMode,
+    ) -> Option<usize> {
+        let Self {
+            s: sc,
+            t: tc,
+            prev,
+            curr,
+            prev2,
+            clusters,
+        } = self;
+        clusters.clear();
+        fill_units(sc, s, mode, clusters);
+        fill_units(tc, t, mode, clusters);
+        // neither metric changes the length by more than one unit per edit
+        if (sc.len() as isize - tc.len() as isize).abs() > tau as isize {
+            return None;
+        }
+        let transpositions: bool = metric == DistanceMetric::DamerauLevenshtein;
+
+        // distances larger than `tau` are all the same, i.e. `tau + 1`, which is also used outside of the band
+        let bound: usize = tau + 1;
+        prev.clear();
+        prev.extend((0..=tc.len()).map(|j| j.min(bound)));
+        curr.clear();
+        curr.resize(tc.len() + 1, bound);
+        prev2.clear();
+        prev2.resize(tc.len() + 1, bound);
+        for i in 1..=sc.len() {
+            curr.iter_mut().for_each(|d| *d = bound);
+            curr[0] = i.min(bound);
+            let (lo, hi): (usize, usize) = (i.saturating_sub(tau).max(1), (i + tau).min(tc.len()));
+            for j in lo..=hi {
+                let substitution: usize = prev[j - 1] + (sc[i - 1] != tc[j - 1]) as usize;
+                curr[j] = substitution
+                    .min(prev[j] + 1)
+                    .min(curr[j - 1] + 1)
+                    .min(bound);
+                if transpositions
+                    && i > 1
+                    && j > 1
+                    && sc[i - 1] == tc[j - 2]
+                    && sc[i - 2] == tc[j - 1]
+                {
+                    curr[j] = curr[j].min(prev2[j - 2] + 1);
                 }
-                j = i;
             }
-            i += 1;
+            // a transposition from two rows back is never cheaper than a substitution from the row in between,
+            // so the distance can't come back below `tau` either way
+            if curr[0] > tau && curr[lo..=hi].iter().all(|d| *d > tau) {
+                return None;
+            }
+            std::mem::swap(prev2, prev);
+            std::mem::swap(prev, curr);
         }
 
-        let epsilon = epsi(from, to, &mismatch, q, i, j);
-        Some(epsilon)
-    } else {
-        None
+        Some(prev[tc.len()]).filter(|ed| *ed <= tau)
+    }
+}
+
+/// Edit distance of two strings, if it's at most `tau`, see [`EditDistanceBuffer::bounded_edit_distance`].
+pub fn bounded_edit_distance(s: &str, t: &str, tau: usize) -> Option<usize> {
+    EditDistanceBuffer::default().bounded_edit_distance(s, t, tau)
+}
+
+/// Which stage decided about a pair of records, see [`filter_pair`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The lengths of the records differ by more than `tau`.
+    LengthFilter,
+    /// The records share no q-gram in the prefix of `x` within `tau` positions, so they're never candidates.
+    PrefixFilter,
+    /// Too many q-grams of `x` mismatch, i.e. `epsilon` is larger than `q * tau`.
+    CountFilter { epsilon: usize },
+    /// The mismatching q-grams of `x` need more than `tau` edit operations to be destroyed.
+    LocationFilter { epsilon: usize },
+    /// A lower bound of the edit distance, by the content of mismatching q-grams, is larger than `tau`.
+    ContentFilter { epsilon: usize },
+    /// The pair passed all filters, but its edit distance `ed` is larger than `tau`.
+    ///
+    /// For pairs of short records, which bypass the filters, `ed` is only a lower bound, i.e. `tau + 1`.
+    Rejected { ed: usize },
+    /// The pair matches with this edit distance.
+    Matched { ed: usize },
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verdict::LengthFilter => write!(f, "length_filter"),
+            Verdict::PrefixFilter => write!(f, "prefix_filter"),
+            Verdict::CountFilter { epsilon } => write!(f, "count_filter({})", epsilon),
+            Verdict::LocationFilter { epsilon } => write!(f, "location_filter({})", epsilon),
+            Verdict::ContentFilter { epsilon } => write!(f, "content_filter({})", epsilon),
+            Verdict::Rejected { ed } => write!(f, "rejected({})", ed),
+            Verdict::Matched { ed } => write!(f, "matched({})", ed),
+        }
     }
 }
 
@@ -232,10 +304,14 @@ fn content_filter(
 /// * `inverted`: The inverted index.
 /// * `q`: A positive integer as the tuning parameter for length of q-grams.
 /// * `tau`: A positive integer as the tuning parameter for threshold for matching.
+/// * `metric`: The distance which pairs are matched by. The filters prune by the Levenshtein distance
+///   within [`DistanceMetric::filter_tau`], which is admissible for both metrics.
+/// * `mode`: The units of the q-grams, see [`crate::tokenizer::Tokenizer::mode`].
 ///
 /// # Return
 ///
 /// Verified matched paris from the candidates set.
+#[allow(clippy::too_many_arguments)]
 pub fn verify(
     x: Vec<PosQGram>,
     line_id: usize,
@@ -243,24 +319,232 @@ pub fn verify(
     y: &mut PosQGramArray,
     candidate_id: usize,
     candidate_content: &str,
-    inverted: &InvertedIndex,
+    inverted: &dyn IndexBackend,
     q: usize,
     tau: usize,
+    metric: DistanceMetric,
+    mode: TokenizerMode,
 ) -> Option<(ID, Vec<(ID, usize)>)> {
-    #[cfg(feature = "cli")]
+    match filter_pair(
+        x,
+        line_id,
+        line_content,
+        y,
+        candidate_id,
+        candidate_content,
+        inverted,
+        q,
+        tau,
+        metric,
+        mode,
+    ) {
+        Verdict::Matched { ed } => Some((line_id, vec![(candidate_id, ed)])),
+        _ => None,
+    }
+}
+
+/// Apply the count, location-based and content-based filters to a candidate pair, and then calculate its edit distance
+/// if it passes all of them, i.e. [`filter_pair_with`] the pipeline of [`crate::filters`].
+///
+/// The parameters are the same as [`verify`].
+///
+/// # Return
+///
+/// The verdict on the pair, i.e. the filter which rejected it, or its edit distance.
+#[allow(clippy::too_many_arguments)]
+pub fn filter_pair(
+    x: Vec<PosQGram>,
+    line_id: usize,
+    line_content: &str,
+    y: &mut PosQGramArray,
+    candidate_id: usize,
+    candidate_content: &str,
+    inverted: &dyn IndexBackend,
+    q: usize,
+    tau: usize,
+    metric: DistanceMetric,
+    mode: TokenizerMode,
+) -> Verdict {
+    let mut buffer: EditDistanceBuffer = EditDistanceBuffer::default();
+    filter_pair_profiled(
+        x,
+        line_id,
+        line_content,
+        y,
+        candidate_id,
+        candidate_content,
+        inverted,
+        q,
+        tau,
+        metric,
+        None,
+        Filters::default(),
+        mode,
+        &mut buffer,
+    )
+}
+
+/// Cost and selectivity of the content-based filter, measured on the first pairs of a join,
+/// to decide whether it's worth it for the rest of the join.
+///
+/// The count and location-based filters are kept regardless, as they're cheap and the later filters depend on them.
+/// Once `sample` pairs went through the content-based filter, it's disabled if it took longer than
+/// the edit distances it saved would have, as estimated by the average time of the edit distances calculated so far.
+#[derive(Debug)]
+pub struct FilterProfile {
+    sample: usize,
+    content_pairs: AtomicUsize,
+    content_pruned: AtomicUsize,
+    content_nanos: AtomicU64,
+    ed_pairs: AtomicUsize,
+    ed_nanos: AtomicU64,
+    content_enabled: AtomicBool,
+}
+
+impl FilterProfile {
+    pub fn new(sample: usize) -> Self {
+        Self {
+            sample,
+            content_pairs: AtomicUsize::new(0),
+            content_pruned: AtomicUsize::new(0),
+            content_nanos: AtomicU64::new(0),
+            ed_pairs: AtomicUsize::new(0),
+            ed_nanos: AtomicU64::new(0),
+            content_enabled: AtomicBool::new(true),
+        }
+    }
+
+    /// Whether the content-based filter is still applied.
+    pub fn content_filter_enabled(&self) -> bool {
+        self.content_enabled.load(Ordering::Relaxed)
+    }
+
+    fn record_ed(&self, elapsed: Duration) {
+        self.ed_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        self.ed_pairs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_content(&self, elapsed: Duration, pruned: bool) {
+        self.content_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        if pruned {
+            self.content_pruned.fetch_add(1, Ordering::Relaxed);
+        }
+        if self.content_pairs.fetch_add(1, Ordering::Relaxed) + 1 != self.sample {
+            return;
+        }
+
+        let ed_pairs: u64 = self.ed_pairs.load(Ordering::Relaxed) as u64;
+        if ed_pairs == 0 {
+            return;
+        }
+        let saved: u64 = self.content_pruned.load(Ordering::Relaxed) as u64
+            * (self.ed_nanos.load(Ordering::Relaxed) / ed_pairs);
+        if self.content_nanos.load(Ordering::Relaxed) > saved {
+            self.content_enabled.store(false, Ordering::Relaxed);
+            #[cfg(feature = "logging")]
+            info!(
+                "Disabled the content-based filter after {} pairs, as it cost more than it saved",
+                self.sample
+            );
+        }
+    }
+}
+
+/// Same as [`filter_pair`], but with the content-based filter profiled, and skipped if the profile disabled it,
+/// only the given filters applied, and the edit distance calculated with the given buffer.
+#[allow(clippy::too_many_arguments)]
+pub fn filter_pair_profiled(
+    x: Vec<PosQGram>,
+    line_id: usize,
+    line_content: &str,
+    y: &mut PosQGramArray,
+    candidate_id: usize,
+    candidate_content: &str,
+    inverted: &dyn IndexBackend,
+    q: usize,
+    tau: usize,
+    metric: DistanceMetric,
+    profile: Option<&FilterProfile>,
+    filters: Filters,
+    mode: TokenizerMode,
+    buffer: &mut EditDistanceBuffer,
+) -> Verdict {
+    // the pipeline of the filters enabled, in their order, without allocating one for each pair
+    let content: ContentFiltering = ContentFiltering { profile };
+    let stages: [(&dyn Filter, bool); 3] = [
+        (&CountFiltering, filters.count),
+        (&LocationFiltering, filters.location),
+        (
+            &content,
+            filters.content && profile.map_or(true, |profile| profile.content_filter_enabled()),
+        ),
+    ];
+    let mut pipeline: [&dyn Filter; 3] = [&CountFiltering; 3];
+    let mut len: usize = 0;
+    for (filter, enabled) in stages {
+        if enabled {
+            pipeline[len] = filter;
+            len += 1;
+        }
+    }
+    filter_pair_with(
+        &pipeline[..len],
+        x,
+        line_id,
+        line_content,
+        y,
+        candidate_id,
+        candidate_content,
+        inverted,
+        q,
+        tau,
+        metric,
+        profile,
+        mode,
+        buffer,
+    )
+}
+
+/// Same as [`filter_pair_profiled`], but with the pair going through the given pipeline of filters in turn, until one
+/// prunes it, e.g. the filters of [`crate::filters`] in another order, or along with filters of the caller's own.
+///
+/// The loosely and strictly mismatching q-grams of the pair are found before the first filter, as all filters
+/// of [`crate::filters`] depend on them, and the edit distance is calculated if no filter prunes the pair.
+#[allow(clippy::too_many_arguments)]
+pub fn filter_pair_with(
+    pipeline: &[&dyn Filter],
+    x: Vec<PosQGram>,
+    line_id: usize,
+    line_content: &str,
+    y: &mut PosQGramArray,
+    candidate_id: usize,
+    candidate_content: &str,
+    inverted: &dyn IndexBackend,
+    q: usize,
+    tau: usize,
+    metric: DistanceMetric,
+    profile: Option<&FilterProfile>,
+    mode: TokenizerMode,
+    buffer: &mut EditDistanceBuffer,
+) -> Verdict {
+    #[cfg(feature = "logging")]
     debug!(
         "Verify `{}: {}` against `{}: {}`",
         line_id, line_content, candidate_id, candidate_content
     );
-    let mut out: Vec<(ID, usize)> = Vec::new();
-
+    // the filters bound the Levenshtein distance, so they prune by its threshold
+    let filter_tau: usize = metric.filter_tau(tau);
+    let count_region: RegionTimer = timeline::region(Region::CountFilter);
     // PosQGramArray is only sorted in increasing order of location, now sort it in increasing order of frequency
     let mut x = PosQGramArray { inner: x };
     x.sort_by_frequency(inverted);
     y.sort_by_frequency(inverted);
 
-    let (mut loose_mismatch, epsilon_1) = compare_qgrams(&x, &y, &inverted, q);
-    #[cfg(feature = "cli")]
+    // q-grams which aren't destroyed are shifted by at most the threshold, rather than by `q`
+    let (loose_mismatch, epsilon_1) = compare_qgrams(&x, y, inverted, filter_tau);
+    #[cfg(feature = "logging")]
     trace!(
         "x: {}\n y: {}\n Loosely-Mismatch: {}\n # of Strongly Mismatch: {}",
         x,
@@ -268,127 +552,177 @@ pub fn verify(
         loose_mismatch,
         epsilon_1
     );
+    drop(count_region);
 
-    // count filtering
-    #[cfg(feature = "cli")]
+    let mut pair: CandidatePair = CandidatePair {
+        x_id: line_id,
+        x: line_content,
+        y_id: candidate_id,
+        y: candidate_content,
+        q,
+        tau: filter_tau,
+        mode,
+        mismatch: loose_mismatch,
+        strict_mismatches: epsilon_1,
+    };
+    if let Some(verdict) = pipeline.iter().find_map(|filter| filter.apply(&mut pair)) {
+        return verdict;
+    }
+
+    // edit distance of pairs which passed all filters
+    let start: Instant = Instant::now();
+    let _region = timeline::region(Region::EditDistance);
+    let ed: usize = buffer.distance_in(line_content, candidate_content, metric, mode);
+    if let Some(profile) = profile {
+        profile.record_ed(start.elapsed());
+    }
+    #[cfg(feature = "logging")]
     trace!(
-        "Count filtering on `{}: {}`: epsilon_1 = {}",
+        "Ed of `{}: {}` against `{}: {}`",
+        line_id,
+        line_content,
         candidate_id,
-        candidate_content,
-        epsilon_1
+        candidate_content
     );
-    if epsilon_1 <= q * tau {
-        // loose_mismatch is a PosQGramArray, which is generated from &x, &y, who were sorted in increasing order of frequency
-        // now sort it in increasing order of location
-        loose_mismatch.par_sort_by_key(|qgram| qgram.loc);
-        let epsilon_2 = min_edit_errors(&loose_mismatch, q);
-
-        // location-based filtering
-        #[cfg(feature = "cli")]
+    if ed <= tau {
+        #[cfg(feature = "logging")]
         trace!(
-            "Location-based filtering on `{}: {}`: epsilon_2 = {}",
+            "Add `{}: {}` to matched set of `{}: {}`",
+            line_id,
+            line_content,
             candidate_id,
-            candidate_content,
-            epsilon_2
+            candidate_content
         );
-        if epsilon_2 <= tau {
-            if let Some(right_error) = sum_right_errors(&mut loose_mismatch, q) {
-                let suffix_sum_array: SuffixSumArray = right_error;
-                #[cfg(feature = "cli")]
-                trace!("Suffix Sum Array: {:?}", suffix_sum_array);
-                let epsilon_3 = content_filter(
-                    line_content,
-                    candidate_content,
-                    loose_mismatch,
-                    suffix_sum_array,
-                    q,
-                    tau,
+        Verdict::Matched { ed }
+    } else {
+        Verdict::Rejected { ed }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use edit_distance::edit_distance;
+
+    #[test]
+    fn test_edit_distance_buffer() {
+        let pairs = [
+            ("kitten", "sitting"),
+            ("hello", "hallo"),
+            ("abc", "abc"),
+            ("", "ab"),
+            ("flaw", "lawn"),
+            ("abcd", "dcba"),
+        ];
+        let mut buffer: EditDistanceBuffer = EditDistanceBuffer::with_capacity(4);
+        for (s, t) in pairs.iter() {
+            let ed: usize = edit_distance(s, t);
+            assert_eq!(buffer.edit_distance(s, t), ed);
+            for tau in 0..5 {
+                assert_eq!(
+                    bounded_edit_distance(s, t, tau),
+                    Some(ed).filter(|ed| *ed <= tau),
+                    "{} {} {}",
+                    s,
+                    t,
+                    tau
                 );
+            }
+        }
+    }
 
-                // content-based filtering
-                if let Some(v) = epsilon_3 {
-                    #[cfg(feature = "cli")]
-                    trace!(
-                        "Content-based filtering on `{}: {}`: epsilon_3 = {}",
-                        candidate_id,
-                        candidate_content,
-                        v,
-                    );
-                    // NOTE: I believe author made a mistake here
-                    if v <= tau {
-                        let ed: usize = edit_distance(line_content, candidate_content);
-                        #[cfg(feature = "cli")]
-                        trace!(
-                            "Ed of `{}: {}` against `{}: {}`",
-                            line_id,
-                            line_content,
-                            candidate_id,
-                            candidate_content
-                        );
-                        if ed <= tau {
-                            #[cfg(feature = "cli")]
-                            trace!(
-                                "Add `{}: {}` to matched set of `{}: {}`",
-                                line_id,
-                                line_content,
-                                candidate_id,
-                                candidate_content
-                            );
-                            out.push((candidate_id, ed));
-                        }
-                    }
-                } else {
-                    // when mismatch is empty, cannot apply content filter, go to this branch
-                    let ed: usize = edit_distance(line_content, candidate_content);
-                    #[cfg(feature = "cli")]
-                    trace!(
-                        "Ed of `{}: {}` against `{}: {}`",
-                        line_id,
-                        line_content,
-                        candidate_id,
-                        candidate_content
-                    );
-                    if ed <= tau {
-                        #[cfg(feature = "cli")]
-                        trace!(
-                            "Add `{}: {}` to matched set of `{}: {}`",
-                            line_id,
-                            line_content,
-                            candidate_id,
-                            candidate_content
-                        );
-                        out.push((candidate_id, ed));
-                    }
-                }
-            } else {
-                // when mismatch is empty, sum_right_errors is empty, go to this branch
-                let ed: usize = edit_distance(line_content, candidate_content);
-                #[cfg(feature = "cli")]
-                trace!(
-                    "Ed of `{}: {}` against `{}: {}`",
-                    line_id,
-                    line_content,
-                    candidate_id,
-                    candidate_content
+    #[test]
+    fn test_damerau_distance() {
+        let metric: DistanceMetric = DistanceMetric::DamerauLevenshtein;
+        // `ca` to `abc` is 3 in the optimal string alignment distance, as `ca` isn't edited again once it's transposed
+        let pairs = [
+            ("John", "Jhon", 1),
+            ("abcd", "badc", 2),
+            ("ca", "abc", 3),
+            ("kitten", "sitting", 3),
+            ("", "ab", 2),
+        ];
+        let mut buffer: EditDistanceBuffer = EditDistanceBuffer::default();
+        for (s, t, d) in pairs.iter() {
+            assert_eq!(
+                buffer.distance_in(s, t, metric, TokenizerMode::Chars),
+                *d,
+                "{} {}",
+                s,
+                t
+            );
+            for tau in 0..5 {
+                let bounded: Option<usize> =
+                    buffer.bounded_distance_in(s, t, tau, metric, TokenizerMode::Chars);
+                assert_eq!(
+                    bounded,
+                    Some(*d).filter(|d| *d <= tau),
+                    "{} {} {}",
+                    s,
+                    t,
+                    tau
                 );
-                if ed <= tau {
-                    #[cfg(feature = "cli")]
-                    trace!(
-                        "Add `{}: {}` to matched set of `{}: {}`",
-                        line_id,
-                        line_content,
-                        candidate_id,
-                        candidate_content
-                    );
-                    out.push((candidate_id, ed));
-                }
             }
         }
+        assert_eq!(DistanceMetric::from_name(metric.name()), Some(metric));
+        assert_eq!(metric.filter_tau(2), 4);
     }
 
-    if !out.is_empty() {
-        Some((line_id, out))
-    } else {
-        None
+    #[test]
+    fn test_filter_pipeline() {
+        struct Lengths;
+        impl Filter for Lengths {
+            fn apply(&self, pair: &mut CandidatePair<'_>) -> Option<Verdict> {
+                Some(Verdict::LengthFilter)
+                    .filter(|_| pair.x.len().abs_diff(pair.y.len()) > pair.tau)
+            }
+        }
+
+        let (q, tau): (usize, usize) = (2, 1);
+        let records: Vec<String> = ["abcdef", "abxdef", "fedcba", "abcdefgh"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let index: InvertedIndex = build_inverted_index(None, &records, q);
+        let verdict = |pipeline: &[&dyn Filter], y: usize| {
+            let mut y_array: PosQGramArray = PosQGramArray::from(&records[y], q);
+            let x: Vec<PosQGram> = PosQGramArray::from(&records[0], q).inner;
+            let mut buffer: EditDistanceBuffer = EditDistanceBuffer::default();
+            filter_pair_with(
+                pipeline,
+                x,
+                0,
+                &records[0],
+                &mut y_array,
+                y,
+                &records[y],
+                &index,
+                q,
+                tau,
+                DistanceMetric::Levenshtein,
+                None,
+                TokenizerMode::Bytes,
+                &mut buffer,
+            )
+        };
+
+        // the filters apply in the order of the pipeline, and without any, the edit distance decides
+        let content: ContentFiltering = ContentFiltering::default();
+        let builtin: [&dyn Filter; 3] = [&CountFiltering, &LocationFiltering, &content];
+        assert_eq!(verdict(&builtin, 1), Verdict::Matched { ed: 1 });
+        assert_eq!(verdict(&builtin, 2), Verdict::CountFilter { epsilon: 5 });
+        assert_eq!(
+            verdict(&[&LocationFiltering, &CountFiltering], 2),
+            Verdict::LocationFilter { epsilon: 3 }
+        );
+        assert_eq!(verdict(&[], 2), Verdict::Rejected { ed: 6 });
+        assert_eq!(
+            verdict(&[&Lengths, &CountFiltering], 3),
+            Verdict::LengthFilter
+        );
+        assert_eq!(
+            verdict(&[&CountFiltering, &LocationFiltering, &content], 3),
+            Verdict::Rejected { ed: 2 }
+        );
     }
 }
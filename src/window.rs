@@ -0,0 +1,223 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::config::EdJoinConfig;
+use crate::errors::*;
+use crate::matching::{is_self_join, join_records};
+use crate::qgram::{read_input, ID};
+use crate::report::{JoinReport, Side};
+
+/// Configuration of a sliding-window join, which finds near-duplicate documents rather than matching whole records.
+///
+/// Each record is split into windows of `size` characters, `step` characters apart, and the last window of a record
+/// is aligned to its end so that its tail is covered too. Records no longer than `size` are a single window.
+/// Windows are joined with `q` and `tau` as records would be, and a pair of records is a match if the windows that matched
+/// between them cover at least `min_coverage` of either of them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Windowing {
+    pub size: usize,
+    pub step: usize,
+    /// Smallest fraction of the characters of a record, in `[0, 1]`, which matched windows must cover.
+    pub min_coverage: f64,
+}
+
+/// A window of a record, as the ID of the record and the span of characters it covers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Window {
+    id: ID,
+    start: usize,
+    end: usize,
+}
+
+/// Matched windows between a pair of records.
+#[derive(Debug, Default)]
+struct Coverage {
+    spans_x: Vec<(usize, usize)>,
+    spans_y: Vec<(usize, usize)>,
+    windows: usize,
+    min_ed: usize,
+}
+
+/// Split all records into windows, as the windows themselves and where they're from.
+fn split_windows(records: &[String], windowing: &Windowing) -> (Vec<String>, Vec<Window>) {
+    let mut contents: Vec<String> = Vec::new();
+    let mut windows: Vec<Window> = Vec::new();
+    for (id, record) in records.iter().enumerate() {
+        // byte offsets of characters, so that windows never split one
+        let offsets: Vec<usize> = record
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(Some(record.len()))
+            .collect();
+        let len: usize = offsets.len() - 1;
+        if len == 0 {
+            continue;
+        }
+        let mut starts: Vec<usize> = (0..len.saturating_sub(windowing.size) + 1)
+            .step_by(windowing.step)
+            .collect();
+        let last: usize = len.saturating_sub(windowing.size);
+        if starts.last() != Some(&last) {
+            starts.push(last);
+        }
+        for start in starts {
+            let end: usize = (start + windowing.size).min(len);
+            contents.push(record[offsets[start]..offsets[end]].to_string());
+            windows.push(Window { id, start, end });
+        }
+    }
+    (contents, windows)
+}
+
+/// Number of characters covered by a set of spans, which may overlap.
+fn covered(spans: &mut [(usize, usize)]) -> usize {
+    spans.sort_unstable();
+    let mut total: usize = 0;
+    let mut reach: usize = 0;
+    for (start, end) in spans.iter() {
+        if *end > reach {
+            total += end - (*start).max(reach);
+            reach = *end;
+        }
+    }
+    total
+}
+
+/// Join the windows of the records of `doc_x` and `doc_y`, and write the pairs of records whose windows cover enough of them.
+///
+/// The output is a CSV file of `x_id`, `y_id`, the number of matched pairs of windows, the coverage of both records,
+/// and the smallest edit distance of their windows. As for a join of whole records, this is the body of
+/// [`crate::matching::ed_join_with_config`] when [`EdJoinConfig::windowing`] is set.
+pub fn window_join(
+    doc_x: &PathBuf,
+    doc_y: &PathBuf,
+    out_name: &Path,
+    config: &EdJoinConfig,
+    windowing: &Windowing,
+) -> Result<JoinReport> {
+    let (q, tau) = (config.q, config.tau);
+    config.validate()?;
+    let self_join: bool = is_self_join(doc_x, doc_y, config.mode)?;
+    let mut report: JoinReport = JoinReport::default();
+
+    let (x_vec, mut warnings_x) = read_input(doc_x, Side::X, config)?;
+    report.warnings.append(&mut warnings_x);
+    let (x_windows, x_spans) = split_windows(&x_vec, windowing);
+    let (y_windows, y_spans) = if self_join || doc_x == doc_y {
+        (x_windows.clone(), x_spans.clone())
+    } else {
+        let (y_vec, mut warnings_y) = read_input(doc_y, Side::Y, config)?;
+        report.warnings.append(&mut warnings_y);
+        split_windows(&y_vec, windowing)
+    };
+    #[cfg(feature = "logging")]
+    info!(
+        "Split the records into {} and {} windows",
+        x_windows.len(),
+        y_windows.len()
+    );
+
+    let matched: Vec<(ID, ID, usize)> = if self_join {
+        join_records(None, &y_windows, q, tau, &*config.tokenizer)
+            .into_iter()
+            // overlapping windows of the same record are not duplicates
+            .filter(|(x_win, y_win, _)| x_spans[*x_win].id != y_spans[*y_win].id)
+            .collect()
+    } else {
+        join_records(Some(&x_windows), &y_windows, q, tau, &*config.tokenizer)
+    };
+
+    let mut pairs: BTreeMap<(ID, ID), Coverage> = BTreeMap::new();
+    for (x_win, y_win, ed) in matched {
+        let (x, y): (&Window, &Window) = (&x_spans[x_win], &y_spans[y_win]);
+        let coverage: &mut Coverage = pairs.entry((x.id, y.id)).or_insert_with(|| Coverage {
+            min_ed: ed,
+            ..Coverage::default()
+        });
+        coverage.spans_x.push((x.start, x.end));
+        coverage.spans_y.push((y.start, y.end));
+        coverage.windows += 1;
+        coverage.min_ed = coverage.min_ed.min(ed);
+    }
+
+    // the length of a record is the end of its last window
+    let lengths = |spans: &[Window]| -> BTreeMap<ID, usize> {
+        spans.iter().map(|window| (window.id, window.end)).collect()
+    };
+    let (len_x, len_y) = (lengths(&x_spans), lengths(&y_spans));
+    let delimiter: char = config.delimiter as char;
+    let mut writer: BufWriter<Box<dyn Write + Send>> =
+        BufWriter::new(config.open_output(out_name)?);
+    for ((x_id, y_id), mut coverage) in pairs {
+        let coverage_x: f64 = covered(&mut coverage.spans_x) as f64 / len_x[&x_id] as f64;
+        let coverage_y: f64 = covered(&mut coverage.spans_y) as f64 / len_y[&y_id] as f64;
+        if coverage_x.max(coverage_y) < windowing.min_coverage {
+            continue;
+        }
+        writeln!(
+            writer,
+            "{x_id}{d}{y_id}{d}{}{d}{:.3}{d}{:.3}{d}{}",
+            coverage.windows,
+            coverage_x,
+            coverage_y,
+            coverage.min_ed,
+            d = delimiter
+        )?;
+    }
+    writer.flush()?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows() {
+        let windowing: Windowing = Windowing {
+            size: 4,
+            step: 3,
+            min_coverage: 0.5,
+        };
+        let records: Vec<String> = ["abcdefghij", "xyz", "", "äöüßab"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let (contents, windows) = split_windows(&records, &windowing);
+        assert_eq!(
+            contents,
+            vec!["abcd", "defg", "ghij", "xyz", "äöüß", "üßab"]
+        );
+        assert_eq!(
+            windows[2],
+            Window {
+                id: 0,
+                start: 6,
+                end: 10
+            }
+        );
+        assert_eq!(
+            windows[3],
+            Window {
+                id: 1,
+                start: 0,
+                end: 3
+            }
+        );
+        assert_eq!(
+            windows[5],
+            Window {
+                id: 3,
+                start: 2,
+                end: 6
+            }
+        );
+
+        assert_eq!(covered(&mut [(6, 10), (0, 4), (3, 7)]), 10);
+        assert_eq!(covered(&mut [(0, 4), (6, 10)]), 8);
+    }
+}